@@ -0,0 +1,339 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    extract::{Path, Query, State},
+    Extension, Json,
+};
+use bonsai_sdk::alpha::SessionId;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use utoipa::{IntoParams, ToSchema};
+use validator::Validate;
+
+use super::{error::DisplayErrorCauses, keys::AdminKey, state::ApiState, Error, Result};
+use crate::storage::{AuditEntry, ProofID, Storage};
+
+const DEFAULT_AUDIT_LOG_SIZE: u64 = 100;
+
+/// What operators have paused or requested.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, ToSchema)]
+pub(crate) struct AdminStatus {
+    /// Whether new callback requests are held back.
+    pub ingestion_paused: bool,
+    /// Whether batches are held back instead of sent to the relay contract.
+    pub sending_paused: bool,
+    /// Whether the relay stops once the requests in flight are settled.
+    pub draining: bool,
+}
+
+/// An action taken by an operator, as recorded in the audit log.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, ToSchema)]
+pub(crate) struct AuditLogEntry {
+    /// Name of the admin key the action was taken with.
+    pub operator: String,
+    pub action: String,
+    /// The callback request the action applies to, if any.
+    pub request_id: Option<String>,
+    /// `ok`, or why the action was refused.
+    pub outcome: String,
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+impl From<AuditEntry> for AuditLogEntry {
+    fn from(entry: AuditEntry) -> Self {
+        Self {
+            operator: entry.operator,
+            action: entry.action,
+            request_id: entry.proof_request_id.map(|id| id.uuid),
+            outcome: entry.outcome,
+            timestamp: entry.timestamp,
+        }
+    }
+}
+
+fn status<S: Storage + Sync + Send + Clone>(s: &ApiState<S>) -> AdminStatus {
+    AdminStatus {
+        ingestion_paused: s.control.ingestion.is_paused(),
+        sending_paused: s.control.sending.is_paused(),
+        draining: s.control.is_draining(),
+    }
+}
+
+/// Record the outcome of an action in the audit log, then pass it on.
+async fn audit<S: Storage + Sync + Send + Clone, T>(
+    s: &ApiState<S>,
+    admin_key: &AdminKey,
+    action: &str,
+    proof_id: Option<ProofID>,
+    result: Result<T>,
+) -> Result<T> {
+    let outcome = match &result {
+        Ok(_) => "ok".to_string(),
+        Err(err) => DisplayErrorCauses(err).to_string(),
+    };
+    info!(
+        target: "audit",
+        operator = admin_key.name,
+        action,
+        request_id = proof_id.as_ref().map(|id| id.uuid.as_str()),
+        outcome,
+        "admin action"
+    );
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    s.storage
+        .record_audit_entry(AuditEntry {
+            operator: admin_key.name.clone(),
+            action: action.to_string(),
+            proof_request_id: proof_id,
+            outcome,
+            timestamp,
+        })
+        .await?;
+    result
+}
+
+/// Get what operators have paused or requested.
+#[utoipa::path(
+    get,
+    path = "/v1/admin/status",
+    responses(
+        (status = 200, description = "Status of the relay", body = AdminStatus),
+        (status = 401, description = "Missing or unknown admin key"),
+    )
+)]
+pub(crate) async fn get_admin_status<S: Storage + Sync + Send + Clone>(
+    State(s): State<ApiState<S>>,
+) -> Json<AdminStatus> {
+    Json(status(&s))
+}
+
+/// Hold back new callback requests. Requests sent to the REST API are
+/// refused, and events read from the chain wait until ingestion resumes.
+#[utoipa::path(
+    post,
+    path = "/v1/admin/ingestion/pause",
+    responses(
+        (status = 200, description = "Ingestion paused", body = AdminStatus),
+        (status = 401, description = "Missing or unknown admin key"),
+        (status = 500, description = "Internal server error"),
+    )
+)]
+pub(crate) async fn post_pause_ingestion<S: Storage + Sync + Send + Clone>(
+    Extension(admin_key): Extension<Arc<AdminKey>>,
+    State(s): State<ApiState<S>>,
+) -> Result<Json<AdminStatus>, Error> {
+    s.control.ingestion.pause();
+    audit(&s, &admin_key, "pause_ingestion", None, Ok(())).await?;
+    Ok(Json(status(&s)))
+}
+
+/// Accept new callback requests again.
+#[utoipa::path(
+    post,
+    path = "/v1/admin/ingestion/resume",
+    responses(
+        (status = 200, description = "Ingestion resumed", body = AdminStatus),
+        (status = 401, description = "Missing or unknown admin key"),
+        (status = 409, description = "The relay is draining"),
+        (status = 500, description = "Internal server error"),
+    )
+)]
+pub(crate) async fn post_resume_ingestion<S: Storage + Sync + Send + Clone>(
+    Extension(admin_key): Extension<Arc<AdminKey>>,
+    State(s): State<ApiState<S>>,
+) -> Result<Json<AdminStatus>, Error> {
+    let result = match s.control.is_draining() {
+        true => Err(Error::Conflict {
+            reason: "the relay is draining".to_string(),
+        }),
+        false => {
+            s.control.ingestion.resume();
+            Ok(())
+        }
+    };
+    audit(&s, &admin_key, "resume_ingestion", None, result).await?;
+    Ok(Json(status(&s)))
+}
+
+/// Hold back batches instead of sending them to the relay contract.
+/// Completed callbacks keep queueing up until sending resumes.
+#[utoipa::path(
+    post,
+    path = "/v1/admin/sending/pause",
+    responses(
+        (status = 200, description = "Sending paused", body = AdminStatus),
+        (status = 401, description = "Missing or unknown admin key"),
+        (status = 409, description = "The relay is draining"),
+        (status = 500, description = "Internal server error"),
+    )
+)]
+pub(crate) async fn post_pause_sending<S: Storage + Sync + Send + Clone>(
+    Extension(admin_key): Extension<Arc<AdminKey>>,
+    State(s): State<ApiState<S>>,
+) -> Result<Json<AdminStatus>, Error> {
+    // A drain only ends once every batch is sent.
+    let result = match s.control.is_draining() {
+        true => Err(Error::Conflict {
+            reason: "the relay is draining".to_string(),
+        }),
+        false => {
+            s.control.sending.pause();
+            Ok(())
+        }
+    };
+    audit(&s, &admin_key, "pause_sending", None, result).await?;
+    Ok(Json(status(&s)))
+}
+
+/// Send batches to the relay contract again.
+#[utoipa::path(
+    post,
+    path = "/v1/admin/sending/resume",
+    responses(
+        (status = 200, description = "Sending resumed", body = AdminStatus),
+        (status = 401, description = "Missing or unknown admin key"),
+        (status = 500, description = "Internal server error"),
+    )
+)]
+pub(crate) async fn post_resume_sending<S: Storage + Sync + Send + Clone>(
+    Extension(admin_key): Extension<Arc<AdminKey>>,
+    State(s): State<ApiState<S>>,
+) -> Result<Json<AdminStatus>, Error> {
+    s.control.sending.resume();
+    audit(&s, &admin_key, "resume_sending", None, Ok(())).await?;
+    Ok(Json(status(&s)))
+}
+
+/// Stop ingesting callback requests, and stop the relay once the requests in
+/// flight are settled. Sending is resumed if it was paused.
+#[utoipa::path(
+    post,
+    path = "/v1/admin/drain",
+    responses(
+        (status = 200, description = "Drain started", body = AdminStatus),
+        (status = 401, description = "Missing or unknown admin key"),
+        (status = 500, description = "Internal server error"),
+    )
+)]
+pub(crate) async fn post_drain<S: Storage + Sync + Send + Clone>(
+    Extension(admin_key): Extension<Arc<AdminKey>>,
+    State(s): State<ApiState<S>>,
+) -> Result<Json<AdminStatus>, Error> {
+    s.control.drain();
+    audit(&s, &admin_key, "drain", None, Ok(())).await?;
+    Ok(Json(status(&s)))
+}
+
+/// Move a callback request back to `new`, resetting its attempts, so that it
+/// is proven again. Requests being sent on chain or already delivered cannot
+/// be requeued.
+#[utoipa::path(
+    post,
+    path = "/v1/admin/requests/{request_id}/requeue",
+    params(("request_id" = String, Path, description = "ID of the callback request")),
+    responses(
+        (status = 200, description = "Callback request requeued"),
+        (status = 401, description = "Missing or unknown admin key"),
+        (status = 404, description = "Unknown callback request"),
+        (status = 409, description = "Callback request cannot be requeued in its state"),
+        (status = 500, description = "Internal server error"),
+    )
+)]
+pub(crate) async fn post_force_requeue<S: Storage + Sync + Send + Clone>(
+    Extension(admin_key): Extension<Arc<AdminKey>>,
+    State(s): State<ApiState<S>>,
+    Path(request_id): Path<String>,
+) -> Result<(), Error> {
+    let id = SessionId::new(request_id);
+    let result = s
+        .storage
+        .force_requeue_proof_request(id.clone())
+        .await
+        .map_err(Error::from);
+    audit(&s, &admin_key, "requeue", Some(id), result).await?;
+    s.notifier.notify_one();
+    Ok(())
+}
+
+/// Cancel a callback request, so that it is not processed further. Requests
+/// being sent on chain or already delivered cannot be cancelled.
+#[utoipa::path(
+    post,
+    path = "/v1/admin/requests/{request_id}/cancel",
+    params(("request_id" = String, Path, description = "ID of the callback request")),
+    responses(
+        (status = 200, description = "Callback request cancelled"),
+        (status = 401, description = "Missing or unknown admin key"),
+        (status = 404, description = "Unknown callback request"),
+        (status = 409, description = "Callback request cannot be cancelled in its state"),
+        (status = 500, description = "Internal server error"),
+    )
+)]
+pub(crate) async fn post_cancel<S: Storage + Sync + Send + Clone>(
+    Extension(admin_key): Extension<Arc<AdminKey>>,
+    State(s): State<ApiState<S>>,
+    Path(request_id): Path<String>,
+) -> Result<(), Error> {
+    let id = SessionId::new(request_id);
+    let result = s
+        .storage
+        .cancel_proof_request(id.clone())
+        .await
+        .map_err(Error::from);
+    audit(&s, &admin_key, "cancel", Some(id), result).await?;
+    Ok(())
+}
+
+/// Pagination of [get_audit_log].
+#[derive(Debug, Deserialize, Validate, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct AuditLogQuery {
+    /// Maximum number of entries to list, 100 by default.
+    #[validate(range(min = 1, max = 1000))]
+    limit: Option<u64>,
+}
+
+/// List the actions of operators, most recent first.
+#[utoipa::path(
+    get,
+    path = "/v1/admin/audit-log",
+    params(AuditLogQuery),
+    responses(
+        (status = 200, description = "Actions of operators", body = [AuditLogEntry]),
+        (status = 400, description = "Bad request error"),
+        (status = 401, description = "Missing or unknown admin key"),
+        (status = 500, description = "Internal server error"),
+    )
+)]
+pub(crate) async fn get_audit_log<S: Storage + Sync + Send + Clone>(
+    State(s): State<ApiState<S>>,
+    Query(query): Query<AuditLogQuery>,
+) -> Result<Json<Vec<AuditLogEntry>>, Error> {
+    query.validate()?;
+    let entries = s
+        .storage
+        .fetch_audit_log(query.limit.unwrap_or(DEFAULT_AUDIT_LOG_SIZE))
+        .await?;
+    Ok(Json(entries.into_iter().map(Into::into).collect()))
+}
@@ -12,22 +12,62 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use axum::{http::Request, middleware::Next, response::Response};
+use std::{sync::Arc, time::SystemTime};
 
-use super::{Error, Result};
+use axum::{extract::State, http::Request, middleware::Next, response::Response};
+use tracing::warn;
 
-pub(crate) async fn authorize<B>(mut req: Request<B>, next: Next<B>) -> Result<Response> {
-    if let Some(auth_header) = req
+use super::{
+    keys::{AdminKey, ApiKey},
+    state::ApiState,
+    Error, Result,
+};
+use crate::storage::Storage;
+
+/// Authenticate the key in the `x-api-key` header and count the request
+/// against its rate limit. The matching [ApiKey] is passed on to the handlers
+/// as a request extension.
+pub(crate) async fn authorize<S: Storage + Sync + Send + Clone, B>(
+    State(s): State<ApiState<S>>,
+    mut req: Request<B>,
+    next: Next<B>,
+) -> Result<Response> {
+    let key = req
         .headers()
         .get("x-api-key")
         .and_then(|header| header.to_str().ok())
-    {
-        let owned_value = auth_header.to_owned();
-        // insert the current user into a request extension so the handler can
-        // extract it
-        req.extensions_mut().insert(owned_value);
-        Ok(next.run(req).await)
-    } else {
-        Err(Error::Unauthorized)
+        .ok_or(Error::Unauthorized)?;
+    let api_key: Arc<ApiKey> = s.keys.authenticate(key)?;
+    if let Err(err) = s.keys.check_rate(&api_key, SystemTime::now()) {
+        warn!(key = api_key.name, "API key exceeded its rate limit");
+        return Err(err);
     }
+
+    req.extensions_mut().insert(api_key);
+    Ok(next.run(req).await)
+}
+
+/// Authenticate the key in the `x-admin-key` header against the admin keys.
+/// The matching [AdminKey] is passed on to the handlers as a request
+/// extension.
+pub(crate) async fn authorize_admin<S: Storage + Sync + Send + Clone, B>(
+    State(s): State<ApiState<S>>,
+    mut req: Request<B>,
+    next: Next<B>,
+) -> Result<Response> {
+    let key = req
+        .headers()
+        .get("x-admin-key")
+        .and_then(|header| header.to_str().ok())
+        .ok_or(Error::Unauthorized)?;
+    let admin_key: Arc<AdminKey> = match s.keys.authenticate_admin(key) {
+        Ok(admin_key) => admin_key,
+        Err(err) => {
+            warn!(path = %req.uri().path(), "rejected unknown admin key");
+            return Err(err);
+        }
+    };
+
+    req.extensions_mut().insert(admin_key);
+    Ok(next.run(req).await)
 }
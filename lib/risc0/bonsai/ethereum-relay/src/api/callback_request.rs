@@ -12,41 +12,73 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use axum::{extract::State, Extension};
+use std::{sync::Arc, time::SystemTime};
+
+use axum::{extract::State, Extension, Json};
 use bonsai_ethereum_contracts::i_bonsai_relay::CallbackRequestFilter;
 use bonsai_sdk::alpha_async::get_client_from_parts;
+use ethers::types::H256;
 
-use super::{bincode::Bincode, state::ApiState, Error, Result};
+use super::{bincode::Bincode, keys::ApiKey, state::ApiState, Error, Result};
 use crate::{
-    downloader::{
-        event_processor::EventProcessor,
-        proxy_callback_proof_processor::ProxyCallbackProofRequestProcessor,
-    },
-    sdk::client::CallbackRequest,
+    downloader::proxy_callback_proof_processor::ProxyCallbackProofRequestProcessor,
+    sdk::client::{CallbackRequest, CallbackRequestResponse},
     storage::Storage,
 };
 
 /// Publish a CallbackRequest to the Relayer.
 ///
-/// Return status 200 on success.
+/// Return status 200 and the ID of the request on success.
 #[utoipa::path(
     post,
     path = "/v1/callbacks",
     request_body = CallbackRequest,
     responses(
-        (status = 200, description = "Callback request sent successfully"),
+        (status = 200, description = "Callback request sent successfully", body = CallbackRequestResponse),
         (status = 400, description = "Bad request error"),
+        (status = 401, description = "Missing or unknown API key"),
+        (status = 403, description = "Image ID, callback contract or webhook URL not allowed for the API key"),
+        (status = 429, description = "Rate limit or daily cycles of the API key exceeded"),
         (status = 500, description = "Internal server error"),
+        (status = 503, description = "Ingestion paused by an operator"),
     )
 )]
 pub(crate) async fn post_callback_request<S: Storage + Sync + Send + Clone>(
-    Extension(api_key): Extension<String>,
+    Extension(api_key): Extension<Arc<ApiKey>>,
     State(s): State<ApiState<S>>,
     Bincode(request): Bincode<CallbackRequest>,
-) -> Result<(), Error> {
-    let client = get_client_from_parts(s.bonsai_url, api_key).await?;
-    let proxy = ProxyCallbackProofRequestProcessor::new(client, s.storage, Some(s.notifier));
-    proxy.process_event(request.into()).await
+) -> Result<Json<CallbackRequestResponse>, Error> {
+    if s.control.ingestion.is_paused() {
+        return Err(Error::Unavailable {
+            reason: "ingestion is paused".to_string(),
+        });
+    }
+    let keys = s.keys.clone();
+    keys.authorize_callback(
+        &api_key,
+        H256(request.image_id),
+        request.callback_contract,
+        request.webhook_url.as_deref(),
+        SystemTime::now(),
+    )?;
+    // Only requests that reach Bonsai count against the daily cycles.
+    let submitted = async {
+        let client = get_client_from_parts(s.bonsai_url, s.bonsai_api_key).await?;
+        let proxy = ProxyCallbackProofRequestProcessor::new(client, s.storage, Some(s.notifier));
+        let webhook_url = request.webhook_url.clone();
+        Ok::<_, Error>(proxy.submit(request.into(), webhook_url).await?)
+    }
+    .await;
+    let proof_id = match submitted {
+        Ok(proof_id) => proof_id,
+        Err(err) => {
+            keys.refund_callback(&api_key, SystemTime::now())?;
+            return Err(err);
+        }
+    };
+    Ok(Json(CallbackRequestResponse {
+        request_id: proof_id.uuid,
+    }))
 }
 
 impl From<CallbackRequest> for CallbackRequestFilter {
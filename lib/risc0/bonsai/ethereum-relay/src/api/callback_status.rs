@@ -0,0 +1,244 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    Extension, Json,
+};
+use bonsai_sdk::alpha::SessionId;
+use ethers::types::{Address, H256};
+use serde::Deserialize;
+use utoipa::IntoParams;
+use validator::{Validate, ValidationError};
+
+use super::{keys::ApiKey, state::ApiState, Error, Result};
+use crate::{
+    sdk::client::{CallbackStatus, CallbackStatusPage, WebhookDeliveryAttempt},
+    storage::{
+        Error as StorageError, ProofRequestFilter, ProofRequestInformation, ProofRequestState,
+        Storage, WebhookDelivery,
+    },
+};
+
+const DEFAULT_PAGE_SIZE: u64 = 50;
+
+impl From<(ProofRequestInformation, ProofRequestState)> for CallbackStatus {
+    fn from((request, state): (ProofRequestInformation, ProofRequestState)) -> Self {
+        let event = request.callback_proof_request_event;
+        Self {
+            request_id: request.proof_request_id.uuid.clone(),
+            state: state.name().to_string(),
+            bonsai_session_id: request.proof_request_id.uuid,
+            image_id: H256(event.image_id),
+            callback_contract: event.callback_contract,
+            tx_hash: state.tx_hash(),
+            attempts: request.attempts,
+            error: request.last_error,
+            revert_data: request.revert_data,
+            webhook_url: request.webhook_url,
+        }
+    }
+}
+
+impl From<WebhookDelivery> for WebhookDeliveryAttempt {
+    fn from(delivery: WebhookDelivery) -> Self {
+        Self {
+            attempt: delivery.attempt,
+            url: delivery.url,
+            status_code: delivery.status_code,
+            error: delivery.error,
+            timestamp: delivery.timestamp,
+        }
+    }
+}
+
+/// Whether the key may see the given request.
+fn is_visible(api_key: &ApiKey, request: &ProofRequestInformation) -> bool {
+    let event = &request.callback_proof_request_event;
+    api_key.allows(
+        H256(event.image_id),
+        event.callback_contract,
+        request.webhook_url.as_deref(),
+    )
+}
+
+/// Get the status of a callback request.
+#[utoipa::path(
+    get,
+    path = "/v1/callbacks/{request_id}",
+    params(("request_id" = String, Path, description = "ID of the callback request")),
+    responses(
+        (status = 200, description = "Status of the callback request", body = CallbackStatus),
+        (status = 401, description = "Missing or unknown API key"),
+        (status = 404, description = "Unknown callback request"),
+        (status = 500, description = "Internal server error"),
+    )
+)]
+pub(crate) async fn get_callback_status<S: Storage + Sync + Send + Clone>(
+    Extension(api_key): Extension<Arc<ApiKey>>,
+    State(s): State<ApiState<S>>,
+    Path(request_id): Path<String>,
+) -> Result<Json<CallbackStatus>, Error> {
+    let id = SessionId::new(request_id);
+    let request = s.storage.get_proof_request(id.clone()).await?;
+    // Requests of other clients are reported as unknown.
+    if !is_visible(&api_key, &request) {
+        return Err(StorageError::ProofNotFound { id }.into());
+    }
+    let state = s.storage.get_proof_request_state(id).await?;
+    Ok(Json((request, state).into()))
+}
+
+/// Get the webhook delivery log of a callback request, oldest attempt first.
+#[utoipa::path(
+    get,
+    path = "/v1/callbacks/{request_id}/deliveries",
+    params(("request_id" = String, Path, description = "ID of the callback request")),
+    responses(
+        (status = 200, description = "Attempts at posting the proof to its webhook", body = [WebhookDeliveryAttempt]),
+        (status = 401, description = "Missing or unknown API key"),
+        (status = 404, description = "Unknown callback request"),
+        (status = 500, description = "Internal server error"),
+    )
+)]
+pub(crate) async fn get_webhook_deliveries<S: Storage + Sync + Send + Clone>(
+    Extension(api_key): Extension<Arc<ApiKey>>,
+    State(s): State<ApiState<S>>,
+    Path(request_id): Path<String>,
+) -> Result<Json<Vec<WebhookDeliveryAttempt>>, Error> {
+    let id = SessionId::new(request_id);
+    // Delivered requests may no longer be kept, but their log is.
+    let deliveries = s.storage.fetch_webhook_deliveries(id.clone()).await?;
+    let visible = match s.storage.get_proof_request(id.clone()).await {
+        Ok(request) => is_visible(&api_key, &request),
+        Err(StorageError::ProofNotFound { .. }) => {
+            !deliveries.is_empty()
+                && deliveries
+                    .iter()
+                    .all(|delivery| api_key.webhook_urls.contains(&delivery.url))
+        }
+        Err(err) => return Err(err.into()),
+    };
+    if !visible {
+        return Err(StorageError::ProofNotFound { id }.into());
+    }
+    Ok(Json(deliveries.into_iter().map(Into::into).collect()))
+}
+
+/// Filters and pagination of [list_callbacks].
+#[derive(Debug, Deserialize, Validate, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct CallbackStatusQuery {
+    /// Only list requests in this state.
+    #[validate(custom = "validate_state")]
+    state: Option<String>,
+    /// Only list requests for this image ID.
+    #[param(value_type = Option<String>)]
+    image_id: Option<H256>,
+    /// Only list requests to this callback contract.
+    #[param(value_type = Option<String>)]
+    callback_contract: Option<Address>,
+    /// Only list requests posted to this webhook URL.
+    webhook_url: Option<String>,
+    /// List the requests after this request ID.
+    after: Option<String>,
+    /// Maximum number of requests to list, 50 by default.
+    #[validate(range(min = 1, max = 500))]
+    limit: Option<u64>,
+}
+
+fn validate_state(state: &str) -> Result<(), ValidationError> {
+    if !ProofRequestState::NAMES.contains(&state) {
+        return Err(ValidationError::new("unknown state"));
+    }
+    Ok(())
+}
+
+/// Restrict a filter to the given values, or to the ones the key may see if
+/// none is given.
+fn restrict<T: PartialEq + Clone>(value: Option<T>, allowed: &[T]) -> Vec<T> {
+    match value {
+        // Values the key may not see match no request.
+        Some(value) if allowed.contains(&value) => vec![value],
+        Some(_) => Vec::new(),
+        None => allowed.to_vec(),
+    }
+}
+
+/// List the callback requests of the client.
+#[utoipa::path(
+    get,
+    path = "/v1/callbacks",
+    params(CallbackStatusQuery),
+    responses(
+        (status = 200, description = "A page of callback requests", body = CallbackStatusPage),
+        (status = 400, description = "Bad request error"),
+        (status = 401, description = "Missing or unknown API key"),
+        (status = 500, description = "Internal server error"),
+    )
+)]
+pub(crate) async fn list_callbacks<S: Storage + Sync + Send + Clone>(
+    Extension(api_key): Extension<Arc<ApiKey>>,
+    State(s): State<ApiState<S>>,
+    Query(query): Query<CallbackStatusQuery>,
+) -> Result<Json<CallbackStatusPage>, Error> {
+    query.validate()?;
+    let image_ids = restrict(query.image_id, &api_key.image_ids);
+    // Filtering by one kind of target leaves out the requests of the other.
+    let (callback_contracts, webhook_urls) = match (query.callback_contract, query.webhook_url) {
+        (None, None) => (
+            api_key.callback_contracts.clone(),
+            api_key.webhook_urls.clone(),
+        ),
+        (callback_contract, webhook_url) => (
+            callback_contract
+                .map(|contract| restrict(Some(contract), &api_key.callback_contracts))
+                .unwrap_or_default(),
+            webhook_url
+                .map(|url| restrict(Some(url), &api_key.webhook_urls))
+                .unwrap_or_default(),
+        ),
+    };
+    if image_ids.is_empty() || (callback_contracts.is_empty() && webhook_urls.is_empty()) {
+        return Ok(Json(CallbackStatusPage {
+            callbacks: Vec::new(),
+            next: None,
+        }));
+    }
+
+    let filter = ProofRequestFilter {
+        state: query.state,
+        image_ids: image_ids.into_iter().map(|image_id| image_id.0).collect(),
+        callback_contracts,
+        webhook_urls,
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    let requests = s
+        .storage
+        .list_proof_requests(&filter, query.after.map(SessionId::new), limit)
+        .await?;
+
+    let next = match requests.len() as u64 == limit {
+        true => requests
+            .last()
+            .map(|(request, _)| request.proof_request_id.uuid.clone()),
+        false => None,
+    };
+    Ok(Json(CallbackStatusPage {
+        callbacks: requests.into_iter().map(Into::into).collect(),
+        next,
+    }))
+}
@@ -0,0 +1,84 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use bonsai_sdk::alpha::SessionId;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::{state::ApiState, Error, Result};
+use crate::storage::{ProofRequestInformation, Storage};
+
+/// A callback request that failed too many times to be retried automatically.
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+pub(crate) struct DeadLetterResponse {
+    /// The ID the relay tracks the request under.
+    pub request_id: String,
+    /// Number of failed attempts at processing the request.
+    pub attempts: u32,
+    /// The error of the last failed attempt.
+    pub last_error: Option<String>,
+}
+
+impl From<ProofRequestInformation> for DeadLetterResponse {
+    fn from(request: ProofRequestInformation) -> Self {
+        Self {
+            request_id: request.proof_request_id.uuid,
+            attempts: request.attempts,
+            last_error: request.last_error,
+        }
+    }
+}
+
+/// List the dead-lettered callback requests.
+#[utoipa::path(
+    get,
+    path = "/v1/dead-letters",
+    responses(
+        (status = 200, description = "Dead-lettered callback requests", body = [DeadLetterResponse]),
+        (status = 500, description = "Internal server error"),
+    )
+)]
+pub(crate) async fn get_dead_letters<S: Storage + Sync + Send + Clone>(
+    State(s): State<ApiState<S>>,
+) -> Result<Json<Vec<DeadLetterResponse>>, Error> {
+    let requests = s.storage.fetch_dead_letter_proof_requests(None).await?;
+    Ok(Json(requests.into_iter().map(Into::into).collect()))
+}
+
+/// Requeue a dead-lettered callback request, resetting its attempts.
+#[utoipa::path(
+    post,
+    path = "/v1/dead-letters/{request_id}/requeue",
+    params(("request_id" = String, Path, description = "ID of the callback request")),
+    responses(
+        (status = 200, description = "Callback request requeued"),
+        (status = 404, description = "Unknown callback request"),
+        (status = 409, description = "Callback request is not dead-lettered"),
+        (status = 500, description = "Internal server error"),
+    )
+)]
+pub(crate) async fn post_requeue_dead_letter<S: Storage + Sync + Send + Clone>(
+    State(s): State<ApiState<S>>,
+    Path(request_id): Path<String>,
+) -> Result<(), Error> {
+    s.storage
+        .requeue_dead_letter_proof_request(SessionId::new(request_id))
+        .await?;
+    s.notifier.notify_one();
+    Ok(())
+}
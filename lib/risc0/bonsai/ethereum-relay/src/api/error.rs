@@ -20,18 +20,24 @@ use std::{
 
 use axum::{http::StatusCode, response};
 use bonsai_sdk::alpha::SdkErr;
-use ethers::{
-    prelude::signer::SignerMiddlewareError,
-    providers::{Provider, Ws},
-};
-use ethers_signers::LocalWallet;
+use ethers::{prelude::signer::SignerMiddlewareError, providers::Provider};
 use tokio::task::JoinError;
 use validator::ValidationErrors;
 
+use crate::{signer::RelaySigner, storage::Error as StorageError, transport::RelayTransport};
+
 #[derive(Debug, thiserror::Error)]
 pub(crate) enum Error {
     #[error("Unauthorized")]
     Unauthorized,
+    #[error("Forbidden: {reason}")]
+    Forbidden { reason: String },
+    #[error("Quota exceeded: {quota}")]
+    QuotaExceeded { quota: String },
+    #[error("Conflict: {reason}")]
+    Conflict { reason: String },
+    #[error("Service unavailable: {reason}")]
+    Unavailable { reason: String },
     #[error("Bonsai SDK error: {0}")]
     Bonsai(#[from] SdkErr),
     #[error("Client error: {0}")]
@@ -49,11 +55,11 @@ pub(crate) enum Error {
     #[error("JoinHandle error")]
     Join(#[from] JoinError),
     #[error("Storage error")]
-    Storage(#[from] crate::storage::Error),
+    Storage(#[from] StorageError),
     #[error("Ethers parse error")]
     EthersParse(#[from] ethers::abi::Error),
     #[error("Signer middleware error")]
-    SignerMiddleware(#[from] SignerMiddlewareError<Provider<Ws>, LocalWallet>),
+    SignerMiddleware(#[from] SignerMiddlewareError<Provider<RelayTransport>, RelaySigner>),
     #[error("Unspecified error")]
     Unspecified(#[from] anyhow::Error),
 }
@@ -65,6 +71,14 @@ impl Error {
                 StatusCode::BAD_REQUEST
             }
             Error::Unauthorized { .. } => StatusCode::UNAUTHORIZED,
+            Error::Forbidden { .. } => StatusCode::FORBIDDEN,
+            Error::QuotaExceeded { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Error::Conflict { .. } => StatusCode::CONFLICT,
+            Error::Unavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            Error::Storage(StorageError::ProofNotFound { .. }) => StatusCode::NOT_FOUND,
+            Error::Storage(
+                StorageError::NotDeadLetter { .. } | StorageError::InvalidAdminAction { .. },
+            ) => StatusCode::CONFLICT,
             Error::Bincode { .. }
             | Error::Storage { .. }
             | Error::SignerMiddleware { .. }
@@ -0,0 +1,158 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{future::ready, sync::Arc};
+
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    response::sse::{Event, KeepAlive, Sse},
+    Extension,
+};
+use ethers::types::{Address, H256};
+use futures::{stream, Stream, StreamExt};
+use serde::Deserialize;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::warn;
+use utoipa::IntoParams;
+use validator::{ValidationError, ValidationErrors};
+
+use super::{keys::ApiKey, state::ApiState, Error, Result};
+use crate::storage::{events::TransitionEvent, Storage};
+
+/// Filters of [get_events].
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct EventsQuery {
+    /// Only stream transitions of requests for this image ID.
+    #[param(value_type = Option<String>)]
+    image_id: Option<H256>,
+    /// Only stream transitions of requests to this callback contract.
+    #[param(value_type = Option<String>)]
+    callback_contract: Option<Address>,
+    /// Only stream transitions of requests posted to this webhook URL.
+    webhook_url: Option<String>,
+    /// Resume after this event ID. The `Last-Event-ID` header takes
+    /// precedence.
+    last_event_id: Option<u64>,
+}
+
+/// The ID in the `Last-Event-ID` header sent by reconnecting clients.
+fn last_event_id(headers: &HeaderMap) -> Result<Option<u64>> {
+    let header = match headers.get("last-event-id") {
+        Some(header) => header,
+        None => return Ok(None),
+    };
+    match header.to_str().ok().and_then(|id| id.parse().ok()) {
+        Some(last_event_id) => Ok(Some(last_event_id)),
+        None => {
+            let mut errors = ValidationErrors::new();
+            errors.add("Last-Event-ID", ValidationError::new("not an event ID"));
+            Err(errors.into())
+        }
+    }
+}
+
+/// Stream the state transitions of callback requests as server-sent events.
+///
+/// Each event has the `transition` type, its sequence number as ID and a
+/// JSON encoded transition as data. Events since the given last event ID are
+/// replayed first, as long as the relay still keeps them. Otherwise, such as
+/// after a restart of the relay, the stream starts with a `resync` event, and
+/// clients should fetch the current state of the requests they track.
+#[utoipa::path(
+    get,
+    path = "/v1/events",
+    params(
+        EventsQuery,
+        ("Last-Event-ID" = Option<u64>, Header, description = "ID of the last event received"),
+    ),
+    responses(
+        (status = 200, description = "Stream of transitions", body = TransitionEvent, content_type = "text/event-stream"),
+        (status = 400, description = "Bad request error"),
+        (status = 401, description = "Missing or unknown API key"),
+    )
+)]
+pub(crate) async fn get_events<S: Storage + Sync + Send + Clone>(
+    Extension(api_key): Extension<Arc<ApiKey>>,
+    State(s): State<ApiState<S>>,
+    headers: HeaderMap,
+    Query(query): Query<EventsQuery>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, Error> {
+    let last_event_id = match last_event_id(&headers)? {
+        Some(last_event_id) => Some(last_event_id),
+        None => query.last_event_id,
+    };
+
+    let (missed, receiver) = s.events.subscribe(last_event_id);
+    let resync = missed.is_none().then(|| {
+        warn!(
+            ?last_event_id,
+            "events after the last event ID are no longer kept"
+        );
+        Ok(Event::default()
+            .event("resync")
+            .data("events after the last event ID are no longer kept"))
+    });
+    let live = stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => return Some((event, receiver)),
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!(
+                        skipped,
+                        "event stream client lagging behind, skipping events"
+                    )
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    let events = stream::iter(missed.unwrap_or_default())
+        .chain(live)
+        .filter(move |event| {
+            let target_matches = match &event.webhook_url {
+                Some(url) => {
+                    query.callback_contract.is_none()
+                        && query
+                            .webhook_url
+                            .as_ref()
+                            .map_or(true, |query| query == url)
+                }
+                None => {
+                    query.webhook_url.is_none()
+                        && query
+                            .callback_contract
+                            .map_or(true, |contract| contract == event.callback_contract)
+                }
+            };
+            ready(
+                api_key.allows(
+                    event.image_id,
+                    event.callback_contract,
+                    event.webhook_url.as_deref(),
+                ) && query.image_id.map_or(true, |id| id == event.image_id)
+                    && target_matches,
+            )
+        })
+        .map(|event| {
+            Event::default()
+                .id(event.id.to_string())
+                .event("transition")
+                .json_data(&event)
+        });
+
+    Ok(Sse::new(stream::iter(resync).chain(events)).keep_alive(KeepAlive::default()))
+}
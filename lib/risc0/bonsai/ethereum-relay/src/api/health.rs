@@ -0,0 +1,83 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use axum::{extract::State, http::header, response::IntoResponse};
+
+use super::{state::ApiState, Error, Result};
+use crate::{metrics, readiness::Component, storage::Storage};
+
+/// Check that the relay is running.
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    responses(
+        (status = 200, description = "The relay is running"),
+    )
+)]
+pub(crate) async fn get_health() -> &'static str {
+    "ok"
+}
+
+/// Check that the relay is ready to serve requests: every component started,
+/// and it is not draining.
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    responses(
+        (status = 200, description = "The relay is ready"),
+        (status = 503, description = "Components not ready yet, or the relay is draining"),
+    )
+)]
+pub(crate) async fn get_readiness<S: Storage + Sync + Send + Clone>(
+    State(s): State<ApiState<S>>,
+) -> Result<&'static str, Error> {
+    let pending = s.readiness.pending();
+    if !pending.is_empty() {
+        return Err(Error::Unavailable {
+            reason: format!(
+                "waiting for: {}",
+                pending
+                    .iter()
+                    .map(Component::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        });
+    }
+    if s.control.is_draining() {
+        return Err(Error::Unavailable {
+            reason: "the relay is draining".to_string(),
+        });
+    }
+    Ok("ok")
+}
+
+/// Export the metrics of the relay in the Prometheus text format.
+///
+/// Metrics of proof requests are labelled by image ID.
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses(
+        (status = 200, description = "Metrics of the relay", content_type = "text/plain"),
+        (status = 500, description = "Internal server error"),
+    )
+)]
+pub(crate) async fn get_metrics<S: Storage + Sync + Send + Clone>(
+    State(s): State<ApiState<S>>,
+) -> Result<impl IntoResponse, Error> {
+    s.metrics.refresh(&s.storage).await?;
+    let body = s.metrics.encode().map_err(anyhow::Error::from)?;
+    Ok(([(header::CONTENT_TYPE, metrics::CONTENT_TYPE)], body))
+}
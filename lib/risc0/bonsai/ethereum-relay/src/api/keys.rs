@@ -0,0 +1,398 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::anyhow;
+use ethers::types::{Address, H256};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{Error, Result};
+
+const SECONDS_PER_MINUTE: u64 = 60;
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// A client of the relay REST API.
+///
+/// Only the SHA-256 hash of the key is kept, so the relay never stores the
+/// key a client sends in its `x-api-key` header.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApiKey {
+    /// Name of the client, used in logs.
+    pub name: String,
+    /// SHA-256 hash of the key.
+    pub key_sha256: H256,
+    /// Image IDs the client may request callbacks for.
+    pub image_ids: Vec<H256>,
+    /// Contracts the client may request callbacks to.
+    pub callback_contracts: Vec<Address>,
+    /// Webhook URLs the client may request proofs to be posted to.
+    pub webhook_urls: Vec<String>,
+    /// Number of requests the client may send per minute.
+    pub requests_per_minute: u32,
+    /// Number of cycles the client may spend per day (UTC).
+    pub daily_cycles: u64,
+    /// Cycles charged for each callback request. Bonsai does not report the
+    /// cycles of a session, so this is an upper bound of the sessions the
+    /// client is expected to run.
+    pub cycles_per_request: u64,
+}
+
+impl Default for ApiKey {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            key_sha256: H256::zero(),
+            image_ids: Vec::new(),
+            callback_contracts: Vec::new(),
+            webhook_urls: Vec::new(),
+            requests_per_minute: 60,
+            daily_cycles: 1 << 36,
+            cycles_per_request: 1 << 26,
+        }
+    }
+}
+
+impl ApiKey {
+    /// An API key with the default quotas, allowed no image IDs and no
+    /// callback contracts yet.
+    pub fn new(name: impl Into<String>, key: &str) -> Self {
+        Self {
+            name: name.into(),
+            key_sha256: Self::hash(key),
+            ..Self::default()
+        }
+    }
+
+    /// The SHA-256 hash of a key, as configured in `key_sha256`.
+    pub fn hash(key: &str) -> H256 {
+        H256(Sha256::digest(key.as_bytes()).into())
+    }
+
+    /// Whether the key may request proofs of the given image, delivered to
+    /// the webhook if there is one, or else to the callback contract.
+    pub(crate) fn allows(
+        &self,
+        image_id: H256,
+        callback_contract: Address,
+        webhook_url: Option<&str>,
+    ) -> bool {
+        self.image_ids.contains(&image_id)
+            && match webhook_url {
+                Some(url) => self.webhook_urls.iter().any(|allowed| allowed == url),
+                None => self.callback_contracts.contains(&callback_contract),
+            }
+    }
+}
+
+/// An operator of the relay admin API.
+///
+/// Like [ApiKey], only the SHA-256 hash of the key sent in the `x-admin-key`
+/// header is kept.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminKey {
+    /// Name of the operator, recorded in the audit log.
+    pub name: String,
+    /// SHA-256 hash of the key.
+    pub key_sha256: H256,
+}
+
+impl AdminKey {
+    pub fn new(name: impl Into<String>, key: &str) -> Self {
+        Self {
+            name: name.into(),
+            key_sha256: ApiKey::hash(key),
+        }
+    }
+}
+
+/// Usage of a key in the current minute and day.
+#[derive(Debug, Default)]
+struct Usage {
+    minute: u64,
+    requests: u32,
+    day: u64,
+    cycles: u64,
+}
+
+/// The API keys accepted by the REST API, and their usage.
+#[derive(Debug, Default)]
+pub(crate) struct KeyStore {
+    keys: HashMap<H256, Arc<ApiKey>>,
+    admin_keys: HashMap<H256, Arc<AdminKey>>,
+    usage: Mutex<HashMap<H256, Usage>>,
+}
+
+impl KeyStore {
+    pub(crate) fn new(keys: Vec<ApiKey>) -> Self {
+        Self {
+            keys: keys
+                .into_iter()
+                .map(|key| (key.key_sha256, Arc::new(key)))
+                .collect(),
+            admin_keys: HashMap::new(),
+            usage: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Also accept the given keys on the admin API.
+    pub(crate) fn with_admin_keys(mut self, admin_keys: Vec<AdminKey>) -> Self {
+        self.admin_keys = admin_keys
+            .into_iter()
+            .map(|key| (key.key_sha256, Arc::new(key)))
+            .collect();
+        self
+    }
+
+    /// The API key matching the key sent by a client.
+    pub(crate) fn authenticate(&self, key: &str) -> Result<Arc<ApiKey>> {
+        self.keys
+            .get(&ApiKey::hash(key))
+            .cloned()
+            .ok_or(Error::Unauthorized)
+    }
+
+    /// The admin key matching the key sent by an operator.
+    pub(crate) fn authenticate_admin(&self, key: &str) -> Result<Arc<AdminKey>> {
+        self.admin_keys
+            .get(&ApiKey::hash(key))
+            .cloned()
+            .ok_or(Error::Unauthorized)
+    }
+
+    /// Count a request against the rate limit of the key.
+    pub(crate) fn check_rate(&self, api_key: &ApiKey, now: SystemTime) -> Result<()> {
+        let minute = seconds_since_epoch(now) / SECONDS_PER_MINUTE;
+        let mut usage = self
+            .usage
+            .lock()
+            .map_err(|_| anyhow!("API key usage lock poisoned"))?;
+        let usage = usage.entry(api_key.key_sha256).or_default();
+        if usage.minute != minute {
+            usage.minute = minute;
+            usage.requests = 0;
+        }
+        if usage.requests >= api_key.requests_per_minute {
+            return Err(Error::QuotaExceeded {
+                quota: format!("{} requests per minute", api_key.requests_per_minute),
+            });
+        }
+        usage.requests += 1;
+        Ok(())
+    }
+
+    /// Check that the key may request a callback for the given image to the
+    /// given contract, or webhook if there is one, and charge the request
+    /// against its daily cycles.
+    pub(crate) fn authorize_callback(
+        &self,
+        api_key: &ApiKey,
+        image_id: H256,
+        callback_contract: Address,
+        webhook_url: Option<&str>,
+        now: SystemTime,
+    ) -> Result<()> {
+        if !api_key.image_ids.contains(&image_id) {
+            return Err(Error::Forbidden {
+                reason: format!("image ID {image_id:?} is not allowed"),
+            });
+        }
+        if !api_key.allows(image_id, callback_contract, webhook_url) {
+            let reason = match webhook_url {
+                Some(url) => format!("webhook URL {url} is not allowed"),
+                None => format!("callback contract {callback_contract:?} is not allowed"),
+            };
+            return Err(Error::Forbidden { reason });
+        }
+
+        let day = seconds_since_epoch(now) / SECONDS_PER_DAY;
+        let mut usage = self
+            .usage
+            .lock()
+            .map_err(|_| anyhow!("API key usage lock poisoned"))?;
+        let usage = usage.entry(api_key.key_sha256).or_default();
+        if usage.day != day {
+            usage.day = day;
+            usage.cycles = 0;
+        }
+        let cycles = usage.cycles.saturating_add(api_key.cycles_per_request);
+        if cycles > api_key.daily_cycles {
+            return Err(Error::QuotaExceeded {
+                quota: format!("{} cycles per day", api_key.daily_cycles),
+            });
+        }
+        usage.cycles = cycles;
+        Ok(())
+    }
+
+    /// Give back the cycles charged by [KeyStore::authorize_callback] for a
+    /// request that was not submitted to Bonsai.
+    pub(crate) fn refund_callback(&self, api_key: &ApiKey, now: SystemTime) -> Result<()> {
+        let day = seconds_since_epoch(now) / SECONDS_PER_DAY;
+        let mut usage = self
+            .usage
+            .lock()
+            .map_err(|_| anyhow!("API key usage lock poisoned"))?;
+        if let Some(usage) = usage.get_mut(&api_key.key_sha256) {
+            // Cycles charged on a previous day were reset already.
+            if usage.day == day {
+                usage.cycles = usage.cycles.saturating_sub(api_key.cycles_per_request);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn seconds_since_epoch(now: SystemTime) -> u64 {
+    now.duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::http::StatusCode;
+
+    use super::*;
+
+    fn key() -> ApiKey {
+        ApiKey {
+            image_ids: vec![H256::repeat_byte(1)],
+            callback_contracts: vec![Address::repeat_byte(2)],
+            webhook_urls: vec!["https://example.com/proofs".to_string()],
+            requests_per_minute: 2,
+            daily_cycles: 300,
+            cycles_per_request: 100,
+            ..ApiKey::new("test", "secret")
+        }
+    }
+
+    fn status(result: Result<()>) -> StatusCode {
+        result.unwrap_err().status_code()
+    }
+
+    #[test]
+    fn test_authenticate() {
+        let store = KeyStore::new(vec![key()]);
+        assert_eq!(store.authenticate("secret").unwrap().name, "test");
+        assert_eq!(
+            store.authenticate("other").unwrap_err().status_code(),
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[test]
+    fn test_admin_keys_are_separate() {
+        let store = KeyStore::new(vec![key()]).with_admin_keys(vec![AdminKey::new("ops", "admin")]);
+        assert_eq!(store.authenticate_admin("admin").unwrap().name, "ops");
+        assert!(store.authenticate("admin").is_err());
+        assert!(store.authenticate_admin("secret").is_err());
+    }
+
+    #[test]
+    fn test_rate_limit_resets_every_minute() {
+        let store = KeyStore::new(vec![key()]);
+        let key = key();
+        let now = UNIX_EPOCH + Duration::from_secs(120);
+
+        store.check_rate(&key, now).unwrap();
+        store
+            .check_rate(&key, now + Duration::from_secs(59))
+            .unwrap();
+        assert_eq!(
+            status(store.check_rate(&key, now + Duration::from_secs(59))),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        store
+            .check_rate(&key, now + Duration::from_secs(60))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_callbacks_are_restricted_and_charged() {
+        let store = KeyStore::new(vec![key()]);
+        let key = key();
+        let (image_id, contract) = (H256::repeat_byte(1), Address::repeat_byte(2));
+        let now = UNIX_EPOCH + Duration::from_secs(SECONDS_PER_DAY);
+
+        assert_eq!(
+            status(store.authorize_callback(&key, H256::repeat_byte(9), contract, None, now)),
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(store.authorize_callback(&key, image_id, Address::repeat_byte(9), None, now)),
+            StatusCode::FORBIDDEN
+        );
+        // Webhook requests are checked against the URL, not the contract.
+        let webhook = Some("https://example.com/proofs");
+        assert_eq!(
+            status(store.authorize_callback(
+                &key,
+                image_id,
+                contract,
+                Some("https://example.com/other"),
+                now
+            )),
+            StatusCode::FORBIDDEN
+        );
+
+        store
+            .authorize_callback(&key, image_id, Address::zero(), webhook, now)
+            .unwrap();
+        for _ in 0..2 {
+            store
+                .authorize_callback(&key, image_id, contract, None, now)
+                .unwrap();
+        }
+        assert_eq!(
+            status(store.authorize_callback(&key, image_id, contract, None, now)),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        let tomorrow = now + Duration::from_secs(SECONDS_PER_DAY);
+        store
+            .authorize_callback(&key, image_id, contract, None, tomorrow)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_refunded_callbacks_are_not_charged() {
+        let store = KeyStore::new(vec![key()]);
+        let key = key();
+        let (image_id, contract) = (H256::repeat_byte(1), Address::repeat_byte(2));
+        let now = UNIX_EPOCH + Duration::from_secs(SECONDS_PER_DAY);
+
+        for _ in 0..3 {
+            store
+                .authorize_callback(&key, image_id, contract, None, now)
+                .unwrap();
+        }
+        store.refund_callback(&key, now).unwrap();
+        store
+            .authorize_callback(&key, image_id, contract, None, now)
+            .unwrap();
+        assert_eq!(
+            status(store.authorize_callback(&key, image_id, contract, None, now)),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+    }
+}
@@ -14,10 +14,16 @@
 
 use self::error::Error;
 
+pub(crate) mod admin;
 pub(crate) mod auth;
 pub(crate) mod bincode;
 pub(crate) mod callback_request;
+pub(crate) mod callback_status;
+pub(crate) mod dead_letters;
 pub(crate) mod error;
+pub(crate) mod events;
+pub(crate) mod health;
+pub(crate) mod keys;
 pub(crate) mod server;
 pub(crate) mod state;
 
@@ -25,6 +31,40 @@ pub(crate) mod state;
 pub mod routes {
     /// Route for `Callback` related APIs.
     pub const CALLBACK_ROUTE: &str = "/v1/callbacks";
+    /// Route getting the status of a callback request.
+    pub const CALLBACK_STATUS_ROUTE: &str = "/v1/callbacks/:request_id";
+    /// Route getting the webhook delivery log of a callback request.
+    pub const WEBHOOK_DELIVERIES_ROUTE: &str = "/v1/callbacks/:request_id/deliveries";
+    /// Route streaming the state transitions of callback requests.
+    pub const EVENTS_ROUTE: &str = "/v1/events";
+    /// Route listing the dead-lettered callback requests.
+    pub const DEAD_LETTERS_ROUTE: &str = "/v1/dead-letters";
+    /// Route requeueing a dead-lettered callback request.
+    pub const REQUEUE_DEAD_LETTER_ROUTE: &str = "/v1/dead-letters/:request_id/requeue";
+    /// Route getting what operators have paused or requested.
+    pub const ADMIN_STATUS_ROUTE: &str = "/v1/admin/status";
+    /// Route pausing the ingestion of callback requests.
+    pub const PAUSE_INGESTION_ROUTE: &str = "/v1/admin/ingestion/pause";
+    /// Route resuming the ingestion of callback requests.
+    pub const RESUME_INGESTION_ROUTE: &str = "/v1/admin/ingestion/resume";
+    /// Route pausing the sending of batches.
+    pub const PAUSE_SENDING_ROUTE: &str = "/v1/admin/sending/pause";
+    /// Route resuming the sending of batches.
+    pub const RESUME_SENDING_ROUTE: &str = "/v1/admin/sending/resume";
+    /// Route draining the relay before it stops.
+    pub const DRAIN_ROUTE: &str = "/v1/admin/drain";
+    /// Route forcing a callback request back to `new`.
+    pub const FORCE_REQUEUE_ROUTE: &str = "/v1/admin/requests/:request_id/requeue";
+    /// Route cancelling a callback request.
+    pub const CANCEL_ROUTE: &str = "/v1/admin/requests/:request_id/cancel";
+    /// Route listing the actions of operators.
+    pub const AUDIT_LOG_ROUTE: &str = "/v1/admin/audit-log";
+    /// Route checking that the relay is running.
+    pub const HEALTH_ROUTE: &str = "/healthz";
+    /// Route checking that the relay is ready to serve requests.
+    pub const READINESS_ROUTE: &str = "/readyz";
+    /// Route exporting the metrics of the relay to Prometheus.
+    pub const METRICS_ROUTE: &str = "/metrics";
 }
 
 pub(crate) type Result<T, E = Error> = ::std::result::Result<T, E>;
@@ -13,7 +13,12 @@
 // limitations under the License.
 
 use anyhow::Context;
-use axum::{extract::DefaultBodyLimit, middleware::from_fn, routing::post, Router};
+use axum::{
+    extract::DefaultBodyLimit,
+    middleware::from_fn_with_state,
+    routing::{get, post},
+    Router,
+};
 use tower_http::trace::{DefaultOnRequest, TraceLayer};
 use tracing::Level;
 use utoipa::OpenApi;
@@ -21,23 +26,120 @@ use utoipa_swagger_ui::SwaggerUi;
 
 use crate::{
     api::{
-        auth::authorize,
+        admin::{
+            __path_get_admin_status, __path_get_audit_log, __path_post_cancel, __path_post_drain,
+            __path_post_force_requeue, __path_post_pause_ingestion, __path_post_pause_sending,
+            __path_post_resume_ingestion, __path_post_resume_sending, get_admin_status,
+            get_audit_log, post_cancel, post_drain, post_force_requeue, post_pause_ingestion,
+            post_pause_sending, post_resume_ingestion, post_resume_sending, AdminStatus,
+            AuditLogEntry,
+        },
+        auth::{authorize, authorize_admin},
         callback_request::{__path_post_callback_request, post_callback_request},
-        routes::CALLBACK_ROUTE,
+        callback_status::{
+            __path_get_callback_status, __path_get_webhook_deliveries, __path_list_callbacks,
+            get_callback_status, get_webhook_deliveries, list_callbacks,
+        },
+        dead_letters::{
+            __path_get_dead_letters, __path_post_requeue_dead_letter, get_dead_letters,
+            post_requeue_dead_letter, DeadLetterResponse,
+        },
+        events::{__path_get_events, get_events},
+        health::{
+            __path_get_health, __path_get_metrics, __path_get_readiness, get_health, get_metrics,
+            get_readiness,
+        },
+        routes::{
+            ADMIN_STATUS_ROUTE, AUDIT_LOG_ROUTE, CALLBACK_ROUTE, CALLBACK_STATUS_ROUTE,
+            CANCEL_ROUTE, DEAD_LETTERS_ROUTE, DRAIN_ROUTE, EVENTS_ROUTE, FORCE_REQUEUE_ROUTE,
+            HEALTH_ROUTE, METRICS_ROUTE, PAUSE_INGESTION_ROUTE, PAUSE_SENDING_ROUTE,
+            READINESS_ROUTE, REQUEUE_DEAD_LETTER_ROUTE, RESUME_INGESTION_ROUTE,
+            RESUME_SENDING_ROUTE, WEBHOOK_DELIVERIES_ROUTE,
+        },
         state::ApiState,
     },
-    sdk::client::CallbackRequest,
-    storage::Storage,
+    readiness::{Component, ReadinessReporter},
+    sdk::{
+        client::{
+            CallbackRequest, CallbackRequestResponse, CallbackStatus, CallbackStatusPage,
+            WebhookDeliveryAttempt,
+        },
+        webhook::WebhookPayload,
+    },
+    storage::{events::TransitionEvent, Storage},
 };
 
 pub(crate) fn app<S: Storage + Sync + Send + Clone + 'static>(state: ApiState<S>) -> Router {
     #[derive(OpenApi)]
-    #[openapi(paths(post_callback_request,), components(schemas(CallbackRequest)))]
+    #[openapi(
+        paths(
+            post_callback_request,
+            get_callback_status,
+            list_callbacks,
+            get_webhook_deliveries,
+            get_events,
+            get_dead_letters,
+            post_requeue_dead_letter,
+            get_admin_status,
+            post_pause_ingestion,
+            post_resume_ingestion,
+            post_pause_sending,
+            post_resume_sending,
+            post_drain,
+            post_force_requeue,
+            post_cancel,
+            get_audit_log,
+            get_health,
+            get_readiness,
+            get_metrics
+        ),
+        components(schemas(
+            CallbackRequest,
+            CallbackRequestResponse,
+            CallbackStatus,
+            CallbackStatusPage,
+            WebhookDeliveryAttempt,
+            WebhookPayload,
+            TransitionEvent,
+            DeadLetterResponse,
+            AdminStatus,
+            AuditLogEntry
+        ))
+    )]
     struct ApiDoc;
 
+    // Operators authenticate with admin keys rather than API keys.
+    let admin = Router::new()
+        .route(ADMIN_STATUS_ROUTE, get(get_admin_status))
+        .route(PAUSE_INGESTION_ROUTE, post(post_pause_ingestion))
+        .route(RESUME_INGESTION_ROUTE, post(post_resume_ingestion))
+        .route(PAUSE_SENDING_ROUTE, post(post_pause_sending))
+        .route(RESUME_SENDING_ROUTE, post(post_resume_sending))
+        .route(DRAIN_ROUTE, post(post_drain))
+        .route(FORCE_REQUEUE_ROUTE, post(post_force_requeue))
+        .route(CANCEL_ROUTE, post(post_cancel))
+        .route(AUDIT_LOG_ROUTE, get(get_audit_log))
+        .layer(from_fn_with_state(state.clone(), authorize_admin));
+
+    // Probes and metrics scrapers do not authenticate.
+    let operations = Router::new()
+        .route(HEALTH_ROUTE, get(get_health))
+        .route(READINESS_ROUTE, get(get_readiness))
+        .route(METRICS_ROUTE, get(get_metrics));
+
     Router::new()
-        .route(CALLBACK_ROUTE, post(post_callback_request))
-        .layer(from_fn(authorize))
+        .route(
+            CALLBACK_ROUTE,
+            get(list_callbacks).post(post_callback_request),
+        )
+        .route(CALLBACK_STATUS_ROUTE, get(get_callback_status))
+        .route(WEBHOOK_DELIVERIES_ROUTE, get(get_webhook_deliveries))
+        .route(EVENTS_ROUTE, get(get_events))
+        .route(DEAD_LETTERS_ROUTE, get(get_dead_letters))
+        .route(REQUEUE_DEAD_LETTER_ROUTE, post(post_requeue_dead_letter))
+        .layer(from_fn_with_state(state.clone(), authorize))
+        .merge(admin)
+        .merge(operations)
         .with_state(state)
         .layer(DefaultBodyLimit::max(256 * 1024 * 1024))
         .layer(TraceLayer::new_for_http().on_request(
@@ -53,14 +155,19 @@ pub(crate) fn app<S: Storage + Sync + Send + Clone + 'static>(state: ApiState<S>
 pub(crate) async fn serve<S: Storage + Sync + Send + Clone + 'static>(
     state: ApiState<S>,
     port: String,
+    readiness: ReadinessReporter,
 ) -> anyhow::Result<()> {
     let bind_address = &format!("0.0.0.0:{port}");
-    axum::Server::bind(
+    let server = axum::Server::try_bind(
         &bind_address
             .parse()
             .context("failed to parse bind address")?,
     )
-    .serve(app(state).into_make_service())
-    .await
-    .context(format!("failed to serve API on {bind_address}"))
+    .context(format!("failed to bind API to {bind_address}"))?;
+    readiness.ready(Component::RestApi);
+
+    server
+        .serve(app(state).into_make_service())
+        .await
+        .context(format!("failed to serve API on {bind_address}"))
 }
@@ -16,7 +16,13 @@ use std::sync::Arc;
 
 use tokio::sync::Notify;
 
-use crate::storage::Storage;
+use super::keys::KeyStore;
+use crate::{
+    control::RelayControl,
+    metrics::RelayMetrics,
+    readiness::ReadinessReporter,
+    storage::{events::TransitionEvents, Storage},
+};
 
 #[derive(Clone)]
 pub(crate) struct ApiState<S>
@@ -24,6 +30,14 @@ where
     S: Storage + Sync + Send + Clone,
 {
     pub(crate) bonsai_url: String,
+    /// Key of the relay on Bonsai, used for the sessions of all clients.
+    pub(crate) bonsai_api_key: String,
+    pub(crate) keys: Arc<KeyStore>,
+    pub(crate) events: Arc<TransitionEvents>,
     pub(crate) storage: S,
     pub(crate) notifier: Arc<Notify>,
+    pub(crate) control: Arc<RelayControl>,
+    pub(crate) metrics: Arc<RelayMetrics>,
+    /// Tracks which components of the relay are ready.
+    pub(crate) readiness: ReadinessReporter,
 }
@@ -12,17 +12,22 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{str::FromStr, time::Duration};
+use std::{path::Path, str::FromStr, time::Duration};
 
 use anyhow::{anyhow, Context, Error, Result};
 use ethers::{
     core::k256::{ecdsa::SigningKey, SecretKey},
     middleware::SignerMiddleware,
     prelude::*,
-    providers::{Provider, Ws},
+    providers::Provider,
 };
 use tracing::{debug, error};
 
+use crate::{
+    signer::{RelaySigner, RemoteSigner},
+    transport::{is_ws_url, RelayTransport},
+};
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct WalletKey(SecretKey);
 
@@ -60,22 +65,66 @@ impl WalletKey {
     pub fn get_key(&self) -> SecretKey {
         self.0.clone()
     }
+
+    /// Decrypt a JSON keystore file.
+    pub fn from_keystore(path: &Path, password: &str) -> Result<Self> {
+        let wallet = LocalWallet::decrypt_keystore(path, password)
+            .context(format!("Failed to decrypt keystore {}.", path.display()))?;
+        Ok(Self(SecretKey::from(wallet.signer())))
+    }
+}
+
+/// Where the key of the relay wallet is held.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WalletSource {
+    /// A private key held in memory.
+    Local(WalletKey),
+    /// A remote signer with a web3signer-compatible API, identified by its
+    /// URL and the public key of the wallet.
+    Remote { url: String, public_key: String },
+}
+
+impl From<WalletKey> for WalletSource {
+    fn from(value: WalletKey) -> Self {
+        Self::Local(value)
+    }
+}
+
+impl TryFrom<String> for WalletSource {
+    type Error = Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Ok(Self::Local(value.try_into()?))
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct EthersClientConfig {
     pub eth_node_url: String,
     pub eth_chain_id: u64,
-    pub wallet_key_identifier: WalletKey,
+    pub wallet_key_identifier: WalletSource,
     pub retries: u64,
     pub wait_time: Duration,
+    /// Interval at which logs are polled from nodes that do not support
+    /// subscriptions.
+    pub log_poll_interval: Duration,
+    /// Maximum number of blocks requested in a single `eth_getLogs` call when
+    /// polling for logs or recovering missed blocks. Smaller ranges are used
+    /// while the node rejects this one.
+    pub log_poll_window: u64,
+    /// Number of blocks built on top of the block of a `CallbackRequest`
+    /// before it is processed. Requests whose block is reorged out in the
+    /// meantime are dropped.
+    pub confirmations: u64,
 }
 
+const DEFAULT_LOG_POLL_INTERVAL: Duration = Duration::from_secs(2);
+const DEFAULT_LOG_POLL_WINDOW: u64 = 1000;
+
 impl EthersClientConfig {
     pub fn new(
         eth_node_url: String,
         eth_chain_id: u64,
-        wallet_key_identifier: WalletKey,
+        wallet_key_identifier: WalletSource,
         retries: u64,
         wait_time: Duration,
     ) -> Self {
@@ -85,32 +134,62 @@ impl EthersClientConfig {
             wallet_key_identifier,
             retries,
             wait_time,
+            log_poll_interval: DEFAULT_LOG_POLL_INTERVAL,
+            log_poll_window: DEFAULT_LOG_POLL_WINDOW,
+            confirmations: 0,
         }
     }
 
-    pub async fn get_client(&self) -> Result<SignerMiddleware<Provider<Ws>, Wallet<SigningKey>>> {
+    /// Set how logs are polled from nodes that do not support subscriptions.
+    pub fn with_log_polling(mut self, interval: Duration, window: u64) -> Self {
+        self.log_poll_interval = interval;
+        self.log_poll_window = window;
+        self
+    }
+
+    /// Set the number of confirmations a `CallbackRequest` needs before it is
+    /// processed.
+    pub fn with_confirmations(mut self, confirmations: u64) -> Self {
+        self.confirmations = confirmations;
+        self
+    }
+
+    /// Whether the configured Ethereum node is reached over a transport that
+    /// supports log subscriptions.
+    pub fn supports_subscriptions(&self) -> bool {
+        is_ws_url(&self.eth_node_url)
+    }
+
+    pub async fn get_client(
+        &self,
+    ) -> Result<SignerMiddleware<Provider<RelayTransport>, RelaySigner>> {
         let provider = self.provider().await?;
         let signer = self.get_signer()?;
         let client = SignerMiddleware::new(provider, signer);
         Ok(client)
     }
 
-    pub async fn provider(&self) -> Result<Provider<Ws>> {
-        let provider = Provider::<Ws>::connect_with_reconnects(self.eth_node_url.clone(), 60)
-            .await
-            .context("Failed to connect to Ethereum node.")?;
-        Ok(provider)
+    pub async fn provider(&self) -> Result<Provider<RelayTransport>> {
+        let transport = RelayTransport::connect(&self.eth_node_url).await?;
+        Ok(Provider::new(transport))
     }
 
-    pub fn get_signer(&self) -> Result<Wallet<SigningKey>> {
-        let signing_key = SigningKey::from(self.wallet_key_identifier.get_key());
-        let signer = LocalWallet::from(signing_key).with_chain_id(self.eth_chain_id);
-        Ok(signer)
+    pub fn get_signer(&self) -> Result<RelaySigner> {
+        let signer = match &self.wallet_key_identifier {
+            WalletSource::Local(key) => {
+                let signing_key = SigningKey::from(key.get_key());
+                RelaySigner::Local(LocalWallet::from(signing_key))
+            }
+            WalletSource::Remote { url, public_key } => {
+                RelaySigner::Remote(RemoteSigner::new(url, public_key, self.eth_chain_id)?)
+            }
+        };
+        Ok(signer.with_chain_id(self.eth_chain_id))
     }
 
     pub async fn get_client_with_reconnects(
         &self,
-    ) -> Result<SignerMiddleware<Provider<Ws>, Wallet<SigningKey>>> {
+    ) -> Result<SignerMiddleware<Provider<RelayTransport>, RelaySigner>> {
         for _ in 0..self.retries {
             let client = self.get_client().await;
             if client.is_ok() {
@@ -0,0 +1,1198 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Configuration of the relay, loaded from a TOML file.
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use ethers::types::{Address, U256};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    transport::{is_http_url, is_ws_url},
+    AdminKey, ApiKey, EthersClientConfig, QuarantinePolicy, Relayer, RestartPolicy, RetryPolicy,
+    TransactionPolicy, WalletKey, WalletSource, WebhookPolicy,
+};
+
+const REDACTED: &str = "<redacted>";
+
+/// Complete configuration of a [Relayer].
+///
+/// Every field has a default, so a configuration file only needs to set the
+/// values that differ from it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RelayConfig {
+    pub chain: ChainConfig,
+    pub wallet: WalletConfig,
+    pub contract: ContractConfig,
+    pub bonsai: BonsaiConfig,
+    pub rest_api: RestApiConfig,
+    pub batching: BatchingConfig,
+    pub retry: RetryConfig,
+    pub proof_retry: ProofRetryConfig,
+    pub transactions: TransactionConfig,
+    pub quarantine: QuarantineConfig,
+    pub webhook: WebhookConfig,
+    pub restart: RestartConfig,
+    pub storage: StorageConfig,
+}
+
+/// Connection to the Ethereum chain.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChainConfig {
+    /// Websocket or HTTP endpoint of the Ethereum node. Logs are subscribed
+    /// to over websockets, and polled over HTTP.
+    pub eth_node_url: String,
+    pub eth_chain_id: u64,
+    /// Interval at which logs are polled over HTTP, in milliseconds.
+    pub log_poll_interval_ms: u64,
+    /// Maximum number of blocks requested in a single `eth_getLogs` call.
+    pub log_poll_window: u64,
+    /// Number of blocks a `CallbackRequest` must be buried under before a
+    /// Bonsai session is created for it.
+    pub confirmations: u64,
+}
+
+impl Default for ChainConfig {
+    fn default() -> Self {
+        Self {
+            eth_node_url: "ws://localhost:8545".to_string(),
+            eth_chain_id: 31337,
+            log_poll_interval_ms: 2000,
+            log_poll_window: 1000,
+            confirmations: 0,
+        }
+    }
+}
+
+/// The wallet sending transactions to the relay contract. Exactly one of a
+/// private key, a keystore or a remote signer must be set.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WalletConfig {
+    /// Private key as a hex string. Only accepted on development chains
+    /// unless `allow_raw_key` is set.
+    pub private_key: Option<String>,
+    /// Path to an encrypted JSON keystore file.
+    pub keystore: Option<PathBuf>,
+    /// File containing the passphrase of the keystore.
+    pub keystore_password_file: Option<PathBuf>,
+    /// Environment variable containing the passphrase of the keystore.
+    pub keystore_password_env: Option<String>,
+    /// URL of a remote signer with a web3signer-compatible API.
+    pub remote_signer_url: Option<String>,
+    /// Hex encoded public key of the wallet held by the remote signer.
+    pub remote_signer_public_key: Option<String>,
+    /// Accept a raw private key on chains other than development chains.
+    pub allow_raw_key: bool,
+}
+
+/// Chain IDs of local development networks, such as Anvil and Hardhat, on
+/// which raw private keys are always accepted.
+const DEV_CHAIN_IDS: [u64; 2] = [1337, 31337];
+
+impl WalletConfig {
+    fn validate(&self, eth_chain_id: u64) -> Result<()> {
+        let sources = [
+            self.private_key.is_some(),
+            self.keystore.is_some(),
+            self.remote_signer_url.is_some(),
+        ];
+        match sources.iter().filter(|set| **set).count() {
+            0 => bail!("no wallet configured: set one of wallet.private_key, wallet.keystore or wallet.remote_signer_url"),
+            1 => {}
+            _ => bail!("only one of wallet.private_key, wallet.keystore and wallet.remote_signer_url may be set"),
+        }
+        if self.private_key.is_some()
+            && !self.allow_raw_key
+            && !DEV_CHAIN_IDS.contains(&eth_chain_id)
+        {
+            bail!("refusing to use a raw private key on chain {eth_chain_id}: use a keystore or remote signer, or set wallet.allow_raw_key");
+        }
+        if self.keystore.is_some()
+            && self.keystore_password_file.is_none()
+            && self.keystore_password_env.is_none()
+        {
+            bail!("wallet.keystore requires wallet.keystore_password_file or wallet.keystore_password_env");
+        }
+        if self.remote_signer_url.is_some() && self.remote_signer_public_key.is_none() {
+            bail!("wallet.remote_signer_url requires wallet.remote_signer_public_key");
+        }
+        Ok(())
+    }
+
+    fn keystore_password(&self) -> Result<String> {
+        if let Some(path) = &self.keystore_password_file {
+            let password = std::fs::read_to_string(path).context(format!(
+                "failed to read keystore password file {}",
+                path.display()
+            ))?;
+            return Ok(password.trim_end_matches(['\r', '\n']).to_string());
+        }
+        let var = self
+            .keystore_password_env
+            .as_ref()
+            .context("no keystore password configured")?;
+        std::env::var(var).context(format!("keystore password variable {var} is not set"))
+    }
+
+    /// Resolve the configured wallet, decrypting the keystore if needed.
+    pub fn source(&self) -> Result<WalletSource> {
+        if let Some(private_key) = &self.private_key {
+            return Ok(WalletSource::Local(private_key.clone().try_into()?));
+        }
+        if let Some(keystore) = &self.keystore {
+            let password = self.keystore_password()?;
+            return Ok(WalletSource::Local(WalletKey::from_keystore(
+                keystore, &password,
+            )?));
+        }
+        match (&self.remote_signer_url, &self.remote_signer_public_key) {
+            (Some(url), Some(public_key)) => Ok(WalletSource::Remote {
+                url: url.clone(),
+                public_key: public_key.clone(),
+            }),
+            _ => bail!("no wallet configured"),
+        }
+    }
+}
+
+/// The deployed Bonsai Relay contract.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ContractConfig {
+    pub relay_address: Option<Address>,
+}
+
+/// Connection to the Bonsai proving service.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BonsaiConfig {
+    pub api_url: String,
+    /// Empty to provide no authentication.
+    pub api_key: String,
+    /// Run a local executor instead of Bonsai, producing no proofs.
+    pub dev_mode: bool,
+}
+
+impl Default for BonsaiConfig {
+    fn default() -> Self {
+        Self {
+            api_url: "http://localhost:8081".to_string(),
+            api_key: String::new(),
+            dev_mode: false,
+        }
+    }
+}
+
+/// The relay REST API.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RestApiConfig {
+    pub enabled: bool,
+    pub port: u16,
+    /// Keys of the clients allowed to use the API. Requests without a known
+    /// key are rejected.
+    pub api_keys: Vec<ApiKey>,
+    /// Keys of the operators allowed to use the admin API. The admin API
+    /// rejects every request if there are none.
+    pub admin_keys: Vec<AdminKey>,
+}
+
+impl Default for RestApiConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            port: 8080,
+            api_keys: Vec::new(),
+            admin_keys: Vec::new(),
+        }
+    }
+}
+
+impl RestApiConfig {
+    fn validate(&self) -> Result<()> {
+        let mut hashes = std::collections::HashSet::new();
+        for key in &self.api_keys {
+            if key.name.is_empty() {
+                bail!("rest_api.api_keys entries must have a name");
+            }
+            if key.key_sha256.is_zero() {
+                bail!("rest_api.api_keys.key_sha256 of {:?} is not set", key.name);
+            }
+            if !hashes.insert(key.key_sha256) {
+                bail!(
+                    "rest_api.api_keys.key_sha256 of {:?} is not unique",
+                    key.name
+                );
+            }
+            if key.image_ids.is_empty()
+                || (key.callback_contracts.is_empty() && key.webhook_urls.is_empty())
+            {
+                bail!(
+                    "rest_api.api_keys entry {:?} must allow at least one image ID and one callback contract or webhook URL",
+                    key.name
+                );
+            }
+            if let Some(url) = key.webhook_urls.iter().find(|url| !is_http_url(url)) {
+                bail!(
+                    "rest_api.api_keys.webhook_urls of {:?} must be HTTP URLs, got {url:?}",
+                    key.name
+                );
+            }
+            if key.requests_per_minute == 0 {
+                bail!(
+                    "rest_api.api_keys.requests_per_minute of {:?} must be greater than zero",
+                    key.name
+                );
+            }
+            if key.cycles_per_request > key.daily_cycles {
+                bail!(
+                    "rest_api.api_keys.cycles_per_request of {:?} must not exceed its daily_cycles",
+                    key.name
+                );
+            }
+        }
+        // Admin keys must not double as client keys either.
+        for key in &self.admin_keys {
+            if key.name.is_empty() {
+                bail!("rest_api.admin_keys entries must have a name");
+            }
+            if key.key_sha256.is_zero() {
+                bail!(
+                    "rest_api.admin_keys.key_sha256 of {:?} is not set",
+                    key.name
+                );
+            }
+            if !hashes.insert(key.key_sha256) {
+                bail!(
+                    "rest_api.admin_keys.key_sha256 of {:?} is not unique",
+                    key.name
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Batching of callbacks sent to the relay contract.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BatchingConfig {
+    /// Number of callbacks after which a batch is sent immediately.
+    pub max_batch_size: usize,
+    /// Interval at which pending callbacks are sent, in milliseconds.
+    pub interval_ms: u64,
+    /// Gas budget of each batch transaction. Callbacks are packed into
+    /// batches whose estimated gas fits in it.
+    pub gas_limit: u64,
+}
+
+impl Default for BatchingConfig {
+    fn default() -> Self {
+        Self {
+            max_batch_size: 3,
+            interval_ms: 1000,
+            gas_limit: 15000000,
+        }
+    }
+}
+
+/// Reconnection to the Ethereum node.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryConfig {
+    pub max_retries: u64,
+    /// Time to wait between attempts, in seconds.
+    pub wait_time_secs: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        const WAIT_TIME_SECS: u64 = 5;
+        Self {
+            max_retries: 7 * 24 * 60 * 60 / WAIT_TIME_SECS, // 1 week
+            wait_time_secs: WAIT_TIME_SECS,
+        }
+    }
+}
+
+/// Retries of proof requests that failed on Bonsai or while preparing their
+/// callback.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProofRetryConfig {
+    /// Number of failed attempts after which a request is dead-lettered.
+    pub max_attempts: u32,
+    /// Delay before the first retry, in milliseconds. It doubles with every
+    /// further attempt.
+    pub initial_backoff_ms: u64,
+    /// Upper bound of the delay between two attempts, in milliseconds.
+    pub max_backoff_ms: u64,
+}
+
+impl Default for ProofRetryConfig {
+    fn default() -> Self {
+        let policy = RetryPolicy::default();
+        Self {
+            max_attempts: policy.max_attempts,
+            initial_backoff_ms: policy.initial_backoff.as_millis() as u64,
+            max_backoff_ms: policy.max_backoff.as_millis() as u64,
+        }
+    }
+}
+
+impl ProofRetryConfig {
+    pub fn policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: self.max_attempts,
+            initial_backoff: Duration::from_millis(self.initial_backoff_ms),
+            max_backoff: Duration::from_millis(self.max_backoff_ms),
+        }
+    }
+}
+
+/// Fees of the batch transactions, and replacement of the ones that stay
+/// unconfirmed.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TransactionConfig {
+    /// Upper bound of the max fee per gas, in gwei.
+    pub max_fee_per_gas_gwei: u64,
+    /// Time after which an unconfirmed transaction is replaced, in seconds.
+    pub confirmation_timeout_secs: u64,
+    /// Percentage by which the fees of a replacement are increased.
+    pub fee_bump_percent: u64,
+    /// Number of replacements after which a transaction is cancelled.
+    pub max_replacements: u32,
+}
+
+const WEI_PER_GWEI: u64 = 1_000_000_000;
+
+impl Default for TransactionConfig {
+    fn default() -> Self {
+        let policy = TransactionPolicy::default();
+        Self {
+            max_fee_per_gas_gwei: (policy.max_fee_per_gas / WEI_PER_GWEI).as_u64(),
+            confirmation_timeout_secs: policy.confirmation_timeout.as_secs(),
+            fee_bump_percent: policy.fee_bump_percent,
+            max_replacements: policy.max_replacements,
+        }
+    }
+}
+
+impl TransactionConfig {
+    pub fn policy(&self) -> TransactionPolicy {
+        TransactionPolicy {
+            max_fee_per_gas: U256::from(self.max_fee_per_gas_gwei) * WEI_PER_GWEI,
+            confirmation_timeout: Duration::from_secs(self.confirmation_timeout_secs),
+            fee_bump_percent: self.fee_bump_percent,
+            max_replacements: self.max_replacements,
+            ..TransactionPolicy::default()
+        }
+    }
+}
+
+/// Callbacks that revert when simulated before they are batched.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuarantineConfig {
+    pub policy: QuarantinePolicy,
+}
+
+/// Delivery of proofs to the webhooks of requests that have one.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhookConfig {
+    /// Key of the HMAC signature of each delivery, shared with the webhook
+    /// receivers.
+    pub signing_key: String,
+    /// How long to wait for a webhook to respond, in seconds.
+    pub timeout_secs: u64,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        let policy = WebhookPolicy::default();
+        Self {
+            signing_key: policy.signing_key,
+            timeout_secs: policy.timeout.as_secs(),
+        }
+    }
+}
+
+impl WebhookConfig {
+    pub fn policy(&self) -> WebhookPolicy {
+        WebhookPolicy {
+            signing_key: self.signing_key.clone(),
+            timeout: Duration::from_secs(self.timeout_secs),
+        }
+    }
+}
+
+/// Restarts of the subsystems of the relay that fail, such as the chain
+/// subscription or the REST API.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RestartConfig {
+    /// Number of restarts of a subsystem allowed within `window_secs`. The
+    /// relay shuts down once a subsystem fails more often than that.
+    pub max_restarts: u32,
+    /// Period over which the failures of a subsystem are counted, in seconds.
+    pub window_secs: u64,
+    /// Delay before the first restart, in milliseconds. It doubles with every
+    /// further failure within the window.
+    pub initial_backoff_ms: u64,
+    /// Upper bound of the delay before a restart, in milliseconds.
+    pub max_backoff_ms: u64,
+}
+
+impl Default for RestartConfig {
+    fn default() -> Self {
+        let policy = RestartPolicy::default();
+        Self {
+            max_restarts: policy.max_restarts,
+            window_secs: policy.window.as_secs(),
+            initial_backoff_ms: policy.initial_backoff.as_millis() as u64,
+            max_backoff_ms: policy.max_backoff.as_millis() as u64,
+        }
+    }
+}
+
+impl RestartConfig {
+    pub fn policy(&self) -> RestartPolicy {
+        RestartPolicy {
+            max_restarts: self.max_restarts,
+            window: Duration::from_secs(self.window_secs),
+            initial_backoff: Duration::from_millis(self.initial_backoff_ms),
+            max_backoff: Duration::from_millis(self.max_backoff_ms),
+        }
+    }
+}
+
+/// Storage of the proof request state.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    pub backend: StorageBackend,
+    /// Database file used by the `sqlite` backend.
+    pub sqlite_path: PathBuf,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            backend: StorageBackend::default(),
+            sqlite_path: PathBuf::from("relay.db"),
+        }
+    }
+}
+
+impl StorageConfig {
+    /// The SQLite database to use, or `None` to keep the state in memory.
+    pub fn sqlite_path(&self) -> Option<PathBuf> {
+        match self.backend {
+            StorageBackend::InMemory => None,
+            StorageBackend::Sqlite => Some(self.sqlite_path.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageBackend {
+    /// State is lost when the relay stops.
+    #[default]
+    InMemory,
+    /// State is persisted in a SQLite database and recovered on restart.
+    Sqlite,
+}
+
+/// Command line and environment overrides of a [RelayConfig], shared by the
+/// binaries that run a relay. Options that are not given leave the
+/// corresponding field of the config file unchanged.
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct RelayOverrides {
+    /// Bonsai Relay contract address on Ethereum. RELAY_CONTRACT_ADDRESS is
+    /// read as well
+    #[arg(long, env, visible_alias = "contract-address")]
+    pub relay_address: Option<Address>,
+
+    /// Ethereum Node endpoint, either websocket or HTTP
+    /// [default: ws://localhost:8545]. ETH_NODE_URL is read as well
+    #[arg(long, env, visible_alias = "eth-node-url")]
+    pub eth_node: Option<String>,
+
+    /// Ethereum chain ID [default: 5 without a config file]
+    #[arg(long, env)]
+    pub eth_chain_id: Option<u64>,
+
+    /// Number of blocks a callback request must be buried under before it is
+    /// processed [default: 0]
+    #[arg(long, env = "RELAY_CONFIRMATIONS")]
+    pub confirmations: Option<u64>,
+
+    /// Private key of the relay wallet as a hex string. Refused on chains
+    /// other than local development chains unless --allow-raw-key is set.
+    /// WALLET_KEY_IDENTIFIER is read as well
+    #[arg(
+        short,
+        long,
+        env,
+        visible_alias = "wallet-key-identifier",
+        short_alias = 'w'
+    )]
+    pub private_key: Option<String>,
+
+    /// Allow a raw private key on chains other than local development chains
+    #[arg(long)]
+    pub allow_raw_key: bool,
+
+    /// Encrypted JSON keystore holding the relay wallet
+    #[arg(long, env = "RELAY_KEYSTORE")]
+    pub keystore: Option<PathBuf>,
+
+    /// File containing the passphrase of the keystore
+    #[arg(long, env = "RELAY_KEYSTORE_PASSWORD_FILE")]
+    pub keystore_password_file: Option<PathBuf>,
+
+    /// Environment variable containing the passphrase of the keystore
+    #[arg(long)]
+    pub keystore_password_env: Option<String>,
+
+    /// URL of a remote signer with a web3signer-compatible API holding the
+    /// relay wallet
+    #[arg(long, env = "RELAY_REMOTE_SIGNER_URL")]
+    pub remote_signer_url: Option<String>,
+
+    /// Public key of the relay wallet on the remote signer, as a hex string
+    #[arg(long, env = "RELAY_REMOTE_SIGNER_PUBLIC_KEY")]
+    pub remote_signer_public_key: Option<String>,
+
+    /// The port of the relay REST API [default: 8080]
+    #[arg(long, env = "RELAY_PORT")]
+    pub port: Option<u16>,
+
+    /// Toggle to enable the relay REST API, --rest-api=false disables it
+    /// [default: true]
+    #[arg(
+        long,
+        env = "RELAY_REST_API",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    pub rest_api: Option<bool>,
+
+    /// Number of callbacks after which a batch is sent immediately
+    #[arg(long, env = "RELAY_MAX_BATCH_SIZE")]
+    pub max_batch_size: Option<usize>,
+
+    /// Interval at which pending callbacks are sent, in milliseconds
+    #[arg(long, env = "RELAY_BATCH_INTERVAL_MS")]
+    pub batch_interval_ms: Option<u64>,
+
+    /// Gas budget of each batch transaction
+    #[arg(long, env = "RELAY_BATCH_GAS_LIMIT")]
+    pub batch_gas_limit: Option<u64>,
+
+    /// Where the proof request state is stored [default: in-memory]
+    #[arg(long, env = "RELAY_STORAGE_BACKEND")]
+    pub storage_backend: Option<StorageBackend>,
+
+    /// SQLite database file used by the sqlite storage backend
+    #[arg(long, env = "RELAY_SQLITE_PATH")]
+    pub sqlite_path: Option<PathBuf>,
+}
+
+impl RelayOverrides {
+    /// Override the fields of `config` given on the command line or in the
+    /// environment.
+    pub fn apply(self, config: &mut RelayConfig) -> Result<()> {
+        self.apply_with_env(config, |name| std::env::var(name).ok())
+    }
+
+    /// Override the fields of `config`, falling back to the environment
+    /// variables of earlier releases for options that are not given.
+    fn apply_with_env(
+        mut self,
+        config: &mut RelayConfig,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<()> {
+        fn set<T>(field: &mut T, value: Option<T>) {
+            if let Some(value) = value {
+                *field = value;
+            }
+        }
+
+        if self.relay_address.is_none() {
+            if let Some(address) = env("RELAY_CONTRACT_ADDRESS") {
+                self.relay_address =
+                    Some(address.parse().context("invalid RELAY_CONTRACT_ADDRESS")?);
+            }
+        }
+        self.eth_node = self.eth_node.or_else(|| env("ETH_NODE_URL"));
+        self.private_key = self.private_key.or_else(|| env("WALLET_KEY_IDENTIFIER"));
+
+        set(
+            &mut config.contract.relay_address,
+            self.relay_address.map(Some),
+        );
+        set(&mut config.chain.eth_node_url, self.eth_node);
+        set(&mut config.chain.eth_chain_id, self.eth_chain_id);
+        set(&mut config.chain.confirmations, self.confirmations);
+        set(&mut config.wallet.private_key, self.private_key.map(Some));
+        config.wallet.allow_raw_key |= self.allow_raw_key;
+        set(&mut config.wallet.keystore, self.keystore.map(Some));
+        set(
+            &mut config.wallet.keystore_password_file,
+            self.keystore_password_file.map(Some),
+        );
+        set(
+            &mut config.wallet.keystore_password_env,
+            self.keystore_password_env.map(Some),
+        );
+        set(
+            &mut config.wallet.remote_signer_url,
+            self.remote_signer_url.map(Some),
+        );
+        set(
+            &mut config.wallet.remote_signer_public_key,
+            self.remote_signer_public_key.map(Some),
+        );
+        set(&mut config.rest_api.port, self.port);
+        set(&mut config.rest_api.enabled, self.rest_api);
+        set(&mut config.batching.max_batch_size, self.max_batch_size);
+        set(&mut config.batching.interval_ms, self.batch_interval_ms);
+        set(&mut config.batching.gas_limit, self.batch_gas_limit);
+        set(&mut config.storage.backend, self.storage_backend);
+        set(&mut config.storage.sqlite_path, self.sqlite_path);
+        Ok(())
+    }
+}
+
+/// Command line and environment overrides of the Bonsai connection of a
+/// [RelayConfig]. They are global, so that binaries with subcommands other
+/// than running a relay can share them.
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct BonsaiOverrides {
+    /// Bonsai API URL [default: http://localhost:8081]
+    #[arg(long, env, global = true)]
+    pub bonsai_api_url: Option<String>,
+
+    /// Bonsai API key
+    /// Defaults to empty, providing no authentication.
+    #[arg(long, env, global = true)]
+    pub bonsai_api_key: Option<String>,
+
+    /// Toggle to enable dev_mode: only a local executor runs your
+    /// zkVM program and no proof is generated.
+    #[arg(long, env, global = true)]
+    pub risc0_dev_mode: bool,
+}
+
+impl BonsaiOverrides {
+    /// Override the Bonsai connection of `config` given on the command line
+    /// or in the environment.
+    pub fn apply(&self, config: &mut RelayConfig) {
+        if let Some(api_url) = &self.bonsai_api_url {
+            config.bonsai.api_url = api_url.clone();
+        }
+        if let Some(api_key) = &self.bonsai_api_key {
+            config.bonsai.api_key = api_key.clone();
+        }
+        config.bonsai.dev_mode |= self.risc0_dev_mode;
+    }
+}
+
+impl RelayConfig {
+    /// Load the configuration from a TOML file.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .context(format!("failed to read config file {}", path.display()))?;
+        toml::from_str(&contents).context(format!("invalid config file {}", path.display()))
+    }
+
+    /// Load the configuration from a TOML file, or use the defaults if no
+    /// path is given.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        path.map_or_else(|| Ok(Self::default()), Self::from_file)
+    }
+
+    /// Check that the configuration is complete and consistent.
+    pub fn validate(&self) -> Result<()> {
+        if !is_ws_url(&self.chain.eth_node_url) && !is_http_url(&self.chain.eth_node_url) {
+            bail!(
+                "chain.eth_node_url must be a websocket or HTTP endpoint, got {:?}",
+                self.chain.eth_node_url
+            );
+        }
+        if self.chain.log_poll_interval_ms == 0 {
+            bail!("chain.log_poll_interval_ms must be greater than zero");
+        }
+        if self.chain.log_poll_window == 0 {
+            bail!("chain.log_poll_window must be greater than zero");
+        }
+        self.wallet.validate(self.chain.eth_chain_id)?;
+        if self.contract.relay_address.is_none() {
+            bail!("contract.relay_address is not set");
+        }
+        if self.bonsai.api_url.is_empty() {
+            bail!("bonsai.api_url is not set");
+        }
+        self.rest_api.validate()?;
+        if self.webhook.signing_key.is_empty()
+            && self
+                .rest_api
+                .api_keys
+                .iter()
+                .any(|key| !key.webhook_urls.is_empty())
+        {
+            bail!("webhook.signing_key must be set when API keys allow webhook URLs");
+        }
+        if self.webhook.timeout_secs == 0 {
+            bail!("webhook.timeout_secs must be greater than zero");
+        }
+        if self.batching.max_batch_size == 0 {
+            bail!("batching.max_batch_size must be greater than zero");
+        }
+        if self.batching.interval_ms == 0 {
+            bail!("batching.interval_ms must be greater than zero");
+        }
+        if self.batching.gas_limit == 0 {
+            bail!("batching.gas_limit must be greater than zero");
+        }
+        if self.retry.max_retries == 0 {
+            bail!("retry.max_retries must be greater than zero");
+        }
+        if self.proof_retry.max_attempts == 0 {
+            bail!("proof_retry.max_attempts must be greater than zero");
+        }
+        if self.proof_retry.initial_backoff_ms > self.proof_retry.max_backoff_ms {
+            bail!("proof_retry.initial_backoff_ms must not exceed proof_retry.max_backoff_ms");
+        }
+        if self.restart.window_secs == 0 {
+            bail!("restart.window_secs must be greater than zero");
+        }
+        if self.restart.initial_backoff_ms > self.restart.max_backoff_ms {
+            bail!("restart.initial_backoff_ms must not exceed restart.max_backoff_ms");
+        }
+        if self.transactions.max_fee_per_gas_gwei == 0 {
+            bail!("transactions.max_fee_per_gas_gwei must be greater than zero");
+        }
+        if self.transactions.confirmation_timeout_secs == 0 {
+            bail!("transactions.confirmation_timeout_secs must be greater than zero");
+        }
+        if self.transactions.fee_bump_percent < 10 {
+            bail!(
+                "transactions.fee_bump_percent must be at least 10, the minimum bump nodes accept"
+            );
+        }
+        if self.storage.backend == StorageBackend::Sqlite
+            && self.storage.sqlite_path.as_os_str().is_empty()
+        {
+            bail!("storage.sqlite_path is not set");
+        }
+        Ok(())
+    }
+
+    /// A copy of the configuration with all secrets replaced, safe to log.
+    pub fn redacted(&self) -> Self {
+        let mut config = self.clone();
+        if config.wallet.private_key.is_some() {
+            config.wallet.private_key = Some(REDACTED.to_string());
+        }
+        if !config.bonsai.api_key.is_empty() {
+            config.bonsai.api_key = REDACTED.to_string();
+        }
+        if !config.webhook.signing_key.is_empty() {
+            config.webhook.signing_key = REDACTED.to_string();
+        }
+        config
+    }
+
+    /// Construct the [Relayer] described by a validated configuration.
+    pub fn relayer(&self) -> Result<Relayer> {
+        Ok(Relayer {
+            rest_api: self.rest_api.enabled,
+            dev_mode: self.bonsai.dev_mode,
+            rest_api_port: self.rest_api.port.to_string(),
+            bonsai_api_url: self.bonsai.api_url.clone(),
+            bonsai_api_key: self.bonsai.api_key.clone(),
+            api_keys: self.rest_api.api_keys.clone(),
+            admin_keys: self.rest_api.admin_keys.clone(),
+            relay_contract_address: self
+                .contract
+                .relay_address
+                .context("contract.relay_address is not set")?,
+            max_batch_size: self.batching.max_batch_size,
+            batch_interval: Duration::from_millis(self.batching.interval_ms),
+            batch_gas_limit: self.batching.gas_limit,
+            storage_path: self.storage.sqlite_path(),
+            retry_policy: self.proof_retry.policy(),
+            transaction_policy: self.transactions.policy(),
+            quarantine_policy: self.quarantine.policy,
+            webhook_policy: self.webhook.policy(),
+            restart_policy: self.restart.policy(),
+        })
+    }
+
+    /// Construct the [EthersClientConfig] described by a validated
+    /// configuration.
+    pub fn client_config(&self) -> Result<EthersClientConfig> {
+        Ok(EthersClientConfig::new(
+            self.chain.eth_node_url.clone(),
+            self.chain.eth_chain_id,
+            self.wallet.source()?,
+            self.retry.max_retries,
+            Duration::from_secs(self.retry.wait_time_secs),
+        )
+        .with_log_polling(
+            Duration::from_millis(self.chain.log_poll_interval_ms),
+            self.chain.log_poll_window,
+        )
+        .with_confirmations(self.chain.confirmations))
+    }
+}
+
+/// Displays the configuration as TOML, with secrets redacted.
+impl fmt::Display for RelayConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let toml = toml::to_string_pretty(&self.redacted()).map_err(|_| fmt::Error)?;
+        write!(f, "{toml}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_config_uses_defaults() {
+        let config: RelayConfig = toml::from_str(
+            r#"
+            [contract]
+            relay_address = "0x5fbdb2315678afecb367f032d93f642f64180aa3"
+
+            [batching]
+            max_batch_size = 10
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.batching.max_batch_size, 10);
+        assert_eq!(config.batching.interval_ms, 1000);
+        assert_eq!(config.rest_api, RestApiConfig::default());
+        assert!(config.contract.relay_address.is_some());
+    }
+
+    #[test]
+    fn test_overrides() {
+        #[derive(clap::Parser)]
+        struct Cli {
+            #[clap(flatten)]
+            relay: RelayOverrides,
+            #[clap(flatten)]
+            bonsai: BonsaiOverrides,
+        }
+
+        let mut config: RelayConfig = toml::from_str(
+            r#"
+            [chain]
+            eth_chain_id = 5
+            confirmations = 3
+
+            [batching]
+            max_batch_size = 10
+            "#,
+        )
+        .unwrap();
+        let cli = <Cli as clap::Parser>::try_parse_from([
+            "relay",
+            "--contract-address",
+            "0x5fbdb2315678afecb367f032d93f642f64180aa3",
+            "--eth-chain-id",
+            "11155111",
+            "-w",
+            "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80",
+            "--storage-backend",
+            "sqlite",
+            "--risc0-dev-mode",
+        ])
+        .unwrap();
+        cli.relay.apply_with_env(&mut config, |_| None).unwrap();
+        cli.bonsai.apply(&mut config);
+
+        assert!(config.contract.relay_address.is_some());
+        assert_eq!(config.chain.eth_chain_id, 11155111);
+        assert!(config.wallet.private_key.is_some());
+        assert_eq!(config.storage.backend, StorageBackend::Sqlite);
+        assert!(config.bonsai.dev_mode);
+        // Fields without an override keep the values of the file.
+        assert_eq!(config.chain.confirmations, 3);
+        assert_eq!(config.batching.max_batch_size, 10);
+        assert_eq!(config.rest_api, RestApiConfig::default());
+    }
+
+    #[test]
+    fn test_legacy_environment_variables() {
+        let legacy = |name: &str| match name {
+            "RELAY_CONTRACT_ADDRESS" => Some("0x5fbdb2315678afecb367f032d93f642f64180aa3".into()),
+            "ETH_NODE_URL" => Some("ws://node:8545".into()),
+            "WALLET_KEY_IDENTIFIER" => Some("0x01".into()),
+            _ => None,
+        };
+        #[derive(clap::Parser)]
+        struct Cli {
+            #[clap(flatten)]
+            relay: RelayOverrides,
+        }
+        let parse = |args: &[&str]| {
+            <Cli as clap::Parser>::try_parse_from(
+                std::iter::once("relay").chain(args.iter().copied()),
+            )
+            .unwrap()
+            .relay
+        };
+
+        let mut config = RelayConfig::default();
+        let overrides = parse(&["--rest-api=false"]);
+        overrides.apply_with_env(&mut config, legacy).unwrap();
+        assert!(config.contract.relay_address.is_some());
+        assert_eq!(config.chain.eth_node_url, "ws://node:8545");
+        assert_eq!(config.wallet.private_key.as_deref(), Some("0x01"));
+        assert!(!config.rest_api.enabled);
+
+        // Options given explicitly take precedence over the legacy names.
+        let mut config = RelayConfig::default();
+        let overrides = parse(&["--eth-node", "http://other:8545", "--rest-api"]);
+        overrides.apply_with_env(&mut config, legacy).unwrap();
+        assert_eq!(config.chain.eth_node_url, "http://other:8545");
+        assert!(config.rest_api.enabled);
+
+        let overrides = parse(&[]);
+        let invalid = |name: &str| (name == "RELAY_CONTRACT_ADDRESS").then(|| "0xzz".to_string());
+        assert!(overrides
+            .apply_with_env(&mut RelayConfig::default(), invalid)
+            .is_err());
+    }
+
+    #[test]
+    fn test_sqlite_storage() {
+        let config: RelayConfig = toml::from_str(
+            r#"
+            [storage]
+            backend = "sqlite"
+            sqlite_path = "/var/lib/relay/state.db"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.storage.sqlite_path(),
+            Some(PathBuf::from("/var/lib/relay/state.db"))
+        );
+        assert_eq!(RelayConfig::default().storage.sqlite_path(), None);
+    }
+
+    #[test]
+    fn test_api_keys() {
+        let config: RelayConfig = toml::from_str(&format!(
+            r#"
+            [[rest_api.api_keys]]
+            name = "client"
+            key_sha256 = "{:?}"
+            image_ids = ["0x{}"]
+            callback_contracts = ["0x{}"]
+            requests_per_minute = 10
+            "#,
+            ApiKey::hash("secret"),
+            "01".repeat(32),
+            "02".repeat(20),
+        ))
+        .unwrap();
+        let key = &config.rest_api.api_keys[0];
+        assert_eq!(key.key_sha256, ApiKey::hash("secret"));
+        assert_eq!(key.callback_contracts, vec![Address::repeat_byte(2)]);
+        assert_eq!(key.requests_per_minute, 10);
+        assert_eq!(key.daily_cycles, ApiKey::default().daily_cycles);
+        assert!(config.rest_api.validate().is_ok());
+
+        let mut config = config;
+        config.rest_api.api_keys[0].image_ids.clear();
+        assert!(config.rest_api.validate().is_err());
+    }
+
+    #[test]
+    fn test_admin_keys() {
+        let mut config: RelayConfig = toml::from_str(&format!(
+            r#"
+            [[rest_api.admin_keys]]
+            name = "ops"
+            key_sha256 = "{:?}"
+            "#,
+            ApiKey::hash("admin"),
+        ))
+        .unwrap();
+        assert_eq!(
+            config.rest_api.admin_keys,
+            vec![AdminKey::new("ops", "admin")]
+        );
+        config.rest_api.validate().unwrap();
+
+        // A key is either a client key or an admin key.
+        config.rest_api.api_keys = vec![ApiKey {
+            image_ids: vec![ethers::types::H256::repeat_byte(1)],
+            callback_contracts: vec![Address::repeat_byte(2)],
+            ..ApiKey::new("client", "admin")
+        }];
+        assert!(config.rest_api.validate().is_err());
+    }
+
+    #[test]
+    fn test_webhook_urls_need_a_signing_key() {
+        let mut config = RelayConfig::default();
+        config.wallet.private_key = Some("0x01".to_string());
+        config.contract.relay_address = Some(Address::repeat_byte(1));
+        config.rest_api.api_keys = vec![ApiKey {
+            image_ids: vec![ethers::types::H256::repeat_byte(1)],
+            webhook_urls: vec!["https://sequencer.example.com/proofs".to_string()],
+            ..ApiKey::new("sequencer", "secret")
+        }];
+        assert!(config.validate().is_err());
+
+        config.webhook.signing_key = "shared-secret".to_string();
+        config.validate().unwrap();
+        assert_eq!(
+            config.relayer().unwrap().webhook_policy.signing_key,
+            "shared-secret"
+        );
+        assert!(!config.to_string().contains("shared-secret"));
+
+        config.rest_api.api_keys[0].webhook_urls = vec!["ftp://example.com".to_string()];
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_unknown_fields_are_rejected() {
+        assert!(toml::from_str::<RelayConfig>("[batching]\nmax_size = 10\n").is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut config = RelayConfig::default();
+        assert!(config.validate().is_err());
+
+        config.wallet.private_key = Some("0x01".to_string());
+        config.contract.relay_address = Some(Address::repeat_byte(1));
+        config.validate().unwrap();
+
+        config.batching.max_batch_size = 0;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_restart_policy() {
+        let mut config: RelayConfig = toml::from_str(
+            r#"
+            [wallet]
+            private_key = "0x01"
+
+            [contract]
+            relay_address = "0x5fbdb2315678afecb367f032d93f642f64180aa3"
+
+            [restart]
+            max_restarts = 2
+            window_secs = 30
+            "#,
+        )
+        .unwrap();
+        config.validate().unwrap();
+        assert_eq!(
+            config.relayer().unwrap().restart_policy,
+            RestartPolicy {
+                max_restarts: 2,
+                window: Duration::from_secs(30),
+                ..RestartPolicy::default()
+            }
+        );
+
+        config.restart.initial_backoff_ms = config.restart.max_backoff_ms + 1;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_raw_key_refused_on_non_dev_chain() {
+        let mut config = RelayConfig::default();
+        config.wallet.private_key = Some("0x01".to_string());
+        config.contract.relay_address = Some(Address::repeat_byte(1));
+        config.chain.eth_chain_id = 1;
+        assert!(config.validate().is_err());
+
+        config.wallet.allow_raw_key = true;
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn test_wallet_sources_are_exclusive() {
+        let mut config = RelayConfig::default();
+        config.contract.relay_address = Some(Address::repeat_byte(1));
+        config.wallet.private_key = Some("0x01".to_string());
+        config.wallet.remote_signer_url = Some("http://localhost:9000".to_string());
+        config.wallet.remote_signer_public_key = Some("0x04".to_string());
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_keystore_wallet() {
+        let dir = std::env::temp_dir().join("bonsai-relay-keystore-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let (wallet, _) = ethers::signers::LocalWallet::new_keystore(
+            &dir,
+            &mut ethers::core::rand::thread_rng(),
+            "passphrase",
+            Some("keystore.json"),
+        )
+        .unwrap();
+        let password_file = dir.join("password");
+        std::fs::write(&password_file, "passphrase\n").unwrap();
+
+        let config = WalletConfig {
+            keystore: Some(dir.join("keystore.json")),
+            keystore_password_file: Some(password_file),
+            ..Default::default()
+        };
+        config.validate(1).unwrap();
+        let WalletSource::Local(key) = config.source().unwrap() else {
+            panic!("expected a local wallet");
+        };
+        assert_eq!(key.get_key(), wallet.signer().into());
+    }
+
+    #[test]
+    fn test_display_redacts_secrets() {
+        let mut config = RelayConfig::default();
+        config.wallet.private_key = Some("secret-key".to_string());
+        config.bonsai.api_key = "secret-api-key".to_string();
+
+        let displayed = config.to_string();
+        assert!(!displayed.contains("secret"));
+        assert!(displayed.contains(REDACTED));
+    }
+}
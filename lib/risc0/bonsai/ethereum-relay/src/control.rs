@@ -0,0 +1,228 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Controls operators use to steer a running relay through the admin API.
+
+use std::time::Duration;
+
+use tokio::sync::watch;
+use tracing::info;
+
+use crate::storage::{Result, Storage};
+
+/// A flag an operator can pause and resume.
+#[derive(Debug)]
+pub(crate) struct Switch {
+    paused: watch::Sender<bool>,
+}
+
+impl Default for Switch {
+    fn default() -> Self {
+        let (paused, _) = watch::channel(false);
+        Self { paused }
+    }
+}
+
+impl Switch {
+    /// Pause, returning whether the switch was running.
+    pub(crate) fn pause(&self) -> bool {
+        !self.paused.send_replace(true)
+    }
+
+    /// Resume, returning whether the switch was paused.
+    pub(crate) fn resume(&self) -> bool {
+        self.paused.send_replace(false)
+    }
+
+    pub(crate) fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    /// Wait until the switch is running.
+    pub(crate) async fn resumed(&self) {
+        let mut paused = self.paused.subscribe();
+        while *paused.borrow_and_update() {
+            // The sender lives as long as `self`.
+            if paused.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// The state of a relay that operators can change while it runs, shared by
+/// the admin API and the components it steers.
+#[derive(Debug)]
+pub(crate) struct RelayControl {
+    /// Whether new callback requests are accepted, from the chain and from
+    /// the REST API.
+    pub(crate) ingestion: Switch,
+    /// Whether batches are sent to the relay contract. Webhook deliveries are
+    /// not affected.
+    pub(crate) sending: Switch,
+    draining: watch::Sender<bool>,
+}
+
+impl Default for RelayControl {
+    fn default() -> Self {
+        let (draining, _) = watch::channel(false);
+        Self {
+            ingestion: Switch::default(),
+            sending: Switch::default(),
+            draining,
+        }
+    }
+}
+
+impl RelayControl {
+    /// Stop ingesting requests and make sure batches are sent, so that the
+    /// relay stops once every request in flight is settled. Returns whether
+    /// a drain was already in progress.
+    pub(crate) fn drain(&self) -> bool {
+        self.ingestion.pause();
+        self.sending.resume();
+        self.draining.send_replace(true)
+    }
+
+    pub(crate) fn is_draining(&self) -> bool {
+        *self.draining.borrow()
+    }
+
+    /// Resolve once a drain was requested and no request is in flight
+    /// anymore, checking every `interval`.
+    pub(crate) async fn drained<S: Storage + Sync + Send>(
+        &self,
+        storage: &S,
+        interval: Duration,
+    ) -> Result<()> {
+        let mut draining = self.draining.subscribe();
+        while !*draining.borrow_and_update() {
+            if draining.changed().await.is_err() {
+                return Ok(());
+            }
+        }
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            let in_flight = in_flight_requests(storage).await?;
+            if in_flight == 0 {
+                return Ok(());
+            }
+            info!(in_flight, "draining relay");
+        }
+    }
+}
+
+/// Number of requests the relay is still working on. Requests waiting for an
+/// operator, or settled, are not counted.
+async fn in_flight_requests<S: Storage + Sync + Send>(storage: &S) -> Result<usize> {
+    Ok(storage.fetch_new_bonsai_requests(None).await?.len()
+        + storage.fetch_pending_bonsai_requests(None).await?.len()
+        + storage.fetch_failed_bonsai_requests(None).await?.len()
+        + storage.fetch_completed_bonsai_requests(None).await?.len()
+        + storage
+            .fetch_preparing_onchain_proof_requests(None)
+            .await?
+            .len())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use bonsai_ethereum_contracts::i_bonsai_relay::CallbackRequestFilter;
+    use bonsai_sdk::alpha::SessionId;
+
+    use super::*;
+    use crate::storage::{in_memory::InMemoryStorage, ProofRequestInformation, ProofRequestState};
+
+    #[tokio::test]
+    async fn test_switch_waits_until_resumed() {
+        let control = Arc::new(RelayControl::default());
+        control.ingestion.resumed().await;
+
+        assert!(control.ingestion.pause());
+        assert!(!control.ingestion.pause());
+        assert!(control.ingestion.is_paused());
+        let waiter = tokio::spawn({
+            let control = control.clone();
+            async move { control.ingestion.resumed().await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiter.is_finished());
+
+        assert!(control.ingestion.resume());
+        tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!control.ingestion.resume());
+    }
+
+    #[tokio::test]
+    async fn test_drain_waits_for_requests_in_flight() {
+        let control = Arc::new(RelayControl::default());
+        let storage = InMemoryStorage::new();
+        let id = SessionId::new("a".to_string());
+        storage
+            .add_new_bonsai_proof_request(ProofRequestInformation {
+                proof_request_id: id.clone(),
+                callback_proof_request_event: CallbackRequestFilter::default(),
+                source_event: None,
+                block_hash: None,
+                attempts: 0,
+                last_error: None,
+                revert_data: None,
+                webhook_url: None,
+            })
+            .await
+            .unwrap();
+        control.sending.pause();
+
+        let drained = tokio::spawn({
+            let (control, storage) = (control.clone(), storage.clone());
+            async move {
+                control
+                    .drained(&storage, Duration::from_millis(10))
+                    .await
+                    .unwrap()
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!drained.is_finished());
+
+        assert!(!control.drain());
+        assert!(control.ingestion.is_paused());
+        assert!(!control.sending.is_paused());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!drained.is_finished());
+
+        // Dead-lettered requests wait for an operator and do not hold the
+        // drain back.
+        for state in [
+            ProofRequestState::Pending,
+            ProofRequestState::Failed,
+            ProofRequestState::DeadLetter,
+        ] {
+            storage
+                .transition_proof_request(id.clone(), state)
+                .await
+                .unwrap();
+        }
+        tokio::time::timeout(Duration::from_secs(1), drained)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}
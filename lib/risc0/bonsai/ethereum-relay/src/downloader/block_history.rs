@@ -12,105 +12,77 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{
-    cmp::{max, min},
-    sync::Arc,
-    time::Duration,
-};
+use std::cmp::min;
 
-use anyhow::{anyhow, Result};
+use anyhow::{bail, Result};
 use ethers::{
     core::types::{BlockNumber, Filter},
-    prelude::{k256::ecdsa::SigningKey, signer::SignerMiddlewareError, SignerMiddleware},
-    providers::{Middleware, MiddlewareError, Provider, ProviderError, StreamExt, Ws},
+    prelude::SignerMiddleware,
+    providers::{JsonRpcError, Middleware, MiddlewareError, Provider},
     types::{Log, U64},
     utils::__serde_json::Value,
 };
-use ethers_signers::Wallet;
-use futures::FutureExt;
-use tokio::sync::mpsc::{self, Sender};
+use tokio::sync::mpsc::Sender;
 use tracing::{debug, error, trace, warn};
 
-use super::block_history;
-use crate::EthersClientConfig;
+use crate::{signer::RelaySigner, transport::RelayTransport, EthersClientConfig};
 
-#[tracing::instrument(skip_all)]
-pub(crate) async fn recover_delay(state: State, sender: mpsc::Sender<Log>) -> Result<State> {
-    match (state.from.as_number(), state.to.as_number()) {
-        (Some(from), Some(to)) if from > to => {
-            error!(?from, ?to, "Invalid block numbers.");
-            Ok(state)
-        }
-        _ => process_logs_until_block(state, sender).await,
-    }
-}
-
-async fn process_logs_until_block(state: State, sender: mpsc::Sender<Log>) -> Result<State> {
-    let State {
-        mut from,
-        to,
-        client_config,
-        ..
-    } = state.clone();
-    debug!(?from, ?to, "Starting to recover delay.");
-    let mut last_processed_block = from.as_number().unwrap_or_default();
-    let mut offset = to;
-    let mut done = false;
-    let mut iterations: u64 = 0;
-    let mut rebuild_client = false;
-    let mut client = Arc::new(client_config.get_client_with_reconnects().await?);
-    loop {
-        iterations += 1;
-        debug!("Starting iteration {iterations}.");
-        trace!(?from, ?offset, ?to, "Current state.");
-        if rebuild_client {
-            debug!("Rebuilding client");
-            client = Arc::new(client_config.get_client_with_reconnects().await?);
-            rebuild_client = false;
-        }
-        if done {
-            debug!("Finished recovering delay.");
-            return Ok(State {
-                from,
-                last_processed_block,
-                ..state
-            });
-        }
-        let filter = Filter::new()
-            .event("Transfer(address,address,uint256)")
-            .from_block(from)
-            .to_block(offset);
-        let start = std::time::Instant::now();
-        match client.subscribe_logs(&filter).await {
-            Err(SignerMiddlewareError::MiddlewareError(error)) => {
-                match_error_response(error, &mut from, &mut offset, to, &mut rebuild_client)?
-            }
-            Err(error) => {
-                error!(?error, "Unknown error found in logs subscription.");
-            }
-            Ok(mut stream) => {
-                debug!("Subscribed to logs.");
-                while let Some(log) = stream.next().now_or_never().flatten() {
+/// Fetch the logs matching `filter` in the blocks after `last_processed_block`
+/// up to and including `to` with `eth_getLogs`, and send them in order.
+///
+/// Blocks are requested in ranges of at most `max_range` blocks. When the
+/// provider rejects a range, the next request uses the range it suggests or
+/// half the current one, and the range grows back after each success.
+/// `last_processed_block` is advanced after each range, so it is accurate even
+/// if an error is returned.
+#[tracing::instrument(skip(client, filter, last_processed_block, sender))]
+pub(crate) async fn fetch_logs<M: Middleware>(
+    client: &M,
+    filter: &Filter,
+    last_processed_block: &mut U64,
+    to: U64,
+    max_range: u64,
+    sender: &Sender<Log>,
+) -> Result<()> {
+    let max_range = max_range.max(1);
+    let mut range = max_range;
+    while *last_processed_block < to {
+        let from = *last_processed_block + 1;
+        let end = min(to, from + range - 1);
+        let range_filter = filter.clone().from_block(from).to_block(end);
+        match client.get_logs(&range_filter).await {
+            Ok(logs) => {
+                debug!(?from, ?end, count = logs.len(), "Fetched logs.");
+                for log in logs {
                     trace!(?log, "Processing log.");
-                    if let Some(block_number) = log.block_number {
-                        last_processed_block = max(block_number, last_processed_block);
-                    }
-                    if let Err(error) = sender.send(log).await {
-                        error!(?error, "Failed to send log to channel.");
+                    if sender.send(log).await.is_err() {
+                        bail!("Log receiver was dropped.");
                     }
                 }
-                debug!("finished processing logs.");
-                match_block_numbers(&mut from, &mut offset, to, &mut done);
+                *last_processed_block = end;
+                range = min(range.saturating_mul(2), max_range);
+            }
+            Err(error) => {
+                if range == 1 {
+                    error!(%error, ?from, "Provider rejected a single block range.");
+                    bail!("Failed to fetch logs of block {from}: {error}");
+                }
+                let suggested_range = error
+                    .as_error_response()
+                    .and_then(parse_error_response)
+                    .filter(|(suggested_from, suggested_to)| {
+                        *suggested_from == from && *suggested_to >= from
+                    })
+                    .map(|(_, suggested_to)| (suggested_to - from).as_u64() + 1);
+                range = match suggested_range {
+                    Some(suggested_range) if suggested_range < range => suggested_range,
+                    _ => range / 2,
+                };
+                warn!(%error, ?from, ?end, range, "Provider rejected block range, shrinking it.");
             }
-        };
-        debug!("End of iteration {iterations:?}");
-        let end = std::time::Instant::now();
-        if end - start < Duration::from_secs(1) {
-            warn!("Processing logs took less than 1 second.");
-            warn!("Sleeping for {:?} seconds.", client_config.wait_time);
-            tokio::time::sleep(client_config.wait_time).await;
         }
     }
+    Ok(())
 }
 
 fn hex_to_u64(hex: &str) -> Option<u64> {
@@ -119,26 +91,22 @@ fn hex_to_u64(hex: &str) -> Option<u64> {
     u64::from_str_radix(hex, 16).ok()
 }
 
-fn parse_error_response(error: ProviderError) -> Option<(BlockNumber, BlockNumber)> {
-    error.as_error_response().and_then(|response| {
-        response.data.clone().and_then(|object| {
-            object.get("from").and_then(|from| {
-                object.get("to").and_then(|to| {
-                    if let (Value::String(from), Value::String(to)) = (from, to) {
-                        let from = BlockNumber::Number(hex_to_u64(from)?.into());
-                        let to = BlockNumber::Number(hex_to_u64(to)?.into());
-                        Some((from, to))
-                    } else {
-                        None
-                    }
-                })
-            })
-        })
-    })
+/// Parse the block range some providers suggest in the data of an error
+/// response to a request for too many logs.
+fn parse_error_response(response: &JsonRpcError) -> Option<(U64, U64)> {
+    let object = response.data.as_ref()?;
+    match (object.get("from")?, object.get("to")?) {
+        (Value::String(from), Value::String(to)) => {
+            Some((hex_to_u64(from)?.into(), hex_to_u64(to)?.into()))
+        }
+        _ => None,
+    }
 }
 
 #[tracing::instrument(skip_all)]
-pub(crate) async fn get_latest_block(client: &Provider<Ws>) -> Result<Option<BlockNumber>> {
+pub(crate) async fn get_latest_block(
+    client: &Provider<RelayTransport>,
+) -> Result<Option<BlockNumber>> {
     Ok(client
         .get_block(BlockNumber::Latest)
         .await?
@@ -146,124 +114,15 @@ pub(crate) async fn get_latest_block(client: &Provider<Ws>) -> Result<Option<Blo
         .map(BlockNumber::Number))
 }
 
-fn update_from_and_offset_from_error_response(
-    old_from: BlockNumber,
-    old_offset: BlockNumber,
-    to: BlockNumber,
-    new_from: BlockNumber,
-    new_to: BlockNumber,
-) -> Result<(BlockNumber, BlockNumber)> {
-    match (old_from, old_offset, to, new_from, new_to) {
-        (
-            BlockNumber::Number(old_from),
-            BlockNumber::Number(_old_offset),
-            BlockNumber::Number(to),
-            BlockNumber::Number(new_from),
-            BlockNumber::Number(new_to),
-        ) => {
-            let from = max(old_from, min(new_from, to));
-            let offset = min(to, max(from + 1, new_to));
-            Ok((from.into(), offset.into()))
-        }
-        (
-            BlockNumber::Earliest,
-            BlockNumber::Number(_old_offset),
-            BlockNumber::Number(to),
-            BlockNumber::Number(new_from),
-            BlockNumber::Number(new_to),
-        ) => {
-            let from = min(new_from, to);
-            let offset = min(to, max(from + 1, new_to));
-            Ok((from.into(), offset.into()))
-        }
-        _ => {
-            error!(
-                ?old_from,
-                ?old_offset,
-                ?new_from,
-                ?new_to,
-                ?to,
-                "Invalid block numbers."
-            );
-            Err(anyhow!("Invalid block numbers."))
-        }
-    }
-}
-
-fn match_error_response(
-    error: ProviderError,
-    from: &mut BlockNumber,
-    offset: &mut BlockNumber,
-    to: BlockNumber,
-    rebuild_client: &mut bool,
-) -> Result<()> {
-    let error_string = error.to_string();
-    match parse_error_response(error) {
-        Some((new_from, new_to)) => {
-            trace!(
-                ?from,
-                ?new_from,
-                ?to,
-                ?new_to,
-                "Got updated values to request in next iteration."
-            );
-            (*from, *offset) =
-                update_from_and_offset_from_error_response(*from, *offset, to, new_from, new_to)?;
-            trace!(?from, ?offset, "Updated values for `from` and `offset`.");
-        }
-        None => {
-            error!(error = %error_string, "Failed to parse error response, rebuilding client to try again.");
-            *rebuild_client = true;
-        }
-    }
-    Ok(())
-}
-
-fn match_block_numbers(
-    from: &mut BlockNumber,
-    offset: &mut BlockNumber,
-    to: BlockNumber,
-    done: &mut bool,
-) {
-    match (from.as_number(), offset.as_number(), to.as_number()) {
-        (_, Some(offset_block), Some(to_block)) if offset_block > to_block => {
-            warn!(
-                %offset_block,
-                %to_block,
-                "`offset` is greater than `to`, setting `offset` to `to`."
-            );
-            *offset = to;
-        }
-        (_, Some(offset_block), Some(to_block)) if offset_block == to_block => {
-            *done = true;
-        }
-        (Some(from_block), Some(offset_block), Some(to_block)) => {
-            trace!(%from, %offset, "Old values");
-            let new_offset = offset_block + (offset_block - from_block);
-            *from = *offset;
-            *offset = min(new_offset, to_block).into();
-            trace!(%from, %offset, "New values");
-        }
-        (None, Some(offset_block), Some(to_block)) => {
-            *from = *offset;
-            *offset = min(offset_block * 2, to_block).into();
-        }
-        _ => {
-            warn!(?from, ?offset, ?to, "Unknown state of block numbers.");
-        }
-    }
-}
-
 #[derive(Clone, Debug)]
 pub(crate) struct State {
     pub client_config: EthersClientConfig,
-    pub client: SignerMiddleware<Provider<Ws>, Wallet<SigningKey>>,
+    pub client: SignerMiddleware<Provider<RelayTransport>, RelaySigner>,
     pub recreate_client: bool,
     pub last_processed_block: U64,
     pub latest_block: U64,
+    /// The relay contract's `CallbackRequest` filter.
     pub filter: Filter,
-    pub from: BlockNumber,
-    pub to: BlockNumber,
 }
 
 impl State {
@@ -277,9 +136,11 @@ impl State {
     }
 }
 
+/// Send the logs of all blocks after the last processed one up to the latest
+/// block, such as those emitted while the relay was stopped or disconnected.
 #[tracing::instrument(skip_all)]
 pub(crate) async fn recover_lost_blocks(state: State, sender: Sender<Log>) -> Result<State> {
-    let latest_block = match block_history::get_latest_block(state.client.provider()).await {
+    let latest_block = match get_latest_block(state.client.provider()).await {
         Ok(Some(block)) => match block.as_number() {
             block @ Some(..) => block,
             None => {
@@ -296,17 +157,37 @@ pub(crate) async fn recover_lost_blocks(state: State, sender: Sender<Log>) -> Re
             None
         }
     };
-    if let Some(latest_block) = latest_block {
-        debug!(?latest_block, "Got latest block number.");
-        let state = State {
-            latest_block,
-            ..state
-        };
-        block_history::recover_delay(state, sender).await
-    } else {
-        Ok(State {
+    let Some(latest_block) = latest_block else {
+        return Ok(State {
             recreate_client: true,
             ..state
-        })
+        });
+    };
+
+    debug!(?latest_block, "Got latest block number.");
+    let mut last_processed_block = state.last_processed_block;
+    let result = fetch_logs(
+        &state.client,
+        &state.filter,
+        &mut last_processed_block,
+        latest_block,
+        state.client_config.log_poll_window,
+        &sender,
+    )
+    .await;
+    let state = State {
+        last_processed_block,
+        latest_block,
+        ..state
+    };
+    match result {
+        Ok(()) => Ok(state),
+        Err(error) => {
+            error!(?error, state = ?state, "Failed to recover all lost blocks.");
+            Ok(State {
+                recreate_client: true,
+                ..state
+            })
+        }
     }
 }
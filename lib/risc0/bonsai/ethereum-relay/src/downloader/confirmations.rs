@@ -0,0 +1,120 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+
+use ethers::{
+    contract::LogMeta,
+    providers::Middleware,
+    types::{U256, U64},
+};
+use tracing::{debug, warn};
+
+/// The events returned by [ConfirmationTracker::take_confirmed].
+#[derive(Debug)]
+pub(crate) struct Confirmed<E> {
+    /// The confirmed events, in order.
+    pub(crate) events: Vec<(E, LogMeta)>,
+    /// The lowest block of the events cancelled because their block was
+    /// reorged. The logs of the blocks from there on must be fetched again,
+    /// since the events may have been included in a later block.
+    pub(crate) first_reorged_block: Option<U64>,
+}
+
+/// Holds events until their block is `confirmations` blocks deep, and cancels
+/// those whose block is no longer part of the canonical chain. Events that
+/// failed to be processed are held again until they are retried.
+#[derive(Debug)]
+pub(crate) struct ConfirmationTracker<E> {
+    confirmations: u64,
+    pending: BTreeMap<(U64, U256), (E, LogMeta)>,
+}
+
+impl<E> ConfirmationTracker<E> {
+    pub(crate) fn new(confirmations: u64) -> Self {
+        Self {
+            confirmations,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    /// Hold an event until it is confirmed.
+    pub(crate) fn add(&mut self, event: E, meta: LogMeta) {
+        self.pending
+            .insert((meta.block_number, meta.log_index), (event, meta));
+    }
+
+    /// Cancel an event whose log was removed from the chain by a reorg.
+    /// Returns whether the event was pending.
+    pub(crate) fn remove(&mut self, meta: &LogMeta) -> bool {
+        self.pending
+            .remove(&(meta.block_number, meta.log_index))
+            .is_some()
+    }
+
+    /// The lowest block holding a pending event.
+    pub(crate) fn first_pending_block(&self) -> Option<U64> {
+        self.pending.keys().next().map(|(block, _)| *block)
+    }
+
+    /// Remove and return, in order, the events that are at least
+    /// `confirmations` blocks deep given the `latest_block`, and still in the
+    /// block they were emitted in. Events whose block hash changed are
+    /// dropped, and the lowest of their blocks is reported.
+    pub(crate) async fn take_confirmed<M: Middleware>(
+        &mut self,
+        client: &M,
+        latest_block: U64,
+    ) -> Result<Confirmed<E>, M::Error> {
+        let mut confirmed = Confirmed {
+            events: Vec::new(),
+            first_reorged_block: None,
+        };
+        let Some(confirmed_block) = latest_block.checked_sub(self.confirmations.into()) else {
+            return Ok(confirmed);
+        };
+
+        while let Some(entry) = self.pending.first_entry() {
+            let block_number = entry.key().0;
+            if block_number > confirmed_block {
+                break;
+            }
+            let Some(block) = client.get_block(block_number).await? else {
+                break;
+            };
+            let canonical_hash = block.hash;
+            // Take all events of the block, so that it is only fetched once.
+            while let Some(entry) = self.pending.first_entry() {
+                if entry.key().0 != block_number {
+                    break;
+                }
+                let (event, meta) = entry.remove();
+                if canonical_hash == Some(meta.block_hash) {
+                    debug!(?block_number, "Event confirmed.");
+                    confirmed.events.push((event, meta));
+                } else {
+                    warn!(
+                        ?block_number,
+                        ?canonical_hash,
+                        expected = ?meta.block_hash,
+                        transaction_hash = ?meta.transaction_hash,
+                        "Block of event was reorged, cancelling it."
+                    );
+                    confirmed.first_reorged_block.get_or_insert(block_number);
+                }
+            }
+        }
+        Ok(confirmed)
+    }
+}
@@ -12,9 +12,16 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use ethers::contract::LogMeta;
+
 #[async_trait::async_trait]
 pub(crate) trait EventProcessor {
     type Event;
 
-    async fn process_event(&self, event: Self::Event) -> Result<(), crate::api::error::Error>;
+    /// Process an event, along with the metadata of the log it was read from.
+    async fn process_event(
+        &self,
+        event: Self::Event,
+        meta: LogMeta,
+    ) -> Result<(), crate::api::error::Error>;
 }
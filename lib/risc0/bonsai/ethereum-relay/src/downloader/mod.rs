@@ -13,6 +13,7 @@
 // limitations under the License.
 
 pub(crate) mod block_history;
+pub(crate) mod confirmations;
 pub(crate) mod event_processor;
 pub(crate) mod proxy_callback_proof_processor;
 pub(crate) mod proxy_callback_proof_request_stream;
@@ -19,12 +19,13 @@ use bonsai_sdk::{
     alpha::Client,
     alpha_async::{create_session, put_input},
 };
+use ethers::contract::LogMeta;
 use tokio::sync::Notify;
-use tracing::info;
+use tracing::{error, info};
 
 use crate::{
     downloader::event_processor::EventProcessor,
-    storage::{ProofRequestInformation, Storage},
+    storage::{EventID, ProofID, ProofRequestInformation, Storage},
 };
 
 #[derive(Clone)]
@@ -44,14 +45,24 @@ impl<S: Storage> ProxyCallbackProofRequestProcessor<S> {
     }
 }
 
-#[async_trait::async_trait]
-impl<S: Storage + Sync + Send> EventProcessor for ProxyCallbackProofRequestProcessor<S> {
-    type Event = CallbackRequestFilter;
+impl<S: Storage + Sync + Send> ProxyCallbackProofRequestProcessor<S> {
+    /// Submit the request to Bonsai and store it, returning the ID under which
+    /// the proof request is tracked. Requests with a webhook URL are posted
+    /// there once proven instead of called back on chain.
+    pub(crate) async fn submit(
+        &self,
+        event: CallbackRequestFilter,
+        webhook_url: Option<String>,
+    ) -> Result<ProofID, crate::api::error::Error> {
+        self.submit_from_source(event, None, webhook_url).await
+    }
 
-    async fn process_event(
+    async fn submit_from_source(
         &self,
         event: CallbackRequestFilter,
-    ) -> Result<(), crate::api::error::Error> {
+        source: Option<&LogMeta>,
+        webhook_url: Option<String>,
+    ) -> Result<ProofID, crate::api::error::Error> {
         let input_id = put_input(self.bonsai_client.clone(), event.input.clone().to_vec()).await?;
         let bonsai_session_id = create_session(
             self.bonsai_client.clone(),
@@ -60,19 +71,60 @@ impl<S: Storage + Sync + Send> EventProcessor for ProxyCallbackProofRequestProce
         )
         .await?;
 
-        // Store the request in storage
-        self.storage
+        // Store the request in storage. Bonsai sessions cannot be cancelled,
+        // so one whose request fails to be stored keeps proving untracked.
+        if let Err(err) = self
+            .storage
             .add_new_bonsai_proof_request(ProofRequestInformation {
-                proof_request_id: bonsai_session_id,
+                proof_request_id: bonsai_session_id.clone(),
                 callback_proof_request_event: event,
+                source_event: source.map(EventID::from),
+                block_hash: source.map(|meta| meta.block_hash),
+                attempts: 0,
+                last_error: None,
+                revert_data: None,
+                webhook_url,
             })
-            .await?;
+            .await
+        {
+            error!(
+                session_id = bonsai_session_id.uuid,
+                "failed to store the proof request of a new Bonsai session"
+            );
+            return Err(err.into());
+        }
 
         if let Some(notifier) = self.notifier.clone() {
             notifier.notify_one()
         }
 
         info!(?input_id, "sent new callback event to bonsai");
+        Ok(bonsai_session_id)
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: Storage + Sync + Send> EventProcessor for ProxyCallbackProofRequestProcessor<S> {
+    type Event = CallbackRequestFilter;
+
+    async fn process_event(
+        &self,
+        event: CallbackRequestFilter,
+        meta: LogMeta,
+    ) -> Result<(), crate::api::error::Error> {
+        // Logs are replayed when backfilling or resubscribing, so the log is
+        // claimed before its Bonsai session is created, and skipped if it
+        // already was.
+        let event_id = EventID::from(&meta);
+        if !self.storage.claim_event(event_id).await? {
+            info!(?event_id, "skipping already processed callback event");
+            return Ok(());
+        }
+        if let Err(err) = self.submit_from_source(event, Some(&meta), None).await {
+            // Let the log be processed again when it is retried or replayed.
+            self.storage.release_event(event_id).await?;
+            return Err(err);
+        }
         Ok(())
     }
 }
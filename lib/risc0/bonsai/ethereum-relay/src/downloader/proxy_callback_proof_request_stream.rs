@@ -12,76 +12,157 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::sync::Arc;
+
 use anyhow::Result;
 use bonsai_ethereum_contracts::i_bonsai_relay::CallbackRequestFilter;
 use ethers::{
-    prelude::{k256::ecdsa::SigningKey, signer::SignerMiddlewareError},
-    providers::{Middleware, Provider, PubsubClient, SubscriptionStream, Ws},
-    types::{Address, BlockNumber, Log},
+    contract::LogMeta,
+    prelude::{signer::SignerMiddlewareError, SignerMiddleware},
+    providers::{Middleware, Provider, PubsubClient, SubscriptionStream},
+    types::{Address, Filter, Log, U64},
 };
-use ethers_signers::Wallet;
 use futures::{Stream, StreamExt};
+use tokio::sync::Mutex;
 use tokio_stream::wrappers::ReceiverStream;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
 use super::{block_history, block_history::State};
-use crate::{api::error::Error, downloader::event_processor::EventProcessor, EthersClientConfig};
+use crate::{
+    api::error::Error,
+    control::RelayControl,
+    downloader::{confirmations::ConfirmationTracker, event_processor::EventProcessor},
+    metrics::RelayMetrics,
+    readiness::{Component, ReadinessReporter},
+    signer::RelaySigner,
+    storage::Storage,
+    transport::RelayTransport,
+    EthersClientConfig,
+};
+
+/// The filter matching the `CallbackRequest` logs of the relay contract.
+pub(crate) fn callback_request_filter(proxy_contract_address: Address) -> Filter {
+    const EVENT_NAME: &str = "CallbackRequest(address,bytes32,bytes,address,bytes4,uint64)";
+
+    Filter::new()
+        .address(proxy_contract_address)
+        .event(EVENT_NAME)
+}
 
 #[derive(Debug)]
 pub(crate) struct ProxyCallbackProofRequestStream<
     EP: EventProcessor<Event = CallbackRequestFilter> + Sync + Send,
+    S: Storage + Sync + Send,
 > {
     client_config: EthersClientConfig,
     proxy_contract_address: Address,
     event_processor: EP,
+    storage: S,
+    readiness: ReadinessReporter,
+    control: Arc<RelayControl>,
+    metrics: Arc<RelayMetrics>,
+    confirmations: Mutex<ConfirmationTracker<CallbackRequestFilter>>,
 }
 
-impl<EP: EventProcessor<Event = CallbackRequestFilter> + Sync + Send>
-    ProxyCallbackProofRequestStream<EP>
+impl<EP: EventProcessor<Event = CallbackRequestFilter> + Sync + Send, S: Storage + Sync + Send>
+    ProxyCallbackProofRequestStream<EP, S>
 {
     pub(crate) fn new(
         client_config: EthersClientConfig,
         proxy_contract_address: Address,
         event_processor: EP,
-    ) -> ProxyCallbackProofRequestStream<EP> {
+        storage: S,
+        readiness: ReadinessReporter,
+        control: Arc<RelayControl>,
+        metrics: Arc<RelayMetrics>,
+    ) -> ProxyCallbackProofRequestStream<EP, S> {
+        let confirmations = Mutex::new(ConfirmationTracker::new(client_config.confirmations));
         Self {
             client_config,
             proxy_contract_address,
             event_processor,
+            storage,
+            readiness,
+            control,
+            metrics,
+            confirmations,
         }
     }
 
     pub(crate) async fn run(self) -> Result<(), Error> {
-        const EVENT_NAME: &str = "CallbackRequest(address,bytes32,bytes,address,bytes4,uint64)";
-
-        let filter = ethers::types::Filter::new()
-            .address(self.proxy_contract_address)
-            .event(EVENT_NAME);
+        let filter = callback_request_filter(self.proxy_contract_address);
         let client = self.client_config.get_client().await?;
-        let last_processed_block_number = client.get_block_number().await?;
-        let last_processed_block = BlockNumber::Number(last_processed_block_number);
+        let latest_block = client.get_block_number().await?;
+        // Resume after the last block processed before the relay stopped, or
+        // start at the current block on the first run.
+        let last_processed_block = match self.storage.get_last_processed_block().await? {
+            Some(block) => {
+                info!(?block, ?latest_block, "Resuming from last processed block.");
+                block.min(latest_block)
+            }
+            None => {
+                self.storage
+                    .advance_last_processed_block(latest_block)
+                    .await?;
+                latest_block
+            }
+        };
         let mut state = State {
             client_config: self.client_config.clone(),
             client,
             recreate_client: false,
-            last_processed_block: last_processed_block_number,
-            latest_block: last_processed_block_number,
+            last_processed_block,
+            latest_block,
             filter,
-            from: last_processed_block,
-            to: last_processed_block,
         };
 
+        if !self.client_config.supports_subscriptions() {
+            info!("Ethereum node does not support subscriptions, polling for logs.");
+            return self.poll(state).await;
+        }
+
+        loop {
+            state = self.recreate_client(state).await?;
+            let client = state.client.clone();
+            let logs = client.subscribe_logs(&state.filter).await;
+            // Recover the logs emitted before the subscription started, such
+            // as while the relay was stopped or disconnected. Logs that are
+            // delivered again by the subscription are deduplicated by the
+            // event processor.
+            state = self.recover_block_delay(state).await;
+            state = self.match_logs(state, logs).await;
+        }
+    }
+
+    /// Repeatedly fetch the logs of all blocks after the last processed one
+    /// with `eth_getLogs`, for nodes that do not support subscriptions.
+    async fn poll(&self, mut state: State) -> Result<(), Error> {
+        let mut interval = tokio::time::interval(self.client_config.log_poll_interval);
         loop {
-            state = self.recreate_client(state.clone()).await?;
-            state = self.recover_block_delay(state.clone()).await;
-            let logs = state.client.subscribe_logs(&state.filter).await;
-            self.match_logs(state.clone(), logs).await;
+            interval.tick().await;
+            state = self.recreate_client(state).await?;
+            state = self.recover_block_delay(state).await;
+            if !state.recreate_client {
+                self.readiness.ready(Component::ChainSubscription);
+            }
+            // Without a subscription, nothing delivers the log of an event
+            // that a reorg moved into another block. Fetch the logs of the
+            // reorged blocks again instead. The stored last processed block
+            // is already before them, since it never passes pending events.
+            if let Some(block) = self.process_confirmed_events(&state.client).await {
+                let block = block.saturating_sub(U64::one());
+                if block < state.last_processed_block {
+                    info!(?block, "Refetching logs after a reorg.");
+                    state.last_processed_block = block;
+                }
+            }
         }
     }
 
     async fn recreate_client(&self, state: State) -> Result<State, Error> {
         let state = if state.recreate_client {
             debug!("Recreating client.");
+            self.metrics.rpc_reconnected();
             state.recreate_client().await?
         } else {
             state
@@ -89,17 +170,127 @@ impl<EP: EventProcessor<Event = CallbackRequestFilter> + Sync + Send>
         Ok(state)
     }
 
-    async fn process_logs(&self, stream: impl Stream<Item = Log>) {
+    /// Store the last processed block, but never past the events still
+    /// waiting for confirmations, so that they are fetched again after a
+    /// restart.
+    async fn store_last_processed_block(&self, block: U64) {
+        let block = match self.confirmations.lock().await.first_pending_block() {
+            Some(first_pending_block) => block.min(first_pending_block.saturating_sub(U64::one())),
+            None => block,
+        };
+        if let Err(error) = self.storage.advance_last_processed_block(block).await {
+            error!(?error, ?block, "Failed to store last processed block");
+        }
+    }
+
+    async fn process_event(&self, event: CallbackRequestFilter, meta: LogMeta) {
+        // Hold the event back while ingestion is paused. The last processed
+        // block does not move past it in the meantime.
+        if self.control.ingestion.is_paused() {
+            info!(?meta, "Ingestion paused, holding back event");
+            self.control.ingestion.resumed().await;
+        }
+        if let Err(error) = self
+            .event_processor
+            .process_event(event.clone(), meta.clone())
+            .await
+        {
+            // Hold the event like one waiting for confirmations, so that it is
+            // retried with them and the last processed block does not move
+            // past it.
+            error!(?error, ?meta, "Error processing event, retrying it later");
+            self.confirmations.lock().await.add(event, meta);
+        }
+    }
+
+    /// Process the events that reached the configured number of
+    /// confirmations, and retry those that failed to be processed. Returns
+    /// the lowest block of the events cancelled by a reorg, if any.
+    async fn process_confirmed_events(
+        &self,
+        client: &SignerMiddleware<Provider<RelayTransport>, RelaySigner>,
+    ) -> Option<U64> {
+        if self
+            .confirmations
+            .lock()
+            .await
+            .first_pending_block()
+            .is_none()
+        {
+            return None;
+        }
+        let latest_block = match client.get_block_number().await {
+            Ok(latest_block) => latest_block,
+            Err(error) => {
+                error!(?error, "Failed to get latest block number");
+                return None;
+            }
+        };
+        let confirmed = self
+            .confirmations
+            .lock()
+            .await
+            .take_confirmed(client, latest_block)
+            .await;
+        match confirmed {
+            Ok(confirmed) => {
+                for (event, meta) in confirmed.events {
+                    self.process_event(event, meta).await;
+                }
+                confirmed.first_reorged_block
+            }
+            Err(error) => {
+                error!(?error, "Failed to check confirmations");
+                None
+            }
+        }
+    }
+
+    async fn process_log(&self, log: Log) {
+        let block_number = log.block_number;
+        let removed = log.removed == Some(true);
+        let meta = LogMeta::from(&log);
+        let parsed_event: Result<CallbackRequestFilter, _> = ethers::contract::parse_log(log);
+        match parsed_event {
+            Ok(_) if removed => {
+                if self.confirmations.lock().await.remove(&meta) {
+                    info!(?meta, "Cancelled event removed by a reorg");
+                } else {
+                    warn!(?meta, "Event removed by a reorg was already processed");
+                }
+            }
+            Ok(event) if self.client_config.confirmations == 0 => {
+                self.process_event(event, meta).await
+            }
+            Ok(event) => self.confirmations.lock().await.add(event, meta),
+            Err(error) => error!(?error, "Error parsing log"),
+        }
+        // Logs arrive in block order, so every block before this one has been
+        // fully processed.
+        if let Some(block_number) = block_number {
+            self.store_last_processed_block(block_number.saturating_sub(U64::one()))
+                .await;
+        }
+    }
+
+    async fn process_logs(
+        &self,
+        stream: impl Stream<Item = Log>,
+        client: &SignerMiddleware<Provider<RelayTransport>, RelaySigner>,
+    ) {
         tokio::pin!(stream);
-        while let Some(log) = stream.next().await {
-            let parsed_event: Result<CallbackRequestFilter, _> = ethers::contract::parse_log(log);
-            match parsed_event {
-                Ok(event) => {
-                    if let Err(error) = self.event_processor.process_event(event).await {
-                        error!(?error, "Error processing event");
-                    }
+        let mut interval = tokio::time::interval(self.client_config.log_poll_interval);
+        loop {
+            tokio::select! {
+                log = stream.next() => match log {
+                    Some(log) => self.process_log(log).await,
+                    None => break,
+                },
+                // The subscription delivers the log of an event that a reorg
+                // moved into another block, so nothing needs to be refetched.
+                _ = interval.tick() => {
+                    self.process_confirmed_events(client).await;
                 }
-                Err(error) => error!(?error, "Error parsing log"),
             }
         }
     }
@@ -109,13 +300,14 @@ impl<EP: EventProcessor<Event = CallbackRequestFilter> + Sync + Send>
         state: State,
         logs: Result<
             SubscriptionStream<'_, impl PubsubClient, Log>,
-            SignerMiddlewareError<Provider<Ws>, Wallet<SigningKey>>,
+            SignerMiddlewareError<Provider<RelayTransport>, RelaySigner>,
         >,
     ) -> State {
         match logs {
             Ok(logs) => {
                 debug!("Successfully subscribed to logs");
-                self.process_logs(logs).await;
+                self.readiness.ready(Component::ChainSubscription);
+                self.process_logs(logs, &state.client).await;
                 state
             }
             Err(error) => {
@@ -127,32 +319,35 @@ impl<EP: EventProcessor<Event = CallbackRequestFilter> + Sync + Send>
             }
         }
     }
+
     async fn recover_block_delay(&self, state: State) -> State {
-        info!(?state, "Starting to recover delay.");
+        debug!(?state, "Starting to recover delay.");
         let original_state = state.clone();
         let (tx, rx) = tokio::sync::mpsc::channel(100);
         let recover_lost_blocks_handler =
             tokio::spawn(async move { block_history::recover_lost_blocks(state, tx).await });
-        self.process_logs(ReceiverStream::new(rx)).await;
+        self.process_logs(ReceiverStream::new(rx), &original_state.client)
+            .await;
         match recover_lost_blocks_handler.await {
             Ok(Ok(new_state)) => {
-                info!(state = ?new_state, "Successfully recovered block delay.");
-                let new_from = BlockNumber::Number(new_state.last_processed_block);
-                let new_to = BlockNumber::Number(new_state.latest_block);
-                State {
-                    filter: new_state.filter.clone().from_block(new_from),
-                    from: new_from,
-                    to: new_to,
-                    ..new_state
-                }
+                debug!(state = ?new_state, "Recovered block delay.");
+                self.store_last_processed_block(new_state.last_processed_block)
+                    .await;
+                new_state
             }
             Ok(Err(error)) => {
                 error!(?error, "Failed to recover block delay.");
-                original_state
+                State {
+                    recreate_client: true,
+                    ..original_state
+                }
             }
             Err(error) => {
                 error!(?error, "Tokio Task `recover_last_blocks_handler` failed.");
-                original_state
+                State {
+                    recreate_client: true,
+                    ..original_state
+                }
             }
         }
     }
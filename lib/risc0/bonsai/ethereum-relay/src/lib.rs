@@ -16,37 +16,76 @@
 
 pub mod sdk;
 
-pub use sdk::{CallbackRequest, Client, ClientError};
+pub use sdk::{
+    CallbackRequest, CallbackRequestResponse, CallbackStatus, CallbackStatusPage, Client,
+    ClientError, WebhookDeliveryAttempt, WebhookPayload,
+};
+
+pub mod config;
 
 mod api;
 mod client_config;
+mod control;
 mod downloader;
+mod metrics;
+mod readiness;
+mod signer;
 mod storage;
+mod supervisor;
 mod tests;
+mod transport;
 mod uploader;
 
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc, time::Duration};
 
-use anyhow::{Context, Result};
-use bonsai_sdk::alpha_async::get_client_from_parts;
-pub use client_config::EthersClientConfig;
+use anyhow::{bail, Context, Result};
+pub use api::keys::{AdminKey, ApiKey};
+use bonsai_sdk::{alpha::Client as BonsaiClient, alpha_async::get_client_from_parts};
+pub use client_config::{EthersClientConfig, WalletKey, WalletSource};
 use downloader::{
     proxy_callback_proof_processor::ProxyCallbackProofRequestProcessor,
     proxy_callback_proof_request_stream::ProxyCallbackProofRequestStream,
 };
 use ethers::core::types::Address;
-use storage::{in_memory::InMemoryStorage, Storage};
-use tokio::sync::Notify;
+use readiness::ReadinessReporter;
+pub use readiness::{Component, Readiness, ReadinessError};
+pub use signer::{RelaySigner, RelaySignerError, RemoteSigner};
+use storage::{
+    events::{PublishingStorage, TransitionEvents},
+    in_memory::InMemoryStorage,
+    sqlite::SqliteStorage,
+    Storage,
+};
+pub use supervisor::RestartPolicy;
+use supervisor::Supervisor;
+use tokio::{sync::Notify, task::JoinHandle};
 use tracing::info;
+pub use transport::{RelayTransport, RelayTransportError};
 use uploader::{
     completed_proofs::manager::BonsaiCompleteProofManager,
     pending_proofs::manager::BonsaiPendingProofManager,
 };
+pub use uploader::{
+    quarantine::QuarantinePolicy, retry::RetryPolicy, transaction_manager::TransactionPolicy,
+    webhook::WebhookPolicy,
+};
 
-use crate::api::{server::serve, state::ApiState};
+use crate::{
+    api::{keys::KeyStore, server::serve, state::ApiState},
+    control::RelayControl,
+    metrics::RelayMetrics,
+};
 
 static DEFAULT_FILTER: &str = "info";
 
+/// Number of proof request transitions kept for clients resuming their event
+/// stream.
+const TRANSITION_EVENT_HISTORY: usize = 1024;
+
+/// Interval at which a draining relay checks whether requests are still in
+/// flight.
+const DRAIN_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
 #[derive(Clone)]
 /// A relayer to integrate Ethereum with Bonsai.
 pub struct Relayer {
@@ -58,15 +97,74 @@ pub struct Relayer {
     pub rest_api_port: String,
     /// Bonsai API URL.
     pub bonsai_api_url: String,
-    /// Bonsai API key.
+    /// Bonsai API key. Sessions requested through the REST API use it as
+    /// well.
     pub bonsai_api_key: String,
+    /// Keys of the clients allowed to use the REST API.
+    pub api_keys: Vec<ApiKey>,
+    /// Keys of the operators allowed to use the admin API.
+    pub admin_keys: Vec<AdminKey>,
     /// The Ethereum address of the deployed Bonsai Relay contract.
     pub relay_contract_address: Address,
+    /// Number of completed callbacks after which a batch is sent to the relay
+    /// contract immediately.
+    pub max_batch_size: usize,
+    /// Interval at which completed callbacks are sent, even if the batch is
+    /// not full.
+    pub batch_interval: Duration,
+    /// Gas budget of each batch transaction. Each transaction is sent with
+    /// the estimated gas of its callbacks.
+    pub batch_gas_limit: u64,
+    /// SQLite database persisting the proof request state across restarts.
+    /// The state is kept in memory if not set.
+    pub storage_path: Option<PathBuf>,
+    /// How proof requests that failed are retried before they are
+    /// dead-lettered.
+    pub retry_policy: RetryPolicy,
+    /// Fees of the batch transactions, and how unconfirmed ones are
+    /// replaced.
+    pub transaction_policy: TransactionPolicy,
+    /// What happens to callbacks that revert when simulated before they are
+    /// batched.
+    pub quarantine_policy: QuarantinePolicy,
+    /// How proofs are posted to the webhooks of requests that have one.
+    pub webhook_policy: WebhookPolicy,
+    /// How failed subsystems are restarted before the relay shuts down.
+    pub restart_policy: RestartPolicy,
 }
 
 impl Relayer {
     /// Run a [Relayer] with an Ethereum Client.
     pub async fn run(self, client_config: EthersClientConfig) -> Result<()> {
+        let (reporter, _) = readiness::channel(self.components());
+        self.run_with_readiness(client_config, reporter).await
+    }
+
+    /// Spawn a [Relayer] with an Ethereum Client, returning a [Readiness]
+    /// that resolves once it can serve requests.
+    pub fn spawn(self, client_config: EthersClientConfig) -> (Readiness, JoinHandle<Result<()>>) {
+        let (reporter, readiness) = readiness::channel(self.components());
+        let handle = tokio::spawn(self.run_with_readiness(client_config, reporter));
+        (readiness, handle)
+    }
+
+    /// The components that must start before the relay is ready.
+    fn components(&self) -> Vec<Component> {
+        let mut components = vec![Component::ChainSubscription];
+        if self.rest_api {
+            components.push(Component::RestApi);
+        }
+        if self.dev_mode {
+            components.push(Component::LocalBonsai);
+        }
+        components
+    }
+
+    async fn run_with_readiness(
+        self,
+        client_config: EthersClientConfig,
+        readiness: ReadinessReporter,
+    ) -> Result<()> {
         // try to load filter from `RUST_LOG` or use reasonably verbose defaults
         let filter = ::tracing_subscriber::EnvFilter::try_from_default_env()
             .unwrap_or_else(|_| DEFAULT_FILTER.into());
@@ -76,12 +174,48 @@ impl Relayer {
             .finish();
         let _ = ::tracing::subscriber::set_global_default(subscriber);
 
+        if self.webhook_policy.signing_key.is_empty()
+            && self.api_keys.iter().any(|key| !key.webhook_urls.is_empty())
+        {
+            bail!("A webhook signing key is required when API keys allow webhook URLs.");
+        }
+
         let bonsai_client =
             get_client_from_parts(self.bonsai_api_url.clone(), self.bonsai_api_key.clone())
                 .await
                 .context("Failed to create Bonsai client.")?;
 
-        let storage = InMemoryStorage::new();
+        match self.storage_path.clone() {
+            Some(path) => {
+                let storage = SqliteStorage::open(&path)
+                    .context(format!("Failed to open SQLite storage {}.", path.display()))?;
+                self.run_with_storage(client_config, readiness, bonsai_client, storage)
+                    .await
+            }
+            None => {
+                self.run_with_storage(
+                    client_config,
+                    readiness,
+                    bonsai_client,
+                    InMemoryStorage::new(),
+                )
+                .await
+            }
+        }
+    }
+
+    async fn run_with_storage<S: Storage + Sync + Send + Clone + 'static>(
+        self,
+        client_config: EthersClientConfig,
+        readiness: ReadinessReporter,
+        bonsai_client: BonsaiClient,
+        storage: S,
+    ) -> Result<()> {
+        // Publish the state transitions of proof requests to the REST API.
+        let transition_events = Arc::new(TransitionEvents::new(TRANSITION_EVENT_HISTORY));
+        let storage = PublishingStorage::new(storage, transition_events.clone());
+        let control = Arc::new(RelayControl::default());
+        let metrics = Arc::new(RelayMetrics::new());
 
         // Setup Downloader
         let new_pending_proof_request_notifier = Arc::new(Notify::new());
@@ -91,100 +225,126 @@ impl Relayer {
             Some(new_pending_proof_request_notifier.clone()),
         );
 
-        let downloader = ProxyCallbackProofRequestStream::new(
-            client_config.clone(),
-            self.relay_contract_address,
-            proxy_callback_proof_request_processor.clone(),
-        );
-
         // Setup Uploader
         let new_complete_proof_notifier = Arc::new(Notify::new());
-
-        let uploader_pending_proof_manager = BonsaiPendingProofManager::new(
-            bonsai_client.clone(),
-            storage.clone(),
-            new_pending_proof_request_notifier.clone(),
-            new_complete_proof_notifier.clone(),
-        );
-
         let send_batch_notifier = Arc::new(Notify::new());
-        let max_batch_size: usize = 3;
-        let send_batch_interval = tokio::time::interval(tokio::time::Duration::from_millis(1000));
-
-        let uploader_complete_proof_manager = BonsaiCompleteProofManager::new(
-            bonsai_client.clone(),
-            self.dev_mode,
-            storage.clone(),
-            new_complete_proof_notifier.clone(),
-            send_batch_notifier.clone(),
-            max_batch_size,
-            self.relay_contract_address,
-            client_config.clone(),
-            send_batch_interval,
-        );
 
         // Setup server API
         let state = ApiState {
             bonsai_url: self.bonsai_api_url.clone(),
+            bonsai_api_key: self.bonsai_api_key.clone(),
+            keys: Arc::new(
+                KeyStore::new(self.api_keys.clone()).with_admin_keys(self.admin_keys.clone()),
+            ),
+            events: transition_events,
             storage: storage.clone(),
             notifier: new_pending_proof_request_notifier.clone(),
+            control: control.clone(),
+            metrics: metrics.clone(),
+            readiness: readiness.clone(),
         };
 
-        // Start everything
-        let server_handle = tokio::spawn(maybe_start_publish_mode(
-            self.rest_api,
-            state,
-            self.rest_api_port,
-        ));
-        let local_bonsai_handle = tokio::spawn(maybe_start_local_bonsai(
-            self.dev_mode,
-            self.bonsai_api_url.clone(),
-        ));
-        let downloader_handle = tokio::spawn(downloader.run());
-        let uploader_pending_proof_manager_handle =
-            tokio::spawn(uploader_pending_proof_manager.run());
-        let uploader_complete_proof_manager_handle =
-            tokio::spawn(uploader_complete_proof_manager.run());
+        // Each subsystem is started again from the same storage, notifiers,
+        // controls and metrics if it fails.
+        let mut supervisor = Supervisor::new(self.restart_policy, metrics.clone());
+        if self.rest_api {
+            let port = self.rest_api_port.clone();
+            let readiness = readiness.clone();
+            supervisor.supervise("server API", move || {
+                serve(state.clone(), port.clone(), readiness.clone())
+            });
+        }
+        if self.dev_mode {
+            let bonsai_url = self.bonsai_api_url.clone();
+            let readiness = readiness.clone();
+            supervisor.supervise("local Bonsai service", move || {
+                start_local_bonsai(bonsai_url.clone(), readiness.clone())
+            });
+        }
+        supervisor.supervise("downloader", {
+            let client_config = client_config.clone();
+            let storage = storage.clone();
+            let control = control.clone();
+            let metrics = metrics.clone();
+            let relay_contract_address = self.relay_contract_address;
+            move || {
+                let downloader = ProxyCallbackProofRequestStream::new(
+                    client_config.clone(),
+                    relay_contract_address,
+                    proxy_callback_proof_request_processor.clone(),
+                    storage.clone(),
+                    readiness.clone(),
+                    control.clone(),
+                    metrics.clone(),
+                );
+                async move { downloader.run().await.map_err(anyhow::Error::from) }
+            }
+        });
+        supervisor.supervise("pending proof manager", {
+            let bonsai_client = bonsai_client.clone();
+            let storage = storage.clone();
+            let new_pending_proof_request_notifier = new_pending_proof_request_notifier.clone();
+            let new_complete_proof_notifier = new_complete_proof_notifier.clone();
+            let metrics = metrics.clone();
+            let retry_policy = self.retry_policy;
+            move || {
+                let manager = BonsaiPendingProofManager::new(
+                    bonsai_client.clone(),
+                    storage.clone(),
+                    new_pending_proof_request_notifier.clone(),
+                    new_complete_proof_notifier.clone(),
+                    retry_policy,
+                    metrics.clone(),
+                );
+                async move { manager.run().await.map_err(anyhow::Error::from) }
+            }
+        });
+        supervisor.supervise("complete proof manager", {
+            let storage = storage.clone();
+            let control = control.clone();
+            let metrics = metrics.clone();
+            let relayer = self.clone();
+            move || {
+                let manager = BonsaiCompleteProofManager::new(
+                    bonsai_client.clone(),
+                    relayer.dev_mode,
+                    storage.clone(),
+                    new_complete_proof_notifier.clone(),
+                    send_batch_notifier.clone(),
+                    relayer.max_batch_size,
+                    relayer.relay_contract_address,
+                    client_config.clone(),
+                    tokio::time::interval(relayer.batch_interval),
+                    relayer.batch_gas_limit,
+                    relayer.retry_policy,
+                    relayer.transaction_policy,
+                    relayer.quarantine_policy,
+                    relayer.webhook_policy.clone(),
+                    control.clone(),
+                    metrics.clone(),
+                );
+                async move { manager.run().await.map_err(anyhow::Error::from) }
+            }
+        });
 
         info!("Relay started");
 
         tokio::select! {
-            err = server_handle, if self.rest_api => {
-                panic!("{}", format!("server API exited: {:?}", err))
-            }
-            err = local_bonsai_handle, if self.dev_mode => {
-                panic!("{}", format!("local Bonsai service exited: {:?}", err))
-            }
-            err = downloader_handle => {
-                panic!("{}", format!("downloader exited: {:?}", err))
-            }
-            err = uploader_pending_proof_manager_handle => {
-                panic!("{}", format!("pending proof manager exited: {:?}", err))
+            failed = supervisor.run() => {
+                failed.context("Relay stopped after a subsystem failed repeatedly.")
             }
-            err = uploader_complete_proof_manager_handle => {
-                panic!("{}", format!("complete proof manager exited: {:?}", err))
+            drained = control.drained(&storage, DRAIN_CHECK_INTERVAL) => {
+                drained.context("Failed to drain the relay.")?;
+                info!("Relay drained, stopping");
+                Ok(())
             }
         }
     }
 }
 
-async fn maybe_start_publish_mode<S: Storage + Sync + Send + Clone + 'static>(
-    publish_mode: bool,
-    state: ApiState<S>,
-    port: String,
-) -> anyhow::Result<()> {
-    if publish_mode {
-        return serve(state, port).await;
-    }
-
-    Ok(())
-}
-
-async fn maybe_start_local_bonsai(dev_mode: bool, bonsai_url: String) -> anyhow::Result<()> {
-    if dev_mode {
-        let port = bonsai_url.split(':').last().context("port not defined")?;
-        return bonsai_rest_api_mock::serve(port.to_string()).await;
-    }
-
-    Ok(())
+async fn start_local_bonsai(bonsai_url: String, readiness: ReadinessReporter) -> Result<()> {
+    let port = bonsai_url.split(':').last().context("port not defined")?;
+    let server = bonsai_rest_api_mock::bind(port.to_string())?;
+    readiness.ready(Component::LocalBonsai);
+    server.await
 }
@@ -12,81 +12,50 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::time::Duration;
+use std::path::PathBuf;
 
 use anyhow::Result;
-use bonsai_ethereum_relay::{EthersClientConfig, Relayer};
+use bonsai_ethereum_relay::config::{BonsaiOverrides, RelayConfig, RelayOverrides};
 use clap::Parser;
-use ethers::core::types::Address;
 
-const DEFAULT_BONSAI_API_URL: &str = "http://localhost:8081";
-const DEFAULT_REST_API_PORT: &str = "8080";
+/// Chain ID relayed to when neither a config file nor --eth-chain-id is given,
+/// as in releases without config files.
+const DEFAULT_ETH_CHAIN_ID: u64 = 5;
 
+/// Settings given here override the corresponding fields of the config file.
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    /// The port of the relay REST API
-    #[arg(short, long, default_value_t = DEFAULT_REST_API_PORT.to_string())]
-    port: String,
+    /// Path to a TOML config file. Fields missing from the file use their
+    /// default values.
+    #[arg(long, env = "RELAY_CONFIG")]
+    config: Option<PathBuf>,
 
-    /// Toggle to disable the relay REST API
-    #[arg(long, default_value_t = true)]
-    rest_api: bool,
+    #[clap(flatten)]
+    relay: RelayOverrides,
 
-    /// Bonsai Relay contract address on Ethereum
-    #[arg(long)]
-    contract_address: Address,
-
-    /// Ethereum Node endpoint
-    #[arg(long)]
-    eth_node_url: String,
-
-    /// Ethereum chain ID
-    #[arg(long, default_value_t = 5)]
-    eth_chain_id: u64,
-
-    /// Wallet Key Identifier. Can be a private key as a hex string, or an AWS
-    /// KMS key identifier
-    #[arg(short, long, env)]
-    wallet_key_identifier: String,
-
-    /// Bonsai API URL
-    #[arg(long, env, default_value_t = DEFAULT_BONSAI_API_URL.to_string())]
-    bonsai_api_url: String,
-
-    /// Bonsai API Key
-    /// Defaults to empty, providing no authentication.
-    #[arg(long, env, default_value = "")]
-    bonsai_api_key: String,
+    #[clap(flatten)]
+    bonsai: BonsaiOverrides,
+}
 
-    /// Toggle to enable dev_mode: only a local executor runs your
-    /// zkVM program and no proof is generated.
-    #[arg(long, env, default_value_t = false)]
-    risc0_dev_mode: bool,
+impl Args {
+    /// Load the config file, if any, and apply the overrides to it.
+    fn config(self) -> Result<RelayConfig> {
+        let mut config = RelayConfig::load(self.config.as_deref())?;
+        if self.config.is_none() {
+            config.chain.eth_chain_id = DEFAULT_ETH_CHAIN_ID;
+        }
+        self.relay.apply(&mut config)?;
+        self.bonsai.apply(&mut config);
+        Ok(config)
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let args = Args::parse();
-
-    let relayer = Relayer {
-        rest_api: args.rest_api,
-        dev_mode: args.risc0_dev_mode,
-        rest_api_port: args.port,
-        bonsai_api_url: args.bonsai_api_url,
-        bonsai_api_key: args.bonsai_api_key,
-        relay_contract_address: args.contract_address,
-    };
-
-    const WAIT_DURATION: Duration = Duration::from_secs(5);
-    const MAX_RETRIES: u64 = 7 * 24 * 60 * 60 / WAIT_DURATION.as_secs(); // 1 week
-    let client_config = EthersClientConfig::new(
-        args.eth_node_url,
-        args.eth_chain_id,
-        args.wallet_key_identifier.try_into()?,
-        MAX_RETRIES,
-        WAIT_DURATION,
-    );
+    let config = Args::parse().config()?;
+    config.validate()?;
+    println!("Relay configuration:\n{config}");
 
-    relayer.run(client_config).await
+    config.relayer()?.run(config.client_config()?).await
 }
@@ -0,0 +1,296 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Prometheus metrics of a running relay.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ethers::types::U256;
+use prometheus::{
+    exponential_buckets, Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter,
+    IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+
+use crate::storage::{Result, Storage};
+
+/// The content type of [RelayMetrics::encode].
+pub(crate) const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// The metrics of a relay, shared by the components that record them and the
+/// REST API that serves them.
+///
+/// Per-state request counts and the last processed block are read from
+/// storage when the metrics are scraped, so that they survive restarts.
+#[derive(Debug, Clone)]
+pub(crate) struct RelayMetrics {
+    registry: Registry,
+    requests: IntGaugeVec,
+    session_seconds: HistogramVec,
+    snark_seconds: HistogramVec,
+    batch_size: Histogram,
+    batch_gas_used: Histogram,
+    batched_callbacks: IntCounterVec,
+    last_batch_timestamp: IntGauge,
+    rpc_reconnects: IntCounter,
+    subsystem_restarts: IntCounterVec,
+    last_processed_block: IntGauge,
+}
+
+impl Default for RelayMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RelayMetrics {
+    pub(crate) fn new() -> Self {
+        // Bonsai sessions and SNARKs take from seconds to hours.
+        let latency_buckets = exponential_buckets(1.0, 2.0, 15).expect("valid buckets");
+        let metrics = Self {
+            registry: Registry::new(),
+            requests: IntGaugeVec::new(
+                Opts::new(
+                    "relay_proof_requests",
+                    "Number of proof requests in each state",
+                ),
+                &["image_id", "state"],
+            )
+            .expect("valid metric"),
+            session_seconds: HistogramVec::new(
+                HistogramOpts::new(
+                    "relay_bonsai_session_seconds",
+                    "Time from starting to poll a Bonsai session to its completion",
+                )
+                .buckets(latency_buckets.clone()),
+                &["image_id"],
+            )
+            .expect("valid metric"),
+            snark_seconds: HistogramVec::new(
+                HistogramOpts::new(
+                    "relay_snark_seconds",
+                    "Time taken by Bonsai to produce the SNARK of a receipt",
+                )
+                .buckets(latency_buckets),
+                &["image_id"],
+            )
+            .expect("valid metric"),
+            batch_size: Histogram::with_opts(
+                HistogramOpts::new(
+                    "relay_batch_size",
+                    "Number of callbacks in each batch sent on chain",
+                )
+                .buckets(exponential_buckets(1.0, 2.0, 10).expect("valid buckets")),
+            )
+            .expect("valid metric"),
+            batch_gas_used: Histogram::with_opts(
+                HistogramOpts::new("relay_batch_gas_used", "Gas used by each batch transaction")
+                    .buckets(exponential_buckets(50_000.0, 2.0, 12).expect("valid buckets")),
+            )
+            .expect("valid metric"),
+            batched_callbacks: IntCounterVec::new(
+                Opts::new(
+                    "relay_batched_callbacks_total",
+                    "Number of callbacks sent on chain in a batch",
+                ),
+                &["image_id"],
+            )
+            .expect("valid metric"),
+            last_batch_timestamp: IntGauge::new(
+                "relay_last_batch_timestamp_seconds",
+                "When the last batch was sent, in seconds since the Unix epoch",
+            )
+            .expect("valid metric"),
+            rpc_reconnects: IntCounter::new(
+                "relay_rpc_reconnects_total",
+                "Number of times the Ethereum client was recreated",
+            )
+            .expect("valid metric"),
+            subsystem_restarts: IntCounterVec::new(
+                Opts::new(
+                    "relay_subsystem_restarts_total",
+                    "Number of times a failed subsystem of the relay was restarted",
+                ),
+                &["subsystem"],
+            )
+            .expect("valid metric"),
+            last_processed_block: IntGauge::new(
+                "relay_last_processed_block",
+                "The last block whose callback requests were all processed",
+            )
+            .expect("valid metric"),
+        };
+
+        let collectors: [Box<dyn prometheus::core::Collector>; 10] = [
+            Box::new(metrics.requests.clone()),
+            Box::new(metrics.session_seconds.clone()),
+            Box::new(metrics.snark_seconds.clone()),
+            Box::new(metrics.batch_size.clone()),
+            Box::new(metrics.batch_gas_used.clone()),
+            Box::new(metrics.batched_callbacks.clone()),
+            Box::new(metrics.last_batch_timestamp.clone()),
+            Box::new(metrics.rpc_reconnects.clone()),
+            Box::new(metrics.subsystem_restarts.clone()),
+            Box::new(metrics.last_processed_block.clone()),
+        ];
+        for collector in collectors {
+            // Names are unique within the registry.
+            metrics.registry.register(collector).expect("unique metric");
+        }
+        metrics
+    }
+
+    pub(crate) fn observe_session(&self, image_id: &[u8; 32], elapsed: Duration) {
+        self.session_seconds
+            .with_label_values(&[&image_id_label(image_id)])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub(crate) fn observe_snark(&self, image_id: &[u8; 32], elapsed: Duration) {
+        self.snark_seconds
+            .with_label_values(&[&image_id_label(image_id)])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Record a batch sent on chain, given the image IDs of its callbacks.
+    pub(crate) fn observe_batch<'a>(
+        &self,
+        image_ids: impl ExactSizeIterator<Item = &'a [u8; 32]>,
+        gas_used: Option<U256>,
+    ) {
+        self.batch_size.observe(image_ids.len() as f64);
+        for image_id in image_ids {
+            self.batched_callbacks
+                .with_label_values(&[&image_id_label(image_id)])
+                .inc();
+        }
+        if let Some(gas_used) = gas_used {
+            self.batch_gas_used.observe(gas_used.low_u64() as f64);
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        self.last_batch_timestamp.set(now as i64);
+    }
+
+    pub(crate) fn rpc_reconnected(&self) {
+        self.rpc_reconnects.inc();
+    }
+
+    pub(crate) fn subsystem_restarted(&self, subsystem: &str) {
+        self.subsystem_restarts
+            .with_label_values(&[subsystem])
+            .inc();
+    }
+
+    /// Read the metrics kept in storage.
+    pub(crate) async fn refresh<S: Storage + Sync + Send>(&self, storage: &S) -> Result<()> {
+        let counts = storage.count_proof_requests().await?;
+        // States a request left since the last scrape must not linger.
+        self.requests.reset();
+        for count in counts {
+            self.requests
+                .with_label_values(&[&image_id_label(&count.image_id), &count.state])
+                .set(count.count as i64);
+        }
+        if let Some(block) = storage.get_last_processed_block().await? {
+            self.last_processed_block.set(block.as_u64() as i64);
+        }
+        Ok(())
+    }
+
+    /// The metrics in the Prometheus text format.
+    pub(crate) fn encode(&self) -> prometheus::Result<String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        // The text encoder only writes UTF-8.
+        Ok(String::from_utf8_lossy(&buffer).into_owned())
+    }
+}
+
+fn image_id_label(image_id: &[u8; 32]) -> String {
+    format!("0x{}", hex::encode(image_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use bonsai_ethereum_contracts::i_bonsai_relay::CallbackRequestFilter;
+    use bonsai_sdk::alpha::SessionId;
+    use ethers::types::U64;
+
+    use super::*;
+    use crate::storage::{in_memory::InMemoryStorage, ProofRequestInformation, ProofRequestState};
+
+    #[tokio::test]
+    async fn test_metrics_are_labelled_by_image_id() {
+        let storage = InMemoryStorage::new();
+        for (id, image_id) in [("a", [1u8; 32]), ("b", [1u8; 32]), ("c", [2u8; 32])] {
+            storage
+                .add_new_bonsai_proof_request(ProofRequestInformation {
+                    proof_request_id: SessionId::new(id.to_string()),
+                    callback_proof_request_event: CallbackRequestFilter {
+                        image_id,
+                        ..Default::default()
+                    },
+                    source_event: None,
+                    block_hash: None,
+                    attempts: 0,
+                    last_error: None,
+                    revert_data: None,
+                    webhook_url: None,
+                })
+                .await
+                .unwrap();
+        }
+        storage
+            .transition_proof_request(SessionId::new("c".to_string()), ProofRequestState::Pending)
+            .await
+            .unwrap();
+        storage
+            .advance_last_processed_block(U64::from(42))
+            .await
+            .unwrap();
+
+        let metrics = RelayMetrics::new();
+        metrics.observe_session(&[2u8; 32], Duration::from_secs(3));
+        metrics.observe_batch([[1u8; 32], [2u8; 32]].iter(), Some(U256::from(100_000)));
+        metrics.rpc_reconnected();
+        metrics.refresh(&storage).await.unwrap();
+        let encoded = metrics.encode().unwrap();
+
+        let image_1 = image_id_label(&[1u8; 32]);
+        let image_2 = image_id_label(&[2u8; 32]);
+        for line in [
+            format!("relay_proof_requests{{image_id=\"{image_1}\",state=\"new\"}} 2"),
+            format!("relay_proof_requests{{image_id=\"{image_2}\",state=\"pending\"}} 1"),
+            format!("relay_bonsai_session_seconds_count{{image_id=\"{image_2}\"}} 1"),
+            format!("relay_batched_callbacks_total{{image_id=\"{image_1}\"}} 1"),
+            "relay_batch_size_sum 2".to_string(),
+            "relay_batch_gas_used_sum 100000".to_string(),
+            "relay_rpc_reconnects_total 1".to_string(),
+            "relay_last_processed_block 42".to_string(),
+        ] {
+            assert!(encoded.contains(&line), "missing {line} in:\n{encoded}");
+        }
+
+        // Counts of states without requests anymore are dropped.
+        storage
+            .transition_proof_request(SessionId::new("c".to_string()), ProofRequestState::New)
+            .await
+            .unwrap();
+        metrics.refresh(&storage).await.unwrap();
+        let encoded = metrics.encode().unwrap();
+        assert!(!encoded.contains("state=\"pending\""));
+    }
+}
@@ -0,0 +1,159 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{collections::BTreeSet, fmt, sync::Arc, time::Duration};
+
+use thiserror::Error;
+use tokio::sync::watch;
+use tracing::info;
+
+/// A component of a running [Relayer](crate::Relayer) that must start before
+/// the relay can serve requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Component {
+    /// The relay REST API is accepting connections.
+    RestApi,
+    /// The local Bonsai mock used in dev mode is accepting connections.
+    LocalBonsai,
+    /// The relay is subscribed to callback requests on the relay contract.
+    ChainSubscription,
+}
+
+impl fmt::Display for Component {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Component::RestApi => write!(f, "REST API"),
+            Component::LocalBonsai => write!(f, "local Bonsai"),
+            Component::ChainSubscription => write!(f, "chain subscription"),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ReadinessError {
+    #[error("relay not ready after {timeout:?}, waiting for: {}", format_components(.pending))]
+    Timeout {
+        timeout: Duration,
+        pending: Vec<Component>,
+    },
+    #[error("relay stopped before it was ready, waiting for: {}", format_components(.pending))]
+    Stopped { pending: Vec<Component> },
+}
+
+fn format_components(components: &[Component]) -> String {
+    components
+        .iter()
+        .map(Component::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Creates a channel tracking the readiness of the given components.
+pub(crate) fn channel(
+    components: impl IntoIterator<Item = Component>,
+) -> (ReadinessReporter, Readiness) {
+    let (tx, rx) = watch::channel(components.into_iter().collect());
+    (ReadinessReporter { tx: Arc::new(tx) }, Readiness { rx })
+}
+
+/// Used by the components of the relay to report that they are ready.
+#[derive(Debug, Clone)]
+pub(crate) struct ReadinessReporter {
+    tx: Arc<watch::Sender<BTreeSet<Component>>>,
+}
+
+impl ReadinessReporter {
+    pub(crate) fn ready(&self, component: Component) {
+        self.tx.send_modify(|pending| {
+            if pending.remove(&component) {
+                info!("{component} ready");
+            }
+        });
+    }
+
+    /// The components that are not ready yet.
+    pub(crate) fn pending(&self) -> Vec<Component> {
+        self.tx.borrow().iter().copied().collect()
+    }
+}
+
+/// Resolves once every component of a running relay is ready.
+#[derive(Debug, Clone)]
+pub struct Readiness {
+    rx: watch::Receiver<BTreeSet<Component>>,
+}
+
+impl Readiness {
+    /// The components that are not ready yet.
+    pub fn pending(&self) -> Vec<Component> {
+        self.rx.borrow().iter().copied().collect()
+    }
+
+    /// Wait until every component is ready, or fail if that takes longer
+    /// than `timeout` or the relay stops first.
+    pub async fn wait(mut self, timeout: Duration) -> Result<(), ReadinessError> {
+        let ready = async {
+            while !self.rx.borrow_and_update().is_empty() {
+                self.rx.changed().await.map_err(|_| ())?;
+            }
+            Ok(())
+        };
+        match tokio::time::timeout(timeout, ready).await {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(())) => Err(ReadinessError::Stopped {
+                pending: self.pending(),
+            }),
+            Err(_) => Err(ReadinessError::Timeout {
+                timeout,
+                pending: self.pending(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_ready_once_all_components_report() {
+        let (reporter, readiness) = channel([Component::RestApi, Component::ChainSubscription]);
+        reporter.ready(Component::RestApi);
+        assert_eq!(readiness.pending(), vec![Component::ChainSubscription]);
+
+        let waiter = tokio::spawn(readiness.wait(Duration::from_secs(5)));
+        reporter.ready(Component::ChainSubscription);
+        waiter.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_timeout_reports_pending_components() {
+        let (_reporter, readiness) = channel([Component::LocalBonsai]);
+        let err = readiness.wait(Duration::from_millis(10)).await.unwrap_err();
+        assert!(matches!(
+            err,
+            ReadinessError::Timeout { pending, .. } if pending == vec![Component::LocalBonsai]
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_stopped_relay_is_reported() {
+        let (reporter, readiness) = channel([Component::RestApi]);
+        drop(reporter);
+        assert!(matches!(
+            readiness.wait(Duration::from_secs(5)).await,
+            Err(ReadinessError::Stopped { .. })
+        ));
+    }
+}
@@ -34,6 +34,71 @@ pub struct CallbackRequest {
     pub function_selector: ethers::types::Selector,
     /// The gas limit.
     pub gas_limit: u64,
+    /// The URL to post the proof to instead of calling back on chain. The
+    /// callback contract, function selector and gas limit are then unused.
+    pub webhook_url: Option<String>,
+}
+
+/// The response to a successfully submitted [CallbackRequest].
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+pub struct CallbackRequestResponse {
+    /// The ID the relay tracks the request under.
+    pub request_id: String,
+}
+
+/// The status of a [CallbackRequest] tracked by the relay.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, ToSchema)]
+pub struct CallbackStatus {
+    /// The ID the relay tracks the request under.
+    pub request_id: String,
+    /// The state of the request: one of `new`, `pending`, `completed`,
+    /// `failed`, `preparing_onchain`, `completed_onchain`, `callback_reverted`,
+    /// `delivered`, `quarantined`, `dead_letter` or `cancelled`.
+    pub state: String,
+    /// The Bonsai session proving the request.
+    pub bonsai_session_id: String,
+    /// The image ID of the guest.
+    #[schema(value_type = String)]
+    pub image_id: ethers::types::H256,
+    /// The Ethereum address of the callback.
+    #[schema(value_type = String)]
+    pub callback_contract: ethers::types::Address,
+    /// The transaction that delivered the callback, once sent on chain.
+    #[schema(value_type = Option<String>)]
+    pub tx_hash: Option<ethers::types::H256>,
+    /// Number of failed attempts at processing the request.
+    pub attempts: u32,
+    /// The error of the last failed attempt.
+    pub error: Option<String>,
+    /// The data the callback reverted with.
+    #[schema(value_type = Option<String>)]
+    pub revert_data: Option<ethers::types::Bytes>,
+    /// The URL the proof is posted to instead of calling back on chain.
+    pub webhook_url: Option<String>,
+}
+
+/// An attempt at posting a proven [CallbackRequest] to its webhook.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, ToSchema)]
+pub struct WebhookDeliveryAttempt {
+    /// Number of the attempt, starting at 1.
+    pub attempt: u32,
+    /// The URL the proof was posted to.
+    pub url: String,
+    /// The HTTP status of the response, if one was received.
+    pub status_code: Option<u16>,
+    /// Why the attempt failed, if it did.
+    pub error: Option<String>,
+    /// When the attempt was made, in seconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+/// A page of [CallbackStatus]es.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, ToSchema)]
+pub struct CallbackStatusPage {
+    /// The requests of the page, ordered by ID.
+    pub callbacks: Vec<CallbackStatus>,
+    /// The ID to pass as `after` to get the next page, if there is one.
+    pub next: Option<String>,
 }
 
 /// The Errors that may occur when processing a [Client] request.
@@ -115,7 +180,7 @@ impl Client {
     pub async fn callback_request(
         &self,
         request: impl Into<CallbackRequest>,
-    ) -> Result<(), ClientError> {
+    ) -> Result<CallbackRequestResponse, ClientError> {
         let res = self
             .client
             .post(format!("{}{CALLBACK_ROUTE}", self.url))
@@ -123,9 +188,40 @@ impl Client {
             .body(bincode::serialize(&request.into())?)
             .send()
             .await?;
-        error_for_status(res).await?;
+        let res = error_for_status(res).await?;
+
+        Ok(res.json().await?)
+    }
+
+    /// Get the status of a [CallbackRequest] from its request ID.
+    pub async fn callback_status(&self, request_id: &str) -> Result<CallbackStatus, ClientError> {
+        let res = self
+            .client
+            .get(format!("{}{CALLBACK_ROUTE}/{request_id}", self.url))
+            .send()
+            .await?;
+        let res = error_for_status(res).await?;
+
+        Ok(res.json().await?)
+    }
+
+    /// Get the webhook delivery log of a [CallbackRequest], oldest attempt
+    /// first.
+    pub async fn webhook_deliveries(
+        &self,
+        request_id: &str,
+    ) -> Result<Vec<WebhookDeliveryAttempt>, ClientError> {
+        let res = self
+            .client
+            .get(format!(
+                "{}{CALLBACK_ROUTE}/{request_id}/deliveries",
+                self.url
+            ))
+            .send()
+            .await?;
+        let res = error_for_status(res).await?;
 
-        Ok(())
+        Ok(res.json().await?)
     }
 }
 
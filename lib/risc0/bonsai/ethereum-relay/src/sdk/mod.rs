@@ -16,5 +16,10 @@
 
 pub mod client;
 pub mod utils;
+pub mod webhook;
 
-pub use client::{CallbackRequest, Client, ClientError};
+pub use client::{
+    CallbackRequest, CallbackRequestResponse, CallbackStatus, CallbackStatusPage, Client,
+    ClientError, WebhookDeliveryAttempt,
+};
+pub use webhook::WebhookPayload;
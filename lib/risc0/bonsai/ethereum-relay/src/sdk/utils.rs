@@ -108,7 +108,7 @@ pub async fn get_ethers_client_config(anvil: Option<&AnvilInstance>) -> Result<E
     let ethers_client_config = EthersClientConfig::new(
         eth_node_url,
         eth_chain_id,
-        wallet_key_identifier,
+        wallet_key_identifier.into(),
         MAX_RETRIES,
         WAIT_DURATION,
     );
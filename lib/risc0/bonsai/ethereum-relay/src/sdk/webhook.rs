@@ -0,0 +1,91 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The proofs the relay posts to webhook targets, and their signatures.
+
+use ethers::types::{Bytes, H256};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use utoipa::ToSchema;
+
+/// The header holding the signature of a webhook delivery.
+pub const SIGNATURE_HEADER: &str = "x-bonsai-relay-signature";
+/// The header holding the time a webhook delivery was signed at, in seconds
+/// since the Unix epoch.
+pub const TIMESTAMP_HEADER: &str = "x-bonsai-relay-timestamp";
+
+/// The JSON body posted to the webhook of a proven [CallbackRequest].
+///
+/// [CallbackRequest]: crate::sdk::client::CallbackRequest
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, ToSchema)]
+pub struct WebhookPayload {
+    /// The ID the relay tracks the request under.
+    pub request_id: String,
+    /// The image ID of the guest.
+    #[schema(value_type = String)]
+    pub image_id: H256,
+    /// The journal committed by the guest.
+    #[schema(value_type = String)]
+    pub journal: Bytes,
+    /// The digest of the post-state of the guest.
+    #[schema(value_type = String)]
+    pub post_state_digest: H256,
+    /// The ABI encoded SNARK seal, empty in dev mode.
+    #[schema(value_type = String)]
+    pub seal: Bytes,
+}
+
+fn mac(secret: &[u8], timestamp: u64, body: &[u8]) -> Hmac<Sha256> {
+    // HMAC accepts keys of any length.
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC key of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    mac
+}
+
+/// The signature of a webhook delivery: the hex encoded HMAC-SHA256 of
+/// `{timestamp}.{body}`, keyed with the secret shared with the relay.
+pub fn sign(secret: &[u8], timestamp: u64, body: &[u8]) -> String {
+    hex::encode(mac(secret, timestamp, body).finalize().into_bytes())
+}
+
+/// Whether `signature` is the signature of a webhook delivery, compared in
+/// constant time. Receivers should also reject old timestamps, so that
+/// deliveries cannot be replayed.
+pub fn verify(secret: &[u8], timestamp: u64, body: &[u8], signature: &str) -> bool {
+    match hex::decode(signature) {
+        Ok(signature) => mac(secret, timestamp, body)
+            .verify_slice(&signature)
+            .is_ok(),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signatures_cover_timestamp_and_body() {
+        let signature = sign(b"secret", 1_700_000_000, b"{}");
+        assert_eq!(signature.len(), 64);
+        assert!(verify(b"secret", 1_700_000_000, b"{}", &signature));
+        assert!(!verify(b"other", 1_700_000_000, b"{}", &signature));
+        assert!(!verify(b"secret", 1_700_000_001, b"{}", &signature));
+        assert!(!verify(b"secret", 1_700_000_000, b"[]", &signature));
+        assert!(!verify(b"secret", 1_700_000_000, b"{}", "not hex"));
+    }
+}
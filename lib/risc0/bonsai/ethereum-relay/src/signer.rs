@@ -0,0 +1,200 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use ethers::{
+    core::k256::ecdsa::VerifyingKey,
+    signers::{LocalWallet, Signer, WalletError},
+    types::{
+        transaction::{eip2718::TypedTransaction, eip712::Eip712},
+        Address, RecoveryMessage, Signature, SignatureError, H256,
+    },
+    utils::{hash_message, keccak256, public_key_to_address},
+};
+use serde_json::json;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum RelaySignerError {
+    #[error("local wallet error: {0}")]
+    Wallet(#[from] WalletError),
+    #[error("remote signer request failed: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("remote signer returned an invalid signature: {0}")]
+    Signature(#[from] SignatureError),
+    #[error("remote signer does not support {0}")]
+    Unsupported(&'static str),
+}
+
+/// A signer reached over HTTP with a web3signer-compatible API.
+///
+/// Every payload is sent to `POST {url}/api/v1/eth1/sign/{public_key}`, which
+/// signs the keccak256 hash of the given data. Returned signatures are checked
+/// against the address of the configured public key.
+#[derive(Debug, Clone)]
+pub struct RemoteSigner {
+    client: reqwest::Client,
+    url: String,
+    public_key: String,
+    address: Address,
+    chain_id: u64,
+}
+
+impl RemoteSigner {
+    /// Creates a remote signer for the given hex encoded secp256k1 public key,
+    /// either SEC1 encoded or as the raw 64 byte point.
+    pub fn new(url: &str, public_key: &str, chain_id: u64) -> Result<Self> {
+        let mut encoded_key = hex::decode(public_key.trim_start_matches("0x"))
+            .context("Failed to decode public key.")?;
+        if encoded_key.len() == 64 {
+            encoded_key.insert(0, 0x04);
+        }
+        let verifying_key =
+            VerifyingKey::from_sec1_bytes(&encoded_key).context("Invalid secp256k1 public key.")?;
+        Ok(Self {
+            client: reqwest::Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+            public_key: format!("0x{}", public_key.trim_start_matches("0x")),
+            address: public_key_to_address(&verifying_key),
+            chain_id,
+        })
+    }
+
+    async fn sign_data(&self, data: &[u8]) -> Result<Signature, RelaySignerError> {
+        let url = format!("{}/api/v1/eth1/sign/{}", self.url, self.public_key);
+        let mut signature: Signature = self
+            .client
+            .post(url)
+            .json(&json!({ "data": format!("0x{}", hex::encode(data)) }))
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?
+            .trim()
+            .parse()?;
+        signature.verify(RecoveryMessage::Hash(H256(keccak256(data))), self.address)?;
+        // Signers return `v` either as the recovery ID or offset by 27.
+        signature.v = 27 + signature.recovery_id()?.to_byte() as u64;
+        Ok(signature)
+    }
+}
+
+#[async_trait]
+impl Signer for RemoteSigner {
+    type Error = RelaySignerError;
+
+    async fn sign_message<S: Send + Sync + AsRef<[u8]>>(
+        &self,
+        message: S,
+    ) -> Result<Signature, Self::Error> {
+        let message = message.as_ref();
+        let mut data = format!("\x19Ethereum Signed Message:\n{}", message.len()).into_bytes();
+        data.extend_from_slice(message);
+        debug_assert_eq!(H256(keccak256(&data)), hash_message(message));
+        self.sign_data(&data).await
+    }
+
+    async fn sign_transaction(&self, tx: &TypedTransaction) -> Result<Signature, Self::Error> {
+        let mut tx = tx.clone();
+        let chain_id = tx.chain_id().map(|id| id.as_u64()).unwrap_or(self.chain_id);
+        tx.set_chain_id(chain_id);
+        let mut signature = self.sign_data(&tx.rlp()).await?;
+        // Convert the recovery ID returned by the signer to an EIP-155 `v`.
+        signature.v = signature.recovery_id()?.to_byte() as u64 + 35 + chain_id * 2;
+        Ok(signature)
+    }
+
+    async fn sign_typed_data<T: Eip712 + Send + Sync>(
+        &self,
+        _payload: &T,
+    ) -> Result<Signature, Self::Error> {
+        Err(RelaySignerError::Unsupported("EIP-712 typed data"))
+    }
+
+    fn address(&self) -> Address {
+        self.address
+    }
+
+    fn chain_id(&self) -> u64 {
+        self.chain_id
+    }
+
+    fn with_chain_id<T: Into<u64>>(mut self, chain_id: T) -> Self {
+        self.chain_id = chain_id.into();
+        self
+    }
+}
+
+/// The signer of the relay wallet, holding its key either locally or on a
+/// [RemoteSigner].
+#[derive(Debug, Clone)]
+pub enum RelaySigner {
+    Local(LocalWallet),
+    Remote(RemoteSigner),
+}
+
+#[async_trait]
+impl Signer for RelaySigner {
+    type Error = RelaySignerError;
+
+    async fn sign_message<S: Send + Sync + AsRef<[u8]>>(
+        &self,
+        message: S,
+    ) -> Result<Signature, Self::Error> {
+        match self {
+            RelaySigner::Local(wallet) => Ok(wallet.sign_message(message).await?),
+            RelaySigner::Remote(signer) => signer.sign_message(message).await,
+        }
+    }
+
+    async fn sign_transaction(&self, tx: &TypedTransaction) -> Result<Signature, Self::Error> {
+        match self {
+            RelaySigner::Local(wallet) => Ok(wallet.sign_transaction(tx).await?),
+            RelaySigner::Remote(signer) => signer.sign_transaction(tx).await,
+        }
+    }
+
+    async fn sign_typed_data<T: Eip712 + Send + Sync>(
+        &self,
+        payload: &T,
+    ) -> Result<Signature, Self::Error> {
+        match self {
+            RelaySigner::Local(wallet) => Ok(wallet.sign_typed_data(payload).await?),
+            RelaySigner::Remote(signer) => signer.sign_typed_data(payload).await,
+        }
+    }
+
+    fn address(&self) -> Address {
+        match self {
+            RelaySigner::Local(wallet) => wallet.address(),
+            RelaySigner::Remote(signer) => signer.address(),
+        }
+    }
+
+    fn chain_id(&self) -> u64 {
+        match self {
+            RelaySigner::Local(wallet) => wallet.chain_id(),
+            RelaySigner::Remote(signer) => signer.chain_id(),
+        }
+    }
+
+    fn with_chain_id<T: Into<u64>>(self, chain_id: T) -> Self {
+        match self {
+            RelaySigner::Local(wallet) => RelaySigner::Local(wallet.with_chain_id(chain_id)),
+            RelaySigner::Remote(signer) => RelaySigner::Remote(signer.with_chain_id(chain_id)),
+        }
+    }
+}
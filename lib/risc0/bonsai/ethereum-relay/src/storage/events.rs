@@ -0,0 +1,499 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use ethers::types::{Address, Bytes, H256, U64};
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tracing::warn;
+use utoipa::ToSchema;
+
+use crate::storage::{
+    AuditEntry, EventID, ProofID, ProofRequestCount, ProofRequestFilter, ProofRequestInformation,
+    ProofRequestState, Result, Storage, WebhookDelivery,
+};
+
+/// A proof request that entered a new state.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub(crate) struct TransitionEvent {
+    /// Sequence number of the event. It keeps increasing across restarts of
+    /// the relay.
+    pub id: u64,
+    /// The ID the relay tracks the request under.
+    pub request_id: String,
+    /// The new state of the request.
+    pub state: String,
+    /// The transaction that delivered the callback, once sent on chain.
+    #[schema(value_type = Option<String>)]
+    pub tx_hash: Option<H256>,
+    /// The image ID of the guest.
+    #[schema(value_type = String)]
+    pub image_id: H256,
+    /// The Ethereum address of the callback.
+    #[schema(value_type = String)]
+    pub callback_contract: Address,
+    /// The URL the proof is posted to instead, if any.
+    pub webhook_url: Option<String>,
+}
+
+#[derive(Debug)]
+struct History {
+    next_id: u64,
+    events: VecDeque<TransitionEvent>,
+}
+
+/// Broadcasts the state transitions of proof requests, keeping the most
+/// recent ones so that subscribers can resume after a disconnection.
+#[derive(Debug)]
+pub(crate) struct TransitionEvents {
+    sender: broadcast::Sender<TransitionEvent>,
+    history: Mutex<History>,
+    capacity: usize,
+}
+
+impl TransitionEvents {
+    /// Keep the `capacity` most recent transitions. The first event ID is the
+    /// number of microseconds since the Unix epoch, so that IDs keep
+    /// increasing across restarts without being stored.
+    pub(crate) fn new(capacity: usize) -> Self {
+        let first_id = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_micros() as u64)
+            .unwrap_or_default();
+        Self::starting_at(capacity, first_id.max(1))
+    }
+
+    fn starting_at(capacity: usize, first_id: u64) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self {
+            sender,
+            history: Mutex::new(History {
+                next_id: first_id,
+                events: VecDeque::with_capacity(capacity),
+            }),
+            capacity,
+        }
+    }
+
+    fn publish(&self, request: &ProofRequestInformation, state: ProofRequestState) {
+        let mut history = match self.history.lock() {
+            Ok(history) => history,
+            Err(_) => {
+                warn!("transition event history poisoned, dropping event");
+                return;
+            }
+        };
+        let event = TransitionEvent {
+            id: history.next_id,
+            request_id: request.proof_request_id.uuid.clone(),
+            state: state.name().to_string(),
+            tx_hash: state.tx_hash(),
+            image_id: H256(request.callback_proof_request_event.image_id),
+            callback_contract: request.callback_proof_request_event.callback_contract,
+            webhook_url: request.webhook_url.clone(),
+        };
+        history.next_id += 1;
+        if history.events.len() == self.capacity {
+            history.events.pop_front();
+        }
+        history.events.push_back(event.clone());
+        // Sending only fails if nobody is subscribed.
+        let _ = self.sender.send(event);
+    }
+
+    /// Subscribe to the transitions, returning the kept transitions that
+    /// follow `last_event_id` and a receiver of the ones to come. The
+    /// transitions are `None` if some of those following `last_event_id` are
+    /// no longer kept, or if it is not the ID of an event of this run.
+    pub(crate) fn subscribe(
+        &self,
+        last_event_id: Option<u64>,
+    ) -> (
+        Option<Vec<TransitionEvent>>,
+        broadcast::Receiver<TransitionEvent>,
+    ) {
+        // Both are taken under the lock, so no event is missed or repeated.
+        let history = self
+            .history
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let receiver = self.sender.subscribe();
+        let first_kept_id = history
+            .events
+            .front()
+            .map_or(history.next_id, |event| event.id);
+        let missed = match last_event_id {
+            Some(last_event_id)
+                if last_event_id < first_kept_id - 1 || last_event_id >= history.next_id =>
+            {
+                None
+            }
+            Some(last_event_id) => Some(
+                history
+                    .events
+                    .iter()
+                    .filter(|event| event.id > last_event_id)
+                    .cloned()
+                    .collect(),
+            ),
+            None => Some(Vec::new()),
+        };
+        (missed, receiver)
+    }
+}
+
+/// A [Storage] publishing every state transition of its proof requests to
+/// [TransitionEvents].
+#[derive(Debug, Clone)]
+pub(crate) struct PublishingStorage<S> {
+    inner: S,
+    events: Arc<TransitionEvents>,
+}
+
+impl<S: Storage + Sync + Send> PublishingStorage<S> {
+    pub(crate) fn new(inner: S, events: Arc<TransitionEvents>) -> Self {
+        Self { inner, events }
+    }
+
+    /// Read the request before it changes state, since some backends forget
+    /// requests once they are completed on chain.
+    async fn request(&self, proof_id: &ProofID) -> Option<ProofRequestInformation> {
+        self.inner.get_proof_request(proof_id.clone()).await.ok()
+    }
+
+    fn publish(&self, request: Option<ProofRequestInformation>, state: ProofRequestState) {
+        if let Some(request) = request {
+            self.events.publish(&request, state);
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: Storage + Sync + Send> Storage for PublishingStorage<S> {
+    async fn add_new_bonsai_proof_request(&self, proof: ProofRequestInformation) -> Result<()> {
+        self.inner
+            .add_new_bonsai_proof_request(proof.clone())
+            .await?;
+        self.publish(Some(proof), ProofRequestState::New);
+        Ok(())
+    }
+
+    async fn fetch_new_bonsai_requests(
+        &self,
+        limit: Option<u64>,
+    ) -> Result<Vec<ProofRequestInformation>> {
+        self.inner.fetch_new_bonsai_requests(limit).await
+    }
+
+    async fn fetch_pending_bonsai_requests(
+        &self,
+        limit: Option<u64>,
+    ) -> Result<Vec<ProofRequestInformation>> {
+        self.inner.fetch_pending_bonsai_requests(limit).await
+    }
+
+    async fn fetch_completed_bonsai_requests(
+        &self,
+        limit: Option<u64>,
+    ) -> Result<Vec<ProofRequestInformation>> {
+        self.inner.fetch_completed_bonsai_requests(limit).await
+    }
+
+    async fn fetch_failed_bonsai_requests(
+        &self,
+        limit: Option<u64>,
+    ) -> Result<Vec<ProofRequestInformation>> {
+        self.inner.fetch_failed_bonsai_requests(limit).await
+    }
+
+    async fn fetch_dead_letter_proof_requests(
+        &self,
+        limit: Option<u64>,
+    ) -> Result<Vec<ProofRequestInformation>> {
+        self.inner.fetch_dead_letter_proof_requests(limit).await
+    }
+
+    async fn fetch_preparing_onchain_proof_requests(
+        &self,
+        limit: Option<u64>,
+    ) -> Result<Vec<ProofRequestInformation>> {
+        self.inner
+            .fetch_preparing_onchain_proof_requests(limit)
+            .await
+    }
+
+    async fn transition_proof_request(
+        &self,
+        proof_id: ProofID,
+        new_state: ProofRequestState,
+    ) -> Result<()> {
+        let request = self.request(&proof_id).await;
+        self.inner
+            .transition_proof_request(proof_id, new_state)
+            .await?;
+        self.publish(request, new_state);
+        Ok(())
+    }
+
+    async fn get_proof_request_state(&self, proof_id: ProofID) -> Result<ProofRequestState> {
+        self.inner.get_proof_request_state(proof_id).await
+    }
+
+    async fn get_proof_request(&self, proof_id: ProofID) -> Result<ProofRequestInformation> {
+        self.inner.get_proof_request(proof_id).await
+    }
+
+    async fn list_proof_requests(
+        &self,
+        filter: &ProofRequestFilter,
+        after: Option<ProofID>,
+        limit: u64,
+    ) -> Result<Vec<(ProofRequestInformation, ProofRequestState)>> {
+        self.inner.list_proof_requests(filter, after, limit).await
+    }
+
+    async fn count_proof_requests(&self) -> Result<Vec<ProofRequestCount>> {
+        self.inner.count_proof_requests().await
+    }
+
+    async fn fetch_callback_reverted_proof_requests(
+        &self,
+        limit: Option<u64>,
+    ) -> Result<Vec<ProofRequestInformation>> {
+        self.inner
+            .fetch_callback_reverted_proof_requests(limit)
+            .await
+    }
+
+    async fn fetch_quarantined_proof_requests(
+        &self,
+        limit: Option<u64>,
+    ) -> Result<Vec<ProofRequestInformation>> {
+        self.inner.fetch_quarantined_proof_requests(limit).await
+    }
+
+    async fn quarantine_proof_request(
+        &self,
+        proof_id: ProofID,
+        revert_data: Bytes,
+        reason: String,
+    ) -> Result<u32> {
+        let request = self.request(&proof_id).await;
+        let attempts = self
+            .inner
+            .quarantine_proof_request(proof_id, revert_data, reason)
+            .await?;
+        self.publish(request, ProofRequestState::Quarantined);
+        Ok(attempts)
+    }
+
+    async fn mark_callback_reverted(
+        &self,
+        proof_id: ProofID,
+        tx_hash: H256,
+        revert_data: Bytes,
+    ) -> Result<()> {
+        let request = self.request(&proof_id).await;
+        self.inner
+            .mark_callback_reverted(proof_id, tx_hash, revert_data)
+            .await?;
+        self.publish(request, ProofRequestState::CallbackReverted(tx_hash));
+        Ok(())
+    }
+
+    async fn record_proof_request_failure(&self, proof_id: ProofID, error: String) -> Result<u32> {
+        self.inner
+            .record_proof_request_failure(proof_id, error)
+            .await
+    }
+
+    async fn requeue_dead_letter_proof_request(&self, proof_id: ProofID) -> Result<()> {
+        let request = self.request(&proof_id).await;
+        self.inner
+            .requeue_dead_letter_proof_request(proof_id)
+            .await?;
+        self.publish(request, ProofRequestState::New);
+        Ok(())
+    }
+
+    async fn force_requeue_proof_request(&self, proof_id: ProofID) -> Result<ProofRequestState> {
+        let request = self.request(&proof_id).await;
+        let state = self.inner.force_requeue_proof_request(proof_id).await?;
+        self.publish(request, ProofRequestState::New);
+        Ok(state)
+    }
+
+    async fn cancel_proof_request(&self, proof_id: ProofID) -> Result<ProofRequestState> {
+        let request = self.request(&proof_id).await;
+        let state = self.inner.cancel_proof_request(proof_id).await?;
+        self.publish(request, ProofRequestState::Cancelled);
+        Ok(state)
+    }
+
+    async fn record_audit_entry(&self, entry: AuditEntry) -> Result<()> {
+        self.inner.record_audit_entry(entry).await
+    }
+
+    async fn fetch_audit_log(&self, limit: u64) -> Result<Vec<AuditEntry>> {
+        self.inner.fetch_audit_log(limit).await
+    }
+
+    async fn record_webhook_delivery(&self, delivery: WebhookDelivery) -> Result<()> {
+        self.inner.record_webhook_delivery(delivery).await
+    }
+
+    async fn fetch_webhook_deliveries(&self, proof_id: ProofID) -> Result<Vec<WebhookDelivery>> {
+        self.inner.fetch_webhook_deliveries(proof_id).await
+    }
+
+    async fn contains_event(&self, event_id: EventID) -> Result<bool> {
+        self.inner.contains_event(event_id).await
+    }
+
+    async fn claim_event(&self, event_id: EventID) -> Result<bool> {
+        self.inner.claim_event(event_id).await
+    }
+
+    async fn release_event(&self, event_id: EventID) -> Result<()> {
+        self.inner.release_event(event_id).await
+    }
+
+    async fn get_last_processed_block(&self) -> Result<Option<U64>> {
+        self.inner.get_last_processed_block().await
+    }
+
+    async fn advance_last_processed_block(&self, block: U64) -> Result<()> {
+        self.inner.advance_last_processed_block(block).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bonsai_ethereum_contracts::i_bonsai_relay::CallbackRequestFilter;
+    use bonsai_sdk::alpha::SessionId;
+
+    use super::*;
+    use crate::storage::in_memory::InMemoryStorage;
+
+    fn test_request(id: &str) -> ProofRequestInformation {
+        ProofRequestInformation {
+            proof_request_id: SessionId::new(id.to_string()),
+            callback_proof_request_event: CallbackRequestFilter {
+                account: Address::repeat_byte(1),
+                image_id: [2; 32],
+                input: Bytes::from(vec![3, 4, 5]),
+                callback_contract: Address::repeat_byte(6),
+                function_selector: [0xab, 0xcd, 0xef, 0xab],
+                gas_limit: 3000000,
+            },
+            source_event: None,
+            block_hash: None,
+            attempts: 0,
+            last_error: None,
+            revert_data: None,
+            webhook_url: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transitions_are_published() {
+        let events = Arc::new(TransitionEvents::starting_at(2, 1));
+        let storage = PublishingStorage::new(InMemoryStorage::new(), events.clone());
+        let (missed, mut receiver) = events.subscribe(None);
+        assert_eq!(missed, Some(Vec::new()));
+
+        let id = SessionId::new("a".to_string());
+        storage
+            .add_new_bonsai_proof_request(test_request("a"))
+            .await
+            .unwrap();
+        for state in [ProofRequestState::Pending, ProofRequestState::Completed] {
+            storage
+                .transition_proof_request(id.clone(), state)
+                .await
+                .unwrap();
+        }
+        // Invalid transitions are not published.
+        assert!(storage
+            .transition_proof_request(id.clone(), ProofRequestState::New)
+            .await
+            .is_err());
+
+        let mut states = Vec::new();
+        for expected_id in 1..=3 {
+            let event = receiver.recv().await.unwrap();
+            assert_eq!(event.id, expected_id);
+            assert_eq!(event.request_id, "a");
+            assert_eq!(event.callback_contract, Address::repeat_byte(6));
+            states.push(event.state);
+        }
+        assert_eq!(states, vec!["new", "pending", "completed"]);
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_subscribers_resume_from_kept_events() {
+        let events = Arc::new(TransitionEvents::starting_at(2, 1));
+        let storage = PublishingStorage::new(InMemoryStorage::new(), events.clone());
+        for id in ["a", "b", "c"] {
+            storage
+                .add_new_bonsai_proof_request(test_request(id))
+                .await
+                .unwrap();
+        }
+
+        // Only the last two events are kept.
+        let (missed, _) = events.subscribe(Some(1));
+        let ids: Vec<u64> = missed.unwrap().iter().map(|event| event.id).collect();
+        assert_eq!(ids, vec![2, 3]);
+        let missed = events.subscribe(Some(2)).0.unwrap();
+        assert_eq!(missed.len(), 1);
+        assert_eq!(missed[0].request_id, "c");
+        assert_eq!(events.subscribe(Some(3)).0, Some(Vec::new()));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_last_event_ids_are_reported() {
+        let events = Arc::new(TransitionEvents::starting_at(2, 100));
+        // Before any event, earlier IDs are from another run.
+        assert_eq!(events.subscribe(Some(50)).0, None);
+
+        let storage = PublishingStorage::new(InMemoryStorage::new(), events.clone());
+        for id in ["a", "b", "c"] {
+            storage
+                .add_new_bonsai_proof_request(test_request(id))
+                .await
+                .unwrap();
+        }
+        // Event 100 is no longer kept, so a client that last saw event 99
+        // missed it.
+        assert_eq!(events.subscribe(Some(99)).0, None);
+        // IDs past the last event are from another run.
+        assert_eq!(events.subscribe(Some(103)).0, None);
+    }
+
+    #[test]
+    fn test_event_ids_increase_across_runs() {
+        let first_run = TransitionEvents::new(2);
+        let first_id = first_run.history.lock().unwrap().next_id;
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        let second_run = TransitionEvents::new(2);
+        assert!(second_run.history.lock().unwrap().next_id > first_id);
+    }
+}
@@ -13,11 +13,16 @@
 // limitations under the License.
 
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, HashSet},
     sync::{Arc, RwLock},
 };
 
-use crate::storage::{Error, ProofID, ProofRequestInformation, ProofRequestState, Storage};
+use ethers::types::{Bytes, H256, U64};
+
+use crate::storage::{
+    AuditEntry, Error, EventID, ProofID, ProofRequestCount, ProofRequestFilter,
+    ProofRequestInformation, ProofRequestState, Storage, WebhookDelivery,
+};
 
 #[derive(Debug, Clone)]
 pub(crate) struct InMemoryStorage {
@@ -26,6 +31,16 @@ pub(crate) struct InMemoryStorage {
     pending_proofs: Arc<RwLock<HashMap<String, ProofRequestInformation>>>,
     completed_proofs: Arc<RwLock<HashMap<String, ProofRequestInformation>>>,
     preparing_onchain_proofs: Arc<RwLock<HashMap<String, ProofRequestInformation>>>,
+    failed_proofs: Arc<RwLock<HashMap<String, ProofRequestInformation>>>,
+    dead_letter_proofs: Arc<RwLock<HashMap<String, ProofRequestInformation>>>,
+    callback_reverted_proofs: Arc<RwLock<HashMap<String, ProofRequestInformation>>>,
+    quarantined_proofs: Arc<RwLock<HashMap<String, ProofRequestInformation>>>,
+    cancelled_proofs: Arc<RwLock<HashMap<String, ProofRequestInformation>>>,
+    webhook_deliveries: Arc<RwLock<HashMap<String, Vec<WebhookDelivery>>>>,
+    audit_log: Arc<RwLock<Vec<AuditEntry>>>,
+    processed_events: Arc<RwLock<HashSet<EventID>>>,
+    claimed_events: Arc<RwLock<HashSet<EventID>>>,
+    last_processed_block: Arc<RwLock<Option<U64>>>,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -53,6 +68,16 @@ impl InMemoryStorage {
             pending_proofs: Arc::new(RwLock::new(HashMap::new())),
             completed_proofs: Arc::new(RwLock::new(HashMap::new())),
             preparing_onchain_proofs: Arc::new(RwLock::new(HashMap::new())),
+            failed_proofs: Arc::new(RwLock::new(HashMap::new())),
+            dead_letter_proofs: Arc::new(RwLock::new(HashMap::new())),
+            callback_reverted_proofs: Arc::new(RwLock::new(HashMap::new())),
+            quarantined_proofs: Arc::new(RwLock::new(HashMap::new())),
+            cancelled_proofs: Arc::new(RwLock::new(HashMap::new())),
+            webhook_deliveries: Arc::new(RwLock::new(HashMap::new())),
+            audit_log: Arc::new(RwLock::new(Vec::new())),
+            processed_events: Arc::new(RwLock::new(HashSet::new())),
+            claimed_events: Arc::new(RwLock::new(HashSet::new())),
+            last_processed_block: Arc::new(RwLock::new(None)),
         }
     }
 
@@ -63,11 +88,60 @@ impl InMemoryStorage {
         match state {
             ProofRequestState::New => self.new_proofs.clone(),
             ProofRequestState::Pending => self.pending_proofs.clone(),
-            ProofRequestState::Failed => Arc::new(RwLock::new(HashMap::new())),
+            ProofRequestState::Failed => self.failed_proofs.clone(),
             ProofRequestState::Completed => self.completed_proofs.clone(),
             ProofRequestState::PreparingOnchain => self.preparing_onchain_proofs.clone(),
-            ProofRequestState::CompletedOnchain(_) => Arc::new(RwLock::new(HashMap::new())),
+            ProofRequestState::CompletedOnchain(_) | ProofRequestState::Delivered => {
+                Arc::new(RwLock::new(HashMap::new()))
+            }
+            ProofRequestState::CallbackReverted(_) => self.callback_reverted_proofs.clone(),
+            ProofRequestState::Quarantined => self.quarantined_proofs.clone(),
+            ProofRequestState::DeadLetter => self.dead_letter_proofs.clone(),
+            ProofRequestState::Cancelled => self.cancelled_proofs.clone(),
+        }
+    }
+
+    /// Move a proof request to `new_state` on behalf of an operator, if
+    /// `allowed` accepts its current state, returning that state.
+    fn force_proof_request_state(
+        &self,
+        proof_id: ProofID,
+        new_state: ProofRequestState,
+        action: &'static str,
+        allowed: fn(&ProofRequestState) -> bool,
+    ) -> Result<ProofRequestState, Error> {
+        let mut proof_states_locked = self.proof_states.write()?;
+        let current_state = match proof_states_locked.get(&proof_id.uuid) {
+            Some(state) => *state,
+            None => return Err(Error::ProofNotFound { id: proof_id }),
+        };
+        if !allowed(&current_state) {
+            return Err(Error::InvalidAdminAction {
+                id: proof_id,
+                state: current_state,
+                action,
+            });
         }
+
+        let mut proof = match self
+            .get_proof_request_set_for_state(current_state)
+            .write()?
+            .remove(&proof_id.uuid)
+        {
+            Some(proof) => proof,
+            None => return Err(Error::ProofNotFound { id: proof_id }),
+        };
+        if new_state == ProofRequestState::New {
+            proof.attempts = 0;
+            proof.last_error = None;
+            proof.revert_data = None;
+        }
+        self.get_proof_request_set_for_state(new_state)
+            .write()?
+            .insert(proof_id.uuid.clone(), proof);
+        proof_states_locked.insert(proof_id.uuid, new_state);
+
+        Ok(current_state)
     }
 }
 
@@ -77,6 +151,12 @@ impl Storage for InMemoryStorage {
         &self,
         proof: ProofRequestInformation,
     ) -> Result<(), Error> {
+        if let Some(event_id) = proof.source_event {
+            if !self.processed_events.write()?.insert(event_id) {
+                return Err(Error::DuplicateEvent { id: event_id });
+            }
+            self.claimed_events.write()?.remove(&event_id);
+        }
         self.proof_states
             .write()?
             .insert(proof.proof_request_id.uuid.clone(), ProofRequestState::New);
@@ -96,6 +176,15 @@ impl Storage for InMemoryStorage {
         Ok(hashmap.values().cloned().collect())
     }
 
+    async fn fetch_pending_bonsai_requests(
+        &self,
+        _limit: Option<u64>,
+    ) -> Result<Vec<ProofRequestInformation>, Error> {
+        let hashmap = self.pending_proofs.read()?;
+
+        Ok(hashmap.values().cloned().collect())
+    }
+
     async fn fetch_completed_bonsai_requests(
         &self,
         _limit: Option<u64>,
@@ -105,6 +194,24 @@ impl Storage for InMemoryStorage {
         Ok(hashmap.values().cloned().collect())
     }
 
+    async fn fetch_failed_bonsai_requests(
+        &self,
+        _limit: Option<u64>,
+    ) -> Result<Vec<ProofRequestInformation>, Error> {
+        let hashmap = self.failed_proofs.read()?;
+
+        Ok(hashmap.values().cloned().collect())
+    }
+
+    async fn fetch_dead_letter_proof_requests(
+        &self,
+        _limit: Option<u64>,
+    ) -> Result<Vec<ProofRequestInformation>, Error> {
+        let hashmap = self.dead_letter_proofs.read()?;
+
+        Ok(hashmap.values().cloned().collect())
+    }
+
     async fn fetch_preparing_onchain_proof_requests(
         &self,
         _limit: Option<u64>,
@@ -158,8 +265,8 @@ impl Storage for InMemoryStorage {
             proof
         };
 
-        if let ProofRequestState::CompletedOnchain(_) = new_state {
-            // We don't need to store onchain transactions in memory
+        if let ProofRequestState::CompletedOnchain(_) | ProofRequestState::Delivered = new_state {
+            // We don't need to store delivered proofs in memory
             proof_states_locked.remove(&proof_id.uuid);
             return Ok(());
         };
@@ -170,4 +277,267 @@ impl Storage for InMemoryStorage {
 
         Ok(())
     }
+
+    async fn get_proof_request(&self, proof_id: ProofID) -> Result<ProofRequestInformation, Error> {
+        let proof_states_locked = self.proof_states.read()?;
+        let state = match proof_states_locked.get(&proof_id.uuid) {
+            Some(state) => *state,
+            None => return Err(Error::ProofNotFound { id: proof_id }),
+        };
+
+        let set = self.get_proof_request_set_for_state(state);
+        let set_locked = set.read()?;
+        match set_locked.get(&proof_id.uuid) {
+            Some(proof) => Ok(proof.clone()),
+            None => Err(Error::ProofNotFound { id: proof_id }),
+        }
+    }
+
+    async fn list_proof_requests(
+        &self,
+        filter: &ProofRequestFilter,
+        after: Option<ProofID>,
+        limit: u64,
+    ) -> Result<Vec<(ProofRequestInformation, ProofRequestState)>, Error> {
+        let after = after.map(|id| id.uuid).unwrap_or_default();
+        let proof_states_locked = self.proof_states.read()?;
+        let mut ids: Vec<&String> = proof_states_locked
+            .keys()
+            .filter(|id| **id > after)
+            .collect();
+        ids.sort();
+
+        let mut requests = Vec::new();
+        for id in ids {
+            if requests.len() as u64 >= limit {
+                break;
+            }
+            let state = proof_states_locked[id];
+            let set = self.get_proof_request_set_for_state(state);
+            let set_locked = set.read()?;
+            if let Some(request) = set_locked.get(id) {
+                if filter.matches(request, &state) {
+                    requests.push((request.clone(), state));
+                }
+            }
+        }
+        Ok(requests)
+    }
+
+    async fn count_proof_requests(&self) -> Result<Vec<ProofRequestCount>, Error> {
+        let proof_states_locked = self.proof_states.read()?;
+        let mut counts = BTreeMap::new();
+        for (id, state) in proof_states_locked.iter() {
+            let set = self.get_proof_request_set_for_state(*state);
+            let set_locked = set.read()?;
+            // Requests settled on chain or delivered are not kept in memory.
+            if let Some(request) = set_locked.get(id) {
+                let image_id = request.callback_proof_request_event.image_id;
+                *counts.entry((image_id, state.name())).or_insert(0) += 1;
+            }
+        }
+        Ok(counts
+            .into_iter()
+            .map(|((image_id, state), count)| ProofRequestCount {
+                image_id,
+                state: state.to_string(),
+                count,
+            })
+            .collect())
+    }
+
+    async fn fetch_callback_reverted_proof_requests(
+        &self,
+        _limit: Option<u64>,
+    ) -> Result<Vec<ProofRequestInformation>, Error> {
+        let hashmap = self.callback_reverted_proofs.read()?;
+
+        Ok(hashmap.values().cloned().collect())
+    }
+
+    async fn fetch_quarantined_proof_requests(
+        &self,
+        _limit: Option<u64>,
+    ) -> Result<Vec<ProofRequestInformation>, Error> {
+        let hashmap = self.quarantined_proofs.read()?;
+
+        Ok(hashmap.values().cloned().collect())
+    }
+
+    async fn quarantine_proof_request(
+        &self,
+        proof_id: ProofID,
+        revert_data: Bytes,
+        reason: String,
+    ) -> Result<u32, Error> {
+        self.transition_proof_request(proof_id.clone(), ProofRequestState::Quarantined)
+            .await?;
+
+        let mut set_locked = self.quarantined_proofs.write()?;
+        let proof = match set_locked.get_mut(&proof_id.uuid) {
+            Some(proof) => proof,
+            None => return Err(Error::ProofNotFound { id: proof_id }),
+        };
+        proof.revert_data = Some(revert_data);
+        proof.attempts += 1;
+        proof.last_error = Some(reason);
+
+        Ok(proof.attempts)
+    }
+
+    async fn mark_callback_reverted(
+        &self,
+        proof_id: ProofID,
+        tx_hash: H256,
+        revert_data: Bytes,
+    ) -> Result<(), Error> {
+        self.transition_proof_request(
+            proof_id.clone(),
+            ProofRequestState::CallbackReverted(tx_hash),
+        )
+        .await?;
+
+        let mut set_locked = self.callback_reverted_proofs.write()?;
+        match set_locked.get_mut(&proof_id.uuid) {
+            Some(proof) => proof.revert_data = Some(revert_data),
+            None => return Err(Error::ProofNotFound { id: proof_id }),
+        }
+
+        Ok(())
+    }
+
+    async fn record_proof_request_failure(
+        &self,
+        proof_id: ProofID,
+        error: String,
+    ) -> Result<u32, Error> {
+        let proof_states_locked = self.proof_states.read()?;
+        let state = match proof_states_locked.get(&proof_id.uuid) {
+            Some(state) => *state,
+            None => return Err(Error::ProofNotFound { id: proof_id }),
+        };
+
+        let set = self.get_proof_request_set_for_state(state);
+        let mut set_locked = set.write()?;
+        let proof = match set_locked.get_mut(&proof_id.uuid) {
+            Some(proof) => proof,
+            None => return Err(Error::ProofNotFound { id: proof_id }),
+        };
+        proof.attempts += 1;
+        proof.last_error = Some(error);
+
+        Ok(proof.attempts)
+    }
+
+    async fn requeue_dead_letter_proof_request(&self, proof_id: ProofID) -> Result<(), Error> {
+        let mut proof_states_locked = self.proof_states.write()?;
+        match proof_states_locked.get(&proof_id.uuid) {
+            Some(ProofRequestState::DeadLetter) => (),
+            Some(state) => {
+                return Err(Error::NotDeadLetter {
+                    id: proof_id,
+                    state: *state,
+                })
+            }
+            None => return Err(Error::ProofNotFound { id: proof_id }),
+        };
+
+        let mut proof = match self.dead_letter_proofs.write()?.remove(&proof_id.uuid) {
+            Some(proof) => proof,
+            None => return Err(Error::ProofNotFound { id: proof_id }),
+        };
+        proof.attempts = 0;
+        proof.last_error = None;
+        self.new_proofs
+            .write()?
+            .insert(proof_id.uuid.clone(), proof);
+        proof_states_locked.insert(proof_id.uuid, ProofRequestState::New);
+
+        Ok(())
+    }
+
+    async fn force_requeue_proof_request(
+        &self,
+        proof_id: ProofID,
+    ) -> Result<ProofRequestState, Error> {
+        self.force_proof_request_state(
+            proof_id,
+            ProofRequestState::New,
+            "requeued",
+            ProofRequestState::can_force_requeue,
+        )
+    }
+
+    async fn cancel_proof_request(&self, proof_id: ProofID) -> Result<ProofRequestState, Error> {
+        self.force_proof_request_state(
+            proof_id,
+            ProofRequestState::Cancelled,
+            "cancelled",
+            ProofRequestState::can_cancel,
+        )
+    }
+
+    async fn record_audit_entry(&self, entry: AuditEntry) -> Result<(), Error> {
+        self.audit_log.write()?.push(entry);
+        Ok(())
+    }
+
+    async fn fetch_audit_log(&self, limit: u64) -> Result<Vec<AuditEntry>, Error> {
+        let limit = usize::try_from(limit).unwrap_or(usize::MAX);
+        Ok(self
+            .audit_log
+            .read()?
+            .iter()
+            .rev()
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+
+    async fn record_webhook_delivery(&self, delivery: WebhookDelivery) -> Result<(), Error> {
+        self.webhook_deliveries
+            .write()?
+            .entry(delivery.proof_request_id.uuid.clone())
+            .or_default()
+            .push(delivery);
+        Ok(())
+    }
+
+    async fn fetch_webhook_deliveries(
+        &self,
+        proof_id: ProofID,
+    ) -> Result<Vec<WebhookDelivery>, Error> {
+        Ok(self
+            .webhook_deliveries
+            .read()?
+            .get(&proof_id.uuid)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn contains_event(&self, event_id: EventID) -> Result<bool, Error> {
+        Ok(self.processed_events.read()?.contains(&event_id))
+    }
+
+    async fn claim_event(&self, event_id: EventID) -> Result<bool, Error> {
+        // Held together, so that the log cannot be added in between.
+        let processed_events = self.processed_events.read()?;
+        let mut claimed_events = self.claimed_events.write()?;
+        Ok(!processed_events.contains(&event_id) && claimed_events.insert(event_id))
+    }
+
+    async fn release_event(&self, event_id: EventID) -> Result<(), Error> {
+        self.claimed_events.write()?.remove(&event_id);
+        Ok(())
+    }
+
+    async fn get_last_processed_block(&self) -> Result<Option<U64>, Error> {
+        Ok(*self.last_processed_block.read()?)
+    }
+
+    async fn advance_last_processed_block(&self, block: U64) -> Result<(), Error> {
+        let mut last_processed_block = self.last_processed_block.write()?;
+        *last_processed_block = (*last_processed_block).max(Some(block));
+        Ok(())
+    }
 }
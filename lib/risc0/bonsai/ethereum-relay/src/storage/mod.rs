@@ -15,13 +15,18 @@
 use std::sync::PoisonError;
 
 use bonsai_ethereum_contracts::i_bonsai_relay::CallbackRequestFilter;
-use ethers::types::H256;
+use ethers::{
+    contract::LogMeta,
+    types::{Address, Bytes, H256, U256, U64},
+};
 
+pub(crate) mod events;
 pub(crate) mod in_memory;
+pub(crate) mod sqlite;
 
 use bonsai_sdk::alpha::SessionId;
 
-use self::in_memory::InMemoryStorageError;
+use self::{in_memory::InMemoryStorageError, sqlite::SqliteStorageError};
 
 pub(crate) type ProofID = SessionId;
 
@@ -31,6 +36,21 @@ pub(crate) type Result<T, E = Error> = std::result::Result<T, E>;
 pub(crate) enum Error {
     #[error("Failed to transition proof request")]
     TransitionProofRequest(#[from] InMemoryStorageError),
+    #[error("SQLite storage error")]
+    Sqlite(#[from] SqliteStorageError),
+    #[error("A proof request already exists for event {id:?}")]
+    DuplicateEvent { id: EventID },
+    #[error("Proof request {id:?} is {state:?}, not dead-lettered")]
+    NotDeadLetter {
+        id: ProofID,
+        state: ProofRequestState,
+    },
+    #[error("Proof request {id:?} is {state:?} and cannot be {action}")]
+    InvalidAdminAction {
+        id: ProofID,
+        state: ProofRequestState,
+        action: &'static str,
+    },
     #[error("Proof not found")]
     ProofNotFound { id: ProofID },
     // TODO: We lose the underlying error here. We should probably wrap it in a
@@ -45,10 +65,78 @@ impl<T> From<PoisonError<T>> for Error {
     }
 }
 
+/// Identifies a `CallbackRequest` log on chain, so that replayed logs are
+/// only processed once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct EventID {
+    pub transaction_hash: H256,
+    pub log_index: U256,
+}
+
+impl From<&LogMeta> for EventID {
+    fn from(meta: &LogMeta) -> Self {
+        Self {
+            transaction_hash: meta.transaction_hash,
+            log_index: meta.log_index,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub(crate) struct ProofRequestInformation {
     pub proof_request_id: ProofID,
     pub callback_proof_request_event: CallbackRequestFilter,
+    /// The log the request was read from, if it was received from the chain
+    /// rather than the REST API.
+    pub source_event: Option<EventID>,
+    /// The hash of the block holding the source event.
+    pub block_hash: Option<H256>,
+    /// Number of failed attempts at processing the request.
+    pub attempts: u32,
+    /// The error of the last failed attempt.
+    pub last_error: Option<String>,
+    /// The data the callback reverted with, if it failed on chain.
+    pub revert_data: Option<Bytes>,
+    /// The URL the proof is posted to instead of calling back on chain.
+    pub webhook_url: Option<String>,
+}
+
+/// An attempt at posting a proof request to its webhook.
+#[derive(Debug, Clone)]
+pub(crate) struct WebhookDelivery {
+    pub proof_request_id: ProofID,
+    pub url: String,
+    /// Number of the attempt, starting at 1.
+    pub attempt: u32,
+    /// The HTTP status of the response, if one was received.
+    pub status_code: Option<u16>,
+    /// Why the attempt failed, if it did.
+    pub error: Option<String>,
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+/// An action taken by an operator through the admin API.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct AuditEntry {
+    /// Name of the admin key the action was taken with.
+    pub operator: String,
+    pub action: String,
+    /// The proof request the action applies to, if any.
+    pub proof_request_id: Option<ProofID>,
+    /// `ok`, or why the action was refused.
+    pub outcome: String,
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+/// The number of proof requests for an image ID in a state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ProofRequestCount {
+    pub image_id: [u8; 32],
+    /// Name of the state, as reported by [ProofRequestState::name].
+    pub state: String,
+    pub count: u64,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -60,24 +148,145 @@ pub(crate) enum ProofRequestState {
     Failed,
     PreparingOnchain,
     CompletedOnchain(H256),
+    // Sent on chain, but the callback itself reverted
+    CallbackReverted(H256),
+    // Posted to its webhook instead of sent on chain
+    Delivered,
+    // Held back because its callback reverts when simulated
+    Quarantined,
+    // Failed too many times, kept for an operator to inspect and requeue
+    DeadLetter,
+    // Cancelled by an operator
+    Cancelled,
 }
 
 impl ProofRequestState {
+    /// The names of the states, as reported by [ProofRequestState::name].
+    pub(crate) const NAMES: [&'static str; 11] = [
+        "new",
+        "pending",
+        "completed",
+        "failed",
+        "preparing_onchain",
+        "completed_onchain",
+        "callback_reverted",
+        "delivered",
+        "quarantined",
+        "dead_letter",
+        "cancelled",
+    ];
+
+    /// The name of the state, without its transaction hash.
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            ProofRequestState::New => "new",
+            ProofRequestState::Pending => "pending",
+            ProofRequestState::Completed => "completed",
+            ProofRequestState::Failed => "failed",
+            ProofRequestState::PreparingOnchain => "preparing_onchain",
+            ProofRequestState::CompletedOnchain(_) => "completed_onchain",
+            ProofRequestState::CallbackReverted(_) => "callback_reverted",
+            ProofRequestState::Delivered => "delivered",
+            ProofRequestState::Quarantined => "quarantined",
+            ProofRequestState::DeadLetter => "dead_letter",
+            ProofRequestState::Cancelled => "cancelled",
+        }
+    }
+
+    /// The transaction that delivered the callback, if it was sent on chain.
+    pub(crate) fn tx_hash(&self) -> Option<H256> {
+        match self {
+            ProofRequestState::CompletedOnchain(tx_hash)
+            | ProofRequestState::CallbackReverted(tx_hash) => Some(*tx_hash),
+            _ => None,
+        }
+    }
+
+    /// Whether an operator may force the request back to `New`. Requests
+    /// being sent on chain, or already delivered, may not.
+    pub(crate) fn can_force_requeue(&self) -> bool {
+        !matches!(
+            self,
+            ProofRequestState::New
+                | ProofRequestState::PreparingOnchain
+                | ProofRequestState::CompletedOnchain(_)
+                | ProofRequestState::Delivered
+        )
+    }
+
+    /// Whether an operator may cancel the request. Requests being sent on
+    /// chain, or already delivered, may not.
+    pub(crate) fn can_cancel(&self) -> bool {
+        !matches!(
+            self,
+            ProofRequestState::PreparingOnchain
+                | ProofRequestState::CompletedOnchain(_)
+                | ProofRequestState::Delivered
+                | ProofRequestState::Cancelled
+        )
+    }
+
     fn is_valid_state_transition(self, new_state: Self) -> bool {
         match (self, new_state) {
             (ProofRequestState::New, ProofRequestState::Pending)
+            // Allow a revert from Pending to New. This is used to retry a request after an
+            // error, or after a restart while the request was in flight.
+            | (ProofRequestState::Pending, ProofRequestState::New)
             | (ProofRequestState::Pending, ProofRequestState::Completed)
             | (ProofRequestState::Pending, ProofRequestState::Failed)
+            // Failed requests are retried until they run out of attempts.
+            | (ProofRequestState::Failed, ProofRequestState::New)
+            | (ProofRequestState::Failed, ProofRequestState::DeadLetter)
+            | (ProofRequestState::PreparingOnchain, ProofRequestState::DeadLetter)
+            // Allow an operator to requeue a dead-lettered request.
+            | (ProofRequestState::DeadLetter, ProofRequestState::New)
             | (ProofRequestState::Completed, ProofRequestState::PreparingOnchain)
             // Allow a revert from PreparingOnchain to Completed. This is useful if the service
             // crashes while preparing a request for sending on chain.
             | (ProofRequestState::PreparingOnchain, ProofRequestState::Completed)
-            | (ProofRequestState::PreparingOnchain, ProofRequestState::CompletedOnchain(_)) => true,
+            | (ProofRequestState::PreparingOnchain, ProofRequestState::CompletedOnchain(_))
+            | (ProofRequestState::PreparingOnchain, ProofRequestState::CallbackReverted(_))
+            | (ProofRequestState::PreparingOnchain, ProofRequestState::Delivered)
+            | (ProofRequestState::PreparingOnchain, ProofRequestState::Quarantined)
+            // Quarantined requests are simulated again once retried.
+            | (ProofRequestState::Quarantined, ProofRequestState::Completed)
+            | (ProofRequestState::Quarantined, ProofRequestState::DeadLetter) => true,
             _ => false,
         }
     }
 }
 
+/// Selects the proof requests returned by [Storage::list_proof_requests].
+/// Empty sets match every request. Requests with a webhook are matched by
+/// their URL rather than their callback contract.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ProofRequestFilter {
+    /// Name of the state of the requests.
+    pub state: Option<String>,
+    pub image_ids: Vec<[u8; 32]>,
+    pub callback_contracts: Vec<Address>,
+    pub webhook_urls: Vec<String>,
+}
+
+impl ProofRequestFilter {
+    pub(crate) fn matches(
+        &self,
+        request: &ProofRequestInformation,
+        state: &ProofRequestState,
+    ) -> bool {
+        let event = &request.callback_proof_request_event;
+        self.state
+            .as_deref()
+            .map_or(true, |name| name == state.name())
+            && (self.image_ids.is_empty() || self.image_ids.contains(&event.image_id))
+            && ((self.callback_contracts.is_empty() && self.webhook_urls.is_empty())
+                || match &request.webhook_url {
+                    Some(url) => self.webhook_urls.contains(url),
+                    None => self.callback_contracts.contains(&event.callback_contract),
+                })
+    }
+}
+
 #[async_trait::async_trait]
 pub(crate) trait Storage {
     async fn add_new_bonsai_proof_request(&self, proof: ProofRequestInformation) -> Result<()>;
@@ -85,10 +294,22 @@ pub(crate) trait Storage {
         &self,
         limit: Option<u64>,
     ) -> Result<Vec<ProofRequestInformation>>;
+    async fn fetch_pending_bonsai_requests(
+        &self,
+        limit: Option<u64>,
+    ) -> Result<Vec<ProofRequestInformation>>;
     async fn fetch_completed_bonsai_requests(
         &self,
         limit: Option<u64>,
     ) -> Result<Vec<ProofRequestInformation>>;
+    async fn fetch_failed_bonsai_requests(
+        &self,
+        limit: Option<u64>,
+    ) -> Result<Vec<ProofRequestInformation>>;
+    async fn fetch_dead_letter_proof_requests(
+        &self,
+        limit: Option<u64>,
+    ) -> Result<Vec<ProofRequestInformation>>;
     async fn fetch_preparing_onchain_proof_requests(
         &self,
         limit: Option<u64>,
@@ -99,4 +320,78 @@ pub(crate) trait Storage {
         new_state: ProofRequestState,
     ) -> Result<()>;
     async fn get_proof_request_state(&self, proof_id: ProofID) -> Result<ProofRequestState>;
+    async fn get_proof_request(&self, proof_id: ProofID) -> Result<ProofRequestInformation>;
+    /// Up to `limit` proof requests matching the filter, with their state,
+    /// ordered by ID and starting after the given ID.
+    async fn list_proof_requests(
+        &self,
+        filter: &ProofRequestFilter,
+        after: Option<ProofID>,
+        limit: u64,
+    ) -> Result<Vec<(ProofRequestInformation, ProofRequestState)>>;
+    /// The number of proof requests in each state, by image ID, ordered by
+    /// image ID and state. Pairs without requests are left out.
+    async fn count_proof_requests(&self) -> Result<Vec<ProofRequestCount>>;
+    async fn fetch_callback_reverted_proof_requests(
+        &self,
+        limit: Option<u64>,
+    ) -> Result<Vec<ProofRequestInformation>>;
+    async fn fetch_quarantined_proof_requests(
+        &self,
+        limit: Option<u64>,
+    ) -> Result<Vec<ProofRequestInformation>>;
+    /// Move a proof request whose callback reverts when simulated to
+    /// `Quarantined`, recording the revert as a failed attempt. Returns the
+    /// number of failed attempts so far.
+    async fn quarantine_proof_request(
+        &self,
+        proof_id: ProofID,
+        revert_data: Bytes,
+        reason: String,
+    ) -> Result<u32>;
+    /// Move a proof request sent on chain in the given transaction to
+    /// `CallbackReverted`, keeping the data its callback reverted with.
+    async fn mark_callback_reverted(
+        &self,
+        proof_id: ProofID,
+        tx_hash: H256,
+        revert_data: Bytes,
+    ) -> Result<()>;
+    /// Record a failed attempt at processing a proof request, returning the
+    /// number of failed attempts so far.
+    async fn record_proof_request_failure(&self, proof_id: ProofID, error: String) -> Result<u32>;
+    /// Move a dead-lettered proof request back to `New`, resetting its
+    /// attempts.
+    async fn requeue_dead_letter_proof_request(&self, proof_id: ProofID) -> Result<()>;
+    /// Move a proof request back to `New`, resetting its attempts, whatever
+    /// state it is in. Returns the state it was in.
+    async fn force_requeue_proof_request(&self, proof_id: ProofID) -> Result<ProofRequestState>;
+    /// Move a proof request to `Cancelled`, so that it is not processed
+    /// further. Returns the state it was in.
+    async fn cancel_proof_request(&self, proof_id: ProofID) -> Result<ProofRequestState>;
+    /// Append an action of an operator to the audit log.
+    async fn record_audit_entry(&self, entry: AuditEntry) -> Result<()>;
+    /// Up to `limit` entries of the audit log, most recent first.
+    async fn fetch_audit_log(&self, limit: u64) -> Result<Vec<AuditEntry>>;
+    /// Append an attempt at posting a proof request to its webhook to the
+    /// delivery log.
+    async fn record_webhook_delivery(&self, delivery: WebhookDelivery) -> Result<()>;
+    /// The delivery log of a proof request, oldest attempt first.
+    async fn fetch_webhook_deliveries(&self, proof_id: ProofID) -> Result<Vec<WebhookDelivery>>;
+    /// Whether a proof request was already created from the given log.
+    async fn contains_event(&self, event_id: EventID) -> Result<bool>;
+    /// Atomically reserve the given log for a new proof request, before its
+    /// Bonsai session is created. Returns false if the log is already
+    /// reserved or a proof request was created from it. The reservation is
+    /// turned into the proof request by
+    /// [Storage::add_new_bonsai_proof_request].
+    async fn claim_event(&self, event_id: EventID) -> Result<bool>;
+    /// Release the reservation of a log whose proof request could not be
+    /// created, so that it is processed again when replayed.
+    async fn release_event(&self, event_id: EventID) -> Result<()>;
+    /// The last block whose `CallbackRequest` logs were all processed.
+    async fn get_last_processed_block(&self) -> Result<Option<U64>>;
+    /// Record that all logs up to and including `block` were processed. The
+    /// stored block never moves backwards.
+    async fn advance_last_processed_block(&self, block: U64) -> Result<()>;
 }
@@ -0,0 +1,1572 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use bonsai_ethereum_contracts::i_bonsai_relay::CallbackRequestFilter;
+use bonsai_sdk::alpha::SessionId;
+use ethers::types::{Address, Bytes, H256, U256, U64};
+use rusqlite::{
+    params, params_from_iter,
+    types::{Type, Value},
+    Connection, OptionalExtension, Row, TransactionBehavior,
+};
+
+use crate::storage::{
+    AuditEntry, Error, EventID, ProofID, ProofRequestCount, ProofRequestFilter,
+    ProofRequestInformation, ProofRequestState, Storage, WebhookDelivery,
+};
+
+/// Schema migrations, applied in order. The number of applied migrations is
+/// tracked in the `user_version` pragma of the database.
+const MIGRATIONS: &[&str] = &[
+    // 1: proof requests and their state.
+    "CREATE TABLE proof_requests (
+        proof_request_id TEXT PRIMARY KEY NOT NULL,
+        state TEXT NOT NULL,
+        onchain_tx_hash BLOB,
+        account BLOB NOT NULL,
+        image_id BLOB NOT NULL,
+        input BLOB NOT NULL,
+        callback_contract BLOB NOT NULL,
+        function_selector BLOB NOT NULL,
+        gas_limit INTEGER NOT NULL
+    );
+    CREATE INDEX proof_requests_by_state ON proof_requests (state);",
+    // 2: the log each request was read from, and the chain event cursor.
+    "ALTER TABLE proof_requests ADD COLUMN event_tx_hash BLOB;
+    ALTER TABLE proof_requests ADD COLUMN event_log_index INTEGER;
+    CREATE UNIQUE INDEX proof_requests_by_event
+        ON proof_requests (event_tx_hash, event_log_index);
+    CREATE TABLE chain_cursor (
+        id INTEGER PRIMARY KEY CHECK (id = 0),
+        last_processed_block INTEGER NOT NULL
+    );",
+    // 3: the block holding the log each request was read from.
+    "ALTER TABLE proof_requests ADD COLUMN event_block_hash BLOB;",
+    // 4: failed attempts at processing each request.
+    "ALTER TABLE proof_requests ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE proof_requests ADD COLUMN last_error TEXT;",
+    // 5: the data a callback reverted with on chain.
+    "ALTER TABLE proof_requests ADD COLUMN revert_data BLOB;",
+    // 6: webhook targets, and the log of their deliveries.
+    "ALTER TABLE proof_requests ADD COLUMN webhook_url TEXT;
+    CREATE TABLE webhook_deliveries (
+        proof_request_id TEXT NOT NULL,
+        attempt INTEGER NOT NULL,
+        url TEXT NOT NULL,
+        status_code INTEGER,
+        error TEXT,
+        timestamp INTEGER NOT NULL
+    );
+    CREATE INDEX webhook_deliveries_by_request ON webhook_deliveries (proof_request_id);",
+    // 7: the actions of operators through the admin API.
+    "CREATE TABLE audit_log (
+        operator TEXT NOT NULL,
+        action TEXT NOT NULL,
+        proof_request_id TEXT,
+        outcome TEXT NOT NULL,
+        timestamp INTEGER NOT NULL
+    );",
+    // 8: logs reserved while the Bonsai session of their request is created.
+    "CREATE TABLE claimed_events (
+        event_tx_hash BLOB NOT NULL,
+        event_log_index INTEGER NOT NULL,
+        PRIMARY KEY (event_tx_hash, event_log_index)
+    );",
+];
+
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Storage persisting proof requests in a SQLite database, so that they
+/// survive a restart of the relay.
+#[derive(Debug, Clone)]
+pub(crate) struct SqliteStorage {
+    connection: Arc<Mutex<Connection>>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum SqliteStorageError {
+    #[error("SQLite error")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("SQLite task failed")]
+    Task(#[from] tokio::task::JoinError),
+    #[error("Database schema version {found} is newer than the supported version {supported}")]
+    UnsupportedSchemaVersion { found: usize, supported: usize },
+    #[error("Invalid proof state transition_proof_request")]
+    InvalidProofStateTransition {
+        proof_id: ProofID,
+        from_state: ProofRequestState,
+        new_state: ProofRequestState,
+    },
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Self {
+        Self::Sqlite(err.into())
+    }
+}
+
+impl SqliteStorage {
+    /// Open the database at the given path, creating it if needed, and bring
+    /// its schema up to date.
+    pub(crate) fn open(path: &Path) -> Result<Self, Error> {
+        let connection = Connection::open(path)?;
+        Self::from_connection(connection)
+    }
+
+    fn from_connection(mut connection: Connection) -> Result<Self, Error> {
+        connection.busy_timeout(BUSY_TIMEOUT)?;
+        migrate(&mut connection)?;
+        // Reservations left by a relay that stopped while creating a Bonsai
+        // session are released, so that their logs are processed again.
+        connection.execute("DELETE FROM claimed_events", [])?;
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    /// Run `f` on the connection in a blocking thread, so that queries and
+    /// the fsync of commits do not stall the tasks of the async runtime.
+    async fn with_connection<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&mut Connection) -> Result<T, Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        let connection = self.connection.clone();
+        tokio::task::spawn_blocking(move || f(&mut *connection.lock()?))
+            .await
+            .map_err(SqliteStorageError::from)?
+    }
+
+    async fn fetch_requests_in_state(
+        &self,
+        state: ProofRequestState,
+        limit: Option<u64>,
+    ) -> Result<Vec<ProofRequestInformation>, Error> {
+        self.with_connection(move |connection| {
+            let mut statement = connection.prepare_cached(
+                "SELECT * FROM proof_requests WHERE state = ?1 ORDER BY rowid LIMIT ?2",
+            )?;
+            let requests = statement
+                .query_map(
+                    params![state_to_sql(state).0, sql_limit(limit)],
+                    request_from_row,
+                )?
+                .collect::<Result<_, _>>()?;
+            Ok(requests)
+        })
+        .await
+    }
+}
+
+/// A negative limit returns all rows.
+fn sql_limit(limit: Option<u64>) -> i64 {
+    limit.map_or(-1, |limit| i64::try_from(limit).unwrap_or(i64::MAX))
+}
+
+/// Placeholders for the values of an `IN` list, numbered from `first`.
+fn placeholders(first: usize, count: usize) -> String {
+    (first..first + count)
+        .map(|index| format!("?{index}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The `SELECT` statement listing the requests matched by `filter`, and its
+/// parameters.
+fn list_query(
+    filter: &ProofRequestFilter,
+    after: Option<ProofID>,
+    limit: u64,
+) -> (String, Vec<Value>) {
+    let mut query = "SELECT * FROM proof_requests WHERE proof_request_id > ?1".to_string();
+    let mut values = vec![Value::Text(after.map(|id| id.uuid).unwrap_or_default())];
+
+    if let Some(state) = &filter.state {
+        values.push(Value::Text(state.clone()));
+        query += &format!(" AND state = ?{}", values.len());
+    }
+    if !filter.image_ids.is_empty() {
+        query += &format!(
+            " AND image_id IN ({})",
+            placeholders(values.len() + 1, filter.image_ids.len())
+        );
+        values.extend(
+            filter
+                .image_ids
+                .iter()
+                .map(|image_id| Value::Blob(image_id.to_vec())),
+        );
+    }
+    // Requests with a webhook are matched by their URL rather than their
+    // callback contract.
+    if !filter.callback_contracts.is_empty() || !filter.webhook_urls.is_empty() {
+        query += &format!(
+            " AND ((webhook_url IS NULL AND callback_contract IN ({})) OR webhook_url IN ({}))",
+            placeholders(values.len() + 1, filter.callback_contracts.len()),
+            placeholders(
+                values.len() + filter.callback_contracts.len() + 1,
+                filter.webhook_urls.len()
+            ),
+        );
+        values.extend(
+            filter
+                .callback_contracts
+                .iter()
+                .map(|contract| Value::Blob(contract.as_bytes().to_vec())),
+        );
+        values.extend(filter.webhook_urls.iter().cloned().map(Value::Text));
+    }
+
+    values.push(Value::Integer(sql_limit(Some(limit))));
+    query += &format!(" ORDER BY proof_request_id LIMIT ?{}", values.len());
+    (query, values)
+}
+
+fn migrate(connection: &mut Connection) -> Result<(), SqliteStorageError> {
+    let transaction = connection.transaction_with_behavior(TransactionBehavior::Exclusive)?;
+    let version: i64 = transaction.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    let version = version as usize;
+    if version > MIGRATIONS.len() {
+        return Err(SqliteStorageError::UnsupportedSchemaVersion {
+            found: version,
+            supported: MIGRATIONS.len(),
+        });
+    }
+    for migration in MIGRATIONS.iter().skip(version) {
+        transaction.execute_batch(migration)?;
+    }
+    transaction.pragma_update(None, "user_version", MIGRATIONS.len() as i64)?;
+    transaction.commit()?;
+    Ok(())
+}
+
+fn event_params(event_id: EventID) -> [Value; 2] {
+    [
+        Value::Blob(event_id.transaction_hash.as_bytes().to_vec()),
+        Value::Integer(event_id.log_index.as_u64() as i64),
+    ]
+}
+
+fn contains_event(connection: &Connection, event_id: EventID) -> rusqlite::Result<bool> {
+    connection.query_row(
+        "SELECT EXISTS (
+            SELECT 1 FROM proof_requests WHERE event_tx_hash = ?1 AND event_log_index = ?2
+        )",
+        event_params(event_id),
+        |row| row.get(0),
+    )
+}
+
+fn get_state(connection: &Connection, proof_id: ProofID) -> Result<ProofRequestState, Error> {
+    connection
+        .query_row(
+            "SELECT state, onchain_tx_hash FROM proof_requests WHERE proof_request_id = ?1",
+            [&proof_id.uuid],
+            state_from_row,
+        )
+        .optional()?
+        .ok_or(Error::ProofNotFound { id: proof_id })
+}
+
+fn transition_proof_request(
+    connection: &Connection,
+    proof_id: ProofID,
+    new_state: ProofRequestState,
+) -> Result<(), Error> {
+    let current_state = match connection
+        .query_row(
+            "SELECT state, onchain_tx_hash FROM proof_requests WHERE proof_request_id = ?1",
+            [&proof_id.uuid],
+            state_from_row,
+        )
+        .optional()?
+    {
+        Some(state) => state,
+        None => return Err(Error::ProofNotFound { id: proof_id }),
+    };
+
+    if !current_state.is_valid_state_transition(new_state) {
+        return Err(SqliteStorageError::InvalidProofStateTransition {
+            proof_id,
+            from_state: current_state,
+            new_state,
+        })?;
+    }
+
+    let (state, onchain_tx_hash) = state_to_sql(new_state);
+    connection.execute(
+        "UPDATE proof_requests SET state = ?1, onchain_tx_hash = ?2 WHERE proof_request_id = ?3",
+        params![state, onchain_tx_hash, proof_id.uuid],
+    )?;
+
+    Ok(())
+}
+
+fn state_to_sql(state: ProofRequestState) -> (&'static str, Option<Vec<u8>>) {
+    (
+        state.name(),
+        state.tx_hash().map(|tx_hash| tx_hash.as_bytes().to_vec()),
+    )
+}
+
+fn state_from_row(row: &Row) -> rusqlite::Result<ProofRequestState> {
+    let state: String = row.get("state")?;
+    match state.as_str() {
+        "new" => Ok(ProofRequestState::New),
+        "pending" => Ok(ProofRequestState::Pending),
+        "completed" => Ok(ProofRequestState::Completed),
+        "failed" => Ok(ProofRequestState::Failed),
+        "preparing_onchain" => Ok(ProofRequestState::PreparingOnchain),
+        "completed_onchain" => {
+            let tx_hash: [u8; 32] = fixed_bytes(row, "onchain_tx_hash")?;
+            Ok(ProofRequestState::CompletedOnchain(H256(tx_hash)))
+        }
+        "callback_reverted" => {
+            let tx_hash: [u8; 32] = fixed_bytes(row, "onchain_tx_hash")?;
+            Ok(ProofRequestState::CallbackReverted(H256(tx_hash)))
+        }
+        "delivered" => Ok(ProofRequestState::Delivered),
+        "quarantined" => Ok(ProofRequestState::Quarantined),
+        "dead_letter" => Ok(ProofRequestState::DeadLetter),
+        "cancelled" => Ok(ProofRequestState::Cancelled),
+        _ => Err(rusqlite::Error::InvalidColumnType(
+            row.as_ref().column_index("state")?,
+            "state".to_string(),
+            Type::Text,
+        )),
+    }
+}
+
+fn invalid_blob(row: &Row, column: &str) -> rusqlite::Error {
+    rusqlite::Error::InvalidColumnType(
+        row.as_ref().column_index(column).unwrap_or_default(),
+        column.to_string(),
+        Type::Blob,
+    )
+}
+
+fn optional_fixed_bytes<const N: usize>(
+    row: &Row,
+    column: &str,
+) -> rusqlite::Result<Option<[u8; N]>> {
+    let bytes: Option<Vec<u8>> = row.get(column)?;
+    bytes
+        .map(|bytes| bytes.try_into().map_err(|_| invalid_blob(row, column)))
+        .transpose()
+}
+
+fn fixed_bytes<const N: usize>(row: &Row, column: &str) -> rusqlite::Result<[u8; N]> {
+    optional_fixed_bytes(row, column)?.ok_or_else(|| invalid_blob(row, column))
+}
+
+fn event_id_from_row(row: &Row) -> rusqlite::Result<Option<EventID>> {
+    let log_index: Option<i64> = row.get("event_log_index")?;
+    match log_index {
+        Some(log_index) => Ok(Some(EventID {
+            transaction_hash: H256(fixed_bytes(row, "event_tx_hash")?),
+            log_index: U256::from(log_index),
+        })),
+        None => Ok(None),
+    }
+}
+
+fn request_from_row(row: &Row) -> rusqlite::Result<ProofRequestInformation> {
+    let gas_limit: i64 = row.get("gas_limit")?;
+    Ok(ProofRequestInformation {
+        proof_request_id: SessionId::new(row.get("proof_request_id")?),
+        callback_proof_request_event: CallbackRequestFilter {
+            account: Address::from(fixed_bytes::<20>(row, "account")?),
+            image_id: fixed_bytes(row, "image_id")?,
+            input: row.get::<_, Vec<u8>>("input")?.into(),
+            callback_contract: Address::from(fixed_bytes::<20>(row, "callback_contract")?),
+            function_selector: fixed_bytes(row, "function_selector")?,
+            gas_limit: gas_limit as u64,
+        },
+        source_event: event_id_from_row(row)?,
+        block_hash: optional_fixed_bytes(row, "event_block_hash")?.map(H256),
+        attempts: row.get("attempts")?,
+        last_error: row.get("last_error")?,
+        revert_data: row
+            .get::<_, Option<Vec<u8>>>("revert_data")?
+            .map(Bytes::from),
+        webhook_url: row.get("webhook_url")?,
+    })
+}
+
+fn audit_entry_from_row(row: &Row) -> rusqlite::Result<AuditEntry> {
+    let timestamp: i64 = row.get("timestamp")?;
+    Ok(AuditEntry {
+        operator: row.get("operator")?,
+        action: row.get("action")?,
+        proof_request_id: row
+            .get::<_, Option<String>>("proof_request_id")?
+            .map(SessionId::new),
+        outcome: row.get("outcome")?,
+        timestamp: timestamp as u64,
+    })
+}
+
+fn delivery_from_row(row: &Row) -> rusqlite::Result<WebhookDelivery> {
+    let timestamp: i64 = row.get("timestamp")?;
+    Ok(WebhookDelivery {
+        proof_request_id: SessionId::new(row.get("proof_request_id")?),
+        url: row.get("url")?,
+        attempt: row.get("attempt")?,
+        status_code: row.get("status_code")?,
+        error: row.get("error")?,
+        timestamp: timestamp as u64,
+    })
+}
+
+#[async_trait::async_trait]
+impl Storage for SqliteStorage {
+    async fn add_new_bonsai_proof_request(
+        &self,
+        proof: ProofRequestInformation,
+    ) -> Result<(), Error> {
+        self.with_connection(move |connection| {
+            let transaction =
+                connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+            if let Some(event_id) = proof.source_event {
+                if contains_event(&transaction, event_id)? {
+                    return Err(Error::DuplicateEvent { id: event_id });
+                }
+            }
+
+            if let Some(event_id) = proof.source_event {
+                transaction.execute(
+                    "DELETE FROM claimed_events WHERE event_tx_hash = ?1 AND event_log_index = ?2",
+                    event_params(event_id),
+                )?;
+            }
+
+            let event = proof.callback_proof_request_event;
+            transaction.execute(
+                "INSERT INTO proof_requests (
+                    proof_request_id, state, account, image_id, input, callback_contract,
+                    function_selector, gas_limit, event_tx_hash, event_log_index,
+                    event_block_hash, webhook_url
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+                params![
+                    proof.proof_request_id.uuid,
+                    state_to_sql(ProofRequestState::New).0,
+                    event.account.as_bytes(),
+                    event.image_id.as_slice(),
+                    event.input.as_ref(),
+                    event.callback_contract.as_bytes(),
+                    event.function_selector.as_slice(),
+                    event.gas_limit as i64,
+                    proof
+                        .source_event
+                        .map(|event_id| event_id.transaction_hash.as_bytes().to_vec()),
+                    proof
+                        .source_event
+                        .map(|event_id| event_id.log_index.as_u64() as i64),
+                    proof
+                        .block_hash
+                        .map(|block_hash| block_hash.as_bytes().to_vec()),
+                    proof.webhook_url,
+                ],
+            )?;
+            transaction.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn fetch_new_bonsai_requests(
+        &self,
+        limit: Option<u64>,
+    ) -> Result<Vec<ProofRequestInformation>, Error> {
+        self.fetch_requests_in_state(ProofRequestState::New, limit)
+            .await
+    }
+
+    async fn fetch_pending_bonsai_requests(
+        &self,
+        limit: Option<u64>,
+    ) -> Result<Vec<ProofRequestInformation>, Error> {
+        self.fetch_requests_in_state(ProofRequestState::Pending, limit)
+            .await
+    }
+
+    async fn fetch_completed_bonsai_requests(
+        &self,
+        limit: Option<u64>,
+    ) -> Result<Vec<ProofRequestInformation>, Error> {
+        self.fetch_requests_in_state(ProofRequestState::Completed, limit)
+            .await
+    }
+
+    async fn fetch_failed_bonsai_requests(
+        &self,
+        limit: Option<u64>,
+    ) -> Result<Vec<ProofRequestInformation>, Error> {
+        self.fetch_requests_in_state(ProofRequestState::Failed, limit)
+            .await
+    }
+
+    async fn fetch_dead_letter_proof_requests(
+        &self,
+        limit: Option<u64>,
+    ) -> Result<Vec<ProofRequestInformation>, Error> {
+        self.fetch_requests_in_state(ProofRequestState::DeadLetter, limit)
+            .await
+    }
+
+    async fn fetch_preparing_onchain_proof_requests(
+        &self,
+        limit: Option<u64>,
+    ) -> Result<Vec<ProofRequestInformation>, Error> {
+        self.fetch_requests_in_state(ProofRequestState::PreparingOnchain, limit)
+            .await
+    }
+
+    async fn get_proof_request_state(&self, proof_id: ProofID) -> Result<ProofRequestState, Error> {
+        self.with_connection(move |connection| get_state(connection, proof_id))
+            .await
+    }
+
+    async fn transition_proof_request(
+        &self,
+        proof_id: ProofID,
+        new_state: ProofRequestState,
+    ) -> Result<(), Error> {
+        self.with_connection(move |connection| {
+            let transaction =
+                connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            transition_proof_request(&transaction, proof_id, new_state)?;
+            transaction.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_proof_request(&self, proof_id: ProofID) -> Result<ProofRequestInformation, Error> {
+        self.with_connection(move |connection| {
+            connection
+                .query_row(
+                    "SELECT * FROM proof_requests WHERE proof_request_id = ?1",
+                    [&proof_id.uuid],
+                    request_from_row,
+                )
+                .optional()?
+                .ok_or(Error::ProofNotFound { id: proof_id })
+        })
+        .await
+    }
+
+    async fn list_proof_requests(
+        &self,
+        filter: &ProofRequestFilter,
+        after: Option<ProofID>,
+        limit: u64,
+    ) -> Result<Vec<(ProofRequestInformation, ProofRequestState)>, Error> {
+        let (query, values) = list_query(filter, after, limit);
+        self.with_connection(move |connection| {
+            let mut statement = connection.prepare(&query)?;
+            let requests = statement
+                .query_map(params_from_iter(values), |row| {
+                    Ok((request_from_row(row)?, state_from_row(row)?))
+                })?
+                .collect::<Result<_, _>>()?;
+            Ok(requests)
+        })
+        .await
+    }
+
+    async fn count_proof_requests(&self) -> Result<Vec<ProofRequestCount>, Error> {
+        self.with_connection(|connection| {
+            let mut statement = connection.prepare_cached(
+                "SELECT image_id, state, COUNT(*) AS count FROM proof_requests
+                GROUP BY image_id, state
+                ORDER BY image_id, state",
+            )?;
+            let counts: Vec<ProofRequestCount> = statement
+                .query_map([], |row| {
+                    let count: i64 = row.get("count")?;
+                    Ok(ProofRequestCount {
+                        image_id: fixed_bytes(row, "image_id")?,
+                        state: row.get("state")?,
+                        count: count as u64,
+                    })
+                })?
+                .collect::<rusqlite::Result<_>>()?;
+            Ok(counts)
+        })
+        .await
+    }
+
+    async fn fetch_callback_reverted_proof_requests(
+        &self,
+        limit: Option<u64>,
+    ) -> Result<Vec<ProofRequestInformation>, Error> {
+        // Only the name of the state is matched, not its transaction hash.
+        self.fetch_requests_in_state(ProofRequestState::CallbackReverted(H256::zero()), limit)
+            .await
+    }
+
+    async fn fetch_quarantined_proof_requests(
+        &self,
+        limit: Option<u64>,
+    ) -> Result<Vec<ProofRequestInformation>, Error> {
+        self.fetch_requests_in_state(ProofRequestState::Quarantined, limit)
+            .await
+    }
+
+    async fn quarantine_proof_request(
+        &self,
+        proof_id: ProofID,
+        revert_data: Bytes,
+        reason: String,
+    ) -> Result<u32, Error> {
+        self.with_connection(move |connection| {
+            let transaction =
+                connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            transition_proof_request(
+                &transaction,
+                proof_id.clone(),
+                ProofRequestState::Quarantined,
+            )?;
+            let attempts = transaction.query_row(
+                "UPDATE proof_requests
+                SET revert_data = ?1, attempts = attempts + 1, last_error = ?2
+                WHERE proof_request_id = ?3 RETURNING attempts",
+                params![revert_data.as_ref(), reason, proof_id.uuid],
+                |row| row.get(0),
+            )?;
+            transaction.commit()?;
+            Ok(attempts)
+        })
+        .await
+    }
+
+    async fn mark_callback_reverted(
+        &self,
+        proof_id: ProofID,
+        tx_hash: H256,
+        revert_data: Bytes,
+    ) -> Result<(), Error> {
+        self.with_connection(move |connection| {
+            let transaction =
+                connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            transition_proof_request(
+                &transaction,
+                proof_id.clone(),
+                ProofRequestState::CallbackReverted(tx_hash),
+            )?;
+            transaction.execute(
+                "UPDATE proof_requests SET revert_data = ?1 WHERE proof_request_id = ?2",
+                params![revert_data.as_ref(), proof_id.uuid],
+            )?;
+            transaction.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn record_proof_request_failure(
+        &self,
+        proof_id: ProofID,
+        error: String,
+    ) -> Result<u32, Error> {
+        self.with_connection(move |connection| {
+            connection
+                .query_row(
+                    "UPDATE proof_requests SET attempts = attempts + 1, last_error = ?1
+                    WHERE proof_request_id = ?2 RETURNING attempts",
+                    params![error, proof_id.uuid],
+                    |row| row.get(0),
+                )
+                .optional()?
+                .ok_or(Error::ProofNotFound { id: proof_id })
+        })
+        .await
+    }
+
+    async fn requeue_dead_letter_proof_request(&self, proof_id: ProofID) -> Result<(), Error> {
+        self.with_connection(move |connection| {
+            let transaction =
+                connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            let current_state = get_state(&transaction, proof_id.clone())?;
+            if current_state != ProofRequestState::DeadLetter {
+                return Err(Error::NotDeadLetter {
+                    id: proof_id,
+                    state: current_state,
+                });
+            }
+
+            transaction.execute(
+                "UPDATE proof_requests SET state = ?1, attempts = 0, last_error = NULL
+                WHERE proof_request_id = ?2",
+                params![state_to_sql(ProofRequestState::New).0, proof_id.uuid],
+            )?;
+            transaction.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn force_requeue_proof_request(
+        &self,
+        proof_id: ProofID,
+    ) -> Result<ProofRequestState, Error> {
+        self.with_connection(move |connection| {
+            let transaction =
+                connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            let current_state = get_state(&transaction, proof_id.clone())?;
+            if !current_state.can_force_requeue() {
+                return Err(Error::InvalidAdminAction {
+                    id: proof_id,
+                    state: current_state,
+                    action: "requeued",
+                });
+            }
+
+            transaction.execute(
+                "UPDATE proof_requests SET state = ?1, onchain_tx_hash = NULL, attempts = 0,
+                    last_error = NULL, revert_data = NULL
+                WHERE proof_request_id = ?2",
+                params![state_to_sql(ProofRequestState::New).0, proof_id.uuid],
+            )?;
+            transaction.commit()?;
+            Ok(current_state)
+        })
+        .await
+    }
+
+    async fn cancel_proof_request(&self, proof_id: ProofID) -> Result<ProofRequestState, Error> {
+        self.with_connection(move |connection| {
+            let transaction =
+                connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            let current_state = get_state(&transaction, proof_id.clone())?;
+            if !current_state.can_cancel() {
+                return Err(Error::InvalidAdminAction {
+                    id: proof_id,
+                    state: current_state,
+                    action: "cancelled",
+                });
+            }
+
+            let (state, onchain_tx_hash) = state_to_sql(ProofRequestState::Cancelled);
+            transaction.execute(
+                "UPDATE proof_requests SET state = ?1, onchain_tx_hash = ?2
+                WHERE proof_request_id = ?3",
+                params![state, onchain_tx_hash, proof_id.uuid],
+            )?;
+            transaction.commit()?;
+            Ok(current_state)
+        })
+        .await
+    }
+
+    async fn record_audit_entry(&self, entry: AuditEntry) -> Result<(), Error> {
+        self.with_connection(move |connection| {
+            connection.execute(
+                "INSERT INTO audit_log (operator, action, proof_request_id, outcome, timestamp)
+                VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    entry.operator,
+                    entry.action,
+                    entry.proof_request_id.map(|id| id.uuid),
+                    entry.outcome,
+                    entry.timestamp as i64,
+                ],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn fetch_audit_log(&self, limit: u64) -> Result<Vec<AuditEntry>, Error> {
+        self.with_connection(move |connection| {
+            let mut statement = connection
+                .prepare_cached("SELECT * FROM audit_log ORDER BY rowid DESC LIMIT ?1")?;
+            let entries = statement
+                .query_map([sql_limit(Some(limit))], audit_entry_from_row)?
+                .collect::<Result<_, _>>()?;
+            Ok(entries)
+        })
+        .await
+    }
+
+    async fn record_webhook_delivery(&self, delivery: WebhookDelivery) -> Result<(), Error> {
+        self.with_connection(move |connection| {
+            connection.execute(
+                "INSERT INTO webhook_deliveries (
+                    proof_request_id, attempt, url, status_code, error, timestamp
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    delivery.proof_request_id.uuid,
+                    delivery.attempt,
+                    delivery.url,
+                    delivery.status_code,
+                    delivery.error,
+                    delivery.timestamp as i64,
+                ],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn fetch_webhook_deliveries(
+        &self,
+        proof_id: ProofID,
+    ) -> Result<Vec<WebhookDelivery>, Error> {
+        self.with_connection(move |connection| {
+            let mut statement = connection.prepare_cached(
+                "SELECT * FROM webhook_deliveries WHERE proof_request_id = ?1 ORDER BY rowid",
+            )?;
+            let deliveries = statement
+                .query_map([proof_id.uuid], delivery_from_row)?
+                .collect::<Result<_, _>>()?;
+            Ok(deliveries)
+        })
+        .await
+    }
+
+    async fn contains_event(&self, event_id: EventID) -> Result<bool, Error> {
+        self.with_connection(move |connection| Ok(contains_event(connection, event_id)?))
+            .await
+    }
+
+    async fn claim_event(&self, event_id: EventID) -> Result<bool, Error> {
+        self.with_connection(move |connection| {
+            let transaction =
+                connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            if contains_event(&transaction, event_id)? {
+                return Ok(false);
+            }
+            let claimed = transaction.execute(
+                "INSERT OR IGNORE INTO claimed_events (event_tx_hash, event_log_index)
+                VALUES (?1, ?2)",
+                event_params(event_id),
+            )? == 1;
+            transaction.commit()?;
+            Ok(claimed)
+        })
+        .await
+    }
+
+    async fn release_event(&self, event_id: EventID) -> Result<(), Error> {
+        self.with_connection(move |connection| {
+            connection.execute(
+                "DELETE FROM claimed_events WHERE event_tx_hash = ?1 AND event_log_index = ?2",
+                event_params(event_id),
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_last_processed_block(&self) -> Result<Option<U64>, Error> {
+        self.with_connection(|connection| {
+            let block: Option<i64> = connection
+                .query_row(
+                    "SELECT last_processed_block FROM chain_cursor WHERE id = 0",
+                    [],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            Ok(block.map(|block| U64::from(block as u64)))
+        })
+        .await
+    }
+
+    async fn advance_last_processed_block(&self, block: U64) -> Result<(), Error> {
+        self.with_connection(move |connection| {
+            connection.execute(
+                "INSERT INTO chain_cursor (id, last_processed_block) VALUES (0, ?1)
+                ON CONFLICT (id) DO UPDATE SET last_processed_block =
+                    MAX(last_processed_block, excluded.last_processed_block)",
+                [block.as_u64() as i64],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_request(id: &str) -> ProofRequestInformation {
+        ProofRequestInformation {
+            proof_request_id: SessionId::new(id.to_string()),
+            callback_proof_request_event: CallbackRequestFilter {
+                account: Address::repeat_byte(1),
+                image_id: [2; 32],
+                input: Bytes::from(vec![3, 4, 5]),
+                callback_contract: Address::repeat_byte(6),
+                function_selector: [0xab, 0xcd, 0xef, 0xab],
+                gas_limit: 3000000,
+            },
+            source_event: None,
+            block_hash: None,
+            attempts: 0,
+            last_error: None,
+            revert_data: None,
+            webhook_url: None,
+        }
+    }
+
+    fn open_in_memory() -> SqliteStorage {
+        SqliteStorage::from_connection(Connection::open_in_memory().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_requests_round_trip() {
+        let storage = open_in_memory();
+        let request = test_request("a");
+        storage
+            .add_new_bonsai_proof_request(request.clone())
+            .await
+            .unwrap();
+
+        let fetched = storage.fetch_new_bonsai_requests(None).await.unwrap();
+        assert_eq!(fetched.len(), 1);
+        assert_eq!(fetched[0].proof_request_id, request.proof_request_id);
+        assert_eq!(
+            fetched[0].callback_proof_request_event,
+            request.callback_proof_request_event
+        );
+    }
+
+    #[tokio::test]
+    async fn test_transitions_are_validated() {
+        let storage = open_in_memory();
+        let id = SessionId::new("a".to_string());
+        storage
+            .add_new_bonsai_proof_request(test_request("a"))
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            storage
+                .transition_proof_request(id.clone(), ProofRequestState::Completed)
+                .await,
+            Err(Error::Sqlite(
+                SqliteStorageError::InvalidProofStateTransition { .. }
+            ))
+        ));
+        assert_eq!(
+            storage.get_proof_request_state(id.clone()).await.unwrap(),
+            ProofRequestState::New
+        );
+
+        let tx_hash = H256::repeat_byte(7);
+        for state in [
+            ProofRequestState::Pending,
+            ProofRequestState::Completed,
+            ProofRequestState::PreparingOnchain,
+            ProofRequestState::CompletedOnchain(tx_hash),
+        ] {
+            storage
+                .transition_proof_request(id.clone(), state)
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            storage.get_proof_request_state(id).await.unwrap(),
+            ProofRequestState::CompletedOnchain(tx_hash)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_state_survives_reopen() {
+        let dir = std::env::temp_dir().join("bonsai-relay-sqlite-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{}.db", uuid::Uuid::new_v4()));
+        let id = SessionId::new("a".to_string());
+
+        {
+            let storage = SqliteStorage::open(&path).unwrap();
+            storage
+                .add_new_bonsai_proof_request(test_request("a"))
+                .await
+                .unwrap();
+            storage
+                .transition_proof_request(id.clone(), ProofRequestState::Pending)
+                .await
+                .unwrap();
+        }
+
+        // Reopening applies no migrations and keeps the stored state.
+        let storage = SqliteStorage::open(&path).unwrap();
+        let pending = storage.fetch_pending_bonsai_requests(None).await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].proof_request_id, id);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_events_are_rejected() {
+        let storage = open_in_memory();
+        let event_id = EventID {
+            transaction_hash: H256::repeat_byte(1),
+            log_index: U256::from(2),
+        };
+        assert!(!storage.contains_event(event_id).await.unwrap());
+
+        storage
+            .add_new_bonsai_proof_request(ProofRequestInformation {
+                source_event: Some(event_id),
+                block_hash: Some(H256::repeat_byte(3)),
+                ..test_request("a")
+            })
+            .await
+            .unwrap();
+        assert!(storage.contains_event(event_id).await.unwrap());
+
+        assert!(matches!(
+            storage
+                .add_new_bonsai_proof_request(ProofRequestInformation {
+                    source_event: Some(event_id),
+                    ..test_request("b")
+                })
+                .await,
+            Err(Error::DuplicateEvent { .. })
+        ));
+        let stored = &storage.fetch_new_bonsai_requests(None).await.unwrap()[0];
+        assert_eq!(stored.source_event, Some(event_id));
+        assert_eq!(stored.block_hash, Some(H256::repeat_byte(3)));
+    }
+
+    #[tokio::test]
+    async fn test_events_are_claimed_once() {
+        let storage = open_in_memory();
+        let event_id = EventID {
+            transaction_hash: H256::repeat_byte(1),
+            log_index: U256::from(2),
+        };
+        assert!(storage.claim_event(event_id).await.unwrap());
+        assert!(!storage.claim_event(event_id).await.unwrap());
+
+        // A released claim can be taken again.
+        storage.release_event(event_id).await.unwrap();
+        assert!(storage.claim_event(event_id).await.unwrap());
+
+        // The request created from a claimed log keeps it claimed.
+        storage
+            .add_new_bonsai_proof_request(ProofRequestInformation {
+                source_event: Some(event_id),
+                ..test_request("a")
+            })
+            .await
+            .unwrap();
+        storage.release_event(event_id).await.unwrap();
+        assert!(!storage.claim_event(event_id).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_claims_are_released_on_reopen() {
+        let dir = std::env::temp_dir().join("bonsai-relay-sqlite-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{}.db", uuid::Uuid::new_v4()));
+        let event_id = EventID {
+            transaction_hash: H256::repeat_byte(1),
+            log_index: U256::from(2),
+        };
+
+        let storage = SqliteStorage::open(&path).unwrap();
+        assert!(storage.claim_event(event_id).await.unwrap());
+        drop(storage);
+
+        let storage = SqliteStorage::open(&path).unwrap();
+        assert!(storage.claim_event(event_id).await.unwrap());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_last_processed_block_never_moves_backwards() {
+        let storage = open_in_memory();
+        assert_eq!(storage.get_last_processed_block().await.unwrap(), None);
+
+        storage
+            .advance_last_processed_block(U64::from(10))
+            .await
+            .unwrap();
+        storage
+            .advance_last_processed_block(U64::from(5))
+            .await
+            .unwrap();
+        assert_eq!(
+            storage.get_last_processed_block().await.unwrap(),
+            Some(U64::from(10))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dead_letter_requeue() {
+        let storage = open_in_memory();
+        let id = SessionId::new("a".to_string());
+        storage
+            .add_new_bonsai_proof_request(test_request("a"))
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            storage.requeue_dead_letter_proof_request(id.clone()).await,
+            Err(Error::NotDeadLetter { .. })
+        ));
+
+        for state in [ProofRequestState::Pending, ProofRequestState::Failed] {
+            storage
+                .transition_proof_request(id.clone(), state)
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            storage
+                .record_proof_request_failure(id.clone(), "first".to_string())
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            storage
+                .record_proof_request_failure(id.clone(), "second".to_string())
+                .await
+                .unwrap(),
+            2
+        );
+        storage
+            .transition_proof_request(id.clone(), ProofRequestState::DeadLetter)
+            .await
+            .unwrap();
+
+        let dead_letters = storage
+            .fetch_dead_letter_proof_requests(None)
+            .await
+            .unwrap();
+        assert_eq!(dead_letters.len(), 1);
+        assert_eq!(dead_letters[0].attempts, 2);
+        assert_eq!(dead_letters[0].last_error.as_deref(), Some("second"));
+
+        storage
+            .requeue_dead_letter_proof_request(id.clone())
+            .await
+            .unwrap();
+        let requeued = storage.fetch_new_bonsai_requests(None).await.unwrap();
+        assert_eq!(requeued.len(), 1);
+        assert_eq!(requeued[0].attempts, 0);
+        assert_eq!(requeued[0].last_error, None);
+    }
+
+    #[tokio::test]
+    async fn test_callback_revert_is_kept() {
+        let storage = open_in_memory();
+        let id = SessionId::new("a".to_string());
+        storage
+            .add_new_bonsai_proof_request(test_request("a"))
+            .await
+            .unwrap();
+        for state in [
+            ProofRequestState::Pending,
+            ProofRequestState::Completed,
+            ProofRequestState::PreparingOnchain,
+        ] {
+            storage
+                .transition_proof_request(id.clone(), state)
+                .await
+                .unwrap();
+        }
+
+        let tx_hash = H256::repeat_byte(7);
+        let revert_data = Bytes::from(vec![0x08, 0xc3, 0x79, 0xa0]);
+        storage
+            .mark_callback_reverted(id.clone(), tx_hash, revert_data.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            storage.get_proof_request_state(id.clone()).await.unwrap(),
+            ProofRequestState::CallbackReverted(tx_hash)
+        );
+        let request = storage.get_proof_request(id).await.unwrap();
+        assert_eq!(request.revert_data, Some(revert_data));
+        assert_eq!(
+            storage
+                .fetch_callback_reverted_proof_requests(None)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_quarantine_keeps_reason() {
+        let storage = open_in_memory();
+        let id = SessionId::new("a".to_string());
+        storage
+            .add_new_bonsai_proof_request(test_request("a"))
+            .await
+            .unwrap();
+        for state in [
+            ProofRequestState::Pending,
+            ProofRequestState::Completed,
+            ProofRequestState::PreparingOnchain,
+        ] {
+            storage
+                .transition_proof_request(id.clone(), state)
+                .await
+                .unwrap();
+        }
+
+        let revert_data = Bytes::from(vec![0xde, 0xad, 0xbe, 0xef]);
+        let attempts = storage
+            .quarantine_proof_request(id.clone(), revert_data.clone(), "not allowed".to_string())
+            .await
+            .unwrap();
+        assert_eq!(attempts, 1);
+
+        let quarantined = storage
+            .fetch_quarantined_proof_requests(None)
+            .await
+            .unwrap();
+        assert_eq!(quarantined.len(), 1);
+        assert_eq!(quarantined[0].revert_data, Some(revert_data));
+        assert_eq!(quarantined[0].last_error.as_deref(), Some("not allowed"));
+
+        // A retried request is simulated again from the completed state.
+        storage
+            .transition_proof_request(id.clone(), ProofRequestState::Completed)
+            .await
+            .unwrap();
+        assert_eq!(
+            storage.get_proof_request_state(id).await.unwrap(),
+            ProofRequestState::Completed
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_proof_requests() {
+        let storage = open_in_memory();
+        for id in ["a", "b", "c", "d"] {
+            storage
+                .add_new_bonsai_proof_request(test_request(id))
+                .await
+                .unwrap();
+        }
+        storage
+            .transition_proof_request(SessionId::new("c".to_string()), ProofRequestState::Pending)
+            .await
+            .unwrap();
+
+        let all = ProofRequestFilter::default();
+        let first_page = storage.list_proof_requests(&all, None, 3).await.unwrap();
+        let ids: Vec<&str> = first_page
+            .iter()
+            .map(|(request, _)| request.proof_request_id.uuid.as_str())
+            .collect();
+        assert_eq!(ids, vec!["a", "b", "c"]);
+        assert_eq!(first_page[2].1, ProofRequestState::Pending);
+        let second_page = storage
+            .list_proof_requests(&all, Some(SessionId::new("c".to_string())), 3)
+            .await
+            .unwrap();
+        assert_eq!(second_page.len(), 1);
+        assert_eq!(second_page[0].0.proof_request_id.uuid, "d");
+
+        let new = ProofRequestFilter {
+            state: Some("new".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            storage
+                .list_proof_requests(&new, None, 10)
+                .await
+                .unwrap()
+                .len(),
+            3
+        );
+        let other_contract = ProofRequestFilter {
+            callback_contracts: vec![Address::repeat_byte(0xff)],
+            ..Default::default()
+        };
+        assert!(storage
+            .list_proof_requests(&other_contract, None, 10)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_list_proof_requests_filters_before_the_limit() {
+        let storage = open_in_memory();
+        let mut requests = Vec::new();
+        for id in ["a", "b", "c"] {
+            requests.push(test_request(id));
+        }
+        let mut other_image = test_request("d");
+        other_image.callback_proof_request_event.image_id = [7; 32];
+        requests.push(other_image);
+        let mut webhook = test_request("e");
+        webhook.callback_proof_request_event.image_id = [7; 32];
+        webhook.webhook_url = Some("https://example.com/proofs".to_string());
+        requests.push(webhook);
+        for request in requests {
+            storage.add_new_bonsai_proof_request(request).await.unwrap();
+        }
+
+        let ids = |page: Vec<(ProofRequestInformation, ProofRequestState)>| {
+            page.into_iter()
+                .map(|(request, _)| request.proof_request_id.uuid)
+                .collect::<Vec<_>>()
+        };
+
+        // The limit applies to the matching requests, not to the scanned ones.
+        let image = ProofRequestFilter {
+            image_ids: vec![[7; 32]],
+            ..Default::default()
+        };
+        assert_eq!(
+            ids(storage.list_proof_requests(&image, None, 1).await.unwrap()),
+            vec!["d"]
+        );
+        assert_eq!(
+            ids(storage
+                .list_proof_requests(&image, Some(SessionId::new("d".to_string())), 1)
+                .await
+                .unwrap()),
+            vec!["e"]
+        );
+
+        // Requests with a webhook are matched by URL, not by contract.
+        let contract = ProofRequestFilter {
+            callback_contracts: vec![Address::repeat_byte(6)],
+            ..Default::default()
+        };
+        assert_eq!(
+            ids(storage
+                .list_proof_requests(&contract, None, 10)
+                .await
+                .unwrap()),
+            vec!["a", "b", "c", "d"]
+        );
+        let url = ProofRequestFilter {
+            webhook_urls: vec!["https://example.com/proofs".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            ids(storage.list_proof_requests(&url, None, 10).await.unwrap()),
+            vec!["e"]
+        );
+        let both = ProofRequestFilter {
+            state: Some("new".to_string()),
+            image_ids: vec![[2; 32], [7; 32]],
+            callback_contracts: vec![Address::repeat_byte(6)],
+            webhook_urls: vec!["https://example.com/proofs".to_string()],
+        };
+        assert_eq!(
+            ids(storage.list_proof_requests(&both, None, 10).await.unwrap()),
+            vec!["a", "b", "c", "d", "e"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_count_proof_requests() {
+        let storage = open_in_memory();
+        let mut other_image = test_request("c");
+        other_image.callback_proof_request_event.image_id = [1; 32];
+        for request in [test_request("a"), test_request("b"), other_image] {
+            storage.add_new_bonsai_proof_request(request).await.unwrap();
+        }
+        storage
+            .transition_proof_request(SessionId::new("b".to_string()), ProofRequestState::Pending)
+            .await
+            .unwrap();
+
+        let count = |image_id, state: &str, count| ProofRequestCount {
+            image_id,
+            state: state.to_string(),
+            count,
+        };
+        assert_eq!(
+            storage.count_proof_requests().await.unwrap(),
+            vec![
+                count([1; 32], "new", 1),
+                count([2; 32], "new", 1),
+                count([2; 32], "pending", 1),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_webhook_deliveries_are_logged() {
+        let storage = open_in_memory();
+        let id = SessionId::new("webhook".to_string());
+        let url = "http://localhost:8080/proofs".to_string();
+        storage
+            .add_new_bonsai_proof_request(ProofRequestInformation {
+                webhook_url: Some(url.clone()),
+                ..test_request(&id.uuid)
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            storage
+                .get_proof_request(id.clone())
+                .await
+                .unwrap()
+                .webhook_url,
+            Some(url.clone())
+        );
+
+        for (attempt, status_code) in [(1, Some(503)), (2, None), (3, Some(204))] {
+            storage
+                .record_webhook_delivery(WebhookDelivery {
+                    proof_request_id: id.clone(),
+                    url: url.clone(),
+                    attempt,
+                    status_code,
+                    error: None,
+                    timestamp: 1_700_000_000 + attempt as u64,
+                })
+                .await
+                .unwrap();
+        }
+
+        let deliveries = storage.fetch_webhook_deliveries(id).await.unwrap();
+        let status_codes: Vec<Option<u16>> = deliveries
+            .iter()
+            .map(|delivery| delivery.status_code)
+            .collect();
+        assert_eq!(status_codes, vec![Some(503), None, Some(204)]);
+        assert_eq!(deliveries[2].attempt, 3);
+        assert!(storage
+            .fetch_webhook_deliveries(SessionId::new("other".to_string()))
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_operators_requeue_and_cancel_requests() {
+        let storage = open_in_memory();
+        let id = SessionId::new("a".to_string());
+        storage
+            .add_new_bonsai_proof_request(test_request("a"))
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            storage.force_requeue_proof_request(id.clone()).await,
+            Err(Error::InvalidAdminAction {
+                state: ProofRequestState::New,
+                ..
+            })
+        ));
+        for state in [ProofRequestState::Pending, ProofRequestState::Failed] {
+            storage
+                .transition_proof_request(id.clone(), state)
+                .await
+                .unwrap();
+        }
+        storage
+            .record_proof_request_failure(id.clone(), "failed".to_string())
+            .await
+            .unwrap();
+        assert_eq!(
+            storage
+                .force_requeue_proof_request(id.clone())
+                .await
+                .unwrap(),
+            ProofRequestState::Failed
+        );
+        let requeued = storage.get_proof_request(id.clone()).await.unwrap();
+        assert_eq!(requeued.attempts, 0);
+        assert_eq!(requeued.last_error, None);
+
+        assert_eq!(
+            storage.cancel_proof_request(id.clone()).await.unwrap(),
+            ProofRequestState::New
+        );
+        assert_eq!(
+            storage.get_proof_request_state(id.clone()).await.unwrap(),
+            ProofRequestState::Cancelled
+        );
+        assert!(matches!(
+            storage.cancel_proof_request(id.clone()).await,
+            Err(Error::InvalidAdminAction { .. })
+        ));
+        // A cancelled request can still be brought back.
+        assert_eq!(
+            storage
+                .force_requeue_proof_request(id.clone())
+                .await
+                .unwrap(),
+            ProofRequestState::Cancelled
+        );
+
+        for state in [
+            ProofRequestState::Pending,
+            ProofRequestState::Completed,
+            ProofRequestState::PreparingOnchain,
+        ] {
+            storage
+                .transition_proof_request(id.clone(), state)
+                .await
+                .unwrap();
+        }
+        assert!(matches!(
+            storage.cancel_proof_request(id.clone()).await,
+            Err(Error::InvalidAdminAction { .. })
+        ));
+        assert!(matches!(
+            storage
+                .cancel_proof_request(SessionId::new("other".to_string()))
+                .await,
+            Err(Error::ProofNotFound { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_audit_log_is_most_recent_first() {
+        let storage = open_in_memory();
+        for (timestamp, action) in [(1, "pause_ingestion"), (2, "cancel"), (3, "drain")] {
+            storage
+                .record_audit_entry(AuditEntry {
+                    operator: "ops".to_string(),
+                    action: action.to_string(),
+                    proof_request_id: (action == "cancel").then(|| SessionId::new("a".to_string())),
+                    outcome: "ok".to_string(),
+                    timestamp,
+                })
+                .await
+                .unwrap();
+        }
+
+        let entries = storage.fetch_audit_log(2).await.unwrap();
+        let actions: Vec<&str> = entries.iter().map(|entry| entry.action.as_str()).collect();
+        assert_eq!(actions, vec!["drain", "cancel"]);
+        assert_eq!(
+            entries[1].proof_request_id,
+            Some(SessionId::new("a".to_string()))
+        );
+        assert_eq!(entries[0].proof_request_id, None);
+    }
+}
@@ -0,0 +1,330 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Restarts of the subsystems of a relay that fail.
+
+use std::{
+    any::Any,
+    collections::VecDeque,
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context, Result};
+use futures::{
+    future::{self, BoxFuture},
+    stream::FuturesUnordered,
+    FutureExt, StreamExt,
+};
+use tokio::task::JoinSet;
+use tracing::{error, warn};
+
+use crate::{metrics::RelayMetrics, uploader::retry::backoff};
+
+/// How failed subsystems of a relay are restarted before the relay gives up
+/// and shuts down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RestartPolicy {
+    /// Number of restarts of a subsystem allowed within `window`. The relay
+    /// shuts down once a subsystem fails more often than that.
+    pub max_restarts: u32,
+    /// Period over which the failures of a subsystem are counted.
+    pub window: Duration,
+    /// Delay before the first restart. It doubles with every further failure
+    /// within the window.
+    pub initial_backoff: Duration,
+    /// Upper bound of the delay before a restart.
+    pub max_backoff: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            max_restarts: 5,
+            window: Duration::from_secs(600),
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RestartPolicy {
+    /// The delay before restarting a subsystem that failed `failures` times
+    /// within the window.
+    pub fn delay(&self, failures: u32) -> Duration {
+        backoff(self.initial_backoff, self.max_backoff, failures)
+    }
+}
+
+type Start = Box<dyn FnMut() -> BoxFuture<'static, Result<()>> + Send>;
+
+struct Subsystem {
+    name: &'static str,
+    /// Starts a new instance of the subsystem, sharing the state of the
+    /// previous ones.
+    start: Start,
+    /// When the subsystem failed within the window, oldest first.
+    failures: VecDeque<Instant>,
+}
+
+/// Runs the subsystems of a relay, restarting the ones that fail with a
+/// backoff. Subsystems are expected to run until the relay stops, so a
+/// subsystem that returns is restarted as well.
+///
+/// Dropping the supervisor stops every subsystem.
+pub(crate) struct Supervisor {
+    policy: RestartPolicy,
+    metrics: Arc<RelayMetrics>,
+    subsystems: Vec<Subsystem>,
+}
+
+impl Supervisor {
+    pub(crate) fn new(policy: RestartPolicy, metrics: Arc<RelayMetrics>) -> Self {
+        Self {
+            policy,
+            metrics,
+            subsystems: Vec::new(),
+        }
+    }
+
+    /// Supervise the subsystem started by `start`.
+    pub(crate) fn supervise<F, Fut>(&mut self, name: &'static str, mut start: F)
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        self.subsystems.push(Subsystem {
+            name,
+            start: Box::new(move || start().boxed()),
+            failures: VecDeque::new(),
+        });
+    }
+
+    /// Run the subsystems until one of them fails more often than the policy
+    /// allows. Every subsystem is then stopped, and the failure is returned
+    /// so that the relay shuts down.
+    pub(crate) async fn run(mut self) -> Result<()> {
+        let mut running = JoinSet::new();
+        let mut restarts = FuturesUnordered::new();
+        for index in 0..self.subsystems.len() {
+            self.start(&mut running, index);
+        }
+
+        loop {
+            tokio::select! {
+                Some(exited) = running.join_next() => {
+                    // Panics are caught in the task, so it can only fail if it
+                    // was aborted.
+                    let (index, err) = exited.context("subsystem task was aborted")?;
+                    let delay = match self.record_failure(index, err) {
+                        Ok(delay) => delay,
+                        Err(err) => {
+                            running.shutdown().await;
+                            return Err(err);
+                        }
+                    };
+                    restarts.push(async move {
+                        tokio::time::sleep(delay).await;
+                        index
+                    });
+                }
+                Some(index) = restarts.next() => self.start(&mut running, index),
+                else => return Ok(()),
+            }
+        }
+    }
+
+    fn start(&mut self, running: &mut JoinSet<(usize, anyhow::Error)>, index: usize) {
+        // Starting a subsystem may panic just like running it.
+        let start = &mut self.subsystems[index].start;
+        let subsystem = match panic::catch_unwind(AssertUnwindSafe(start)) {
+            Ok(subsystem) => AssertUnwindSafe(subsystem).catch_unwind().boxed(),
+            Err(panic) => future::ready(Err(panic)).boxed(),
+        };
+        running.spawn(async move {
+            let err = match subsystem.await {
+                Ok(Ok(())) => anyhow!("exited"),
+                Ok(Err(err)) => err,
+                Err(panic) => anyhow!("panicked: {}", panic_message(panic.as_ref())),
+            };
+            (index, err)
+        });
+    }
+
+    /// Record that a subsystem failed, returning the delay before it is
+    /// restarted, or an error if it failed too often.
+    fn record_failure(&mut self, index: usize, err: anyhow::Error) -> Result<Duration> {
+        let policy = self.policy;
+        let subsystem = &mut self.subsystems[index];
+        let now = Instant::now();
+        subsystem.failures.push_back(now);
+        while subsystem
+            .failures
+            .front()
+            .map_or(false, |failed| now.duration_since(*failed) > policy.window)
+        {
+            subsystem.failures.pop_front();
+        }
+
+        let name = subsystem.name;
+        let failures = subsystem.failures.len() as u32;
+        if failures > policy.max_restarts {
+            error!(
+                subsystem = name,
+                failures,
+                error = format!("{err:#}"),
+                "subsystem keeps failing, shutting down the relay"
+            );
+            return Err(err.context(format!(
+                "{name} failed {failures} times within {:?}",
+                policy.window
+            )));
+        }
+
+        let delay = policy.delay(failures);
+        warn!(
+            subsystem = name,
+            failures,
+            ?delay,
+            error = format!("{err:#}"),
+            "subsystem failed, restarting"
+        );
+        self.metrics.subsystem_restarted(name);
+        Ok(delay)
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    match panic.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => panic
+            .downcast_ref::<String>()
+            .map(String::as_str)
+            .unwrap_or("unknown panic"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+
+    fn test_policy() -> RestartPolicy {
+        RestartPolicy {
+            max_restarts: 3,
+            window: Duration::from_secs(60),
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(10),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_subsystem_is_restarted_alone() {
+        let flaky_starts = Arc::new(AtomicU32::new(0));
+        let steady_starts = Arc::new(AtomicU32::new(0));
+        let mut supervisor = Supervisor::new(test_policy(), Arc::new(RelayMetrics::new()));
+        supervisor.supervise("flaky", {
+            let starts = flaky_starts.clone();
+            move || {
+                let starts = starts.clone();
+                async move {
+                    match starts.fetch_add(1, Ordering::SeqCst) {
+                        0 => Err::<(), _>(anyhow!("connection lost")),
+                        1 => panic!("connection lost again"),
+                        _ => futures::future::pending::<Result<()>>().await,
+                    }
+                }
+            }
+        });
+        supervisor.supervise("steady", {
+            let starts = steady_starts.clone();
+            move || {
+                starts.fetch_add(1, Ordering::SeqCst);
+                futures::future::pending::<Result<()>>()
+            }
+        });
+
+        let supervisor = tokio::spawn(supervisor.run());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!supervisor.is_finished());
+        assert_eq!(flaky_starts.load(Ordering::SeqCst), 3);
+        assert_eq!(steady_starts.load(Ordering::SeqCst), 1);
+        supervisor.abort();
+    }
+
+    #[tokio::test]
+    async fn test_panicking_start_is_restarted() {
+        let starts = Arc::new(AtomicU32::new(0));
+        let mut supervisor = Supervisor::new(test_policy(), Arc::new(RelayMetrics::new()));
+        supervisor.supervise("flaky", {
+            let starts = starts.clone();
+            move || {
+                if starts.fetch_add(1, Ordering::SeqCst) == 0 {
+                    panic!("failed to connect");
+                }
+                futures::future::pending::<Result<()>>()
+            }
+        });
+
+        let supervisor = tokio::spawn(supervisor.run());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!supervisor.is_finished());
+        assert_eq!(starts.load(Ordering::SeqCst), 2);
+        supervisor.abort();
+
+        let mut supervisor = Supervisor::new(test_policy(), Arc::new(RelayMetrics::new()));
+        supervisor.supervise("broken", || -> BoxFuture<'static, Result<()>> {
+            panic!("failed to bind")
+        });
+        let err = tokio::time::timeout(Duration::from_secs(5), supervisor.run())
+            .await
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "broken failed 4 times within 60s: panicked: failed to bind"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_persistent_failure_stops_every_subsystem() {
+        // Dropped once the task running the steady subsystem is stopped.
+        let steady = Arc::new(());
+        let mut supervisor = Supervisor::new(test_policy(), Arc::new(RelayMetrics::new()));
+        supervisor.supervise("broken", || async { Err::<(), _>(anyhow!("bind failed")) });
+        supervisor.supervise("steady", {
+            let steady = steady.clone();
+            move || {
+                let steady = steady.clone();
+                async move {
+                    let _steady = steady;
+                    futures::future::pending::<Result<()>>().await
+                }
+            }
+        });
+
+        let err = tokio::time::timeout(Duration::from_secs(5), supervisor.run())
+            .await
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "broken failed 4 times within 60s: bind failed"
+        );
+        assert_eq!(Arc::strong_count(&steady), 1);
+    }
+}
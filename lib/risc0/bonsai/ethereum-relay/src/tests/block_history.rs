@@ -0,0 +1,217 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{fmt::Debug, sync::Mutex};
+
+    use bonsai_ethereum_contracts::i_bonsai_relay::CallbackRequestFilter;
+    use ethers::{
+        contract::EthEvent,
+        providers::{JsonRpcClient, JsonRpcError, MockError, Provider},
+        types::{Address, Bytes, Filter, Log, H256, U256, U64},
+        utils::keccak256,
+    };
+    use serde::{de::DeserializeOwned, Serialize};
+    use serde_json::{json, Value};
+    use tokio::sync::mpsc;
+
+    use crate::downloader::{
+        block_history::fetch_logs, proxy_callback_proof_request_stream::callback_request_filter,
+    };
+
+    /// An in-process Ethereum node serving `eth_getLogs` for a fixed set of
+    /// logs, which rejects requests for more than `max_range` blocks.
+    #[derive(Debug)]
+    struct MockNode {
+        logs: Vec<Log>,
+        max_range: u64,
+        /// Whether rejections suggest a smaller block range.
+        suggest_range: bool,
+        requested_ranges: Mutex<Vec<(u64, u64)>>,
+    }
+
+    fn block_number(value: &Value) -> u64 {
+        serde_json::from_value::<U64>(value.clone())
+            .unwrap()
+            .as_u64()
+    }
+
+    #[async_trait::async_trait]
+    impl JsonRpcClient for MockNode {
+        type Error = MockError;
+
+        async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+        where
+            T: Debug + Serialize + Send + Sync,
+            R: DeserializeOwned + Send,
+        {
+            assert_eq!(method, "eth_getLogs");
+            let filter = &serde_json::to_value(params)?[0];
+            let from = block_number(&filter["fromBlock"]);
+            let to = block_number(&filter["toBlock"]);
+            self.requested_ranges.lock().unwrap().push((from, to));
+
+            if to - from + 1 > self.max_range {
+                let data = self.suggest_range.then(|| {
+                    json!({
+                        "from": format!("{from:#x}"),
+                        "to": format!("{:#x}", from + self.max_range - 1),
+                    })
+                });
+                return Err(MockError::JsonRpcError(JsonRpcError {
+                    code: -32005,
+                    message: "query exceeds max block range".to_string(),
+                    data,
+                }));
+            }
+
+            let logs: Vec<&Log> = self
+                .logs
+                .iter()
+                .filter(|log| {
+                    let block = log.block_number.unwrap().as_u64();
+                    from <= block
+                        && block <= to
+                        && serde_json::to_value(log.address).unwrap() == filter["address"]
+                        && serde_json::to_value(log.topics[0]).unwrap() == filter["topics"][0]
+                })
+                .collect();
+            Ok(serde_json::from_value(serde_json::to_value(logs)?)?)
+        }
+    }
+
+    fn log(address: Address, topic: H256, block: u64) -> Log {
+        Log {
+            address,
+            topics: vec![topic],
+            data: Bytes::default(),
+            block_number: Some(U64::from(block)),
+            transaction_hash: Some(H256::from_low_u64_be(block)),
+            log_index: Some(U256::zero()),
+            ..Default::default()
+        }
+    }
+
+    /// A node holding a relay `CallbackRequest` log in each of the blocks 5 to
+    /// 20, which the relay missed while it was disconnected after block 4, and
+    /// unrelated `Transfer` logs of another contract.
+    fn node_with_missed_logs(relay: Address, max_range: u64) -> MockNode {
+        let callback_request = CallbackRequestFilter::signature();
+        let transfer = H256(keccak256("Transfer(address,address,uint256)"));
+        let token = Address::repeat_byte(0xee);
+
+        let mut logs: Vec<Log> = (5..=20)
+            .map(|block| log(relay, callback_request, block))
+            .collect();
+        logs.push(log(token, transfer, 7));
+        logs.push(log(token, transfer, 12));
+        logs.sort_by_key(|log| log.block_number);
+
+        MockNode {
+            logs,
+            max_range,
+            suggest_range: false,
+            requested_ranges: Mutex::new(Vec::new()),
+        }
+    }
+
+    async fn recover(
+        provider: &Provider<MockNode>,
+        filter: &Filter,
+        last_processed_block: &mut U64,
+        max_range: u64,
+    ) -> (anyhow::Result<()>, Vec<Log>) {
+        let (sender, mut receiver) = mpsc::channel(100);
+        let result = fetch_logs(
+            provider,
+            filter,
+            last_processed_block,
+            U64::from(20),
+            max_range,
+            &sender,
+        )
+        .await;
+        drop(sender);
+
+        let mut logs = Vec::new();
+        while let Some(log) = receiver.recv().await {
+            logs.push(log);
+        }
+        (result, logs)
+    }
+
+    #[tokio::test]
+    async fn test_logs_missed_during_disconnect_are_recovered() {
+        let relay = Address::repeat_byte(1);
+        let filter = callback_request_filter(relay);
+        let provider = Provider::new(node_with_missed_logs(relay, 3));
+
+        let mut last_processed_block = U64::from(4);
+        let (result, logs) = recover(&provider, &filter, &mut last_processed_block, 10).await;
+        result.unwrap();
+
+        assert_eq!(last_processed_block, U64::from(20));
+        assert!(logs.iter().all(|log| log.address == relay));
+        let blocks: Vec<u64> = logs
+            .iter()
+            .map(|log| log.block_number.unwrap().as_u64())
+            .collect();
+        assert_eq!(blocks, (5..=20).collect::<Vec<_>>());
+
+        // The range shrinks after the node rejects it, and every range is
+        // requested without gaps.
+        let ranges = provider.as_ref().requested_ranges.lock().unwrap().clone();
+        assert_eq!(ranges[0], (5, 14));
+        let accepted: Vec<(u64, u64)> = ranges
+            .into_iter()
+            .filter(|(from, to)| to - from < 3)
+            .collect();
+        assert_eq!(accepted.first().unwrap().0, 5);
+        assert_eq!(accepted.last().unwrap().1, 20);
+        assert!(accepted.windows(2).all(|pair| pair[0].1 + 1 == pair[1].0));
+    }
+
+    #[tokio::test]
+    async fn test_suggested_range_is_used() {
+        let relay = Address::repeat_byte(1);
+        let filter = callback_request_filter(relay);
+        let provider = Provider::new(MockNode {
+            suggest_range: true,
+            ..node_with_missed_logs(relay, 2)
+        });
+
+        let mut last_processed_block = U64::from(4);
+        let (result, logs) = recover(&provider, &filter, &mut last_processed_block, 10).await;
+        result.unwrap();
+
+        assert_eq!(logs.len(), 16);
+        let ranges = provider.as_ref().requested_ranges.lock().unwrap().clone();
+        assert_eq!(&ranges[..2], &[(5, 14), (5, 6)]);
+    }
+
+    #[tokio::test]
+    async fn test_rejected_single_block_fails() {
+        let relay = Address::repeat_byte(1);
+        let filter = callback_request_filter(relay);
+        let provider = Provider::new(node_with_missed_logs(relay, 0));
+
+        let mut last_processed_block = U64::from(4);
+        let (result, logs) = recover(&provider, &filter, &mut last_processed_block, 10).await;
+
+        assert!(result.is_err());
+        assert!(logs.is_empty());
+        assert_eq!(last_processed_block, U64::from(4));
+    }
+}
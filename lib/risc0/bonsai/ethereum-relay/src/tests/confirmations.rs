@@ -0,0 +1,168 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{collections::HashMap, fmt::Debug};
+
+    use ethers::{
+        contract::LogMeta,
+        providers::{JsonRpcClient, MockError, Provider},
+        types::{Address, Block, H256, U256, U64},
+    };
+    use serde::{de::DeserializeOwned, Serialize};
+    use serde_json::Value;
+
+    use crate::downloader::confirmations::ConfirmationTracker;
+
+    /// An in-process Ethereum node serving the hashes of its canonical blocks.
+    #[derive(Debug, Default)]
+    struct CanonicalChain {
+        hashes: HashMap<u64, H256>,
+    }
+
+    #[async_trait::async_trait]
+    impl JsonRpcClient for CanonicalChain {
+        type Error = MockError;
+
+        async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+        where
+            T: Debug + Serialize + Send + Sync,
+            R: DeserializeOwned + Send,
+        {
+            assert_eq!(method, "eth_getBlockByNumber");
+            let number: U64 = serde_json::from_value(serde_json::to_value(params)?[0].clone())?;
+            let block = match self.hashes.get(&number.as_u64()) {
+                Some(hash) => serde_json::to_value(Block::<H256> {
+                    hash: Some(*hash),
+                    number: Some(number),
+                    ..Default::default()
+                })?,
+                None => Value::Null,
+            };
+            Ok(serde_json::from_value(block)?)
+        }
+    }
+
+    fn meta(block: u64, block_hash: H256) -> LogMeta {
+        LogMeta {
+            address: Address::repeat_byte(1),
+            block_number: U64::from(block),
+            block_hash,
+            transaction_hash: H256::from_low_u64_be(block),
+            transaction_index: U64::zero(),
+            log_index: U256::zero(),
+        }
+    }
+
+    fn chain(blocks: impl IntoIterator<Item = u64>) -> Provider<CanonicalChain> {
+        Provider::new(CanonicalChain {
+            hashes: blocks
+                .into_iter()
+                .map(|block| (block, canonical_hash(block)))
+                .collect(),
+        })
+    }
+
+    fn canonical_hash(block: u64) -> H256 {
+        H256::from_low_u64_be(block + 1000)
+    }
+
+    #[tokio::test]
+    async fn test_events_are_held_until_confirmed() {
+        let client = chain(0..20);
+        let mut tracker = ConfirmationTracker::new(2);
+        tracker.add("a", meta(10, canonical_hash(10)));
+        tracker.add("b", meta(11, canonical_hash(11)));
+        assert_eq!(tracker.first_pending_block(), Some(U64::from(10)));
+
+        let confirmed = tracker
+            .take_confirmed(&client, U64::from(11))
+            .await
+            .unwrap()
+            .events;
+        assert!(confirmed.is_empty());
+
+        let confirmed = tracker
+            .take_confirmed(&client, U64::from(12))
+            .await
+            .unwrap()
+            .events;
+        assert_eq!(confirmed.len(), 1);
+        assert_eq!(confirmed[0].0, "a");
+        assert_eq!(tracker.first_pending_block(), Some(U64::from(11)));
+
+        let confirmed = tracker
+            .take_confirmed(&client, U64::from(13))
+            .await
+            .unwrap()
+            .events;
+        assert_eq!(confirmed.len(), 1);
+        assert_eq!(confirmed[0].0, "b");
+        assert_eq!(tracker.first_pending_block(), None);
+    }
+
+    #[tokio::test]
+    async fn test_reorged_events_are_cancelled() {
+        let client = chain(0..20);
+        let mut tracker = ConfirmationTracker::new(1);
+        // The event was seen in a block that was replaced by a reorg.
+        tracker.add("reorged", meta(10, H256::repeat_byte(0xff)));
+        tracker.add("kept", meta(11, canonical_hash(11)));
+
+        let confirmed = tracker
+            .take_confirmed(&client, U64::from(12))
+            .await
+            .unwrap();
+        assert_eq!(confirmed.first_reorged_block, Some(U64::from(10)));
+        let confirmed = confirmed.events;
+        assert_eq!(confirmed.len(), 1);
+        assert_eq!(confirmed[0].0, "kept");
+        assert_eq!(confirmed[0].1.block_hash, canonical_hash(11));
+        assert_eq!(tracker.first_pending_block(), None);
+    }
+
+    #[tokio::test]
+    async fn test_removed_events_are_cancelled() {
+        let client = chain(0..20);
+        let mut tracker = ConfirmationTracker::new(1);
+        tracker.add("removed", meta(10, canonical_hash(10)));
+
+        assert!(tracker.remove(&meta(10, canonical_hash(10))));
+        assert!(!tracker.remove(&meta(10, canonical_hash(10))));
+
+        let confirmed = tracker
+            .take_confirmed(&client, U64::from(12))
+            .await
+            .unwrap()
+            .events;
+        assert!(confirmed.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_events_of_unknown_blocks_stay_pending() {
+        // The node does not know block 10 yet, e.g. while it is syncing.
+        let client = chain(11..20);
+        let mut tracker = ConfirmationTracker::new(0);
+        tracker.add("a", meta(10, canonical_hash(10)));
+
+        let confirmed = tracker
+            .take_confirmed(&client, U64::from(12))
+            .await
+            .unwrap()
+            .events;
+        assert!(confirmed.is_empty());
+        assert_eq!(tracker.first_pending_block(), Some(U64::from(10)));
+    }
+}
@@ -0,0 +1,461 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use bonsai_ethereum_contracts::i_bonsai_relay::CallbackRequestFilter;
+    use ethers::{
+        prelude::*,
+        types::{Address, Bytes, H256},
+    };
+    use tokio::sync::mpsc;
+
+    use crate::{
+        control::RelayControl,
+        downloader::{
+            event_processor::EventProcessor,
+            proxy_callback_proof_request_stream::ProxyCallbackProofRequestStream,
+        },
+        metrics::RelayMetrics,
+        readiness::{self, Component},
+        sdk::utils,
+        storage::{in_memory::InMemoryStorage, Storage},
+        EthersClientConfig,
+    };
+
+    /// Forwards every event and its log metadata to a channel.
+    struct ChannelEventProcessor(mpsc::UnboundedSender<(CallbackRequestFilter, LogMeta)>);
+
+    #[async_trait::async_trait]
+    impl EventProcessor for ChannelEventProcessor {
+        type Event = CallbackRequestFilter;
+
+        async fn process_event(
+            &self,
+            event: CallbackRequestFilter,
+            meta: LogMeta,
+        ) -> Result<(), crate::api::error::Error> {
+            self.0
+                .send((event, meta))
+                .expect("receiver should be alive");
+            Ok(())
+        }
+    }
+
+    /// Fails to process every event while `fail` is set, and forwards them to
+    /// a channel otherwise.
+    struct FlakyEventProcessor {
+        fail: Arc<AtomicBool>,
+        sender: mpsc::UnboundedSender<(CallbackRequestFilter, LogMeta)>,
+    }
+
+    #[async_trait::async_trait]
+    impl EventProcessor for FlakyEventProcessor {
+        type Event = CallbackRequestFilter;
+
+        async fn process_event(
+            &self,
+            event: CallbackRequestFilter,
+            meta: LogMeta,
+        ) -> Result<(), crate::api::error::Error> {
+            if self.fail.load(Ordering::SeqCst) {
+                return Err(anyhow::anyhow!("Bonsai is unavailable").into());
+            }
+            self.sender
+                .send((event, meta))
+                .expect("receiver should be alive");
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn integration_test_http_log_polling() {
+        abigen!(Proxy, "../ethereum/out/ProxyTest.sol/Proxy.json");
+
+        let anvil = utils::get_anvil().expect("test requires a local anvil instance");
+
+        // Reach the node over HTTP, which does not support subscriptions.
+        let ws_config = utils::get_ethers_client_config(Some(&anvil))
+            .await
+            .expect("Failed to get ethers client config");
+        let ethers_client_config = EthersClientConfig {
+            eth_node_url: anvil.endpoint(),
+            ..ws_config
+        }
+        .with_log_polling(Duration::from_millis(100), 2);
+        assert!(!ethers_client_config.supports_subscriptions());
+
+        let ethers_client = Arc::new(
+            ethers_client_config
+                .get_client()
+                .await
+                .expect("could not get client"),
+        );
+        let proxy = Proxy::deploy(ethers_client.clone(), ())
+            .expect("should be able to deploy the Proxy contract")
+            .send()
+            .await
+            .expect("deployment should succeed");
+
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let (reporter, readiness) = readiness::channel([Component::ChainSubscription]);
+        let stream = ProxyCallbackProofRequestStream::new(
+            ethers_client_config,
+            proxy.address(),
+            ChannelEventProcessor(sender),
+            InMemoryStorage::new(),
+            reporter,
+            Arc::new(RelayControl::default()),
+            Arc::new(RelayMetrics::new()),
+        );
+        let stream_handle = tokio::spawn(stream.run());
+        readiness
+            .wait(Duration::from_secs(10))
+            .await
+            .expect("polling should start");
+
+        // Emit more events than fit in a single polling window.
+        for i in 0..5u8 {
+            proxy
+                .request_callback(
+                    [i; 32],
+                    Bytes::from(vec![i]),
+                    Address::repeat_byte(i),
+                    [0xab, 0xcd, 0xef, 0xab],
+                    3000000,
+                )
+                .send()
+                .await
+                .expect("request should be sent")
+                .await
+                .expect("request should be mined");
+        }
+
+        for i in 0..5u8 {
+            let (event, _) = tokio::time::timeout(Duration::from_secs(10), receiver.recv())
+                .await
+                .expect("event should be polled")
+                .expect("stream should be running");
+            assert_eq!(H256::from(event.image_id), H256::repeat_byte(i));
+            assert_eq!(event.input, Bytes::from(vec![i]));
+            assert_eq!(event.callback_contract, Address::repeat_byte(i));
+        }
+
+        stream_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn integration_test_backfill_from_last_processed_block() {
+        abigen!(Proxy, "../ethereum/out/ProxyTest.sol/Proxy.json");
+
+        let anvil = utils::get_anvil().expect("test requires a local anvil instance");
+        let ethers_client_config = utils::get_ethers_client_config(Some(&anvil))
+            .await
+            .expect("Failed to get ethers client config")
+            .with_log_polling(Duration::from_millis(100), 2);
+
+        let ethers_client = Arc::new(
+            ethers_client_config
+                .get_client()
+                .await
+                .expect("could not get client"),
+        );
+        let proxy = Proxy::deploy(ethers_client.clone(), ())
+            .expect("should be able to deploy the Proxy contract")
+            .send()
+            .await
+            .expect("deployment should succeed");
+
+        // Pretend the relay stopped after processing the deployment block.
+        let storage = InMemoryStorage::new();
+        storage
+            .advance_last_processed_block(
+                ethers_client
+                    .get_block_number()
+                    .await
+                    .expect("should get block number"),
+            )
+            .await
+            .expect("storage should succeed");
+
+        // Emit events while the relay is not running.
+        for i in 0..3u8 {
+            proxy
+                .request_callback(
+                    [i; 32],
+                    Bytes::from(vec![i]),
+                    Address::repeat_byte(i),
+                    [0xab, 0xcd, 0xef, 0xab],
+                    3000000,
+                )
+                .send()
+                .await
+                .expect("request should be sent")
+                .await
+                .expect("request should be mined");
+        }
+
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let (reporter, _) = readiness::channel([Component::ChainSubscription]);
+        let stream = ProxyCallbackProofRequestStream::new(
+            ethers_client_config,
+            proxy.address(),
+            ChannelEventProcessor(sender),
+            storage.clone(),
+            reporter,
+            Arc::new(RelayControl::default()),
+            Arc::new(RelayMetrics::new()),
+        );
+        let stream_handle = tokio::spawn(stream.run());
+
+        for i in 0..3u8 {
+            let (event, _) = tokio::time::timeout(Duration::from_secs(10), receiver.recv())
+                .await
+                .expect("missed event should be backfilled")
+                .expect("stream should be running");
+            assert_eq!(H256::from(event.image_id), H256::repeat_byte(i));
+        }
+        let latest_block = ethers_client
+            .get_block_number()
+            .await
+            .expect("should get block number");
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while storage
+                .get_last_processed_block()
+                .await
+                .expect("storage should succeed")
+                != Some(latest_block)
+            {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        })
+        .await
+        .expect("the last processed block should reach the latest block");
+
+        stream_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn integration_test_http_log_polling_refetches_reorged_event() {
+        abigen!(Proxy, "../ethereum/out/ProxyTest.sol/Proxy.json");
+
+        let anvil = utils::get_anvil().expect("test requires a local anvil instance");
+        let ws_config = utils::get_ethers_client_config(Some(&anvil))
+            .await
+            .expect("Failed to get ethers client config");
+        let ethers_client_config = EthersClientConfig {
+            eth_node_url: anvil.endpoint(),
+            ..ws_config
+        }
+        .with_log_polling(Duration::from_millis(100), 2)
+        .with_confirmations(2);
+
+        let ethers_client = Arc::new(
+            ethers_client_config
+                .get_client()
+                .await
+                .expect("could not get client"),
+        );
+        let proxy = Proxy::deploy(ethers_client.clone(), ())
+            .expect("should be able to deploy the Proxy contract")
+            .send()
+            .await
+            .expect("deployment should succeed");
+
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let (reporter, readiness) = readiness::channel([Component::ChainSubscription]);
+        let stream = ProxyCallbackProofRequestStream::new(
+            ethers_client_config,
+            proxy.address(),
+            ChannelEventProcessor(sender),
+            InMemoryStorage::new(),
+            reporter,
+            Arc::new(RelayControl::default()),
+            Arc::new(RelayMetrics::new()),
+        );
+        let stream_handle = tokio::spawn(stream.run());
+        readiness
+            .wait(Duration::from_secs(10))
+            .await
+            .expect("polling should start");
+
+        let provider = ethers_client.provider();
+        let mine = || async {
+            provider
+                .request::<_, String>("evm_mine", ())
+                .await
+                .expect("block should be mined");
+        };
+        let request_callback = || async {
+            proxy
+                .request_callback(
+                    [1; 32],
+                    Bytes::from(vec![1]),
+                    Address::repeat_byte(1),
+                    [0xab, 0xcd, 0xef, 0xab],
+                    3000000,
+                )
+                .send()
+                .await
+                .expect("request should be sent")
+                .await
+                .expect("request should be mined")
+                .expect("request should have a receipt")
+        };
+
+        // Emit the event and let the relay fetch it while it waits for
+        // confirmations.
+        let snapshot: U256 = provider
+            .request("evm_snapshot", ())
+            .await
+            .expect("snapshot should be taken");
+        let original_block = request_callback()
+            .await
+            .block_number
+            .expect("receipt should have a block");
+        mine().await;
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        // Replace the block with an empty one and include the event in the
+        // next block, which the relay has already fetched the logs of.
+        let reverted: bool = provider
+            .request("evm_revert", [snapshot])
+            .await
+            .expect("snapshot should be reverted");
+        assert!(reverted);
+        mine().await;
+        let reorged_block = request_callback()
+            .await
+            .block_number
+            .expect("receipt should have a block");
+        assert_eq!(reorged_block, original_block + 1);
+        for _ in 0..3 {
+            mine().await;
+        }
+
+        let (event, meta) = tokio::time::timeout(Duration::from_secs(10), receiver.recv())
+            .await
+            .expect("reorged event should be fetched again")
+            .expect("stream should be running");
+        assert_eq!(H256::from(event.image_id), H256::repeat_byte(1));
+        assert_eq!(meta.block_number, reorged_block);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert!(receiver.try_recv().is_err());
+
+        stream_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn integration_test_failed_events_are_retried() {
+        abigen!(Proxy, "../ethereum/out/ProxyTest.sol/Proxy.json");
+
+        let anvil = utils::get_anvil().expect("test requires a local anvil instance");
+        let ws_config = utils::get_ethers_client_config(Some(&anvil))
+            .await
+            .expect("Failed to get ethers client config");
+        let ethers_client_config = EthersClientConfig {
+            eth_node_url: anvil.endpoint(),
+            ..ws_config
+        }
+        .with_log_polling(Duration::from_millis(100), 2);
+
+        let ethers_client = Arc::new(
+            ethers_client_config
+                .get_client()
+                .await
+                .expect("could not get client"),
+        );
+        let proxy = Proxy::deploy(ethers_client.clone(), ())
+            .expect("should be able to deploy the Proxy contract")
+            .send()
+            .await
+            .expect("deployment should succeed");
+
+        let fail = Arc::new(AtomicBool::new(true));
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let storage = InMemoryStorage::new();
+        let (reporter, readiness) = readiness::channel([Component::ChainSubscription]);
+        let stream = ProxyCallbackProofRequestStream::new(
+            ethers_client_config,
+            proxy.address(),
+            FlakyEventProcessor {
+                fail: fail.clone(),
+                sender,
+            },
+            storage.clone(),
+            reporter,
+            Arc::new(RelayControl::default()),
+            Arc::new(RelayMetrics::new()),
+        );
+        let stream_handle = tokio::spawn(stream.run());
+        readiness
+            .wait(Duration::from_secs(10))
+            .await
+            .expect("polling should start");
+
+        let event_block = proxy
+            .request_callback(
+                [1; 32],
+                Bytes::from(vec![1]),
+                Address::repeat_byte(1),
+                [0xab, 0xcd, 0xef, 0xab],
+                3000000,
+            )
+            .send()
+            .await
+            .expect("request should be sent")
+            .await
+            .expect("request should be mined")
+            .expect("request should have a receipt")
+            .block_number
+            .expect("receipt should have a block");
+        for _ in 0..3 {
+            ethers_client
+                .provider()
+                .request::<_, String>("evm_mine", ())
+                .await
+                .expect("block should be mined");
+        }
+
+        // While processing fails, the last processed block stays before the
+        // event, so that it is fetched again after a restart.
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert!(receiver.try_recv().is_err());
+        let last_processed_block = storage
+            .get_last_processed_block()
+            .await
+            .expect("storage should succeed")
+            .expect("last processed block should be stored");
+        assert!(last_processed_block < event_block);
+
+        // Once processing succeeds, the event is retried.
+        fail.store(false, Ordering::SeqCst);
+        let (event, meta) = tokio::time::timeout(Duration::from_secs(10), receiver.recv())
+            .await
+            .expect("failed event should be retried")
+            .expect("stream should be running");
+        assert_eq!(H256::from(event.image_id), H256::repeat_byte(1));
+        assert_eq!(meta.block_number, event_block);
+
+        stream_handle.abort();
+    }
+}
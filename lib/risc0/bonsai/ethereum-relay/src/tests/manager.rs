@@ -17,11 +17,21 @@ pub(crate) mod tests {
     use std::sync::Arc;
 
     use bonsai_ethereum_contracts::i_bonsai_relay::CallbackRequestFilter;
-    use bonsai_sdk::alpha_async::get_client_from_parts;
+    use bonsai_sdk::{
+        alpha::{responses::SessionStatusRes, SessionId},
+        alpha_async::get_client_from_parts,
+    };
     use ethers::types::{Address, Bytes, H256};
     use tokio::sync::Notify;
+    use uuid::Uuid;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
 
     use crate::{
+        control::RelayControl,
+        metrics::RelayMetrics,
         sdk::utils,
         storage::{
             in_memory::InMemoryStorage, Error as StorageError, ProofRequestInformation,
@@ -31,6 +41,10 @@ pub(crate) mod tests {
         uploader::{
             completed_proofs::manager::BonsaiCompleteProofManager,
             pending_proofs::manager::BonsaiPendingProofManager,
+            quarantine::{revert_reason, QuarantinePolicy},
+            retry::RetryPolicy,
+            transaction_manager::TransactionPolicy,
+            webhook::WebhookPolicy,
         },
     };
 
@@ -51,6 +65,8 @@ pub(crate) mod tests {
             storage.clone(),
             notifier.clone(),
             done_notifer.clone(),
+            RetryPolicy::default(),
+            Arc::new(RelayMetrics::new()),
         );
 
         // add a pending proof request to storage
@@ -65,6 +81,12 @@ pub(crate) mod tests {
                     function_selector: [0xab, 0xcd, 0xef, 0xab],
                     gas_limit: 3000000,
                 },
+                source_event: None,
+                block_hash: None,
+                attempts: 0,
+                last_error: None,
+                revert_data: None,
+                webhook_url: None,
             })
             .await
             .expect("storage should succeed");
@@ -84,6 +106,89 @@ pub(crate) mod tests {
         done_notifer.notified().await;
     }
 
+    #[tokio::test]
+    async fn integration_test_failed_proof_is_dead_lettered() {
+        // Mock API server reporting the session as failed
+        let server = MockServer::start().await;
+        let proof_id = SessionId::new(Uuid::new_v4().to_string());
+        Mock::given(method("GET"))
+            .and(path(format!("sessions/status/{}", proof_id.uuid)))
+            .respond_with(ResponseTemplate::new(200).set_body_json(SessionStatusRes {
+                status: "FAILED".to_string(),
+                receipt_url: None,
+            }))
+            .mount(&server)
+            .await;
+
+        let bonsai_client = get_client_from_parts(server.uri(), String::default())
+            .await
+            .unwrap();
+        let storage = InMemoryStorage::new();
+        let notifier = Arc::new(Notify::new());
+
+        let mut manager = BonsaiPendingProofManager::new(
+            bonsai_client,
+            storage.clone(),
+            notifier.clone(),
+            Arc::new(Notify::new()),
+            RetryPolicy::default(),
+            Arc::new(RelayMetrics::new()),
+        );
+
+        storage
+            .add_new_bonsai_proof_request(ProofRequestInformation {
+                proof_request_id: proof_id.clone(),
+                callback_proof_request_event: CallbackRequestFilter {
+                    account: Address::default(),
+                    image_id: H256::default().into(),
+                    input: Bytes::default(),
+                    callback_contract: Address::default(),
+                    function_selector: [0xab, 0xcd, 0xef, 0xab],
+                    gas_limit: 3000000,
+                },
+                source_event: None,
+                block_hash: None,
+                attempts: 0,
+                last_error: None,
+                revert_data: None,
+                webhook_url: None,
+            })
+            .await
+            .expect("storage should succeed");
+
+        notifier.notify_one();
+        manager.step().await.expect("step should succeed");
+        manager.step().await.expect("step should succeed");
+
+        // A failed session never succeeds, so it is not retried.
+        assert_eq!(
+            storage
+                .get_proof_request_state(proof_id.clone())
+                .await
+                .unwrap(),
+            ProofRequestState::DeadLetter
+        );
+        let dead_letters = storage
+            .fetch_dead_letter_proof_requests(None)
+            .await
+            .unwrap();
+        assert_eq!(dead_letters[0].attempts, 1);
+        assert!(dead_letters[0]
+            .last_error
+            .as_deref()
+            .unwrap()
+            .contains("FAILED"));
+
+        storage
+            .requeue_dead_letter_proof_request(proof_id.clone())
+            .await
+            .expect("requeue should succeed");
+        assert_eq!(
+            storage.get_proof_request_state(proof_id).await.unwrap(),
+            ProofRequestState::New
+        );
+    }
+
     #[tokio::test]
     async fn integration_test_completed_proof_manager() {
         abigen!(Proxy, "../ethereum/out/ProxyTest.sol/Proxy.json");
@@ -140,6 +245,15 @@ pub(crate) mod tests {
             proxy.address(),
             ethers_client_config.clone(),
             send_batch_interval,
+            3000000,
+            RetryPolicy::default(),
+            TransactionPolicy::default(),
+            // The test proxy has no invokeCallback, so the pre-flight
+            // simulation of every callback reverts.
+            QuarantinePolicy::Deliver,
+            WebhookPolicy::default(),
+            Arc::new(RelayControl::default()),
+            Arc::new(RelayMetrics::new()),
         );
 
         // add a complete proof request to storage
@@ -154,6 +268,12 @@ pub(crate) mod tests {
                     function_selector: [0xab, 0xcd, 0xef, 0xab],
                     gas_limit: 3000000,
                 },
+                source_event: None,
+                block_hash: None,
+                attempts: 0,
+                last_error: None,
+                revert_data: None,
+                webhook_url: None,
             })
             .await
             .expect("storage should succeed");
@@ -219,4 +339,345 @@ pub(crate) mod tests {
             _ => false,
         });
     }
+
+    #[tokio::test]
+    async fn integration_test_reverted_batch_transaction_is_not_completed() {
+        abigen!(
+            RevertingProxy,
+            "../ethereum/out/ProxyTest.sol/RevertingProxy.json"
+        );
+        use bonsai_ethereum_contracts::i_bonsai_relay;
+        use ethers::prelude::*;
+
+        let anvil = utils::get_anvil();
+        let ethers_client_config = utils::get_ethers_client_config(anvil.as_ref())
+            .await
+            .expect("Failed to get ethers client config");
+        let ethers_client = Arc::new(
+            ethers_client_config
+                .get_client()
+                .await
+                .expect("could not get client"),
+        );
+        let (proof_id, server) = get_test_bonsai_server().await;
+
+        // The relay reverts every batch of callbacks it is sent.
+        let proxy = RevertingProxy::deploy(ethers_client.clone(), ())
+            .expect("should be able to deploy the RevertingProxy contract")
+            .send()
+            .await
+            .expect("deployment should succeed");
+
+        let bonsai_client = get_client_from_parts(server.uri(), String::default())
+            .await
+            .unwrap();
+        let storage = InMemoryStorage::new();
+        let new_complete_proofs_notifier = Arc::new(Notify::new());
+        let send_batch_notifier = Arc::new(Notify::new());
+        let mut send_batch_interval =
+            tokio::time::interval(tokio::time::Duration::from_millis(10000000000));
+        send_batch_interval.tick().await;
+
+        let mut manager = BonsaiCompleteProofManager::new(
+            bonsai_client,
+            true,
+            storage.clone(),
+            new_complete_proofs_notifier.clone(),
+            send_batch_notifier.clone(),
+            3,
+            proxy.address(),
+            ethers_client_config.clone(),
+            send_batch_interval,
+            3000000,
+            RetryPolicy::default(),
+            TransactionPolicy::default(),
+            QuarantinePolicy::Deliver,
+            WebhookPolicy::default(),
+            Arc::new(RelayControl::default()),
+            Arc::new(RelayMetrics::new()),
+        );
+
+        storage
+            .add_new_bonsai_proof_request(ProofRequestInformation {
+                proof_request_id: proof_id.clone(),
+                callback_proof_request_event: i_bonsai_relay::CallbackRequestFilter {
+                    account: Address::default(),
+                    image_id: H256::default().into(),
+                    input: Bytes::default(),
+                    callback_contract: Address::default(),
+                    function_selector: [0xab, 0xcd, 0xef, 0xab],
+                    gas_limit: 3000000,
+                },
+                source_event: None,
+                block_hash: None,
+                attempts: 0,
+                last_error: None,
+                revert_data: None,
+                webhook_url: None,
+            })
+            .await
+            .expect("storage should succeed");
+        storage
+            .transition_proof_request(proof_id.clone(), ProofRequestState::Pending)
+            .await
+            .expect("should transition to pending");
+        storage
+            .transition_proof_request(proof_id.clone(), ProofRequestState::Completed)
+            .await
+            .expect("should transition to pending to completed");
+
+        new_complete_proofs_notifier.notify_one();
+        manager.step().await.expect("step should succeed");
+        manager.step().await.expect("step should succeed");
+        send_batch_notifier.notify_one();
+        manager.step().await.expect("step should succeed");
+
+        // The callback is recorded as reverted in the mined transaction, with
+        // the data the batch reverted with.
+        let tx_hash = match storage
+            .get_proof_request_state(proof_id.clone())
+            .await
+            .expect("proof should exist")
+        {
+            ProofRequestState::CallbackReverted(tx_hash) => tx_hash,
+            state => panic!("unexpected state {state:?}"),
+        };
+        let receipt = ethers_client
+            .get_transaction_receipt(tx_hash)
+            .await
+            .expect("receipt should be fetched")
+            .expect("transaction should be mined");
+        assert_eq!(receipt.status, Some(U64::zero()));
+        let request = storage
+            .get_proof_request(proof_id.clone())
+            .await
+            .expect("proof should exist");
+        assert_eq!(
+            revert_reason(&request.revert_data.expect("revert data should be kept")),
+            "RevertingProxy: batch reverted"
+        );
+    }
+
+    #[tokio::test]
+    async fn integration_test_dropped_callback_is_dead_lettered() {
+        abigen!(
+            RevertingProxy,
+            "../ethereum/out/ProxyTest.sol/RevertingProxy.json"
+        );
+        use bonsai_ethereum_contracts::i_bonsai_relay;
+        use ethers::prelude::*;
+
+        let anvil = utils::get_anvil();
+        let ethers_client_config = utils::get_ethers_client_config(anvil.as_ref())
+            .await
+            .expect("Failed to get ethers client config");
+        let ethers_client = Arc::new(
+            ethers_client_config
+                .get_client()
+                .await
+                .expect("could not get client"),
+        );
+        let (proof_id, server) = get_test_bonsai_server().await;
+
+        // The relay reverts every callback, simulated or not.
+        let proxy = RevertingProxy::deploy(ethers_client.clone(), ())
+            .expect("should be able to deploy the RevertingProxy contract")
+            .send()
+            .await
+            .expect("deployment should succeed");
+
+        let bonsai_client = get_client_from_parts(server.uri(), String::default())
+            .await
+            .unwrap();
+        let storage = InMemoryStorage::new();
+        let new_complete_proofs_notifier = Arc::new(Notify::new());
+        let send_batch_notifier = Arc::new(Notify::new());
+        let mut send_batch_interval =
+            tokio::time::interval(tokio::time::Duration::from_millis(10000000000));
+        send_batch_interval.tick().await;
+
+        let mut manager = BonsaiCompleteProofManager::new(
+            bonsai_client,
+            true,
+            storage.clone(),
+            new_complete_proofs_notifier.clone(),
+            send_batch_notifier.clone(),
+            3,
+            proxy.address(),
+            ethers_client_config.clone(),
+            send_batch_interval,
+            3000000,
+            RetryPolicy::default(),
+            TransactionPolicy::default(),
+            QuarantinePolicy::Drop,
+            WebhookPolicy::default(),
+            Arc::new(RelayControl::default()),
+            Arc::new(RelayMetrics::new()),
+        );
+
+        storage
+            .add_new_bonsai_proof_request(ProofRequestInformation {
+                proof_request_id: proof_id.clone(),
+                callback_proof_request_event: i_bonsai_relay::CallbackRequestFilter {
+                    account: Address::default(),
+                    image_id: H256::default().into(),
+                    input: Bytes::default(),
+                    callback_contract: Address::default(),
+                    function_selector: [0xab, 0xcd, 0xef, 0xab],
+                    gas_limit: 3000000,
+                },
+                source_event: None,
+                block_hash: None,
+                attempts: 0,
+                last_error: None,
+                revert_data: None,
+                webhook_url: None,
+            })
+            .await
+            .expect("storage should succeed");
+        storage
+            .transition_proof_request(proof_id.clone(), ProofRequestState::Pending)
+            .await
+            .expect("should transition to pending");
+        storage
+            .transition_proof_request(proof_id.clone(), ProofRequestState::Completed)
+            .await
+            .expect("should transition to pending to completed");
+
+        new_complete_proofs_notifier.notify_one();
+        manager.step().await.expect("step should succeed");
+        manager.step().await.expect("step should succeed");
+
+        // The callback is dead-lettered instead of staying quarantined, with
+        // the reason its simulation reverted.
+        assert_eq!(
+            storage
+                .get_proof_request_state(proof_id.clone())
+                .await
+                .expect("proof should exist"),
+            ProofRequestState::DeadLetter
+        );
+        let request = storage
+            .get_proof_request(proof_id.clone())
+            .await
+            .expect("proof should exist");
+        assert_eq!(
+            revert_reason(&request.revert_data.expect("revert data should be kept")),
+            "RevertingProxy: callback reverted"
+        );
+    }
+
+    #[tokio::test]
+    async fn integration_test_completed_proof_manager_webhook() {
+        // Mock API server
+        let (proof_id, server) = get_test_bonsai_server().await;
+
+        // The webhook fails once, then accepts the proof.
+        let webhook = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/proofs"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .mount(&webhook)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/proofs"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&webhook)
+            .await;
+        let webhook_url = format!("{}/proofs", webhook.uri());
+
+        let anvil = utils::get_anvil();
+        let ethers_client_config = utils::get_ethers_client_config(anvil.as_ref())
+            .await
+            .expect("Failed to get ethers client config");
+        let bonsai_client = get_client_from_parts(server.uri(), String::default())
+            .await
+            .unwrap();
+        let storage = InMemoryStorage::new();
+        let new_complete_proofs_notifier = Arc::new(Notify::new());
+        let mut send_batch_interval =
+            tokio::time::interval(tokio::time::Duration::from_millis(10000000000));
+        send_batch_interval.tick().await;
+
+        let mut manager = BonsaiCompleteProofManager::new(
+            bonsai_client,
+            true,
+            storage.clone(),
+            new_complete_proofs_notifier.clone(),
+            Arc::new(Notify::new()),
+            3,
+            Address::default(),
+            ethers_client_config,
+            send_batch_interval,
+            3000000,
+            RetryPolicy {
+                initial_backoff: std::time::Duration::from_millis(10),
+                ..Default::default()
+            },
+            TransactionPolicy::default(),
+            QuarantinePolicy::default(),
+            WebhookPolicy {
+                signing_key: "secret".to_string(),
+                ..Default::default()
+            },
+            Arc::new(RelayControl::default()),
+            Arc::new(RelayMetrics::new()),
+        );
+
+        storage
+            .add_new_bonsai_proof_request(ProofRequestInformation {
+                proof_request_id: proof_id.clone(),
+                callback_proof_request_event: CallbackRequestFilter {
+                    account: Address::default(),
+                    image_id: H256::default().into(),
+                    input: Bytes::default(),
+                    callback_contract: Address::default(),
+                    function_selector: [0; 4],
+                    gas_limit: 0,
+                },
+                source_event: None,
+                block_hash: None,
+                attempts: 0,
+                last_error: None,
+                revert_data: None,
+                webhook_url: Some(webhook_url.clone()),
+            })
+            .await
+            .expect("storage should succeed");
+        storage
+            .transition_proof_request(proof_id.clone(), ProofRequestState::Pending)
+            .await
+            .expect("should transition to pending");
+        storage
+            .transition_proof_request(proof_id.clone(), ProofRequestState::Completed)
+            .await
+            .expect("should transition to completed");
+        new_complete_proofs_notifier.notify_one();
+
+        // Fetch the proof, then post it to the webhook twice.
+        for _ in 0..4 {
+            manager.step().await.expect("step should succeed");
+        }
+
+        let deliveries = storage
+            .fetch_webhook_deliveries(proof_id.clone())
+            .await
+            .unwrap();
+        let status_codes: Vec<Option<u16>> = deliveries
+            .iter()
+            .map(|delivery| delivery.status_code)
+            .collect();
+        assert_eq!(status_codes, vec![Some(503), Some(200)]);
+        assert!(deliveries
+            .iter()
+            .all(|delivery| delivery.url == webhook_url));
+        assert_eq!(webhook.received_requests().await.unwrap().len(), 2);
+
+        // Delivered requests are no longer kept in memory.
+        assert!(matches!(
+            storage.get_proof_request_state(proof_id.clone()).await,
+            Err(StorageError::ProofNotFound { .. })
+        ));
+    }
 }
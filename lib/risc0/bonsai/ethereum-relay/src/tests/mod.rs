@@ -12,6 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod block_history;
 mod bonsai_pending_proof_requests;
+mod confirmations;
+mod log_polling;
 mod manager;
+mod remote_signer;
+mod transaction_manager;
 mod utils;
@@ -0,0 +1,175 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::net::SocketAddr;
+
+    use axum::{extract::State, routing::post, Json, Router};
+    use ethers::{
+        core::k256::elliptic_curve::sec1::ToEncodedPoint,
+        signers::{LocalWallet, Signer},
+        types::{
+            transaction::eip2718::TypedTransaction, Address, Eip1559TransactionRequest,
+            TransactionRequest, H256,
+        },
+        utils::keccak256,
+    };
+    use serde::Deserialize;
+
+    use crate::signer::{RelaySignerError, RemoteSigner};
+
+    const CHAIN_ID: u64 = 31337;
+
+    #[derive(Deserialize)]
+    struct SignRequest {
+        data: String,
+    }
+
+    #[derive(Clone)]
+    struct TestSigner {
+        wallet: LocalWallet,
+        /// Return `v` as the bare recovery ID (0 or 1) instead of 27 or 28.
+        bare_recovery_id: bool,
+    }
+
+    async fn sign(State(signer): State<TestSigner>, Json(request): Json<SignRequest>) -> String {
+        let data = hex::decode(request.data.trim_start_matches("0x")).unwrap();
+        let mut signature = signer.wallet.sign_hash(H256(keccak256(data))).unwrap();
+        if signer.bare_recovery_id {
+            signature.v -= 27;
+        }
+        format!("0x{signature}")
+    }
+
+    async fn spawn_signer(signer: TestSigner) -> String {
+        let app = Router::new()
+            .route("/api/v1/eth1/sign/:identifier", post(sign))
+            .with_state(signer);
+        let server = axum::Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0)))
+            .serve(app.into_make_service());
+        let url = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+        url
+    }
+
+    /// Starts a local stand-in for a web3signer instance holding the given
+    /// wallet and returns its URL.
+    pub(crate) async fn spawn_test_remote_signer(wallet: LocalWallet) -> String {
+        spawn_signer(TestSigner {
+            wallet,
+            bare_recovery_id: false,
+        })
+        .await
+    }
+
+    fn public_key(wallet: &LocalWallet) -> String {
+        hex::encode(
+            wallet
+                .signer()
+                .verifying_key()
+                .to_encoded_point(false)
+                .as_bytes(),
+        )
+    }
+
+    fn test_wallet() -> LocalWallet {
+        "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80"
+            .parse::<LocalWallet>()
+            .unwrap()
+            .with_chain_id(CHAIN_ID)
+    }
+
+    #[tokio::test]
+    async fn test_remote_signer_matches_local_wallet() {
+        let wallet = test_wallet();
+        let url = spawn_test_remote_signer(wallet.clone()).await;
+        let signer = RemoteSigner::new(&url, &public_key(&wallet), CHAIN_ID).unwrap();
+        assert_eq!(signer.address(), wallet.address());
+
+        let transactions: [TypedTransaction; 2] = [
+            TransactionRequest::new()
+                .to(Address::repeat_byte(1))
+                .value(1000)
+                .gas(21000)
+                .gas_price(1)
+                .nonce(0)
+                .into(),
+            Eip1559TransactionRequest::new()
+                .to(Address::repeat_byte(1))
+                .data(vec![1, 2, 3])
+                .gas(3000000)
+                .max_fee_per_gas(2)
+                .max_priority_fee_per_gas(1)
+                .nonce(7)
+                .into(),
+        ];
+        for tx in transactions.iter() {
+            assert_eq!(
+                signer.sign_transaction(tx).await.unwrap(),
+                wallet.sign_transaction(tx).await.unwrap()
+            );
+        }
+
+        assert_eq!(
+            signer.sign_message("relay").await.unwrap(),
+            wallet.sign_message("relay").await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_remote_signer_accepts_bare_recovery_id() {
+        let wallet = test_wallet();
+        let url = spawn_signer(TestSigner {
+            wallet: wallet.clone(),
+            bare_recovery_id: true,
+        })
+        .await;
+        let signer = RemoteSigner::new(&url, &public_key(&wallet), CHAIN_ID).unwrap();
+
+        // Signatures of several transactions almost surely use both recovery
+        // IDs.
+        for nonce in 0..8u64 {
+            let tx: TypedTransaction = TransactionRequest::new()
+                .to(Address::repeat_byte(1))
+                .value(1000)
+                .gas(21000)
+                .gas_price(1)
+                .nonce(nonce)
+                .into();
+            assert_eq!(
+                signer.sign_transaction(&tx).await.unwrap(),
+                wallet.sign_transaction(&tx).await.unwrap()
+            );
+        }
+
+        assert_eq!(
+            signer.sign_message("relay").await.unwrap(),
+            wallet.sign_message("relay").await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_remote_signer_rejects_signature_from_other_key() {
+        let wallet = test_wallet();
+        let other_wallet = LocalWallet::new(&mut ethers::core::rand::thread_rng());
+        let url = spawn_test_remote_signer(other_wallet).await;
+        let signer = RemoteSigner::new(&url, &public_key(&wallet), CHAIN_ID).unwrap();
+
+        assert!(matches!(
+            signer.sign_message("relay").await,
+            Err(RelaySignerError::Signature(_))
+        ));
+    }
+}
@@ -0,0 +1,274 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{fmt::Debug, sync::Mutex, time::Duration};
+
+    use ethers::{
+        providers::{JsonRpcClient, JsonRpcError, Middleware, MockError, Provider},
+        types::{Address, Block, Eip1559TransactionRequest, TransactionReceipt, H256, U256, U64},
+    };
+    use serde::{de::DeserializeOwned, Serialize};
+    use serde_json::{json, Value};
+
+    use crate::uploader::transaction_manager::{
+        TransactionError, TransactionManager, TransactionPolicy,
+    };
+
+    const BASE_FEE: u64 = 10_000_000_000;
+
+    /// An in-process Ethereum node that mines every transaction paying at
+    /// least `min_max_fee` per gas, and optionally every cancellation. The
+    /// first `receipt_failures` receipt requests fail.
+    #[derive(Debug)]
+    struct MockNode {
+        nonce: U256,
+        min_max_fee: U256,
+        mine_cancellations: bool,
+        receipt_failures: Mutex<u32>,
+        sent: Mutex<Vec<Value>>,
+        mined: Mutex<Vec<H256>>,
+    }
+
+    impl MockNode {
+        fn new(nonce: u64, min_max_fee: U256) -> Self {
+            Self {
+                nonce: U256::from(nonce),
+                min_max_fee,
+                mine_cancellations: false,
+                receipt_failures: Mutex::new(0),
+                sent: Mutex::new(Vec::new()),
+                mined: Mutex::new(Vec::new()),
+            }
+        }
+
+        fn sent(&self) -> Vec<Value> {
+            self.sent.lock().unwrap().clone()
+        }
+    }
+
+    fn u256(value: &Value) -> U256 {
+        serde_json::from_value(value.clone()).unwrap()
+    }
+
+    #[async_trait::async_trait]
+    impl JsonRpcClient for MockNode {
+        type Error = MockError;
+
+        async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+        where
+            T: Debug + Serialize + Send + Sync,
+            R: DeserializeOwned + Send,
+        {
+            let params = serde_json::to_value(params)?;
+            let result = match method {
+                "eth_getTransactionCount" => serde_json::to_value(self.nonce)?,
+                "eth_getBlockByNumber" => serde_json::to_value(Block::<H256> {
+                    number: Some(U64::one()),
+                    base_fee_per_gas: Some(U256::from(BASE_FEE)),
+                    ..Default::default()
+                })?,
+                "eth_feeHistory" => json!({
+                    "oldestBlock": "0x1",
+                    "baseFeePerGas": [format!("{BASE_FEE:#x}"), format!("{BASE_FEE:#x}")],
+                    "gasUsedRatio": [0.5],
+                    "reward": [["0x3b9aca00"]],
+                }),
+                "eth_sendTransaction" => {
+                    let tx = params[0].clone();
+                    let mut sent = self.sent.lock().unwrap();
+                    sent.push(tx.clone());
+                    let tx_hash = H256::from_low_u64_be(sent.len() as u64);
+
+                    let cancellation = tx["to"] == tx["from"];
+                    if u256(&tx["maxFeePerGas"]) >= self.min_max_fee
+                        || (cancellation && self.mine_cancellations)
+                    {
+                        self.mined.lock().unwrap().push(tx_hash);
+                    }
+                    serde_json::to_value(tx_hash)?
+                }
+                "eth_getTransactionReceipt" if *self.receipt_failures.lock().unwrap() > 0 => {
+                    *self.receipt_failures.lock().unwrap() -= 1;
+                    return Err(MockError::JsonRpcError(JsonRpcError {
+                        code: -32000,
+                        message: "receipt unavailable".to_string(),
+                        data: None,
+                    }));
+                }
+                "eth_getTransactionReceipt" => {
+                    let tx_hash: H256 = serde_json::from_value(params[0].clone())?;
+                    match self.mined.lock().unwrap().contains(&tx_hash) {
+                        true => serde_json::to_value(TransactionReceipt {
+                            transaction_hash: tx_hash,
+                            block_number: Some(U64::one()),
+                            status: Some(U64::one()),
+                            ..Default::default()
+                        })?,
+                        false => Value::Null,
+                    }
+                }
+                _ => {
+                    return Err(MockError::JsonRpcError(JsonRpcError {
+                        code: -32601,
+                        message: format!("method {method} not found"),
+                        data: None,
+                    }))
+                }
+            };
+            Ok(serde_json::from_value(result)?)
+        }
+    }
+
+    fn sender() -> Address {
+        Address::repeat_byte(0xaa)
+    }
+
+    fn provider(node: MockNode) -> Provider<MockNode> {
+        Provider::new(node).with_sender(sender())
+    }
+
+    fn policy(max_fee_per_gas: U256) -> TransactionPolicy {
+        TransactionPolicy {
+            max_fee_per_gas,
+            confirmation_timeout: Duration::from_millis(20),
+            fee_bump_percent: 20,
+            max_replacements: 3,
+            poll_interval: Duration::from_millis(5),
+        }
+    }
+
+    fn transaction() -> Eip1559TransactionRequest {
+        Eip1559TransactionRequest::new()
+            .to(Address::repeat_byte(0xbb))
+            .data(vec![1, 2, 3])
+            .gas(100_000)
+    }
+
+    /// The max fee per gas the node suggests.
+    async fn estimated_max_fee() -> U256 {
+        let (max_fee, _) = provider(MockNode::new(0, U256::MAX))
+            .estimate_eip1559_fees(None)
+            .await
+            .unwrap();
+        max_fee
+    }
+
+    #[tokio::test]
+    async fn test_underpriced_transaction_is_replaced() {
+        let estimate = estimated_max_fee().await;
+        // Only the second replacement, paying 44% more, is mined.
+        let provider = provider(MockNode::new(3, estimate * 130 / 100));
+        let manager = TransactionManager::new(policy(U256::MAX));
+
+        let receipt = manager
+            .send(&provider, transaction().into())
+            .await
+            .expect("replacement should be mined");
+
+        let sent = provider.as_ref().sent();
+        assert_eq!(sent.len(), 3);
+        assert_eq!(receipt.transaction_hash, H256::from_low_u64_be(3));
+        let max_fees: Vec<U256> = sent.iter().map(|tx| u256(&tx["maxFeePerGas"])).collect();
+        assert_eq!(max_fees[0], estimate);
+        assert!(max_fees
+            .windows(2)
+            .all(|fees| fees[1] >= fees[0] * 110 / 100));
+        for tx in &sent {
+            assert_eq!(u256(&tx["nonce"]), U256::from(3));
+            assert_eq!(tx["data"], sent[0]["data"]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_receipt_polling_errors_are_retried() {
+        let node = MockNode::new(0, U256::zero());
+        *node.receipt_failures.lock().unwrap() = 1;
+        let provider = provider(node);
+        let manager = TransactionManager::new(policy(U256::MAX));
+
+        let receipt = manager
+            .send(&provider, transaction().into())
+            .await
+            .expect("transaction should be mined");
+
+        // The transaction is neither replaced nor cancelled.
+        assert_eq!(provider.as_ref().sent().len(), 1);
+        assert_eq!(receipt.transaction_hash, H256::from_low_u64_be(1));
+    }
+
+    #[tokio::test]
+    async fn test_fees_are_capped() {
+        let cap = estimated_max_fee().await / 2;
+        let provider = provider(MockNode::new(0, U256::zero()));
+        let manager = TransactionManager::new(policy(cap));
+
+        manager
+            .send(&provider, transaction().into())
+            .await
+            .expect("transaction should be mined");
+
+        let sent = provider.as_ref().sent();
+        assert_eq!(u256(&sent[0]["maxFeePerGas"]), cap);
+        assert!(u256(&sent[0]["maxPriorityFeePerGas"]) <= cap);
+    }
+
+    #[tokio::test]
+    async fn test_stuck_transaction_is_cancelled() {
+        let estimate = estimated_max_fee().await;
+        // Nothing but the cancellation is mined, and the cap leaves no room to
+        // replace the transaction.
+        let mut node = MockNode::new(5, U256::MAX);
+        node.mine_cancellations = true;
+        let provider = provider(node);
+        let manager = TransactionManager::new(policy(estimate));
+
+        let result = manager.send(&provider, transaction().into()).await;
+        assert!(
+            matches!(result, Err(TransactionError::Cancelled { nonce, .. }) if nonce == U256::from(5))
+        );
+
+        let sent = provider.as_ref().sent();
+        assert_eq!(sent.len(), 2);
+        let cancellation = &sent[1];
+        assert_eq!(cancellation["to"], json!(sender()));
+        assert_eq!(u256(&cancellation["value"]), U256::zero());
+        assert_eq!(u256(&cancellation["nonce"]), U256::from(5));
+        assert!(u256(&cancellation["maxFeePerGas"]) > estimate);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_sends_use_distinct_nonces() {
+        let provider = provider(MockNode::new(7, U256::zero()));
+        let manager = TransactionManager::new(policy(U256::MAX));
+
+        let (first, second) = tokio::join!(
+            manager.send(&provider, transaction().into()),
+            manager.send(&provider, transaction().into()),
+        );
+        first.unwrap();
+        second.unwrap();
+        manager.send(&provider, transaction().into()).await.unwrap();
+
+        let mut nonces: Vec<U256> = provider
+            .as_ref()
+            .sent()
+            .iter()
+            .map(|tx| u256(&tx["nonce"]))
+            .collect();
+        nonces.sort();
+        assert_eq!(nonces, vec![U256::from(7), U256::from(8), U256::from(9)]);
+    }
+}
@@ -19,7 +19,10 @@ pub(crate) mod tests {
         responses::{CreateSessRes, SessionStatusRes, SnarkProof, SnarkStatusRes},
         SessionId,
     };
-    use ethers::types::{Address, Bytes, H256, U256};
+    use ethers::{
+        contract::LogMeta,
+        types::{Address, Bytes, H256, U256},
+    };
     use risc0_zkvm::{receipt::InnerReceipt, Receipt};
     use uuid::Uuid;
     use wiremock::{
@@ -118,6 +121,7 @@ pub(crate) mod tests {
         async fn process_event(
             &self,
             event: CallbackRequestFilter,
+            _meta: LogMeta,
         ) -> Result<(), crate::api::error::Error> {
             assert_eq!(event.account, self.expected_account);
             assert_eq!(H256::from(event.image_id), self.expected_image_id);
@@ -0,0 +1,140 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{fmt::Debug, str::FromStr};
+
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use ethers::{
+    providers::{
+        Http, HttpClientError, JsonRpcClient, JsonRpcError, ProviderError, PubsubClient, RpcError,
+        Ws, WsClientError,
+    },
+    types::U256,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+
+/// Number of times a websocket connection is re-established before failing.
+const WS_RECONNECTS: usize = 60;
+
+/// The JSON-RPC transport used to reach the Ethereum node, selected by the
+/// scheme of its URL.
+///
+/// Websocket endpoints support log subscriptions. HTTP endpoints do not, so
+/// the relay polls them for logs instead.
+#[derive(Debug, Clone)]
+pub enum RelayTransport {
+    Ws(Ws),
+    Http(Http),
+}
+
+#[derive(Error, Debug)]
+pub enum RelayTransportError {
+    #[error(transparent)]
+    Ws(#[from] WsClientError),
+    #[error(transparent)]
+    Http(#[from] HttpClientError),
+    #[error("subscriptions are not supported over HTTP")]
+    SubscriptionsUnsupported,
+}
+
+impl RpcError for RelayTransportError {
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            RelayTransportError::Ws(error) => error.as_error_response(),
+            RelayTransportError::Http(error) => error.as_error_response(),
+            RelayTransportError::SubscriptionsUnsupported => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            RelayTransportError::Ws(error) => error.as_serde_error(),
+            RelayTransportError::Http(error) => error.as_serde_error(),
+            RelayTransportError::SubscriptionsUnsupported => None,
+        }
+    }
+}
+
+impl From<RelayTransportError> for ProviderError {
+    fn from(error: RelayTransportError) -> Self {
+        ProviderError::JsonRpcClientError(Box::new(error))
+    }
+}
+
+impl RelayTransport {
+    /// Connect to the Ethereum node at the given `ws://`, `wss://`, `http://`
+    /// or `https://` URL.
+    pub async fn connect(url: &str) -> Result<Self> {
+        if is_ws_url(url) {
+            let ws = Ws::connect_with_reconnects(url, WS_RECONNECTS)
+                .await
+                .context("Failed to connect to Ethereum node.")?;
+            Ok(RelayTransport::Ws(ws))
+        } else if is_http_url(url) {
+            let http = Http::from_str(url).context("Invalid Ethereum node URL.")?;
+            Ok(RelayTransport::Http(http))
+        } else {
+            bail!("Unsupported Ethereum node URL scheme: {url}")
+        }
+    }
+
+    /// Whether the transport supports log subscriptions.
+    pub fn supports_subscriptions(&self) -> bool {
+        matches!(self, RelayTransport::Ws(_))
+    }
+}
+
+pub(crate) fn is_ws_url(url: &str) -> bool {
+    url.starts_with("ws://") || url.starts_with("wss://")
+}
+
+pub(crate) fn is_http_url(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+#[async_trait]
+impl JsonRpcClient for RelayTransport {
+    type Error = RelayTransportError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        match self {
+            RelayTransport::Ws(ws) => Ok(ws.request(method, params).await?),
+            RelayTransport::Http(http) => Ok(http.request(method, params).await?),
+        }
+    }
+}
+
+impl PubsubClient for RelayTransport {
+    type NotificationStream = <Ws as PubsubClient>::NotificationStream;
+
+    fn subscribe<T: Into<U256>>(&self, id: T) -> Result<Self::NotificationStream, Self::Error> {
+        match self {
+            RelayTransport::Ws(ws) => Ok(ws.subscribe(id)?),
+            RelayTransport::Http(_) => Err(RelayTransportError::SubscriptionsUnsupported),
+        }
+    }
+
+    fn unsubscribe<T: Into<U256>>(&self, id: T) -> Result<(), Self::Error> {
+        match self {
+            RelayTransport::Ws(ws) => Ok(ws.unsubscribe(id)?),
+            RelayTransport::Http(_) => Err(RelayTransportError::SubscriptionsUnsupported),
+        }
+    }
+}
@@ -12,6 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::{sync::Arc, time::Instant};
+
 use bonsai_ethereum_contracts::i_bonsai_relay::{
     Callback, CallbackAuthorization, CallbackRequestFilter,
 };
@@ -19,16 +21,20 @@ use bonsai_sdk::{
     alpha::{Client, SessionId},
     alpha_async::{download, session_status},
 };
-use ethers::abi;
+use ethers::{abi, types::Bytes};
 use risc0_zkvm::Receipt;
 
 use super::snark::tokenize_snark_proof;
-use crate::{api, uploader::completed_proofs::error::CompleteProofError};
+use crate::{api, metrics::RelayMetrics, uploader::completed_proofs::error::CompleteProofError};
 
 #[derive(Debug, Clone)]
 pub(crate) struct CompleteProof {
     pub bonsai_proof_id: SessionId,
     pub ethereum_callback: Callback,
+    pub image_id: [u8; 32],
+    pub journal: Bytes,
+    /// The URL to post the proof to instead of sending the callback on chain.
+    pub webhook_url: Option<String>,
 }
 
 pub(crate) async fn get_complete_proof(
@@ -36,6 +42,8 @@ pub(crate) async fn get_complete_proof(
     dev_mode: bool,
     bonsai_proof_id: SessionId,
     callback_request: CallbackRequestFilter,
+    webhook_url: Option<String>,
+    metrics: Arc<RelayMetrics>,
 ) -> Result<CompleteProof, CompleteProofError> {
     let bonsai_response = session_status(bonsai_client.clone(), bonsai_proof_id.clone())
         .await
@@ -60,11 +68,13 @@ pub(crate) async fn get_complete_proof(
             id: bonsai_proof_id.clone(),
         })?;
 
+    let snark_started = Instant::now();
     let snark_id =
         super::snark::get_snark_id(bonsai_client.clone(), bonsai_proof_id.clone()).await?;
     let snark_proof =
         super::snark::get_snark_proof(bonsai_client.clone(), snark_id, bonsai_proof_id.clone())
             .await?;
+    metrics.observe_snark(&callback_request.image_id, snark_started.elapsed());
     let seal = match dev_mode {
         true => vec![],
         false => abi::encode(&[tokenize_snark_proof(&snark_proof).map_err(|_| {
@@ -113,5 +123,8 @@ pub(crate) async fn get_complete_proof(
     Ok(CompleteProof {
         bonsai_proof_id,
         ethereum_callback,
+        image_id: callback_request.image_id,
+        journal: receipt.journal.into(),
+        webhook_url,
     })
 }
@@ -13,22 +13,17 @@
 // limitations under the License.
 
 use displaydoc::Display;
-use ethers::{prelude::ProviderError, types::H256};
 use thiserror::Error;
 use tokio::task::JoinError;
 
 use crate::{
     api::error::Error,
     storage::{Error as StorageError, ProofID},
+    uploader::transaction_manager::TransactionError,
 };
 
 #[derive(Debug, thiserror::Error)]
 pub(crate) enum BonsaiCompleteProofManagerError {
-    #[error("Ethers failed")]
-    Ethers {
-        #[source]
-        source: Box<dyn std::error::Error + Send + Sync>,
-    },
     #[error("Ethers Client failed")]
     EthersClient(#[from] anyhow::Error),
     #[error("Failed to operate on storage")]
@@ -41,22 +36,8 @@ pub(crate) enum BonsaiCompleteProofManagerError {
     CompleteProof(#[from] CompleteProofError),
     #[error("Join Error")]
     JoinHandle(#[from] JoinError),
-    #[error("Failed to confirm transaction on chain")]
-    Confirmation {
-        #[source]
-        source: ProviderError,
-        tx_hash: H256,
-    },
-}
-
-impl BonsaiCompleteProofManagerError {
-    pub(crate) fn get_proof_request_id(self) -> Option<ProofID> {
-        match self {
-            Self::Storage { id, .. } => id,
-            Self::CompleteProof(e) => Some(e.get_proof_request_id()),
-            _ => None,
-        }
-    }
+    #[error("Failed to send transaction")]
+    Transaction(#[from] TransactionError),
 }
 
 // Cannot use async functions that return snafu errors with tokio::spawn cleanly
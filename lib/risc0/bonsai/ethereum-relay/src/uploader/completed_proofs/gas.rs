@@ -0,0 +1,159 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Gas estimates of `invokeCallbacks` transactions.
+
+use bonsai_ethereum_contracts::i_bonsai_relay::Callback;
+use ethers::abi::AbiEncode;
+
+use super::complete_proof::CompleteProof;
+
+/// Intrinsic gas of a transaction.
+const TRANSACTION_BASE_GAS: u64 = 21_000;
+/// Gas of the Groth16 verification authorizing each callback.
+const GROTH16_VERIFICATION_GAS: u64 = 300_000;
+/// Gas spent by the relay contract around each callback: decoding, hashing
+/// the journal and the call itself.
+const CALLBACK_OVERHEAD_GAS: u64 = 20_000;
+const CALLDATA_ZERO_BYTE_GAS: u64 = 4;
+const CALLDATA_NONZERO_BYTE_GAS: u64 = 16;
+
+/// Estimated gas of invoking a single callback in a batch.
+pub(crate) fn callback_gas(callback: &Callback) -> u64 {
+    // A call only forwards 63/64 of the remaining gas, so the relay needs a
+    // little more than the gas limit of the callback to honour it.
+    let forwarded_gas = callback.gas_limit.saturating_mul(64) / 63;
+    let calldata_gas: u64 = callback
+        .clone()
+        .encode()
+        .iter()
+        .map(|byte| match byte {
+            0 => CALLDATA_ZERO_BYTE_GAS,
+            _ => CALLDATA_NONZERO_BYTE_GAS,
+        })
+        .sum();
+    GROTH16_VERIFICATION_GAS
+        .saturating_add(CALLBACK_OVERHEAD_GAS)
+        .saturating_add(calldata_gas)
+        .saturating_add(forwarded_gas)
+}
+
+/// Estimated gas of a transaction invoking the given callbacks.
+pub(crate) fn batch_gas<'a>(callbacks: impl IntoIterator<Item = &'a Callback>) -> u64 {
+    callbacks
+        .into_iter()
+        .fold(TRANSACTION_BASE_GAS, |gas, callback| {
+            gas.saturating_add(callback_gas(callback))
+        })
+}
+
+/// Split the proofs, in order, into batches whose estimated gas fits in the
+/// budget. A proof whose callback alone exceeds the budget gets a batch of
+/// its own.
+pub(crate) fn pack_batches(
+    proofs: Vec<CompleteProof>,
+    max_batch_size: usize,
+    gas_budget: u64,
+) -> Vec<Vec<CompleteProof>> {
+    let mut batches = Vec::new();
+    let mut batch: Vec<CompleteProof> = Vec::new();
+    let mut gas = TRANSACTION_BASE_GAS;
+    for proof in proofs {
+        let proof_gas = callback_gas(&proof.ethereum_callback);
+        if !batch.is_empty()
+            && (batch.len() >= max_batch_size || gas.saturating_add(proof_gas) > gas_budget)
+        {
+            batches.push(std::mem::take(&mut batch));
+            gas = TRANSACTION_BASE_GAS;
+        }
+        gas = gas.saturating_add(proof_gas);
+        batch.push(proof);
+    }
+    if !batch.is_empty() {
+        batches.push(batch);
+    }
+    batches
+}
+
+#[cfg(test)]
+mod tests {
+    use bonsai_ethereum_contracts::i_bonsai_relay::CallbackAuthorization;
+    use bonsai_sdk::alpha::SessionId;
+    use ethers::types::{Address, Bytes};
+
+    use super::*;
+
+    fn proof(id: &str, gas_limit: u64, payload_len: usize) -> CompleteProof {
+        CompleteProof {
+            bonsai_proof_id: SessionId::new(id.to_string()),
+            ethereum_callback: Callback {
+                auth: CallbackAuthorization {
+                    seal: Bytes::default(),
+                    post_state_digest: [0; 32],
+                },
+                callback_contract: Address::repeat_byte(1),
+                payload: Bytes::from(vec![0xff; payload_len]),
+                gas_limit,
+            },
+            image_id: [0; 32],
+            journal: Bytes::default(),
+            webhook_url: None,
+        }
+    }
+
+    fn ids(batches: &[Vec<CompleteProof>]) -> Vec<Vec<String>> {
+        batches
+            .iter()
+            .map(|batch| {
+                batch
+                    .iter()
+                    .map(|proof| proof.bonsai_proof_id.uuid.clone())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_callback_gas_grows_with_payload_and_gas_limit() {
+        let small = callback_gas(&proof("a", 100_000, 32).ethereum_callback);
+        assert!(small > GROTH16_VERIFICATION_GAS + 100_000);
+        assert!(callback_gas(&proof("a", 100_000, 1024).ethereum_callback) > small);
+        assert!(callback_gas(&proof("a", 200_000, 32).ethereum_callback) > small);
+    }
+
+    #[test]
+    fn test_batches_fit_in_the_budget() {
+        let proofs = vec![
+            proof("a", 100_000, 32),
+            proof("b", 100_000, 32),
+            proof("c", 1_000_000, 32),
+            proof("d", 100_000, 32),
+        ];
+        let budget = batch_gas([&proofs[0].ethereum_callback, &proofs[1].ethereum_callback]);
+
+        let batches = pack_batches(proofs, 10, budget);
+        assert_eq!(ids(&batches), vec![vec!["a", "b"], vec!["c"], vec!["d"]]);
+        assert!(batch_gas(batches[0].iter().map(|proof| &proof.ethereum_callback)) <= budget);
+    }
+
+    #[test]
+    fn test_batches_respect_the_maximum_size() {
+        let proofs = (0..5).map(|i| proof(&i.to_string(), 10_000, 32)).collect();
+        let batches = pack_batches(proofs, 2, u64::MAX);
+        assert_eq!(
+            ids(&batches),
+            vec![vec!["0", "1"], vec!["2", "3"], vec!["4"]]
+        );
+    }
+}
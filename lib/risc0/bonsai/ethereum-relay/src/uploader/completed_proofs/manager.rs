@@ -12,25 +12,36 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::sync::Arc;
+use std::{collections::VecDeque, sync::Arc, time::Duration};
 
 use bonsai_ethereum_contracts::{i_bonsai_relay::Callback, IBonsaiRelay};
 use bonsai_sdk::alpha::Client;
-use ethers::prelude::{k256::ecdsa::SigningKey, *};
-use futures::{stream::FuturesUnordered, StreamExt};
+use ethers::prelude::*;
+use futures::{future::BoxFuture, stream::FuturesUnordered, StreamExt};
 use tokio::{sync::Notify, task::JoinHandle};
-use tracing::info;
+use tracing::{error, info, warn};
 
 use crate::{
-    storage::{ProofRequestState, Storage},
-    uploader::completed_proofs::{
-        complete_proof::{get_complete_proof, CompleteProof},
-        error::*,
+    control::RelayControl,
+    metrics::RelayMetrics,
+    signer::RelaySigner,
+    storage::{ProofID, ProofRequestState, Storage, WebhookDelivery},
+    transport::RelayTransport,
+    uploader::{
+        completed_proofs::{
+            complete_proof::{get_complete_proof, CompleteProof},
+            error::*,
+            gas::{batch_gas, pack_batches},
+        },
+        quarantine::{revert_reason, QuarantinePolicy},
+        retry::{error_message, RetryPolicy},
+        transaction_manager::{TransactionManager, TransactionPolicy},
+        webhook::{WebhookDeliverer, WebhookPolicy},
     },
     EthersClientConfig,
 };
 
-const BONSAI_RELAY_GAS_LIMIT: u64 = 3000000;
+type RelayContract = IBonsaiRelay<SignerMiddleware<Provider<RelayTransport>, RelaySigner>>;
 
 pub(crate) struct BonsaiCompleteProofManager<S: Storage> {
     client: Client,
@@ -43,7 +54,16 @@ pub(crate) struct BonsaiCompleteProofManager<S: Storage> {
     ethers_client_config: EthersClientConfig,
     send_batch_notifier: Arc<Notify>,
     send_batch_interval: tokio::time::Interval,
+    gas_budget: u64,
+    retry_policy: RetryPolicy,
+    transaction_manager: TransactionManager,
+    quarantine_policy: QuarantinePolicy,
+    webhook_deliverer: WebhookDeliverer,
+    control: Arc<RelayControl>,
+    metrics: Arc<RelayMetrics>,
     futures_set: FuturesUnordered<JoinHandle<Result<CompleteProof, CompleteProofError>>>,
+    retries: FuturesUnordered<BoxFuture<'static, ProofID>>,
+    webhook_deliveries: FuturesUnordered<BoxFuture<'static, (CompleteProof, WebhookDelivery)>>,
 }
 
 impl<S: Storage> BonsaiCompleteProofManager<S> {
@@ -58,6 +78,13 @@ impl<S: Storage> BonsaiCompleteProofManager<S> {
         proxy_contract_address: Address,
         ethers_client_config: EthersClientConfig,
         send_batch_interval: tokio::time::Interval,
+        gas_budget: u64,
+        retry_policy: RetryPolicy,
+        transaction_policy: TransactionPolicy,
+        quarantine_policy: QuarantinePolicy,
+        webhook_policy: WebhookPolicy,
+        control: Arc<RelayControl>,
+        metrics: Arc<RelayMetrics>,
     ) -> Self {
         Self {
             client,
@@ -70,63 +97,151 @@ impl<S: Storage> BonsaiCompleteProofManager<S> {
             ethers_client_config,
             send_batch_notifier,
             send_batch_interval,
+            gas_budget,
+            retry_policy,
+            transaction_manager: TransactionManager::new(transaction_policy),
+            quarantine_policy,
+            webhook_deliverer: WebhookDeliverer::new(&webhook_policy),
+            control,
+            metrics,
             futures_set: FuturesUnordered::new(),
+            retries: FuturesUnordered::new(),
+            webhook_deliveries: FuturesUnordered::new(),
         }
     }
 
     async fn send_batch(&mut self) -> Result<(), BonsaiCompleteProofManagerError> {
-        if self.ready_to_send_batch.is_empty() {
+        // While sending is paused, the batch stays queued until it resumes.
+        if self.ready_to_send_batch.is_empty() || self.control.sending.is_paused() {
             return Ok(());
         }
-        let contract_call = {
-            let ethers_client = self.ethers_client_config.get_client().await?;
-            let bonsay_relay =
-                IBonsaiRelay::<SignerMiddleware<Provider<Ws>, Wallet<SigningKey>>>::new(
-                    self.proxy_contract_address,
-                    Arc::new(ethers_client),
-                );
-            let proof_batch: Vec<Callback> = self
-                .ready_to_send_batch
-                .clone()
-                .into_iter()
-                .map(|complete_proof| complete_proof.ethereum_callback)
+        let ethers_client = self.ethers_client_config.get_client().await?;
+        let bonsai_relay = RelayContract::new(self.proxy_contract_address, Arc::new(ethers_client));
+
+        let mut batches: VecDeque<Vec<CompleteProof>> = pack_batches(
+            self.ready_to_send_batch.clone(),
+            self.max_batch_size,
+            self.gas_budget,
+        )
+        .into();
+        while let Some(mut batch) = batches.pop_front() {
+            let proof_batch: Vec<Callback> = batch
+                .iter()
+                .map(|complete_proof| complete_proof.ethereum_callback.clone())
                 .collect();
+            let gas = batch_gas(&proof_batch);
 
-            info!("sending batch");
-            bonsay_relay
-                .invoke_callbacks(proof_batch)
-                .gas(BONSAI_RELAY_GAS_LIMIT)
-        };
+            // If the node cannot execute the batch with the estimated gas, send
+            // each half on its own instead.
+            if batch.len() > 1 {
+                if let Err(err) = bonsai_relay
+                    .invoke_callbacks(proof_batch.clone())
+                    .gas(gas)
+                    .estimate_gas()
+                    .await
+                {
+                    warn!(error = %err, size = batch.len(), gas, "failed to estimate batch, splitting it");
+                    let second_half = batch.split_off(batch.len() / 2);
+                    batches.push_front(second_half);
+                    batches.push_front(batch);
+                    continue;
+                }
+            }
 
-        let pending_tx =
-            contract_call
-                .send()
-                .await
-                .map_err(|e| BonsaiCompleteProofManagerError::Ethers {
-                    source: Box::new(e),
-                })?;
-        let tx_hash = pending_tx.tx_hash();
+            // Nothing of a reverted transaction took effect, so its callbacks
+            // are sent again in halves, isolating the one that makes it revert.
+            if self
+                .send_callbacks(&bonsai_relay, &batch, proof_batch, gas)
+                .await?
+            {
+                let second_half = batch.split_off(batch.len() / 2);
+                batches.push_front(second_half);
+                batches.push_front(batch);
+            }
+        }
 
-        pending_tx
-            .await
-            .map_err(|e| BonsaiCompleteProofManagerError::Confirmation { source: e, tx_hash })?;
+        Ok(())
+    }
 
-        for completed_proof in self.ready_to_send_batch.clone().into_iter() {
-            self.storage
-                .transition_proof_request(
-                    completed_proof.bonsai_proof_id.clone(),
-                    ProofRequestState::CompletedOnchain(tx_hash),
-                )
-                .await
-                .map_err(|e| BonsaiCompleteProofManagerError::Storage {
-                    source: e,
-                    id: Some(completed_proof.bonsai_proof_id.clone()),
-                })?;
+    /// Send a batch of callbacks in a single transaction and record the
+    /// outcome of each. Returns whether the transaction of a batch of several
+    /// callbacks reverted, in which case they are left to be sent again.
+    async fn send_callbacks(
+        &mut self,
+        bonsai_relay: &RelayContract,
+        batch: &[CompleteProof],
+        proof_batch: Vec<Callback>,
+        gas: u64,
+    ) -> Result<bool, BonsaiCompleteProofManagerError> {
+        info!(size = batch.len(), gas, "sending batch");
+        let contract_call = bonsai_relay.invoke_callbacks(proof_batch.clone()).gas(gas);
+        let receipt = self
+            .transaction_manager
+            .send(bonsai_relay.client().as_ref(), contract_call.tx)
+            .await?;
+        let tx_hash = receipt.transaction_hash;
+        self.metrics.observe_batch(
+            batch.iter().map(|complete_proof| &complete_proof.image_id),
+            receipt.gas_used,
+        );
+
+        let transaction_reverted = receipt.status == Some(U64::zero());
+        if transaction_reverted && batch.len() > 1 {
+            warn!(
+                ?tx_hash,
+                size = batch.len(),
+                "batch transaction reverted, splitting it"
+            );
+            return Ok(true);
         }
 
-        self.ready_to_send_batch.clear();
+        // invokeCallbacks does not revert when one of the callbacks does, so the
+        // outcome of each callback is taken from a replay of the transaction on
+        // the state of the block before the one it was mined in.
+        let replay_block = receipt
+            .block_number
+            .map(|block_number| BlockId::from(block_number.saturating_sub(U64::one())));
+        let outcomes = if transaction_reverted {
+            replay_batch_revert(bonsai_relay, &proof_batch, gas, replay_block)
+                .await
+                .map(|revert_data| vec![Some(revert_data)])
+        } else {
+            replay_callbacks(bonsai_relay, &proof_batch, gas, replay_block).await
+        };
+        // Callbacks whose outcome is unknown are not reported as completed, but
+        // kept as reverted for an operator to inspect.
+        let outcomes = outcomes.unwrap_or_else(|err| {
+            error!(?tx_hash, error = %err, "failed to replay batch, marking its callbacks reverted");
+            vec![Some(Bytes::default()); batch.len()]
+        });
 
-        Ok(())
+        for (completed_proof, revert_data) in batch.iter().zip(outcomes) {
+            let proof_id = completed_proof.bonsai_proof_id.clone();
+            self.ready_to_send_batch
+                .retain(|ready| ready.bonsai_proof_id != proof_id);
+            let result = match revert_data {
+                Some(revert_data) => {
+                    warn!(?proof_id, ?tx_hash, ?revert_data, "callback reverted");
+                    self.storage
+                        .mark_callback_reverted(proof_id.clone(), tx_hash, revert_data)
+                        .await
+                }
+                None => {
+                    self.storage
+                        .transition_proof_request(
+                            proof_id.clone(),
+                            ProofRequestState::CompletedOnchain(tx_hash),
+                        )
+                        .await
+                }
+            };
+            result.map_err(|e| BonsaiCompleteProofManagerError::Storage {
+                source: e,
+                id: Some(proof_id),
+            })?;
+        }
+
+        Ok(false)
     }
 
     async fn process_new_complete_proof_requests(
@@ -146,6 +261,8 @@ impl<S: Storage> BonsaiCompleteProofManager<S> {
                 self.dev_mode,
                 request.proof_request_id.clone(),
                 request.callback_proof_request_event,
+                request.webhook_url,
+                self.metrics.clone(),
             ));
             self.futures_set.push(completed_proof_request_handler);
 
@@ -170,10 +287,42 @@ impl<S: Storage> BonsaiCompleteProofManager<S> {
         &mut self,
         completed_proof_result: Result<CompleteProof, CompleteProofError>,
     ) -> Result<(), BonsaiCompleteProofManagerError> {
-        let completed_proof = completed_proof_result?;
+        let completed_proof = match completed_proof_result {
+            Ok(completed_proof) => completed_proof,
+            Err(err) => return self.handle_failed_complete_proof(err).await,
+        };
+
+        // Proofs with a webhook are posted there rather than sent on chain.
+        if let Some(url) = completed_proof.webhook_url.clone() {
+            self.webhook_deliveries
+                .push(
+                    self.webhook_deliverer
+                        .schedule(completed_proof, url, 1, Duration::ZERO),
+                );
+            return Ok(());
+        }
+
+        if let Some(revert_data) = self.preflight(&completed_proof).await {
+            let reason = revert_reason(&revert_data);
+            if self.quarantine_policy != QuarantinePolicy::Deliver {
+                return self
+                    .quarantine_complete_proof(completed_proof.bonsai_proof_id, revert_data, reason)
+                    .await;
+            }
+            warn!(
+                proof_id = ?completed_proof.bonsai_proof_id,
+                reason,
+                "callback reverts when simulated, delivering it anyway"
+            );
+        }
 
         self.ready_to_send_batch.push(completed_proof.clone());
-        if self.ready_to_send_batch.len() >= self.max_batch_size {
+        let ready_gas = batch_gas(
+            self.ready_to_send_batch
+                .iter()
+                .map(|complete_proof| &complete_proof.ethereum_callback),
+        );
+        if self.ready_to_send_batch.len() >= self.max_batch_size || ready_gas >= self.gas_budget {
             self.send_batch_notifier.notify_one();
         }
 
@@ -181,6 +330,288 @@ impl<S: Storage> BonsaiCompleteProofManager<S> {
         Ok(())
     }
 
+    /// Simulate the callback of a completed proof on its own, returning the
+    /// data it reverts with. Callbacks that cannot be simulated are assumed
+    /// to succeed, leaving the outcome to the batch transaction.
+    async fn preflight(&self, completed_proof: &CompleteProof) -> Option<Bytes> {
+        let ethers_client = match self.ethers_client_config.get_client().await {
+            Ok(ethers_client) => ethers_client,
+            Err(err) => {
+                warn!(error = %err, "failed to get client for pre-flight simulation");
+                return None;
+            }
+        };
+        let bonsai_relay = RelayContract::new(self.proxy_contract_address, Arc::new(ethers_client));
+        match bonsai_relay
+            .invoke_callback(completed_proof.ethereum_callback.clone())
+            .call()
+            .await
+        {
+            Ok(()) => None,
+            Err(err) => match err.as_revert() {
+                Some(revert_data) => Some(revert_data.clone()),
+                None => {
+                    warn!(
+                        proof_id = ?completed_proof.bonsai_proof_id,
+                        error = %err,
+                        "failed to simulate callback"
+                    );
+                    None
+                }
+            },
+        }
+    }
+
+    async fn quarantine_complete_proof(
+        &mut self,
+        proof_id: ProofID,
+        revert_data: Bytes,
+        reason: String,
+    ) -> Result<(), BonsaiCompleteProofManagerError> {
+        let attempts = self
+            .storage
+            .quarantine_proof_request(proof_id.clone(), revert_data, reason.clone())
+            .await
+            .map_err(|e| BonsaiCompleteProofManagerError::Storage {
+                source: e,
+                id: Some(proof_id.clone()),
+            })?;
+
+        match self.quarantine_policy {
+            QuarantinePolicy::Retry if self.retry_policy.should_retry(attempts) => {
+                let delay = self.retry_policy.delay(attempts);
+                warn!(
+                    ?proof_id,
+                    attempts,
+                    ?delay,
+                    reason,
+                    "callback reverts when simulated, quarantined until retried"
+                );
+                self.retries
+                    .push(self.retry_policy.schedule(proof_id, attempts));
+            }
+            QuarantinePolicy::Retry | QuarantinePolicy::Drop => {
+                self.storage
+                    .transition_proof_request(proof_id.clone(), ProofRequestState::DeadLetter)
+                    .await
+                    .map_err(|e| BonsaiCompleteProofManagerError::Storage {
+                        source: e,
+                        id: Some(proof_id.clone()),
+                    })?;
+                error!(
+                    ?proof_id,
+                    attempts, reason, "quarantined callback dead-lettered"
+                );
+            }
+            QuarantinePolicy::Deliver => {
+                warn!(
+                    ?proof_id,
+                    reason, "callback reverts when simulated, quarantined"
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Pick up the requests quarantined before a restart, according to the
+    /// current quarantine policy.
+    async fn resume_quarantined_proof_requests(
+        &mut self,
+    ) -> Result<(), BonsaiCompleteProofManagerError> {
+        let quarantined_requests = self
+            .storage
+            .fetch_quarantined_proof_requests(None)
+            .await
+            .map_err(|e| BonsaiCompleteProofManagerError::Storage {
+                source: e,
+                id: None,
+            })?;
+
+        for request in quarantined_requests.into_iter() {
+            match self.quarantine_policy {
+                QuarantinePolicy::Retry => self.retries.push(
+                    self.retry_policy
+                        .schedule(request.proof_request_id, request.attempts),
+                ),
+                QuarantinePolicy::Deliver => {
+                    self.storage
+                        .transition_proof_request(
+                            request.proof_request_id.clone(),
+                            ProofRequestState::Completed,
+                        )
+                        .await
+                        .map_err(|e| BonsaiCompleteProofManagerError::Storage {
+                            source: e,
+                            id: Some(request.proof_request_id.clone()),
+                        })?;
+                }
+                QuarantinePolicy::Drop => {
+                    self.storage
+                        .transition_proof_request(
+                            request.proof_request_id.clone(),
+                            ProofRequestState::DeadLetter,
+                        )
+                        .await
+                        .map_err(|e| BonsaiCompleteProofManagerError::Storage {
+                            source: e,
+                            id: Some(request.proof_request_id.clone()),
+                        })?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_failed_complete_proof(
+        &mut self,
+        err: CompleteProofError,
+    ) -> Result<(), BonsaiCompleteProofManagerError> {
+        let message = error_message(&err);
+        let proof_id = err.get_proof_request_id();
+        let attempts = self
+            .storage
+            .record_proof_request_failure(proof_id.clone(), message.clone())
+            .await
+            .map_err(|e| BonsaiCompleteProofManagerError::Storage {
+                source: e,
+                id: Some(proof_id.clone()),
+            })?;
+
+        if self.retry_policy.should_retry(attempts) {
+            let delay = self.retry_policy.delay(attempts);
+            warn!(
+                ?proof_id,
+                attempts,
+                ?delay,
+                error = message,
+                "completed proof failed, retrying"
+            );
+            self.retries
+                .push(self.retry_policy.schedule(proof_id, attempts));
+        } else {
+            self.storage
+                .transition_proof_request(proof_id.clone(), ProofRequestState::DeadLetter)
+                .await
+                .map_err(|e| BonsaiCompleteProofManagerError::Storage {
+                    source: e,
+                    id: Some(proof_id.clone()),
+                })?;
+            error!(
+                ?proof_id,
+                attempts,
+                error = message,
+                "completed proof dead-lettered"
+            );
+        }
+
+        Ok(())
+    }
+
+    async fn handle_webhook_delivery(
+        &mut self,
+        completed_proof: CompleteProof,
+        delivery: WebhookDelivery,
+    ) -> Result<(), BonsaiCompleteProofManagerError> {
+        let proof_id = completed_proof.bonsai_proof_id.clone();
+        let (url, attempt, error) = (
+            delivery.url.clone(),
+            delivery.attempt,
+            delivery.error.clone(),
+        );
+        self.storage
+            .record_webhook_delivery(delivery)
+            .await
+            .map_err(|e| BonsaiCompleteProofManagerError::Storage {
+                source: e,
+                id: Some(proof_id.clone()),
+            })?;
+
+        let error = match error {
+            Some(error) => error,
+            None => {
+                self.storage
+                    .transition_proof_request(proof_id.clone(), ProofRequestState::Delivered)
+                    .await
+                    .map_err(|e| BonsaiCompleteProofManagerError::Storage {
+                        source: e,
+                        id: Some(proof_id.clone()),
+                    })?;
+                info!(?proof_id, url, attempt, "proof posted to webhook");
+                return Ok(());
+            }
+        };
+
+        let attempts = self
+            .storage
+            .record_proof_request_failure(proof_id.clone(), error.clone())
+            .await
+            .map_err(|e| BonsaiCompleteProofManagerError::Storage {
+                source: e,
+                id: Some(proof_id.clone()),
+            })?;
+        if self.retry_policy.should_retry(attempts) {
+            let delay = self.retry_policy.delay(attempts);
+            warn!(
+                ?proof_id,
+                url,
+                attempt,
+                ?delay,
+                error,
+                "webhook delivery failed, retrying"
+            );
+            self.webhook_deliveries
+                .push(
+                    self.webhook_deliverer
+                        .schedule(completed_proof, url, attempt + 1, delay),
+                );
+        } else {
+            self.storage
+                .transition_proof_request(proof_id.clone(), ProofRequestState::DeadLetter)
+                .await
+                .map_err(|e| BonsaiCompleteProofManagerError::Storage {
+                    source: e,
+                    id: Some(proof_id.clone()),
+                })?;
+            error!(
+                ?proof_id,
+                url, attempts, error, "webhook delivery dead-lettered"
+            );
+        }
+
+        Ok(())
+    }
+
+    async fn retry_failed_complete_proof(
+        &mut self,
+        proof_id: ProofID,
+    ) -> Result<(), BonsaiCompleteProofManagerError> {
+        // An operator may have cancelled or requeued the request meanwhile.
+        let state = self
+            .storage
+            .get_proof_request_state(proof_id.clone())
+            .await
+            .map_err(|e| BonsaiCompleteProofManagerError::Storage {
+                source: e,
+                id: Some(proof_id.clone()),
+            })?;
+        if !matches!(
+            state,
+            ProofRequestState::PreparingOnchain | ProofRequestState::Quarantined
+        ) {
+            return Ok(());
+        }
+        self.storage
+            .transition_proof_request(proof_id.clone(), ProofRequestState::Completed)
+            .await
+            .map_err(|e| BonsaiCompleteProofManagerError::Storage {
+                source: e,
+                id: Some(proof_id),
+            })?;
+        self.process_new_complete_proof_requests().await
+    }
+
     async fn reset_inflight_proof_requests(
         &mut self,
     ) -> Result<(), BonsaiCompleteProofManagerError> {
@@ -215,6 +646,12 @@ impl<S: Storage> BonsaiCompleteProofManager<S> {
                 let completed_proof_result = completed_proof_handle?;
                 self.handle_complete_proof_result(completed_proof_result).await?
             }
+            Some(proof_id) = self.retries.next() => {
+                self.retry_failed_complete_proof(proof_id).await?
+            }
+            Some((completed_proof, delivery)) = self.webhook_deliveries.next() => {
+                self.handle_webhook_delivery(completed_proof, delivery).await?
+            }
             _ = self.new_complete_proofs_notifier.notified() => {
                 self.process_new_complete_proof_requests().await?
             }
@@ -233,6 +670,7 @@ impl<S: Storage> BonsaiCompleteProofManager<S> {
 
     pub(crate) async fn run(mut self) -> Result<(), BonsaiCompleteProofManagerError> {
         self.reset_inflight_proof_requests().await?;
+        self.resume_quarantined_proof_requests().await?;
         self.process_new_complete_proof_requests().await?;
 
         loop {
@@ -242,23 +680,66 @@ impl<S: Storage> BonsaiCompleteProofManager<S> {
                     return e;
                 }
                 Err(err) => {
-                    // An error occurred processing the completed proof.
-                    println!("error occurred managing pending proof requests: {:?}", err);
-                    if let Some(proof_request_id) = err.get_proof_request_id() {
-                        // Store the proof as new so that it can be retried.
-                        //
-                        // What do we do if this call to storage fails?
-                        if let Err(err) = self
-                            .storage
-                            .transition_proof_request(proof_request_id, ProofRequestState::New)
-                            .await
-                        {
-                            println!("failed to retry failed proof request: {:?}", err)
-                        }
-                    }
+                    // Failed proof requests are retried by
+                    // `handle_failed_complete_proof`. A failed batch stays
+                    // queued and is sent again with the next batch.
+                    error!(
+                        error = error_message(&err),
+                        "error managing completed proof requests"
+                    )
                 }
                 _ => (),
             }
         }
     }
 }
+
+/// Replay a batch on the state at `block`, returning the data each callback
+/// reverts with, or `None` for callbacks that succeed.
+async fn replay_callbacks<M: Middleware + 'static>(
+    bonsai_relay: &IBonsaiRelay<M>,
+    batch: &[Callback],
+    gas_limit: u64,
+    block: Option<BlockId>,
+) -> Result<Vec<Option<Bytes>>, ContractError<M>> {
+    let mut call = bonsai_relay.invoke_callbacks(batch.to_vec()).gas(gas_limit);
+    call.block = block;
+    let results = call.call().await?;
+
+    let mut reverts = Vec::with_capacity(batch.len());
+    for (callback, succeeded) in batch.iter().zip(results) {
+        if succeeded {
+            reverts.push(None);
+            continue;
+        }
+        // invokeCallback reverts with the revert data of the callback.
+        let mut call = bonsai_relay.invoke_callback(callback.clone());
+        call.block = block;
+        let revert_data = match call.call().await {
+            Err(err) => err.as_revert().cloned().unwrap_or_default(),
+            Ok(()) => Bytes::default(),
+        };
+        reverts.push(Some(revert_data));
+    }
+    reverts.resize(batch.len(), None);
+    Ok(reverts)
+}
+
+/// Replay a batch whose transaction reverted on the state at `block`,
+/// returning the data it reverts with.
+async fn replay_batch_revert<M: Middleware + 'static>(
+    bonsai_relay: &IBonsaiRelay<M>,
+    batch: &[Callback],
+    gas_limit: u64,
+    block: Option<BlockId>,
+) -> Result<Bytes, ContractError<M>> {
+    let mut call = bonsai_relay.invoke_callbacks(batch.to_vec()).gas(gas_limit);
+    call.block = block;
+    match call.call().await {
+        Ok(_) => Ok(Bytes::default()),
+        Err(err) => match err.as_revert() {
+            Some(revert_data) => Ok(revert_data.clone()),
+            None => Err(err),
+        },
+    }
+}
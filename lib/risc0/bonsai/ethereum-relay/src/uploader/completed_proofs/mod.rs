@@ -14,5 +14,6 @@
 
 mod complete_proof;
 mod error;
+mod gas;
 pub(crate) mod manager;
 mod snark;
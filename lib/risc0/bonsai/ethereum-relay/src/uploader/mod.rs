@@ -14,3 +14,7 @@
 
 pub mod completed_proofs;
 pub mod pending_proofs;
+pub mod quarantine;
+pub mod retry;
+pub mod transaction_manager;
+pub mod webhook;
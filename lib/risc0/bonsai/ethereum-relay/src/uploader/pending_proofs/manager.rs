@@ -12,20 +12,24 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Instant};
 
 use bonsai_sdk::alpha::Client;
-use futures::{stream::FuturesUnordered, StreamExt};
+use futures::{future::BoxFuture, stream::FuturesUnordered, StreamExt};
 use tokio::{
     sync::Notify,
     task::{JoinError, JoinHandle},
 };
-use tracing::info;
+use tracing::{error, info, warn};
 
 use crate::{
+    metrics::RelayMetrics,
     storage::{Error as StorageError, ProofRequestState, Storage},
-    uploader::pending_proofs::pending_proof_request_future::{
-        Error as PendingProofError, PendingProofRequest, ProofRequestID,
+    uploader::{
+        pending_proofs::pending_proof_request_future::{
+            Error as PendingProofError, PendingProofRequest, ProofRequestID,
+        },
+        retry::{error_message, RetryPolicy},
     },
 };
 
@@ -35,8 +39,6 @@ pub(crate) enum BonsaiPendingProofManagerError {
     Storage(#[from] StorageError),
     #[error("Error in join handle")]
     JoinHandleFailed(#[from] JoinError),
-    #[error("Error marking proof request new")]
-    PendingProof(#[from] PendingProofError),
 }
 
 pub(crate) struct BonsaiPendingProofManager<S: Storage> {
@@ -44,7 +46,10 @@ pub(crate) struct BonsaiPendingProofManager<S: Storage> {
     storage: S,
     new_pending_proof_request_notifier: Arc<Notify>,
     complete_proof_manager_notifier: Arc<Notify>,
+    retry_policy: RetryPolicy,
+    metrics: Arc<RelayMetrics>,
     futures_set: FuturesUnordered<JoinHandle<Result<ProofRequestID, PendingProofError>>>,
+    retries: FuturesUnordered<BoxFuture<'static, ProofRequestID>>,
 }
 
 impl<S: Storage> BonsaiPendingProofManager<S> {
@@ -53,13 +58,18 @@ impl<S: Storage> BonsaiPendingProofManager<S> {
         storage: S,
         new_pending_proof_request_notifier: Arc<Notify>,
         complete_proof_manager_notifier: Arc<Notify>,
+        retry_policy: RetryPolicy,
+        metrics: Arc<RelayMetrics>,
     ) -> Self {
         Self {
             client,
             storage,
             new_pending_proof_request_notifier,
             complete_proof_manager_notifier,
+            retry_policy,
+            metrics,
             futures_set: FuturesUnordered::new(),
+            retries: FuturesUnordered::new(),
         }
     }
 
@@ -71,7 +81,16 @@ impl<S: Storage> BonsaiPendingProofManager<S> {
         for request in pending_proof_requests.into_iter() {
             let pending_proof_request =
                 PendingProofRequest::new(self.client.clone(), request.proof_request_id.clone());
-            let pending_proof_request_handler = tokio::spawn(pending_proof_request);
+            let image_id = request.callback_proof_request_event.image_id;
+            let metrics = self.metrics.clone();
+            let pending_proof_request_handler = tokio::spawn(async move {
+                let started = Instant::now();
+                let result = pending_proof_request.await;
+                if result.is_ok() {
+                    metrics.observe_session(&image_id, started.elapsed());
+                }
+                result
+            });
             self.futures_set.push(pending_proof_request_handler);
 
             self.storage
@@ -89,25 +108,129 @@ impl<S: Storage> BonsaiPendingProofManager<S> {
     }
 
     pub(crate) async fn handle_pending_proof_result(
-        &self,
+        &mut self,
         pending_proof_result: Result<ProofRequestID, PendingProofError>,
     ) -> Result<(), BonsaiPendingProofManagerError> {
-        let (completed_proof_id, state) = match pending_proof_result {
-            Ok(session_id) => (session_id, ProofRequestState::Completed),
-            Err(err) => (err.get_proof_request_id(), ProofRequestState::Failed),
+        // An operator may have cancelled or requeued the request meanwhile.
+        let proof_id = match &pending_proof_result {
+            Ok(proof_id) => proof_id.clone(),
+            Err(err) => err.get_proof_request_id(),
         };
-
-        self.storage
-            .transition_proof_request(completed_proof_id.clone(), ProofRequestState::Completed)
+        let state = self
+            .storage
+            .get_proof_request_state(proof_id.clone())
             .await?;
+        if state != ProofRequestState::Pending {
+            info!(
+                ?proof_id,
+                ?state,
+                "proof request no longer pending, ignoring its session"
+            );
+            return Ok(());
+        }
 
-        let log_id = completed_proof_id.clone();
-        match state {
-            ProofRequestState::Completed => {
+        let err = match pending_proof_result {
+            Ok(completed_proof_id) => {
+                self.storage
+                    .transition_proof_request(
+                        completed_proof_id.clone(),
+                        ProofRequestState::Completed,
+                    )
+                    .await?;
                 self.complete_proof_manager_notifier.notify_one();
+
+                let log_id = completed_proof_id;
                 info!(?log_id, "pending proof done");
+                return Ok(());
+            }
+            Err(err) => err,
+        };
+
+        let retryable = err.is_retryable();
+        let message = error_message(&err);
+        let failed_proof_id = err.get_proof_request_id();
+        self.storage
+            .transition_proof_request(failed_proof_id.clone(), ProofRequestState::Failed)
+            .await?;
+        let attempts = self
+            .storage
+            .record_proof_request_failure(failed_proof_id.clone(), message.clone())
+            .await?;
+
+        let log_id = failed_proof_id.clone();
+        if retryable && self.retry_policy.should_retry(attempts) {
+            let delay = self.retry_policy.delay(attempts);
+            warn!(
+                ?log_id,
+                attempts,
+                ?delay,
+                error = message,
+                "pending proof failed, retrying"
+            );
+            self.retries
+                .push(self.retry_policy.schedule(failed_proof_id, attempts));
+        } else {
+            self.storage
+                .transition_proof_request(failed_proof_id, ProofRequestState::DeadLetter)
+                .await?;
+            error!(
+                ?log_id,
+                attempts,
+                error = message,
+                "pending proof dead-lettered"
+            );
+        }
+
+        Ok(())
+    }
+
+    async fn retry_failed_proof_request(
+        &mut self,
+        proof_id: ProofRequestID,
+    ) -> Result<(), BonsaiPendingProofManagerError> {
+        // An operator may have cancelled or requeued the request meanwhile.
+        if self
+            .storage
+            .get_proof_request_state(proof_id.clone())
+            .await?
+            != ProofRequestState::Failed
+        {
+            return Ok(());
+        }
+        self.storage
+            .transition_proof_request(proof_id, ProofRequestState::New)
+            .await?;
+        self.process_new_pending_proof_requests().await
+    }
+
+    async fn reset_inflight_proof_requests(
+        &mut self,
+    ) -> Result<(), BonsaiPendingProofManagerError> {
+        let inflight_requests = self.storage.fetch_pending_bonsai_requests(None).await?;
+
+        for request in inflight_requests.into_iter() {
+            self.storage
+                .transition_proof_request(request.proof_request_id.clone(), ProofRequestState::New)
+                .await?;
+        }
+
+        // Requests that failed before a restart resume their backoff.
+        let failed_requests = self.storage.fetch_failed_bonsai_requests(None).await?;
+
+        for request in failed_requests.into_iter() {
+            if self.retry_policy.should_retry(request.attempts) {
+                self.retries.push(
+                    self.retry_policy
+                        .schedule(request.proof_request_id, request.attempts),
+                );
+            } else {
+                self.storage
+                    .transition_proof_request(
+                        request.proof_request_id,
+                        ProofRequestState::DeadLetter,
+                    )
+                    .await?;
             }
-            _ => info!(?log_id, "pending proof failed"),
         }
 
         Ok(())
@@ -119,6 +242,9 @@ impl<S: Storage> BonsaiPendingProofManager<S> {
                     let pending_proof_result = pending_proof_handle?;
                     self.handle_pending_proof_result(pending_proof_result).await?
             }
+            Some(proof_id) = self.retries.next() => {
+                self.retry_failed_proof_request(proof_id).await?
+            }
             _ = self.new_pending_proof_request_notifier.notified() => {
                 self.process_new_pending_proof_requests().await?
             }
@@ -128,6 +254,7 @@ impl<S: Storage> BonsaiPendingProofManager<S> {
     }
 
     pub(crate) async fn run(mut self) -> Result<(), BonsaiPendingProofManagerError> {
+        self.reset_inflight_proof_requests().await?;
         self.process_new_pending_proof_requests().await?;
 
         loop {
@@ -136,24 +263,13 @@ impl<S: Storage> BonsaiPendingProofManager<S> {
                     // if a task panics, just fail
                     return e;
                 }
-                Err(BonsaiPendingProofManagerError::PendingProof(source)) => {
-                    // An error occurred processing the pending proof.
-                    println!(
-                        "error occurred managing pending proof requests: {:?}",
-                        source
-                    );
-                    // Store the proof as new so that it can be retried.
-                    //
-                    // TODO: What do we do if this call to storage fails?
-                    self.storage
-                        .transition_proof_request(
-                            source.get_proof_request_id(),
-                            ProofRequestState::New,
-                        )
-                        .await?
+                Err(err) => {
+                    error!(
+                        error = error_message(&err),
+                        "error managing pending proof requests"
+                    )
                 }
-
-                _ => (),
+                Ok(()) => (),
             }
         }
     }
@@ -41,12 +41,18 @@ pub(crate) enum Error {
 }
 
 impl Error {
-    pub(crate) fn get_proof_request_id(self) -> ProofRequestID {
+    pub(crate) fn get_proof_request_id(&self) -> ProofRequestID {
         match self {
-            Error::ClientAPI { source: _, id } => id,
-            Error::ProofRequestError { status: _, id } => id,
+            Error::ClientAPI { source: _, id } => id.clone(),
+            Error::ProofRequestError { status: _, id } => id.clone(),
         }
     }
+
+    /// Whether polling the proof request again may succeed. A proof request
+    /// that ended in a failed status on Bonsai never will.
+    pub(crate) fn is_retryable(&self) -> bool {
+        matches!(self, Error::ClientAPI { .. })
+    }
 }
 
 type PollingBonsaiFuture =
@@ -0,0 +1,80 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use ethers::{
+    abi::{self, ParamType, Token},
+    types::Bytes,
+};
+use serde::{Deserialize, Serialize};
+
+/// Selector of `Error(string)`, the revert of `require` and `revert` with a
+/// message.
+const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+/// Selector of `Panic(uint256)`, the revert of failed assertions, overflows
+/// and the like.
+const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+/// What happens to a callback that reverts when simulated before batching.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuarantinePolicy {
+    /// Quarantine the callback and simulate it again following the
+    /// [RetryPolicy](crate::RetryPolicy), dead-lettering it once it runs out
+    /// of attempts.
+    #[default]
+    Retry,
+    /// Send the callback anyway.
+    Deliver,
+    /// Dead-letter the callback right away, leaving it to an operator to
+    /// requeue it.
+    Drop,
+}
+
+/// A human readable reason for the given revert data.
+pub(crate) fn revert_reason(revert_data: &Bytes) -> String {
+    if revert_data.is_empty() {
+        return "reverted without data".to_string();
+    }
+    let (selector, data) = revert_data.split_at(revert_data.len().min(4));
+    let decoded = match selector {
+        s if s == ERROR_SELECTOR => abi::decode(&[ParamType::String], data).ok(),
+        s if s == PANIC_SELECTOR => abi::decode(&[ParamType::Uint(256)], data).ok(),
+        _ => None,
+    };
+    match decoded.as_deref() {
+        Some([Token::String(message)]) => message.clone(),
+        Some([Token::Uint(code)]) => format!("panic {code:#x}"),
+        _ => format!("reverted with {revert_data}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::{abi::AbiEncode, types::U256};
+
+    use super::*;
+
+    #[test]
+    fn test_revert_reasons() {
+        let error = [ERROR_SELECTOR.to_vec(), "not allowed".to_string().encode()].concat();
+        assert_eq!(revert_reason(&error.into()), "not allowed");
+
+        let panic = [PANIC_SELECTOR.to_vec(), U256::from(0x11).encode()].concat();
+        assert_eq!(revert_reason(&panic.into()), "panic 0x11");
+
+        let custom = Bytes::from(vec![0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(revert_reason(&custom), "reverted with 0xdeadbeef");
+        assert_eq!(revert_reason(&Bytes::default()), "reverted without data");
+    }
+}
@@ -0,0 +1,104 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+
+use crate::storage::ProofID;
+
+/// How failed proof requests are retried before they are dead-lettered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Number of failed attempts after which a request is dead-lettered.
+    pub max_attempts: u32,
+    /// Delay before the first retry. It doubles with every further attempt.
+    pub initial_backoff: Duration,
+    /// Upper bound of the delay between two attempts.
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_secs(5),
+            max_backoff: Duration::from_secs(300),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Whether a request that failed `attempts` times may be retried.
+    pub fn should_retry(&self, attempts: u32) -> bool {
+        attempts < self.max_attempts
+    }
+
+    /// The delay before retrying a request that failed `attempts` times.
+    pub fn delay(&self, attempts: u32) -> Duration {
+        backoff(self.initial_backoff, self.max_backoff, attempts)
+    }
+
+    /// A future resolving to the given proof request once its retry is due.
+    pub(crate) fn schedule(&self, proof_id: ProofID, attempts: u32) -> BoxFuture<'static, ProofID> {
+        let delay = self.delay(attempts);
+        Box::pin(async move {
+            tokio::time::sleep(delay).await;
+            proof_id
+        })
+    }
+}
+
+/// The delay after the given number of failures, starting at `initial` and
+/// doubling with every further failure up to `max`.
+pub(crate) fn backoff(initial: Duration, max: Duration, failures: u32) -> Duration {
+    let exponent = failures.saturating_sub(1).min(31);
+    initial.saturating_mul(1 << exponent).min(max)
+}
+
+/// The error and its sources, as stored with a failed proof request.
+pub(crate) fn error_message(err: &dyn std::error::Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(err) = source {
+        message = format!("{message}: {err}");
+        source = err.source();
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_up_to_the_maximum() {
+        let (initial, max) = (Duration::from_secs(1), Duration::from_secs(10));
+        let delays: Vec<u64> = (1..=6)
+            .map(|failures| backoff(initial, max, failures).as_secs())
+            .collect();
+        assert_eq!(delays, vec![1, 2, 4, 8, 10, 10]);
+        assert_eq!(backoff(initial, max, u32::MAX), max);
+    }
+
+    #[test]
+    fn test_retries_are_limited() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            ..Default::default()
+        };
+        assert!(policy.should_retry(9));
+        assert!(!policy.should_retry(10));
+    }
+}
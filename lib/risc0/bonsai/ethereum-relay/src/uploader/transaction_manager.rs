@@ -0,0 +1,283 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use ethers::{
+    providers::{BlockNumber, Middleware},
+    types::{
+        transaction::eip2718::TypedTransaction, Address, Eip1559TransactionRequest,
+        TransactionReceipt, H256, U256,
+    },
+};
+use tokio::{sync::Mutex, time::Instant};
+use tracing::{info, warn};
+
+/// Fees of the transactions sent by the relay, and how transactions that stay
+/// unconfirmed are replaced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransactionPolicy {
+    /// Upper bound of the max fee per gas of a transaction, in wei.
+    pub max_fee_per_gas: U256,
+    /// Time after which an unconfirmed transaction is replaced with higher
+    /// fees.
+    pub confirmation_timeout: Duration,
+    /// Percentage by which the fees of a replacement are increased. Nodes
+    /// only accept replacements that raise the fees by at least 10%.
+    pub fee_bump_percent: u64,
+    /// Number of replacements after which a transaction is cancelled.
+    pub max_replacements: u32,
+    /// Interval at which the receipts of sent transactions are polled.
+    pub poll_interval: Duration,
+}
+
+impl Default for TransactionPolicy {
+    fn default() -> Self {
+        Self {
+            // 500 gwei
+            max_fee_per_gas: U256::from(500_000_000_000u64),
+            confirmation_timeout: Duration::from_secs(120),
+            fee_bump_percent: 20,
+            max_replacements: 5,
+            poll_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum TransactionError {
+    #[error("Ethers failed")]
+    Ethers {
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    #[error("No sender address configured")]
+    NoSender,
+    #[error("Transaction with nonce {nonce} was cancelled by {tx_hash:?}")]
+    Cancelled { nonce: U256, tx_hash: H256 },
+    #[error("Transaction with nonce {nonce} is still unconfirmed after its cancellation")]
+    Unconfirmed { nonce: U256 },
+}
+
+impl TransactionError {
+    fn ethers<E: std::error::Error + Send + Sync + 'static>(err: E) -> Self {
+        Self::Ethers {
+            source: Box::new(err),
+        }
+    }
+}
+
+/// Sends the transactions of the relay wallet.
+///
+/// The manager hands out nonces itself, so that concurrent sends never
+/// collide, and replaces transactions that are not confirmed in time with
+/// higher fees until they are mined or cancelled.
+#[derive(Debug)]
+pub(crate) struct TransactionManager {
+    policy: TransactionPolicy,
+    /// The nonce of the next transaction, or `None` if it must be fetched
+    /// from the node.
+    next_nonce: Mutex<Option<U256>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Fees {
+    max_fee_per_gas: U256,
+    max_priority_fee_per_gas: U256,
+}
+
+impl TransactionManager {
+    pub(crate) fn new(policy: TransactionPolicy) -> Self {
+        Self {
+            policy,
+            next_nonce: Mutex::new(None),
+        }
+    }
+
+    /// Send a transaction from the default sender of the client and wait for
+    /// it, or one of its replacements, to be mined.
+    pub(crate) async fn send<M: Middleware + 'static>(
+        &self,
+        client: &M,
+        tx: TypedTransaction,
+    ) -> Result<TransactionReceipt, TransactionError> {
+        let from = client.default_sender().ok_or(TransactionError::NoSender)?;
+        let nonce = self.reserve_nonce(client, from).await?;
+        let mut fees = self.estimate_fees(client).await?;
+
+        let mut request = Eip1559TransactionRequest::new().from(from).nonce(nonce);
+        request.to = tx.to().cloned();
+        request.data = tx.data().cloned();
+        request.gas = tx.gas().cloned();
+        request.value = tx.value().cloned();
+
+        let first_hash = match self.broadcast(client, &request, fees).await {
+            Ok(tx_hash) => tx_hash,
+            Err(err) => {
+                // The nonce was never used, so it is handed out again.
+                self.reset_nonce().await;
+                return Err(err);
+            }
+        };
+        let mut sent = vec![first_hash];
+
+        for replacement in 1..=self.policy.max_replacements {
+            if let Some(receipt) = self.wait_for_receipt(client, &sent).await {
+                return Ok(receipt);
+            }
+            fees = match self.bump(fees) {
+                Some(fees) => fees,
+                None => {
+                    warn!(%nonce, "transaction fees reached the cap");
+                    break;
+                }
+            };
+            warn!(%nonce, replacement, ?fees, "transaction unconfirmed, replacing it");
+            match self.broadcast(client, &request, fees).await {
+                Ok(tx_hash) => sent.push(tx_hash),
+                // One of the sent transactions may have been mined meanwhile.
+                Err(err) => warn!(%nonce, error = %err, "failed to replace transaction"),
+            }
+        }
+        if let Some(receipt) = self.wait_for_receipt(client, &sent).await {
+            return Ok(receipt);
+        }
+
+        // Free the nonce with a transfer of nothing to the sender itself, so
+        // that later transactions are not stuck behind this one. The fees of
+        // the cancellation may exceed the cap, but it only uses 21000 gas.
+        let cancellation = Eip1559TransactionRequest::new()
+            .from(from)
+            .to(from)
+            .nonce(nonce)
+            .value(U256::zero())
+            .gas(21000);
+        let fees = bump_fees(fees, self.policy.fee_bump_percent);
+        warn!(%nonce, ?fees, "cancelling transaction");
+        let cancel_hash = self.broadcast(client, &cancellation, fees).await?;
+        sent.push(cancel_hash);
+
+        match self.wait_for_receipt(client, &sent).await {
+            Some(receipt) if receipt.transaction_hash == cancel_hash => {
+                Err(TransactionError::Cancelled {
+                    nonce,
+                    tx_hash: cancel_hash,
+                })
+            }
+            Some(receipt) => Ok(receipt),
+            None => Err(TransactionError::Unconfirmed { nonce }),
+        }
+    }
+
+    async fn reserve_nonce<M: Middleware + 'static>(
+        &self,
+        client: &M,
+        from: Address,
+    ) -> Result<U256, TransactionError> {
+        let mut next_nonce = self.next_nonce.lock().await;
+        let nonce = match *next_nonce {
+            Some(nonce) => nonce,
+            None => client
+                .get_transaction_count(from, Some(BlockNumber::Pending.into()))
+                .await
+                .map_err(TransactionError::ethers)?,
+        };
+        *next_nonce = Some(nonce + 1);
+        Ok(nonce)
+    }
+
+    async fn reset_nonce(&self) {
+        *self.next_nonce.lock().await = None;
+    }
+
+    /// The EIP-1559 fees estimated by the node, capped by the policy.
+    async fn estimate_fees<M: Middleware + 'static>(
+        &self,
+        client: &M,
+    ) -> Result<Fees, TransactionError> {
+        let (max_fee_per_gas, max_priority_fee_per_gas) = client
+            .estimate_eip1559_fees(None)
+            .await
+            .map_err(TransactionError::ethers)?;
+        let max_fee_per_gas = max_fee_per_gas.min(self.policy.max_fee_per_gas);
+        Ok(Fees {
+            max_fee_per_gas,
+            max_priority_fee_per_gas: max_priority_fee_per_gas.min(max_fee_per_gas),
+        })
+    }
+
+    /// The fees of a replacement, or `None` if the cap leaves no room for a
+    /// bump the node would accept.
+    fn bump(&self, fees: Fees) -> Option<Fees> {
+        let bumped = bump_fees(fees, self.policy.fee_bump_percent);
+        if bumped.max_fee_per_gas > self.policy.max_fee_per_gas {
+            return None;
+        }
+        Some(bumped)
+    }
+
+    async fn broadcast<M: Middleware + 'static>(
+        &self,
+        client: &M,
+        request: &Eip1559TransactionRequest,
+        fees: Fees,
+    ) -> Result<H256, TransactionError> {
+        let request = request
+            .clone()
+            .max_fee_per_gas(fees.max_fee_per_gas)
+            .max_priority_fee_per_gas(fees.max_priority_fee_per_gas);
+        let pending_tx = client
+            .send_transaction(request, None)
+            .await
+            .map_err(TransactionError::ethers)?;
+        let tx_hash = pending_tx.tx_hash();
+        info!(?tx_hash, nonce = ?request.nonce, "transaction sent");
+        Ok(tx_hash)
+    }
+
+    /// Poll the receipts of the given transactions, all sharing a nonce,
+    /// until one of them is mined or the confirmation timeout expires.
+    /// Failures to fetch a receipt are retried until then, since the
+    /// transactions may still be mined.
+    async fn wait_for_receipt<M: Middleware + 'static>(
+        &self,
+        client: &M,
+        sent: &[H256],
+    ) -> Option<TransactionReceipt> {
+        let deadline = Instant::now() + self.policy.confirmation_timeout;
+        loop {
+            for tx_hash in sent {
+                match client.get_transaction_receipt(*tx_hash).await {
+                    Ok(Some(receipt)) => return Some(receipt),
+                    Ok(None) => (),
+                    Err(err) => {
+                        warn!(?tx_hash, error = %err, "failed to fetch transaction receipt")
+                    }
+                }
+            }
+            if Instant::now() >= deadline {
+                return None;
+            }
+            tokio::time::sleep(self.policy.poll_interval).await;
+        }
+    }
+}
+
+fn bump_fees(fees: Fees, percent: u64) -> Fees {
+    let bump = |fee: U256| fee + fee * percent / 100;
+    Fees {
+        max_fee_per_gas: bump(fees.max_fee_per_gas),
+        max_priority_fee_per_gas: bump(fees.max_priority_fee_per_gas),
+    }
+}
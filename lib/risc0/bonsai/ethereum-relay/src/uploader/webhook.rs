@@ -0,0 +1,278 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use ethers::types::H256;
+use futures::future::BoxFuture;
+use reqwest::{header, Client};
+
+use crate::{
+    sdk::webhook::{sign, WebhookPayload, SIGNATURE_HEADER, TIMESTAMP_HEADER},
+    storage::WebhookDelivery,
+    uploader::{completed_proofs::complete_proof::CompleteProof, retry::error_message},
+};
+
+/// How proofs are posted to webhook targets. Failed deliveries are retried
+/// according to the [RetryPolicy](crate::RetryPolicy) of the relay.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebhookPolicy {
+    /// The key of the HMAC signature of each delivery, shared with the
+    /// webhook receivers. Nothing is posted while it is empty.
+    pub signing_key: String,
+    /// How long to wait for a webhook to respond.
+    pub timeout: Duration,
+}
+
+impl Default for WebhookPolicy {
+    fn default() -> Self {
+        Self {
+            signing_key: String::new(),
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Posts completed proofs to their webhooks.
+#[derive(Debug, Clone)]
+pub(crate) struct WebhookDeliverer {
+    client: Client,
+    /// `None` if no signing key is configured, in which case every delivery
+    /// fails rather than posting proofs the receivers cannot verify.
+    signing_key: Option<Arc<[u8]>>,
+    timeout: Duration,
+}
+
+impl WebhookDeliverer {
+    pub(crate) fn new(policy: &WebhookPolicy) -> Self {
+        Self {
+            client: Client::new(),
+            signing_key: (!policy.signing_key.is_empty())
+                .then(|| policy.signing_key.as_bytes().into()),
+            timeout: policy.timeout,
+        }
+    }
+
+    /// A future posting the proof to the webhook once the delay elapsed,
+    /// resolving to the proof and the outcome of the attempt.
+    pub(crate) fn schedule(
+        &self,
+        complete_proof: CompleteProof,
+        url: String,
+        attempt: u32,
+        delay: Duration,
+    ) -> BoxFuture<'static, (CompleteProof, WebhookDelivery)> {
+        let deliverer = self.clone();
+        Box::pin(async move {
+            tokio::time::sleep(delay).await;
+            let delivery = deliverer.deliver(&complete_proof, url, attempt).await;
+            (complete_proof, delivery)
+        })
+    }
+
+    async fn deliver(
+        &self,
+        complete_proof: &CompleteProof,
+        url: String,
+        attempt: u32,
+    ) -> WebhookDelivery {
+        let payload = WebhookPayload {
+            request_id: complete_proof.bonsai_proof_id.uuid.clone(),
+            image_id: H256(complete_proof.image_id),
+            journal: complete_proof.journal.clone(),
+            post_state_digest: H256(complete_proof.ethereum_callback.auth.post_state_digest),
+            seal: complete_proof.ethereum_callback.auth.seal.clone(),
+        };
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        let mut delivery = WebhookDelivery {
+            proof_request_id: complete_proof.bonsai_proof_id.clone(),
+            url,
+            attempt,
+            status_code: None,
+            error: None,
+            timestamp,
+        };
+
+        let Some(signing_key) = &self.signing_key else {
+            delivery.error = Some("no webhook signing key is configured".to_string());
+            return delivery;
+        };
+        let body = match serde_json::to_vec(&payload) {
+            Ok(body) => body,
+            Err(err) => {
+                delivery.error = Some(error_message(&err));
+                return delivery;
+            }
+        };
+        let signature = sign(signing_key, timestamp, &body);
+        let response = self
+            .client
+            .post(&delivery.url)
+            .timeout(self.timeout)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header(SIGNATURE_HEADER, signature)
+            .body(body)
+            .send()
+            .await;
+
+        match response {
+            Ok(response) => {
+                let status = response.status();
+                delivery.status_code = Some(status.as_u16());
+                if !status.is_success() {
+                    delivery.error = Some(format!("webhook responded with {status}"));
+                }
+            }
+            Err(err) => delivery.error = Some(error_message(&err)),
+        }
+        delivery
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, sync::Mutex};
+
+    use axum::{extract::State, http::HeaderMap, routing::post, Router};
+    use bonsai_ethereum_contracts::i_bonsai_relay::{Callback, CallbackAuthorization};
+    use bonsai_sdk::alpha::SessionId;
+    use ethers::types::{Address, Bytes};
+    use wiremock::{matchers::method, Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+    use crate::sdk::webhook::verify;
+
+    type Received = Arc<Mutex<Vec<(HeaderMap, axum::body::Bytes)>>>;
+
+    fn complete_proof() -> CompleteProof {
+        CompleteProof {
+            bonsai_proof_id: SessionId::new("session".to_string()),
+            ethereum_callback: Callback {
+                auth: CallbackAuthorization {
+                    seal: Bytes::from(vec![1, 2, 3]),
+                    post_state_digest: [4; 32],
+                },
+                payload: Bytes::default(),
+                gas_limit: 0,
+                callback_contract: Address::zero(),
+            },
+            image_id: [5; 32],
+            journal: Bytes::from(vec![6, 7]),
+            webhook_url: None,
+        }
+    }
+
+    /// A local webhook keeping the requests it receives.
+    fn start_webhook() -> (String, Received) {
+        let received = Received::default();
+        let app = Router::new()
+            .route(
+                "/proofs",
+                post(
+                    |State(received): State<Received>,
+                     headers: HeaderMap,
+                     body: axum::body::Bytes| async move {
+                        received.lock().unwrap().push((headers, body));
+                    },
+                ),
+            )
+            .with_state(received.clone());
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/proofs", listener.local_addr().unwrap());
+        let server = axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service());
+        tokio::spawn(server);
+        (url, received)
+    }
+
+    #[tokio::test]
+    async fn test_deliveries_are_signed() {
+        let (url, received) = start_webhook();
+        let deliverer = WebhookDeliverer::new(&WebhookPolicy {
+            signing_key: "secret".to_string(),
+            ..Default::default()
+        });
+        let (_, delivery) = deliverer
+            .schedule(complete_proof(), url.clone(), 1, Duration::ZERO)
+            .await;
+        assert_eq!(delivery.url, url);
+        assert_eq!(delivery.status_code, Some(200));
+        assert_eq!(delivery.error, None);
+
+        let (headers, body) = received.lock().unwrap().pop().unwrap();
+        let timestamp: u64 = headers[TIMESTAMP_HEADER].to_str().unwrap().parse().unwrap();
+        assert_eq!(timestamp, delivery.timestamp);
+        let signature = headers[SIGNATURE_HEADER].to_str().unwrap();
+        assert!(verify(b"secret", timestamp, &body, signature));
+
+        let payload: WebhookPayload = serde_json::from_slice(&body).unwrap();
+        assert_eq!(payload.request_id, "session");
+        assert_eq!(payload.image_id, H256([5; 32]));
+        assert_eq!(payload.journal, Bytes::from(vec![6, 7]));
+        assert_eq!(payload.post_state_digest, H256([4; 32]));
+        assert_eq!(payload.seal, Bytes::from(vec![1, 2, 3]));
+    }
+
+    #[tokio::test]
+    async fn test_failed_deliveries_are_logged() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&server)
+            .await;
+
+        let deliverer = WebhookDeliverer::new(&WebhookPolicy {
+            signing_key: "secret".to_string(),
+            ..Default::default()
+        });
+        let (_, delivery) = deliverer
+            .schedule(complete_proof(), server.uri(), 2, Duration::ZERO)
+            .await;
+        assert_eq!(delivery.attempt, 2);
+        assert_eq!(delivery.status_code, Some(503));
+        assert!(delivery.error.unwrap().contains("503"));
+
+        // Nothing listens on port 9 of the local host.
+        let (_, delivery) = deliverer
+            .schedule(
+                complete_proof(),
+                "http://127.0.0.1:9/proofs".to_string(),
+                3,
+                Duration::ZERO,
+            )
+            .await;
+        assert_eq!(delivery.status_code, None);
+        assert!(delivery.error.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_unsigned_deliveries_are_refused() {
+        let (url, received) = start_webhook();
+        let deliverer = WebhookDeliverer::new(&WebhookPolicy::default());
+        let (_, delivery) = deliverer
+            .schedule(complete_proof(), url, 1, Duration::ZERO)
+            .await;
+        assert_eq!(delivery.status_code, None);
+        assert!(delivery.error.unwrap().contains("signing key"));
+        assert!(received.lock().unwrap().is_empty());
+    }
+}
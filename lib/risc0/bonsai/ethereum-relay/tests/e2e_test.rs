@@ -27,7 +27,7 @@ mod tests {
             client::{CallbackRequest, Client},
             utils,
         },
-        Relayer,
+        ApiKey, Relayer,
     };
     use bonsai_sdk::{
         alpha::{Client as BonsaiClient, SdkErr},
@@ -147,7 +147,18 @@ mod tests {
             rest_api_port: "8080".to_string(),
             bonsai_api_url: get_bonsai_url(),
             bonsai_api_key: get_api_key(),
+            api_keys: Vec::new(),
+            admin_keys: Vec::new(),
             relay_contract_address: bonsai_relay_contract,
+            max_batch_size: 3,
+            batch_interval: Duration::from_millis(1000),
+            batch_gas_limit: 3000000,
+            storage_path: None,
+            retry_policy: Default::default(),
+            transaction_policy: Default::default(),
+            quarantine_policy: Default::default(),
+            webhook_policy: Default::default(),
+            restart_policy: Default::default(),
         };
 
         dbg!("starting bonsai relayer");
@@ -290,13 +301,29 @@ mod tests {
         );
 
         // run the bonsai relayer
+        let slice_io_id: [u8; 32] = bytemuck::cast(SLICE_IO_ID);
         let relayer = Relayer {
             rest_api: true,
             dev_mode: dev_mode().unwrap(),
             rest_api_port: "8080".to_string(),
             bonsai_api_url: get_bonsai_url(),
             bonsai_api_key: get_api_key(),
+            api_keys: vec![ApiKey {
+                image_ids: vec![ethers_H256(slice_io_id)],
+                callback_contracts: vec![counter.address()],
+                ..ApiKey::new("e2e", &get_api_key())
+            }],
+            admin_keys: Vec::new(),
             relay_contract_address: bonsai_relay_contract,
+            max_batch_size: 3,
+            batch_interval: Duration::from_millis(1000),
+            batch_gas_limit: 3000000,
+            storage_path: None,
+            retry_policy: Default::default(),
+            transaction_policy: Default::default(),
+            quarantine_policy: Default::default(),
+            webhook_policy: Default::default(),
+            restart_policy: Default::default(),
         };
 
         dbg!("starting bonsai relayer");
@@ -334,6 +361,7 @@ mod tests {
             gas_limit,
             image_id,
             input,
+            webhook_url: None,
         };
 
         let relay_client =
@@ -373,6 +401,24 @@ mod tests {
             );
             sleep(Duration::new(1, 0)).await
         }
-        assert_eq!(value, expected_value)
+        assert_eq!(value, expected_value);
+
+        // Probes and scrapers do not need an API key.
+        for route in ["/healthz", "/readyz"] {
+            let res = reqwest::get(format!("http://localhost:8080{route}"))
+                .await
+                .expect("probe should respond");
+            assert!(res.status().is_success(), "{route} failed");
+        }
+        let metrics = reqwest::get("http://localhost:8080/metrics")
+            .await
+            .expect("metrics should be served")
+            .text()
+            .await
+            .expect("metrics should be text");
+        assert!(metrics.contains(&format!(
+            "relay_batched_callbacks_total{{image_id=\"0x{}\"}} 1",
+            hex::encode(image_id)
+        )));
     }
 }
@@ -59,3 +59,30 @@ contract Proxy {
         emit ProofsSubmitted();
     }
 }
+
+/// @notice A relay whose callbacks always revert, in a batch or simulated on their own.
+contract RevertingProxy {
+    /// @notice Data required to authorize a callback to be sent through the relay.
+    struct CallbackAuthorization {
+        bytes seal;
+        bytes32 postStateDigest;
+    }
+
+    /// @notice Callback data, provided by the Relay service.
+    struct Callback {
+        CallbackAuthorization auth;
+        address callbackContract;
+        bytes payload;
+        uint64 gasLimit;
+    }
+
+    // Submit proofs
+    function invokeCallbacks(Callback[] calldata) external pure returns (bool[] memory) {
+        revert("RevertingProxy: batch reverted");
+    }
+
+    // Simulate single callbacks
+    function invokeCallback(Callback calldata) external pure {
+        revert("RevertingProxy: callback reverted");
+    }
+}
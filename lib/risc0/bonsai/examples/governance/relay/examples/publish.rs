@@ -51,6 +51,7 @@ async fn main() {
         gas_limit: 3000000,
         image_id: image_id.into(),
         input,
+        webhook_url: None,
     };
 
     // Send the callback request to the Bonsai Relay.
@@ -15,7 +15,7 @@
 use std::io::Write;
 
 use anyhow::Context;
-use bonsai_ethereum_relay::{EthersClientConfig, Relayer};
+use bonsai_ethereum_relay::{config::BatchingConfig, EthersClientConfig, Relayer};
 use bonsai_ethereum_relay_cli::{resolve_guest_entry, resolve_image_output, Output};
 use bonsai_sdk::{
     alpha::{responses::SnarkProof, SdkErr},
@@ -234,13 +234,25 @@ async fn main() -> anyhow::Result<()> {
             connection_retry_attempts,
             connection_retry_interval,
         } => {
+            let batching = BatchingConfig::default();
             let relayer = Relayer {
                 rest_api: true,
                 dev_mode: dev_mode,
                 rest_api_port: "8080".to_string(),
                 bonsai_api_url: args.global_opts.bonsai_api_url.clone(),
                 bonsai_api_key: args.global_opts.bonsai_api_key.clone(),
+                api_keys: Vec::new(),
+                admin_keys: Vec::new(),
                 relay_contract_address: relay_address,
+                max_batch_size: batching.max_batch_size,
+                batch_interval: std::time::Duration::from_millis(batching.interval_ms),
+                batch_gas_limit: batching.gas_limit,
+                storage_path: None,
+                retry_policy: Default::default(),
+                transaction_policy: Default::default(),
+                quarantine_policy: Default::default(),
+                webhook_policy: Default::default(),
+                restart_policy: Default::default(),
             };
             let client_config = EthersClientConfig::new(
                 eth_node,
@@ -17,7 +17,10 @@ mod prover;
 mod routes;
 mod state;
 
-use std::sync::{Arc, RwLock};
+use std::{
+    future::Future,
+    sync::{Arc, RwLock},
+};
 
 use anyhow::Context;
 use axum::{
@@ -62,8 +65,14 @@ fn app(state: Arc<RwLock<BonsaiState>>, prover_handle: ProverHandle) -> Router {
 ///
 /// Note that this mock only performs execution, no proving.
 pub async fn serve(port: String) -> anyhow::Result<()> {
+    bind(port)?.await
+}
+
+/// Bind the Local Bonsai API to the given port, returning a future that
+/// serves it. Requests are accepted as soon as this function returns.
+pub fn bind(port: String) -> anyhow::Result<impl Future<Output = anyhow::Result<()>>> {
     let local_url = format!("http://localhost:{port}");
-    let bind_address = &format!("0.0.0.0:{port}");
+    let bind_address = format!("0.0.0.0:{port}");
     let state = Arc::new(RwLock::new(BonsaiState::new(local_url)));
 
     let (sender, receiver) = mpsc::channel(8);
@@ -73,18 +82,21 @@ pub async fn serve(port: String) -> anyhow::Result<()> {
 
     tokio::spawn(async move { prover.run().await });
 
-    let handle = axum::Server::bind(
+    let handle = axum::Server::try_bind(
         &bind_address
             .parse()
             .context("failed to parse bind address")?,
     )
+    .context(format!("failed to bind Local Bonsai API to {bind_address}"))?
     .serve(app(state, prover_handle).into_make_service());
 
     info!("Local Bonsai started on {bind_address}");
 
-    handle.await.context(format!(
-        "failed to serve Local Bonsai API on {bind_address}"
-    ))
+    Ok(async move {
+        handle.await.context(format!(
+            "failed to serve Local Bonsai API on {bind_address}"
+        ))
+    })
 }
 
 #[cfg(test)]
@@ -12,6 +12,46 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::{env, fs, path::PathBuf};
+
+/// Suffix of the sidecar files declaring the journal ABI of a guest.
+const JOURNAL_SCHEMA_SUFFIX: &str = ".journal.json";
+
 fn main() {
     risc0_build::embed_methods();
+    embed_journal_schemas();
+}
+
+/// Generates journal_schemas.rs next to methods.rs, embedding the journal ABI
+/// declared by each guest in `guest/src/bin/<name>.journal.json`.
+fn embed_journal_schemas() {
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let bin_dir = manifest_dir.join("guest/src/bin");
+    println!("cargo:rerun-if-changed={}", bin_dir.display());
+
+    let mut schemas: Vec<(String, PathBuf)> = fs::read_dir(&bin_dir)
+        .unwrap()
+        .filter_map(|entry| {
+            let path = entry.unwrap().path();
+            let file_name = path.file_name()?.to_str()?;
+            let guest_name = file_name.strip_suffix(JOURNAL_SCHEMA_SUFFIX)?;
+            Some((guest_name.to_uppercase().replace('-', "_"), path))
+        })
+        .collect();
+    schemas.sort();
+
+    let entries: Vec<String> = schemas
+        .iter()
+        .map(|(name, path)| format!("(\"{name}\", include_str!({:?}))", path.display()))
+        .collect();
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    fs::write(
+        out_dir.join("journal_schemas.rs"),
+        format!(
+            "/// Journal ABI schemas as (guest name, JSON ABI parameter list) pairs.\n\
+             pub const JOURNAL_SCHEMAS: &[(&str, &str)] = &[{}];\n",
+            entries.join(",")
+        ),
+    )
+    .unwrap();
 }
@@ -0,0 +1,120 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Summaries of the guests defined in the methods crate.
+
+use std::collections::BTreeSet;
+
+use anyhow::{Context, Result};
+use risc0_build::GuestListEntry;
+use risc0_zkvm::{Program, MEM_SIZE, PAGE_SIZE};
+use serde_json::{json, Value};
+
+use crate::{execution_stats, ExecutionStats};
+
+/// Static properties of a guest image and, if a sample input was given, the
+/// resources used to execute it.
+#[derive(Debug, Clone)]
+pub struct GuestSummary {
+    pub name: String,
+    pub image_id: [u8; 32],
+    /// Size of the ELF binary in bytes.
+    pub elf_size: usize,
+    /// Number of pages in the memory image loaded from the ELF.
+    pub page_count: usize,
+    pub stats: Option<ExecutionStats>,
+}
+
+impl GuestSummary {
+    /// Summarize a guest, executing it locally if a sample input is given.
+    pub fn new(guest_entry: &GuestListEntry, sample_input: Option<Vec<u8>>) -> Result<Self> {
+        let program = Program::load_elf(guest_entry.elf, MEM_SIZE as u32)
+            .context(format!("Failed to load ELF of {}", guest_entry.name))?;
+        let page_count = program
+            .image
+            .keys()
+            .map(|addr| *addr as usize / PAGE_SIZE)
+            .collect::<BTreeSet<_>>()
+            .len();
+        let stats = sample_input
+            .map(|input| execution_stats(guest_entry.elf, input))
+            .transpose()
+            .context(format!("Failed to execute {}", guest_entry.name))?;
+
+        Ok(Self {
+            name: guest_entry.name.to_string(),
+            image_id: bytemuck::cast(guest_entry.image_id),
+            elf_size: guest_entry.elf.len(),
+            page_count,
+            stats,
+        })
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "name": self.name,
+            "image_id": format!("0x{}", hex::encode(self.image_id)),
+            "elf_size": self.elf_size,
+            "page_count": self.page_count,
+            "cycles": self.stats.map(|stats| stats.cycles),
+            "segments": self.stats.map(|stats| stats.segments),
+        })
+    }
+}
+
+/// Render the summaries as a plain text table. Execution statistics are shown
+/// as `-` for guests without a sample input.
+pub fn format_table(summaries: &[GuestSummary]) -> String {
+    let header = [
+        "NAME", "IMAGE ID", "ELF SIZE", "PAGES", "CYCLES", "SEGMENTS",
+    ];
+    let rows: Vec<[String; 6]> = summaries
+        .iter()
+        .map(|summary| {
+            [
+                summary.name.clone(),
+                format!("0x{}", hex::encode(summary.image_id)),
+                summary.elf_size.to_string(),
+                summary.page_count.to_string(),
+                summary
+                    .stats
+                    .map_or("-".to_string(), |stats| stats.cycles.to_string()),
+                summary
+                    .stats
+                    .map_or("-".to_string(), |stats| stats.segments.to_string()),
+            ]
+        })
+        .collect();
+
+    let mut widths = header.map(str::len);
+    for row in rows.iter() {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    std::iter::once(header.map(String::from))
+        .chain(rows)
+        .map(|row| {
+            row.iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{cell:<width$}"))
+                .collect::<Vec<_>>()
+                .join("  ")
+                .trim_end()
+                .to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
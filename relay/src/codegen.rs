@@ -0,0 +1,193 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Generation of image ID constants for use outside of Rust.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use risc0_build::GuestListEntry;
+use serde_json::{json, Map, Value};
+
+const SOLIDITY_HEADER: &str = r#"// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+// This file is automatically generated by the `codegen` subcommand of
+// bonsai-ethereum-relay-cli. Do not edit it by hand.
+
+pragma solidity ^0.8.17;
+"#;
+
+fn image_id_hex(guest_entry: &GuestListEntry) -> String {
+    hex::encode(bytemuck::cast::<[u32; 8], [u8; 32]>(guest_entry.image_id))
+}
+
+/// Render a Solidity library with a `<NAME>_ID` constant per guest.
+pub fn solidity_image_ids(guest_list: &[GuestListEntry]) -> String {
+    let constants: String = guest_list
+        .iter()
+        .map(|guest_entry| {
+            format!(
+                "    bytes32 public constant {}_ID = bytes32(0x{});\n",
+                guest_entry.name,
+                image_id_hex(guest_entry)
+            )
+        })
+        .collect();
+    format!("{SOLIDITY_HEADER}\nlibrary ImageID {{\n{constants}}}\n")
+}
+
+/// Render a JSON manifest mapping each guest name to its image ID.
+pub fn json_manifest(guest_list: &[GuestListEntry]) -> Result<String> {
+    let manifest: Map<String, Value> = guest_list
+        .iter()
+        .map(|guest_entry| {
+            (
+                guest_entry.name.to_string(),
+                json!(format!("0x{}", image_id_hex(guest_entry))),
+            )
+        })
+        .collect();
+    Ok(serde_json::to_string_pretty(&manifest)? + "\n")
+}
+
+/// The generated files and their expected contents.
+pub fn generate(
+    guest_list: &[GuestListEntry],
+    solidity_path: &Path,
+    manifest_path: &Path,
+) -> Result<Vec<(PathBuf, String)>> {
+    Ok(vec![
+        (solidity_path.to_path_buf(), solidity_image_ids(guest_list)),
+        (manifest_path.to_path_buf(), json_manifest(guest_list)?),
+    ])
+}
+
+/// Write the generated files to disk.
+pub fn write(files: &[(PathBuf, String)]) -> Result<()> {
+    for (path, contents) in files {
+        std::fs::write(path, contents).context(format!("failed to write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Fail if any of the files on disk differ from the generated contents.
+pub fn check(files: &[(PathBuf, String)]) -> Result<()> {
+    let stale: Vec<String> = files
+        .iter()
+        .filter(|(path, contents)| {
+            std::fs::read_to_string(path).map_or(true, |existing| existing != *contents)
+        })
+        .map(|(path, _)| path.display().to_string())
+        .collect();
+    if !stale.is_empty() {
+        bail!(
+            "generated files are out of date with the built guests: {}; run the codegen subcommand to update them",
+            stale.join(", ")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GUEST_LIST: &[GuestListEntry] = &[
+        GuestListEntry {
+            name: "FIBONACCI",
+            elf: &[],
+            image_id: [1, 2, 3, 4, 5, 6, 7, 8],
+            path: "",
+        },
+        GuestListEntry {
+            name: "FINALIZE_VOTES",
+            elf: &[],
+            image_id: [u32::MAX; 8],
+            path: "",
+        },
+    ];
+
+    const FIBONACCI_ID: &str = "0100000002000000030000000400000005000000060000000700000008000000";
+
+    #[test]
+    fn test_solidity_image_ids() {
+        let solidity = solidity_image_ids(GUEST_LIST);
+        assert!(solidity.starts_with(SOLIDITY_HEADER));
+        assert!(solidity.ends_with(&format!(
+            "library ImageID {{\n    bytes32 public constant FIBONACCI_ID = bytes32(0x{FIBONACCI_ID});\n    bytes32 public constant FINALIZE_VOTES_ID = bytes32(0x{});\n}}\n",
+            "ff".repeat(32)
+        )));
+    }
+
+    #[test]
+    fn test_json_manifest() {
+        let manifest: Value = serde_json::from_str(&json_manifest(GUEST_LIST).unwrap()).unwrap();
+        assert_eq!(
+            manifest,
+            json!({
+                "FIBONACCI": format!("0x{FIBONACCI_ID}"),
+                "FINALIZE_VOTES": format!("0x{}", "ff".repeat(32)),
+            })
+        );
+    }
+
+    #[test]
+    fn test_check() {
+        let dir = std::env::temp_dir().join(format!("relay-codegen-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let files = generate(
+            GUEST_LIST,
+            &dir.join("ImageID.sol"),
+            &dir.join("image_ids.json"),
+        )
+        .unwrap();
+
+        // Missing files are out of date.
+        assert!(check(&files).is_err());
+
+        write(&files).unwrap();
+        check(&files).unwrap();
+
+        // So are files that no longer match the guests.
+        let mut changed = GUEST_LIST.to_vec();
+        changed[0].image_id = [0; 8];
+        let stale = generate(
+            &changed,
+            &dir.join("ImageID.sol"),
+            &dir.join("image_ids.json"),
+        )
+        .unwrap();
+        let err = check(&stale).unwrap_err().to_string();
+        assert!(
+            err.contains("ImageID.sol") && err.contains("image_ids.json"),
+            "{err}"
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
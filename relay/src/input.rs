@@ -0,0 +1,349 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Encoding of guest inputs given on the command line.
+
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use ethers::abi::{
+    param_type::Reader,
+    token::{LenientTokenizer, Tokenizer},
+    ParamType, Token,
+};
+use serde_json::Value;
+
+/// A single typed guest argument, given as `<type>:<value>`.
+///
+/// The type is any Solidity ABI type, e.g. `uint256`, `bytes` or `bytes[]`.
+/// If the value starts with `@`, the remainder is read as a file path. Files
+/// given for `bytes` and `string` arguments are used verbatim, while arrays and
+/// tuples are read as JSON.
+#[derive(Debug, Clone)]
+pub struct InputArg {
+    kind: ParamType,
+    value: String,
+}
+
+impl FromStr for InputArg {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (kind, value) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow!("expected <type>:<value>, got {s:?}"))?;
+        let kind = Reader::read(kind.trim()).context(format!("invalid ABI type {kind:?}"))?;
+        Ok(Self {
+            kind,
+            value: value.to_string(),
+        })
+    }
+}
+
+impl InputArg {
+    /// Convert the argument into an ABI token, reading any referenced file.
+    pub fn tokenize(&self) -> Result<Token> {
+        let Some(path) = self.value.strip_prefix('@') else {
+            return LenientTokenizer::tokenize(&self.kind, &self.value)
+                .context(format!("failed to parse {:?} as {}", self.value, self.kind));
+        };
+        let contents =
+            std::fs::read(path).context(format!("failed to read argument file {path}"))?;
+        match &self.kind {
+            ParamType::Bytes => Ok(Token::Bytes(contents)),
+            ParamType::String => Ok(Token::String(
+                String::from_utf8(contents).context(format!("{path} is not valid UTF-8"))?,
+            )),
+            ParamType::Array(_) | ParamType::FixedArray(..) | ParamType::Tuple(_) => {
+                let json: Value = serde_json::from_slice(&contents)
+                    .context(format!("failed to parse {path} as JSON"))?;
+                tokenize_json(&self.kind, &json).context(format!("invalid contents in {path}"))
+            }
+            kind => {
+                let text =
+                    String::from_utf8(contents).context(format!("{path} is not valid UTF-8"))?;
+                LenientTokenizer::tokenize(kind, text.trim())
+                    .context(format!("failed to parse {path} as {kind}"))
+            }
+        }
+    }
+}
+
+/// Convert a JSON value into an ABI token of the given type.
+///
+/// Arrays and tuples map to JSON arrays; every other type is given as a JSON
+/// string (or number/boolean) in the same format accepted on the command line.
+fn tokenize_json(kind: &ParamType, value: &Value) -> Result<Token> {
+    match (kind, value) {
+        (ParamType::Array(inner), Value::Array(items)) => Ok(Token::Array(
+            items
+                .iter()
+                .map(|item| tokenize_json(inner, item))
+                .collect::<Result<_>>()?,
+        )),
+        (ParamType::FixedArray(inner, len), Value::Array(items)) => {
+            if items.len() != *len {
+                bail!("expected {len} elements for {kind}, found {}", items.len());
+            }
+            Ok(Token::FixedArray(
+                items
+                    .iter()
+                    .map(|item| tokenize_json(inner, item))
+                    .collect::<Result<_>>()?,
+            ))
+        }
+        (ParamType::Tuple(kinds), Value::Array(items)) => {
+            if items.len() != kinds.len() {
+                bail!(
+                    "expected {} elements for {kind}, found {}",
+                    kinds.len(),
+                    items.len()
+                );
+            }
+            Ok(Token::Tuple(
+                kinds
+                    .iter()
+                    .zip(items)
+                    .map(|(kind, item)| tokenize_json(kind, item))
+                    .collect::<Result<_>>()?,
+            ))
+        }
+        (ParamType::Array(_) | ParamType::FixedArray(..) | ParamType::Tuple(_), _) => {
+            bail!("expected a JSON array for {kind}, found {value}")
+        }
+        (_, Value::String(s)) => Ok(LenientTokenizer::tokenize(kind, s)?),
+        (_, Value::Number(_) | Value::Bool(_)) => {
+            Ok(LenientTokenizer::tokenize(kind, &value.to_string())?)
+        }
+        _ => bail!("unsupported JSON value for {kind}: {value}"),
+    }
+}
+
+/// ABI-encode a list of typed arguments into a guest input.
+pub fn encode_args(args: &[InputArg]) -> Result<Vec<u8>> {
+    let tokens = args
+        .iter()
+        .map(InputArg::tokenize)
+        .collect::<Result<Vec<_>>>()?;
+    Ok(ethers::abi::encode(&tokens))
+}
+
+/// Read a pre-encoded guest input from a file, or from stdin if the path is
+/// `-`.
+pub fn read_input_file(path: &Path) -> Result<Vec<u8>> {
+    let mut input = Vec::new();
+    if path == Path::new("-") {
+        std::io::stdin()
+            .read_to_end(&mut input)
+            .context("failed to read input from stdin")?;
+    } else {
+        input = std::fs::read(path).context(format!("failed to read {}", path.display()))?;
+    }
+    Ok(input)
+}
+
+/// Where the guest input given on the command line comes from.
+pub enum InputSource {
+    /// Pre-encoded input as a hex string.
+    Hex(String),
+    /// Typed arguments to be ABI-encoded.
+    Args(Vec<InputArg>),
+    /// Pre-encoded input read from a file, or stdin.
+    File(PathBuf),
+}
+
+impl InputSource {
+    /// Select the input source from the mutually exclusive CLI options.
+    /// Returns `None` if no input was given.
+    pub fn from_options(
+        hex: Option<String>,
+        args: Vec<InputArg>,
+        file: Option<PathBuf>,
+    ) -> Option<Self> {
+        match (hex, args, file) {
+            (Some(hex), _, _) => Some(Self::Hex(hex)),
+            (None, _, Some(file)) => Some(Self::File(file)),
+            (None, args, None) if !args.is_empty() => Some(Self::Args(args)),
+            _ => None,
+        }
+    }
+
+    /// Produce the raw guest input bytes.
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        match self {
+            Self::Hex(hex) => {
+                hex::decode(hex.trim_start_matches("0x")).context("Failed to decode input")
+            }
+            Self::Args(args) => encode_args(args),
+            Self::File(path) => read_input_file(path),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::{Address, U256};
+
+    use super::*;
+
+    fn tokenize(arg: &str) -> Result<Token> {
+        arg.parse::<InputArg>()?.tokenize()
+    }
+
+    /// Write `contents` to a file unique to the calling test.
+    fn write_file(name: &str, contents: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("relay-input-{}-{name}", std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_tokenize_each_param_type() {
+        let address = "00000000000000000000000000000000000000aa";
+        for (arg, expected) in [
+            ("uint256:42", Token::Uint(U256::from(42))),
+            ("uint8:16", Token::Uint(U256::from(16))),
+            ("int256:-1", Token::Int(U256::MAX)),
+            ("bool:true", Token::Bool(true)),
+            (
+                &format!("address:{address}") as &str,
+                Token::Address(address.parse::<Address>().unwrap()),
+            ),
+            ("bytes:0102", Token::Bytes(vec![1, 2])),
+            ("bytes2:0102", Token::FixedBytes(vec![1, 2])),
+            ("string:hello", Token::String("hello".to_string())),
+            (
+                "uint32[]:[1,2]",
+                Token::Array(vec![Token::Uint(1.into()), Token::Uint(2.into())]),
+            ),
+            (
+                "bool[2]:[true,false]",
+                Token::FixedArray(vec![Token::Bool(true), Token::Bool(false)]),
+            ),
+            (
+                "(uint64,string):(7,seven)",
+                Token::Tuple(vec![
+                    Token::Uint(7.into()),
+                    Token::String("seven".to_string()),
+                ]),
+            ),
+        ] {
+            assert_eq!(tokenize(arg).unwrap(), expected, "{arg}");
+        }
+    }
+
+    #[test]
+    fn test_value_may_contain_colons() {
+        assert_eq!(
+            tokenize("string:a:b").unwrap(),
+            Token::String("a:b".to_string())
+        );
+    }
+
+    #[test]
+    fn test_file_arguments() {
+        let raw = write_file("raw", &[0, 159, 146, 150]);
+        assert_eq!(
+            tokenize(&format!("bytes:@{}", raw.display())).unwrap(),
+            Token::Bytes(vec![0, 159, 146, 150])
+        );
+        // Not valid UTF-8.
+        assert!(tokenize(&format!("string:@{}", raw.display())).is_err());
+
+        let text = write_file("text", b"hello\n");
+        assert_eq!(
+            tokenize(&format!("string:@{}", text.display())).unwrap(),
+            Token::String("hello\n".to_string())
+        );
+
+        let number = write_file("number", b" 12\n");
+        assert_eq!(
+            tokenize(&format!("uint256:@{}", number.display())).unwrap(),
+            Token::Uint(12.into())
+        );
+
+        let json = write_file("json", br#"[["01", 2, true], ["", "3", false]]"#);
+        assert_eq!(
+            tokenize(&format!("(bytes,uint8,bool)[]:@{}", json.display())).unwrap(),
+            Token::Array(vec![
+                Token::Tuple(vec![
+                    Token::Bytes(vec![1]),
+                    Token::Uint(2.into()),
+                    Token::Bool(true),
+                ]),
+                Token::Tuple(vec![
+                    Token::Bytes(vec![]),
+                    Token::Uint(3.into()),
+                    Token::Bool(false),
+                ]),
+            ])
+        );
+
+        for path in [raw, text, number, json] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_malformed_arguments_are_errors() {
+        for arg in [
+            "42",
+            "uint257:1",
+            "notatype:1",
+            "uint256:abc",
+            "bool:maybe",
+            "address:0x1234",
+            "bytes:zz",
+            "uint8[2]:[1]",
+            "(uint8,bool):(1)",
+            "bytes:@/nonexistent/relay-input",
+        ] {
+            assert!(tokenize(arg).is_err(), "{arg} should not parse");
+        }
+    }
+
+    #[test]
+    fn test_malformed_json_files_are_errors() {
+        for (name, kind, contents) in [
+            ("not-json", "uint8[]", &b"[1, 2"[..]),
+            ("not-array", "uint8[]", b"{\"a\": 1}"),
+            ("short", "uint8[3]", b"[1, 2]"),
+            ("long-tuple", "(uint8,bool)", b"[1, true, 3]"),
+            ("null", "uint8[]", b"[null]"),
+            ("nested", "uint8[][]", b"[1]"),
+        ] {
+            let path = write_file(name, contents);
+            let result = tokenize(&format!("{kind}:@{}", path.display()));
+            std::fs::remove_file(&path).unwrap();
+            assert!(result.is_err(), "{name} should not parse");
+        }
+    }
+
+    #[test]
+    fn test_encode_args() {
+        let args = ["uint256:1", "bool:true"]
+            .iter()
+            .map(|arg| arg.parse::<InputArg>().unwrap())
+            .collect::<Vec<_>>();
+        let encoded = encode_args(&args).unwrap();
+        assert_eq!(
+            encoded,
+            ethers::abi::encode(&[Token::Uint(1.into()), Token::Bool(true)])
+        );
+    }
+}
@@ -0,0 +1,160 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Decoding of guest journals using the ABI schema declared by each guest.
+
+use anyhow::{Context, Result};
+use ethers::{
+    abi::{Param, ParamType, Token},
+    types::I256,
+};
+use serde_json::{Map, Value};
+
+/// The ABI parameters committed to the journal by a guest.
+#[derive(Debug, Clone)]
+pub struct JournalSchema {
+    params: Vec<Param>,
+}
+
+impl JournalSchema {
+    /// Parse a schema from a JSON list of ABI parameters, in the same format
+    /// as the `inputs` of a function in a Solidity JSON ABI.
+    pub fn from_json(json: &str) -> Result<Self> {
+        let params = serde_json::from_str(json).context("invalid journal schema")?;
+        Ok(Self { params })
+    }
+
+    /// Find the schema declared for the guest with the given name, if any.
+    pub fn lookup(schemas: &[(&str, &str)], guest_name: &str) -> Result<Option<Self>> {
+        schemas
+            .iter()
+            .find(|(name, _)| *name == guest_name)
+            .map(|(name, json)| {
+                Self::from_json(json).context(format!("invalid journal schema for {name}"))
+            })
+            .transpose()
+    }
+
+    /// Decode an ABI encoded journal into a JSON object keyed by parameter
+    /// name. Unnamed parameters are keyed by their position.
+    pub fn decode(&self, journal: &[u8]) -> Result<Map<String, Value>> {
+        let kinds: Vec<ParamType> = self.params.iter().map(|p| p.kind.clone()).collect();
+        let tokens = ethers::abi::decode(&kinds, journal)
+            .context("journal does not match the declared schema")?;
+        Ok(self
+            .params
+            .iter()
+            .zip(tokens)
+            .enumerate()
+            .map(|(i, (param, token))| {
+                let name = match param.name.is_empty() {
+                    true => i.to_string(),
+                    false => param.name.clone(),
+                };
+                (name, token_to_json(token))
+            })
+            .collect())
+    }
+}
+
+/// Convert a decoded ABI token into a JSON value. Integers are rendered as
+/// decimal strings so that 256-bit values are not truncated.
+pub fn token_to_json(token: Token) -> Value {
+    match token {
+        Token::Address(address) => Value::String(format!("{address:?}")),
+        Token::FixedBytes(bytes) | Token::Bytes(bytes) => {
+            Value::String(format!("0x{}", hex::encode(bytes)))
+        }
+        Token::Int(value) => Value::String(I256::from_raw(value).to_string()),
+        Token::Uint(value) => Value::String(value.to_string()),
+        Token::Bool(value) => Value::Bool(value),
+        Token::String(value) => Value::String(value),
+        Token::FixedArray(tokens) | Token::Array(tokens) | Token::Tuple(tokens) => {
+            Value::Array(tokens.into_iter().map(token_to_json).collect())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::{
+        abi::encode,
+        types::{Address, U256},
+    };
+    use serde_json::json;
+
+    use super::*;
+
+    const SCHEMA: &str = r#"[
+        {"name": "n", "type": "uint256"},
+        {"name": "delta", "type": "int256"},
+        {"name": "", "type": "bool"},
+        {"name": "owner", "type": "address"},
+        {"name": "digest", "type": "bytes"},
+        {"name": "values", "type": "uint32[]"}
+    ]"#;
+
+    #[test]
+    fn test_decode_journal() {
+        let schema = JournalSchema::from_json(SCHEMA).unwrap();
+        let journal = encode(&[
+            Token::Uint(U256::from(10)),
+            Token::Int(I256::from(-5).into_raw()),
+            Token::Bool(true),
+            Token::Address(Address::from_low_u64_be(0xaa)),
+            Token::Bytes(vec![1, 2]),
+            Token::Array(vec![Token::Uint(1.into()), Token::Uint(2.into())]),
+        ]);
+        assert_eq!(
+            Value::Object(schema.decode(&journal).unwrap()),
+            json!({
+                "n": "10",
+                "delta": "-5",
+                "2": true,
+                "owner": "0x00000000000000000000000000000000000000aa",
+                "digest": "0x0102",
+                "values": ["1", "2"],
+            })
+        );
+    }
+
+    #[test]
+    fn test_journal_not_matching_schema() {
+        let schema = JournalSchema::from_json(SCHEMA).unwrap();
+        let journal = encode(&[Token::Uint(U256::from(10))]);
+        let err = schema.decode(&journal).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("journal does not match the declared schema"));
+    }
+
+    #[test]
+    fn test_lookup_schema() {
+        let schemas = [("FIBONACCI", SCHEMA), ("BROKEN", "{}")];
+        assert!(JournalSchema::lookup(&schemas, "FIBONACCI")
+            .unwrap()
+            .is_some());
+        assert!(JournalSchema::lookup(&schemas, "MISSING")
+            .unwrap()
+            .is_none());
+        let err = JournalSchema::lookup(&schemas, "BROKEN").unwrap_err();
+        assert_eq!(err.to_string(), "invalid journal schema for BROKEN");
+    }
+
+    #[test]
+    fn test_unsupported_type() {
+        let err = JournalSchema::from_json(r#"[{"name": "x", "type": "float"}]"#).unwrap_err();
+        assert_eq!(err.to_string(), "invalid journal schema");
+    }
+}
@@ -12,19 +12,26 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod catalog;
+pub mod codegen;
+pub mod input;
+pub mod journal;
+
 use std::time::Duration;
 
 use anyhow::{anyhow, bail, Context, Result};
 use bonsai_sdk::alpha::{responses::SnarkProof, Client, SdkErr};
 use risc0_build::GuestListEntry;
 use risc0_zkvm::{
-    Executor, ExecutorEnv, MemoryImage, Program, Receipt, ReceiptMetadata, MEM_SIZE, PAGE_SIZE,
+    Executor, ExecutorEnv, MemoryImage, Program, Receipt, ReceiptMetadata, Session, MEM_SIZE,
+    PAGE_SIZE,
 };
 
 /// Result of executing a guest image, possibly containing a proof.
 pub enum Output {
     Execution {
         journal: Vec<u8>,
+        stats: ExecutionStats,
     },
     Bonsai {
         journal: Vec<u8>,
@@ -36,6 +43,22 @@ pub enum Output {
 /// Execute and prove the guest locally, on this machine, as opposed to sending
 /// the proof request to the Bonsai service.
 pub fn execute_locally(elf: &[u8], input: Vec<u8>) -> Result<Output> {
+    let session = execute(elf, input)?;
+    let stats = ExecutionStats::from_session(&session)?;
+
+    Ok(Output::Execution {
+        journal: session.journal,
+        stats,
+    })
+}
+
+/// Execute the guest locally and return the resulting [ExecutionStats],
+/// without producing a proof.
+pub fn execution_stats(elf: &[u8], input: Vec<u8>) -> Result<ExecutionStats> {
+    ExecutionStats::from_session(&execute(elf, input)?)
+}
+
+fn execute(elf: &[u8], input: Vec<u8>) -> Result<Session> {
     // Execute the guest program, generating the session trace needed to prove the
     // computation.
     let env = ExecutorEnv::builder()
@@ -43,13 +66,27 @@ pub fn execute_locally(elf: &[u8], input: Vec<u8>) -> Result<Output> {
         .build()
         .context("Failed to build exec env")?;
     let mut exec = Executor::from_elf(env, elf).context("Failed to instantiate executor")?;
-    let session = exec
-        .run()
-        .context(format!("Failed to run executor {:?}", &input))?;
+    exec.run()
+        .context(format!("Failed to run executor {:?}", &input))
+}
 
-    Ok(Output::Execution {
-        journal: session.journal,
-    })
+/// Resource usage of a guest execution.
+#[derive(Debug, Clone, Copy)]
+pub struct ExecutionStats {
+    /// Total number of cycles over all segments, including padding.
+    pub cycles: u64,
+    /// Number of segments the execution was split into.
+    pub segments: usize,
+}
+
+impl ExecutionStats {
+    pub fn from_session(session: &Session) -> Result<Self> {
+        let segments = session.resolve().context("Failed to resolve segments")?;
+        Ok(Self {
+            cycles: segments.iter().map(|segment| 1u64 << segment.po2).sum(),
+            segments: segments.len(),
+        })
+    }
 }
 
 pub const POLL_INTERVAL_SEC: u64 = 4;
@@ -177,11 +214,10 @@ pub fn resolve_guest_entry<'a>(
 }
 
 pub async fn resolve_image_output(
-    input: &str,
+    input: Vec<u8>,
     guest_entry: &GuestListEntry<'static>,
     dev_mode: bool,
 ) -> Result<Output> {
-    let input = hex::decode(input.trim_start_matches("0x")).context("Failed to decode input")?;
     let elf = guest_entry.elf;
 
     if dev_mode {
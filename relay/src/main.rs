@@ -12,27 +12,43 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::io::Write;
+use std::{collections::HashMap, io::Write, path::PathBuf, time::Duration};
 
 use anyhow::Context;
-use bonsai_ethereum_relay::{EthersClientConfig, Relayer};
-use bonsai_ethereum_relay_cli::{resolve_guest_entry, resolve_image_output, Output};
+use bonsai_ethereum_relay::{
+    config::{BonsaiConfig, BonsaiOverrides, RelayConfig, RelayOverrides},
+    sdk::client::{CallbackRequest, Client},
+    ApiKey,
+};
+use bonsai_ethereum_relay_cli::{
+    catalog::{format_table, GuestSummary},
+    codegen, execution_stats,
+    input::{read_input_file, InputArg, InputSource},
+    journal::JournalSchema,
+    resolve_guest_entry, resolve_image_output, Output,
+};
 use bonsai_sdk::{
     alpha::{responses::SnarkProof, SdkErr},
     alpha_async::{get_client_from_parts, put_image},
 };
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use ethers::{
     abi::{Hash, Token, Tokenizable},
-    types::{Address, U256},
+    types::{Address, H256, U256},
 };
-use methods::GUEST_LIST;
+use methods::{GUEST_LIST, JOURNAL_SCHEMAS};
+use risc0_build::GuestListEntry;
 use risc0_zkvm::sha::Digest;
+use serde_json::json;
 
 /// Index 0 private key generated by default in Anvil.
 const ANVIL_DEFAULT_KEY: &'static str =
     "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
 
+/// Relay API key accepted by `run --dev-callback-contract`, and sent by
+/// `request` unless another key is given.
+const DEV_RELAY_API_KEY: &'static str = "dev-relay-api-key";
+
 #[derive(Subcommand)]
 enum Command {
     /// Runs the RISC-V ELF binary.
@@ -40,8 +56,40 @@ enum Command {
         /// The name of the guest binary
         guest_binary: String,
 
-        /// The input to provide to the guest binary
-        input: Option<String>,
+        #[clap(flatten)]
+        input: InputOpts,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = QueryFormat::Hex)]
+        format: QueryFormat,
+    },
+    /// List the guests with their image IDs and sizes, and the cycles used
+    /// to execute them on sample inputs.
+    Guests {
+        /// A sample input to execute a guest with, given as <GUEST>=<PATH>,
+        /// where the file contains the already encoded input. May be repeated.
+        #[arg(long, value_name = "GUEST=PATH", value_parser = parse_sample_input)]
+        sample_input: Vec<(String, PathBuf)>,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = GuestsFormat::Table)]
+        format: GuestsFormat,
+    },
+    /// Generate a Solidity library and a JSON manifest with the image IDs of
+    /// all guests.
+    Codegen {
+        /// Path of the generated Solidity library
+        #[arg(long, default_value = "contracts/ImageID.sol")]
+        solidity_path: PathBuf,
+
+        /// Path of the generated JSON manifest
+        #[arg(long, default_value = "image_ids.json")]
+        manifest_path: PathBuf,
+
+        /// Check that the files on disk match the built guests instead of
+        /// writing them
+        #[arg(long)]
+        check: bool,
     },
     /// Upload the RISC-V ELF binary to Bonsai.
     Upload {
@@ -49,48 +97,133 @@ enum Command {
         /// If not provided, all defined guests will be uploaded.
         guest_binary: Option<String>,
     },
-    /// Upload the RISC-V ELF binary to Bonsai.
+    /// Send a callback request to the Bonsai Relay REST API.
+    Request {
+        /// The name or image ID of the guest binary
+        guest_binary: String,
+
+        /// Address of the contract to receive the callback
+        callback_contract: Address,
+
+        /// Signature of the callback function, used to compute its selector
+        /// (e.g. "storeResult(uint256,uint256)")
+        function_signature: String,
+
+        #[clap(flatten)]
+        input: InputOpts,
+
+        /// Gas limit for the callback
+        #[arg(long, default_value_t = 100000)]
+        gas_limit: u64,
+
+        /// Post the proof to this URL instead of calling back on chain
+        #[arg(long)]
+        webhook_url: Option<String>,
+
+        /// Bonsai Relay API URL
+        #[arg(long, env, default_value = "http://localhost:8080")]
+        bonsai_relay_api_url: String,
+
+        /// Key of the client on the Bonsai Relay API, sent as `x-api-key`
+        #[arg(long, env, default_value = DEV_RELAY_API_KEY)]
+        relay_api_key: String,
+    },
+    /// Run the Bonsai Relay, uploading all locally defined images.
+    ///
+    /// Without a configured wallet, the relay uses the first private key of a
+    /// default Anvil instance.
     Run {
-        /// Bonsai Relay contract address on Ethereum
-        #[arg(long, env)]
-        relay_address: Address,
-
-        /// Ethereum Node endpoint.
-        #[arg(long, env, default_value = "ws://localhost:8545")]
-        eth_node: String,
-
-        /// Ethereum chain ID
-        #[arg(long, default_value_t = 31337)]
-        eth_chain_id: u64,
-
-        /// Wallet Key Identifier.
-        /// Can be a private key as a hex string, or an AWS KMS key identifier.
-        /// Defaults to the first private key of a deafult Anvil instance.
-        #[arg(
-            short,
-            long,
-            env,
-            default_value = ANVIL_DEFAULT_KEY
-        )]
-        private_key: String,
+        /// Path to a TOML relay config file. The options below override the
+        /// corresponding fields of the file.
+        #[arg(long, env = "RELAY_CONFIG")]
+        config: Option<PathBuf>,
+
+        #[clap(flatten)]
+        overrides: RelayOverrides,
+
+        /// Seconds to wait for the relay to start before uploading images
+        #[arg(long, default_value_t = 60)]
+        ready_timeout_secs: u64,
+
+        /// For local development, accept the relay API key
+        /// "dev-relay-api-key" for callback requests of any local guest to
+        /// this contract. May be repeated.
+        #[arg(long = "dev-callback-contract", value_name = "ADDRESS")]
+        dev_callback_contracts: Vec<Address>,
     },
 }
 
+/// Input to provide to the guest binary.
+#[derive(Debug, Args)]
+struct InputOpts {
+    /// The input to provide to the guest binary, as a hex string of the
+    /// already encoded bytes
+    #[arg(conflicts_with_all = ["args", "input_file"])]
+    input: Option<String>,
+
+    /// A typed argument to ABI-encode as guest input, given as
+    /// <type>:<value> (e.g. uint256:10, bytes:@sig.bin, bytes[]:@pubkeys.json).
+    /// May be repeated.
+    #[arg(long = "arg", value_name = "TYPE:VALUE", conflicts_with = "input_file")]
+    args: Vec<InputArg>,
+
+    /// Read the already encoded guest input from a file, or from stdin if set
+    /// to "-"
+    #[arg(long, value_name = "PATH")]
+    input_file: Option<PathBuf>,
+}
+
+impl InputOpts {
+    /// Encode the guest input, returning `None` if no input was given.
+    fn encode(self) -> anyhow::Result<Option<Vec<u8>>> {
+        InputSource::from_options(self.input, self.args, self.input_file)
+            .map(|source| source.encode())
+            .transpose()
+            .context("failed to encode guest input")
+    }
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum QueryFormat {
+    /// ABI encoded hex, as consumed by the Foundry FFI integration.
+    Hex,
+    /// JSON with the decoded journal, image ID and execution statistics.
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum GuestsFormat {
+    /// Plain text table.
+    Table,
+    /// JSON array with one object per guest.
+    Json,
+}
+
+/// Parse a <GUEST>=<PATH> pair.
+fn parse_sample_input(s: &str) -> anyhow::Result<(String, PathBuf)> {
+    let (guest, path) = s
+        .split_once('=')
+        .ok_or_else(|| anyhow::anyhow!("expected <GUEST>=<PATH>, got {s:?}"))?;
+    Ok((guest.to_string(), PathBuf::from(path)))
+}
+
 #[derive(Debug, Args)]
 struct GlobalOpts {
-    /// Bonsai API URL
-    #[arg(long, env, global = true, default_value = "http://localhost:8081")]
-    bonsai_api_url: String,
-
-    /// Bonsai API key
-    /// Defaults to empty, providing no authentication.
-    #[arg(long, env, global = true, default_value = "")]
-    bonsai_api_key: String,
-
-    /// Toggle to enable dev_mode: only a local executor runs your
-    /// zkVM program and no proof is generated.
-    #[arg(long, env, global = true, default_value_t = false)]
-    risc0_dev_mode: bool,
+    #[clap(flatten)]
+    bonsai: BonsaiOverrides,
+}
+
+impl GlobalOpts {
+    fn bonsai_api_url(&self) -> String {
+        self.bonsai
+            .bonsai_api_url
+            .clone()
+            .unwrap_or_else(|| BonsaiConfig::default().api_url)
+    }
+
+    fn bonsai_api_key(&self) -> String {
+        self.bonsai.bonsai_api_key.clone().unwrap_or_default()
+    }
 }
 
 #[derive(Parser)]
@@ -135,67 +268,88 @@ fn tokenize_snark_proof(proof: &SnarkProof) -> anyhow::Result<Token> {
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let args = App::parse();
-    let dev_mode = args.global_opts.risc0_dev_mode;
+    let dev_mode = args.global_opts.bonsai.risc0_dev_mode;
 
     match args.command {
         Command::Query {
             guest_binary,
             input,
+            format,
         } => {
             // Search list for requested binary name
             let guest_entry = resolve_guest_entry(GUEST_LIST, &guest_binary)
                 .context("failed to resolve guest entry")?;
 
-            // Execute or return image id
-            let output_tokens = match &input {
-                // Input provided. Return the Ethereum ABI encoded journal and
-                Some(input) => {
-                    let output = resolve_image_output(input, &guest_entry, dev_mode)
+            let input = input.encode()?;
+
+            // Execute, if an input was provided
+            let output = match &input {
+                Some(input) => Some(
+                    resolve_image_output(input.clone(), &guest_entry, dev_mode)
                         .await
-                        .context("failed to resolve image output")?;
-                    match (dev_mode, output) {
-                        (true, Output::Execution { journal }) => {
-                            vec![Token::Bytes(journal)]
-                        }
-                        (
-                            false,
-                            Output::Bonsai {
-                                journal,
-                                receipt_metadata,
-                                snark_proof,
-                            },
-                        ) => {
-                            vec![
-                                Token::Bytes(journal),
-                                Hash::from(<[u8; 32]>::from(receipt_metadata.post.digest()))
-                                    .into_token(),
-                                Token::Bytes(ethers::abi::encode(&[tokenize_snark_proof(
-                                    &snark_proof,
-                                )?])),
-                            ]
-                        }
-                        _ => {
-                            anyhow::bail!("invalid dev mode and output combination: {:?}", dev_mode)
-                        }
-                    }
-                }
-                // No input. Return the Ethereum ABI encoded bytes32 image ID.
-                None => vec![
-                    Hash::from(bytemuck::cast::<_, [u8; 32]>(guest_entry.image_id)).into_token(),
-                ],
+                        .context("failed to resolve image output")?,
+                ),
+                None => None,
             };
 
-            let output = hex::encode(ethers::abi::encode(&output_tokens));
-            print!("{output}");
+            match format {
+                QueryFormat::Hex => {
+                    let output_tokens = query_output_tokens(&guest_entry, dev_mode, output)?;
+                    let output = hex::encode(ethers::abi::encode(&output_tokens));
+                    print!("{output}");
+                }
+                QueryFormat::Json => {
+                    let report = query_report(&guest_entry, input, output)?;
+                    println!("{}", serde_json::to_string_pretty(&report)?);
+                }
+            }
             std::io::stdout()
                 .flush()
                 .context("failed to flush stdout buffer")?;
         }
+        Command::Guests {
+            sample_input,
+            format,
+        } => {
+            // Key the sample inputs by the name of the guest they belong to.
+            let mut sample_inputs = HashMap::new();
+            for (guest_binary, path) in sample_input {
+                let guest_entry = resolve_guest_entry(GUEST_LIST, &guest_binary)
+                    .context("failed to resolve guest entry")?;
+                sample_inputs.insert(guest_entry.name, read_input_file(&path)?);
+            }
+
+            let summaries = GUEST_LIST
+                .iter()
+                .map(|guest_entry| {
+                    GuestSummary::new(guest_entry, sample_inputs.remove(guest_entry.name))
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+
+            match format {
+                GuestsFormat::Table => println!("{}", format_table(&summaries)),
+                GuestsFormat::Json => {
+                    let summaries: Vec<_> = summaries.iter().map(GuestSummary::to_json).collect();
+                    println!("{}", serde_json::to_string_pretty(&summaries)?);
+                }
+            }
+        }
+        Command::Codegen {
+            solidity_path,
+            manifest_path,
+            check,
+        } => {
+            let files = codegen::generate(GUEST_LIST, &solidity_path, &manifest_path)?;
+            match check {
+                true => codegen::check(&files)?,
+                false => codegen::write(&files)?,
+            }
+        }
         Command::Upload { guest_binary } => {
             let image_ids = upload_images(
                 guest_binary,
-                &args.global_opts.bonsai_api_url,
-                &args.global_opts.bonsai_api_key,
+                &args.global_opts.bonsai_api_url(),
+                &args.global_opts.bonsai_api_key(),
             )
             .await?;
 
@@ -212,42 +366,190 @@ async fn main() -> anyhow::Result<()> {
                 .flush()
                 .context("failed to flush stdout buffer")?;
         }
-        Command::Run {
-            relay_address,
-            eth_node,
-            eth_chain_id,
-            private_key,
+        Command::Request {
+            guest_binary,
+            callback_contract,
+            function_signature,
+            input,
+            gas_limit,
+            webhook_url,
+            bonsai_relay_api_url,
+            relay_api_key,
         } => {
-            let relayer = Relayer {
-                rest_api: true,
-                dev_mode: dev_mode,
-                rest_api_port: "8080".to_string(),
-                bonsai_api_url: args.global_opts.bonsai_api_url.clone(),
-                bonsai_api_key: args.global_opts.bonsai_api_key.clone(),
-                relay_contract_address: relay_address,
+            let input = input.encode()?.context(
+                "a guest input is required: pass it as a hex string, with --arg or with --input-file",
+            )?;
+            if input.is_empty() {
+                anyhow::bail!("the guest input is empty");
+            }
+            let relay_client = Client::from_parts(bonsai_relay_api_url, relay_api_key)
+                .context("failed to initialize the relay client")?;
+
+            let request = CallbackRequest {
+                callback_contract,
+                function_selector: ethers::utils::id(function_signature.replace(' ', "")),
+                gas_limit,
+                image_id: resolve_image_id(&guest_binary)?,
+                input,
+                webhook_url,
             };
-            let client_config =
-                EthersClientConfig::new(eth_node, eth_chain_id, private_key.try_into()?);
-            let server_handle = tokio::spawn(relayer.run(client_config));
-
-            // HACK: Wait 1 second to give local Bonsai a chance to start.
-            std::thread::sleep(std::time::Duration::from_secs(1));
-
-            // Upload all locally defined images.
-            upload_images(
-                None,
-                &args.global_opts.bonsai_api_url,
-                &args.global_opts.bonsai_api_key,
-            )
-            .await?;
+            let response = relay_client
+                .callback_request(request)
+                .await
+                .context("callback request failed")?;
+            println!("{}", response.request_id);
+        }
+        Command::Run {
+            config,
+            overrides,
+            ready_timeout_secs,
+            dev_callback_contracts,
+        } => {
+            let mut config = RelayConfig::load(config.as_deref())?;
+            overrides.apply(&mut config)?;
+            args.global_opts.bonsai.apply(&mut config);
+            if !dev_callback_contracts.is_empty() {
+                config.rest_api.api_keys.push(ApiKey {
+                    image_ids: GUEST_LIST
+                        .iter()
+                        .map(|guest_entry| {
+                            H256(bytemuck::cast::<_, [u8; 32]>(guest_entry.image_id))
+                        })
+                        .collect(),
+                    callback_contracts: dev_callback_contracts,
+                    ..ApiKey::new("dev", DEV_RELAY_API_KEY)
+                });
+            }
+            if config.wallet.private_key.is_none()
+                && config.wallet.keystore.is_none()
+                && config.wallet.remote_signer_url.is_none()
+            {
+                config.wallet.private_key = Some(ANVIL_DEFAULT_KEY.to_string());
+            }
+            config.validate()?;
+            println!("Relay configuration:\n{config}");
+
+            let relayer = config.relayer()?;
+            let client_config = config.client_config()?;
+            let (readiness, server_handle) = relayer.spawn(client_config);
+
+            // Wait for the REST API, local Bonsai and the chain subscription.
+            if let Err(err) = readiness
+                .wait(Duration::from_secs(ready_timeout_secs))
+                .await
+            {
+                server_handle.abort();
+                return Err(err.into());
+            }
+
+            // Upload all locally defined images. A failed upload only affects
+            // requests for that guest, so the relay keeps running.
+            for guest_entry in GUEST_LIST.iter() {
+                if let Err(err) =
+                    upload_image(guest_entry, &config.bonsai.api_url, &config.bonsai.api_key).await
+                {
+                    eprintln!("Failed to upload {}: {err:?}", guest_entry.name);
+                }
+            }
 
-            // Wait for the server to exit.
-            let _ = server_handle.await;
+            // Wait for the relay to stop, either drained by an operator or
+            // after a subsystem failed repeatedly.
+            server_handle.await??;
         }
     }
     Ok(())
 }
 
+/// ABI encode the result of a query for the Foundry FFI integration.
+fn query_output_tokens(
+    guest_entry: &GuestListEntry,
+    dev_mode: bool,
+    output: Option<Output>,
+) -> anyhow::Result<Vec<Token>> {
+    let Some(output) = output else {
+        // No input. Return the Ethereum ABI encoded bytes32 image ID.
+        return Ok(vec![
+            Hash::from(bytemuck::cast::<_, [u8; 32]>(guest_entry.image_id)).into_token(),
+        ]);
+    };
+
+    // Input provided. Return the Ethereum ABI encoded journal and, if proven on
+    // Bonsai, the post state digest and seal.
+    match (dev_mode, output) {
+        (true, Output::Execution { journal, .. }) => Ok(vec![Token::Bytes(journal)]),
+        (
+            false,
+            Output::Bonsai {
+                journal,
+                receipt_metadata,
+                snark_proof,
+            },
+        ) => Ok(vec![
+            Token::Bytes(journal),
+            Hash::from(<[u8; 32]>::from(receipt_metadata.post.digest())).into_token(),
+            Token::Bytes(ethers::abi::encode(&[tokenize_snark_proof(&snark_proof)?])),
+        ]),
+        _ => anyhow::bail!("invalid dev mode and output combination: {:?}", dev_mode),
+    }
+}
+
+/// Build a human readable report of a query, decoding the journal with the
+/// schema declared by the guest, if any.
+fn query_report(
+    guest_entry: &GuestListEntry<'static>,
+    input: Option<Vec<u8>>,
+    output: Option<Output>,
+) -> anyhow::Result<serde_json::Value> {
+    let image_id = bytemuck::cast::<_, [u8; 32]>(guest_entry.image_id);
+    let mut report = json!({
+        "guest": guest_entry.name,
+        "image_id": format!("0x{}", hex::encode(image_id)),
+    });
+    let (Some(input), Some(output)) = (input, output) else {
+        return Ok(report);
+    };
+
+    let (journal, stats) = match output {
+        Output::Execution { journal, stats } => (journal, stats),
+        Output::Bonsai {
+            journal,
+            receipt_metadata,
+            snark_proof,
+        } => {
+            report["post_state_digest"] =
+                json!(format!("0x{}", hex::encode(receipt_metadata.post.digest())));
+            report["seal"] = json!(format!(
+                "0x{}",
+                hex::encode(ethers::abi::encode(&[tokenize_snark_proof(&snark_proof)?]))
+            ));
+            // Bonsai does not report resource usage, so re-run the guest locally.
+            let stats = execution_stats(guest_entry.elf, input)
+                .context("failed to execute guest locally")?;
+            (journal, stats)
+        }
+    };
+
+    report["journal"] = match JournalSchema::lookup(JOURNAL_SCHEMAS, guest_entry.name)? {
+        Some(schema) => schema.decode(&journal)?.into(),
+        None => json!(format!("0x{}", hex::encode(&journal))),
+    };
+    report["cycles"] = json!(stats.cycles);
+    report["segments"] = json!(stats.segments);
+    Ok(report)
+}
+
+/// Resolve the image ID of a guest in the GUEST_LIST by name or image ID, or
+/// accept any other image ID given as a hex string.
+fn resolve_image_id(guest_binary: &String) -> anyhow::Result<[u8; 32]> {
+    match resolve_guest_entry(GUEST_LIST, guest_binary) {
+        Ok(guest_entry) => Ok(bytemuck::cast(guest_entry.image_id)),
+        Err(err) => hex::decode(guest_binary.trim_start_matches("0x"))
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or(err),
+    }
+}
+
 /// Upload a single specified image, or, if guest_binary is None, upload all
 /// images in the GUEST_LIST. Returns a list of uploaded image IDs.
 async fn upload_images(
@@ -264,29 +566,27 @@ async fn upload_images(
     // Upload each guest binary.
     let mut image_ids = Vec::<Digest>::new();
     for guest_entry in guest_entries.iter() {
-        // Search list for requested binary name
-        let image_id = hex::encode(Vec::from(bytemuck::cast::<[u32; 8], [u8; 32]>(
-            guest_entry.image_id,
-        )));
-
-        // upload binary to Bonsai
-        let bonsai_client =
-            get_client_from_parts(bonsai_api_url.to_string(), bonsai_api_key.to_string()).await?;
-        let img_id = image_id.clone();
-
-        match put_image(
-            bonsai_client.clone(),
-            img_id.clone(),
-            guest_entry.elf.to_vec(),
-        )
-        .await
-        {
-            Ok(()) | Err(SdkErr::ImageIdExists) => Ok::<_, anyhow::Error>(()),
-            Err(err) => Err(err.into()),
-        }?;
-
+        upload_image(guest_entry, bonsai_api_url, bonsai_api_key).await?;
         image_ids.push(guest_entry.image_id.into());
     }
 
     Ok(image_ids)
 }
+
+/// Upload a guest binary to Bonsai, succeeding if it was already uploaded.
+async fn upload_image(
+    guest_entry: &GuestListEntry,
+    bonsai_api_url: &str,
+    bonsai_api_key: &str,
+) -> anyhow::Result<()> {
+    let image_id = hex::encode(Vec::from(bytemuck::cast::<[u32; 8], [u8; 32]>(
+        guest_entry.image_id,
+    )));
+    let bonsai_client =
+        get_client_from_parts(bonsai_api_url.to_string(), bonsai_api_key.to_string()).await?;
+
+    match put_image(bonsai_client, image_id, guest_entry.elf.to_vec()).await {
+        Ok(()) | Err(SdkErr::ImageIdExists) => Ok(()),
+        Err(err) => Err(err.into()),
+    }
+}